@@ -0,0 +1,49 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use cuboard_core::{
+    cube::{CubeMove, CubeState},
+    solver::{self, CubieCoords},
+};
+
+// the same operations as `cuboard bench --engine`, measured by criterion
+fn engine(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let moves = (0..1000)
+        .map(|_| CubeMove::from_repr(rng.gen_range(0..12)).unwrap())
+        .collect::<Vec<_>>();
+    let tables = solver::turn_tables();
+
+    c.bench_function("state apply", |b| {
+        let mut state = CubeState::default();
+        let mut moves = moves.iter().cycle();
+        b.iter(|| state.apply(*moves.next().unwrap()));
+        black_box(state);
+    });
+    c.bench_function("coords turn", |b| {
+        let mut coords = CubieCoords::of(&CubeState::default());
+        let mut tables = tables.iter().cycle();
+        b.iter(|| coords = coords.then(&tables.next().unwrap().1));
+        black_box(coords);
+    });
+    c.bench_function("scramble of 20", |b| {
+        b.iter(|| solver::random_scramble(20, &mut rng));
+    });
+    let scrambles = (0..100)
+        .map(|_| {
+            let turns = solver::random_scramble(5, &mut rng);
+            let moves = turns
+                .iter()
+                .flat_map(|turn| turn.moves())
+                .collect::<Vec<_>>();
+            CubeState::from_moves(&moves)
+        })
+        .collect::<Vec<_>>();
+    c.bench_function("solve of 5", |b| {
+        let mut scrambles = scrambles.iter().cycle();
+        b.iter(|| solver::solve(scrambles.next().unwrap(), &CubeState::default(), 5));
+    });
+}
+
+criterion_group!(benches, engine);
+criterion_main!(benches);
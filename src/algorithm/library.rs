@@ -0,0 +1,301 @@
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+
+use super::{expand_moves, parse_algorithm, CubeOrientation, Move};
+use crate::cube::{CornerPosition, CubeMove, CubeState, EdgePosition};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Display, EnumIter)]
+pub enum AlgorithmSet {
+    #[strum(serialize = "F2L")]
+    F2l,
+    #[strum(serialize = "OLL")]
+    Oll,
+    #[strum(serialize = "PLL")]
+    Pll,
+    #[strum(serialize = "trigger")]
+    Trigger,
+}
+
+impl FromStr for AlgorithmSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AlgorithmSet::iter()
+            .find(|set| set.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown algorithm set: {}", s))
+    }
+}
+
+impl AlgorithmSet {
+    // the pieces solved by the algorithms of this set, if any
+    fn is_goal(self, state: &CubeState) -> bool {
+        let corners_solved = |range: std::ops::Range<usize>| {
+            range
+                .into_iter()
+                .all(|i| state.corners[i].0 as usize == i && state.corners[i].1.repr() == 0)
+        };
+        let edges_solved = |range: std::ops::Range<usize>| {
+            range
+                .into_iter()
+                .all(|i| state.edges[i].0 as usize == i && state.edges[i].1.repr() == 0)
+        };
+        match self {
+            // the cross and the front right pair
+            AlgorithmSet::F2l => is_cross_solved(state) && is_pair_solved(state, 0),
+            // the first two layers, and the last layer facing up
+            AlgorithmSet::Oll => {
+                corners_solved(4..8)
+                    && edges_solved(4..12)
+                    && state.corners.iter().all(|c| c.1.repr() == 0)
+                    && state.edges.iter().all(|e| e.1.repr() == 0)
+            }
+            // solved, up to a turn of the last layer
+            AlgorithmSet::Pll => {
+                let mut state = *state;
+                (0..4).any(|_| {
+                    state.apply(CubeMove::U);
+                    state.is_solved()
+                })
+            }
+            AlgorithmSet::Trigger => false,
+        }
+    }
+}
+
+// the F2L slots FR, FL, BL and BR, as the corner and the edge of their pairs
+pub const F2L_SLOTS: [(CornerPosition, EdgePosition); 4] = [
+    (CornerPosition::DFR, EdgePosition::FR),
+    (CornerPosition::DLF, EdgePosition::FL),
+    (CornerPosition::DBL, EdgePosition::BL),
+    (CornerPosition::DRB, EdgePosition::BR),
+];
+// the rotation holding each slot at front right, where the F2L algorithms insert
+const SLOT_ROTATIONS: [&str; 4] = ["", "y'", "y2", "y"];
+
+fn is_cross_solved(state: &CubeState) -> bool {
+    (4..8).all(|i| state.edges[i].0 as usize == i && state.edges[i].1.repr() == 0)
+}
+
+fn is_pair_solved(state: &CubeState, slot: usize) -> bool {
+    let (corner, edge) = F2L_SLOTS[slot];
+    let (placed_corner, placed_edge) = (state.corners[corner as usize], state.edges[edge as usize]);
+    placed_corner.0 == corner
+        && placed_corner.1.repr() == 0
+        && placed_edge.0 == edge
+        && placed_edge.1.repr() == 0
+}
+
+// the first unsolved F2L slot
+pub fn next_f2l_pair(state: &CubeState) -> Option<usize> {
+    (0..F2L_SLOTS.len()).find(|&slot| !is_pair_solved(state, slot))
+}
+
+// the F2L algorithm inserting the pair of the slot without breaking the cross and the solved
+// pairs, with the rotation and the setup turn of U before it
+pub fn find_insertion(state: &CubeState, slot: usize) -> Option<String> {
+    if !is_cross_solved(state) {
+        return None;
+    }
+    let algs = LIBRARY.iter().filter(|alg| alg.set == AlgorithmSet::F2l);
+    algs.flat_map(|alg| ["", "U", "U2", "U'"].map(|setup| (setup, alg)))
+        .find_map(|(setup, alg)| {
+            let text = [SLOT_ROTATIONS[slot], setup, alg.notation]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let (turns, _) =
+                expand_moves(&parse_algorithm(&text).ok()?, CubeOrientation::default());
+            let mut res = *state;
+            for mv in turns {
+                res.apply(mv);
+            }
+            let kept = (0..F2L_SLOTS.len())
+                .all(|other| !is_pair_solved(state, other) || is_pair_solved(&res, other));
+            (is_cross_solved(&res) && is_pair_solved(&res, slot) && kept).then_some(text)
+        })
+}
+
+pub struct NamedAlgorithm {
+    pub set: AlgorithmSet,
+    pub name: &'static str,
+    pub notation: &'static str,
+    pub description: &'static str,
+}
+
+impl NamedAlgorithm {
+    pub fn moves(&self) -> Vec<Move> {
+        parse_algorithm(self.notation).unwrap()
+    }
+
+    // the face turns on the cube held white up and green front
+    pub fn turns(&self) -> Vec<CubeMove> {
+        expand_moves(&self.moves(), CubeOrientation::default()).0
+    }
+
+    // whether the algorithm solves its goal from the state, after turning U to set it up
+    pub fn matches(&self, state: &CubeState) -> bool {
+        let turns = self.turns();
+        let mut state = *state;
+        (0..4).any(|_| {
+            state.apply(CubeMove::U);
+            let mut res = state;
+            for &mv in &turns {
+                res.apply(mv);
+            }
+            self.set.is_goal(&res)
+        })
+    }
+}
+
+macro_rules! alg {
+    ($set:ident, $name:expr, $notation:expr, $description:expr) => {
+        NamedAlgorithm {
+            set: AlgorithmSet::$set,
+            name: $name,
+            notation: $notation,
+            description: $description,
+        }
+    };
+}
+
+#[rustfmt::skip]
+pub const LIBRARY: &[NamedAlgorithm] = &[
+    alg!(Trigger, "sexy", "R U R' U'", "the most common trigger"),
+    alg!(Trigger, "reverse sexy", "U R U' R'", "undo of the sexy move"),
+    alg!(Trigger, "left sexy", "L' U' L U", "mirror of the sexy move"),
+    alg!(Trigger, "sledgehammer", "R' F R F'", "pair breaker"),
+    alg!(Trigger, "hedgeslammer", "F R' F' R", "inverse of the sledgehammer"),
+    alg!(Trigger, "sune", "R U R' U R U2 R'", "corner twister"),
+    alg!(Trigger, "niklas", "R U' L' U R' U' L", "corner 3-cycle"),
+
+    alg!(F2l, "F2L 1", "U R U' R'", "pair on top, corner white right"),
+    alg!(F2l, "F2L 2", "U' F' U F", "pair on top, corner white front"),
+    alg!(F2l, "F2L 3", "F' U' F", "connected pair on top, edge left"),
+    alg!(F2l, "F2L 4", "R U R'", "connected pair on top, edge back"),
+    alg!(F2l, "F2L 5", "U' R U R' U2 R U' R'", "split pair, edge back"),
+    alg!(F2l, "F2L 6", "U F' U' F U2 F' U F", "split pair, edge left"),
+    alg!(F2l, "F2L 37", "R U' R' U' R U2 R' U' R U' R'", "pair in slot, edge flipped"),
+
+    alg!(Oll, "OLL 21", "R U2 R' U' R U R' U' R U' R'", "cross, H"),
+    alg!(Oll, "OLL 22", "R U2 R2 U' R2 U' R2 U2 R", "cross, pi"),
+    alg!(Oll, "OLL 23", "R2 D R' U2 R D' R' U2 R'", "cross, headlights"),
+    alg!(Oll, "OLL 24", "r U R' U' r' F R F'", "cross, chameleon"),
+    alg!(Oll, "OLL 25", "F' r U R' U' r' F R", "cross, bowtie"),
+    alg!(Oll, "OLL 26", "R U2 R' U' R U' R'", "cross, antisune"),
+    alg!(Oll, "OLL 27", "R U R' U R U2 R'", "cross, sune"),
+    alg!(Oll, "OLL 28", "r U R' U' M U R U' R'", "corners oriented, arrow"),
+    alg!(Oll, "OLL 33", "R U R' U' R' F R F'", "T shape"),
+    alg!(Oll, "OLL 44", "f R U R' U' f'", "P shape"),
+    alg!(Oll, "OLL 45", "F R U R' U' F'", "T shape"),
+    alg!(Oll, "OLL 57", "R U R' U' M' U R U' r'", "corners oriented, H"),
+
+    alg!(Pll, "Aa", "x R' U R' D2 R U' R' D2 R2 x'", "corner 3-cycle, clockwise"),
+    alg!(Pll, "Ab", "x R2 D2 R U R' D2 R U' R x'", "corner 3-cycle, counterclockwise"),
+    alg!(Pll, "E", "x' R U' R' D R U R' D' R U R' D R U' R' D' x", "corner double swap"),
+    alg!(Pll, "F", "R' U' F' R U R' U' R' F R2 U' R' U' R U R' U R", "adjacent swaps, headlights"),
+    alg!(Pll, "Ga", "R2 U R' U R' U' R U' R2 U' D R' U R D'", "G permutation"),
+    alg!(Pll, "Gb", "R' U' R U D' R2 U R' U R U' R U' R2 D", "G permutation"),
+    alg!(Pll, "Gc", "R2 U' R U' R U R' U R2 U D' R U' R' D", "G permutation"),
+    alg!(Pll, "Gd", "R U R' U' D R2 U' R U' R' U R' U R2 D'", "G permutation"),
+    alg!(Pll, "H", "M2 U M2 U2 M2 U M2", "opposite edge swaps"),
+    alg!(Pll, "Ja", "R' U L' U2 R U' R' U2 R L", "adjacent swaps, left block"),
+    alg!(Pll, "Jb", "R U R' F' R U R' U' R' F R2 U' R'", "adjacent swaps, right block"),
+    alg!(Pll, "Na", "R U R' U R U R' F' R U R' U' R' F R2 U' R' U2 R U' R'", "diagonal swaps"),
+    alg!(Pll, "Nb", "R' U R U' R' F' U' F R U R' F R' F' R U' R", "diagonal swaps"),
+    alg!(Pll, "Ra", "R U' R' U' R U R D R' U' R D' R' U2 R'", "adjacent swaps, headlights"),
+    alg!(Pll, "Rb", "R2 F R U R U' R' F' R U2 R' U2 R", "adjacent swaps, headlights"),
+    alg!(Pll, "T", "R U R' U' R' F R2 U' R' U' R U R' F'", "adjacent swaps, T shape"),
+    alg!(Pll, "Ua", "M2 U M U2 M' U M2", "edge 3-cycle, counterclockwise"),
+    alg!(Pll, "Ub", "M2 U' M U2 M' U' M2", "edge 3-cycle, clockwise"),
+    alg!(Pll, "V", "R' U R' U' R D' R' D R' U D' R2 U' R2 D R2", "diagonal swaps, V shape"),
+    alg!(Pll, "Y", "F R U' R' U' R U R' F' R U R' U' R' F R F'", "diagonal swaps, Y shape"),
+    alg!(Pll, "Z", "M2 U M2 U M' U2 M2 U2 M' U2", "adjacent edge swaps"),
+];
+
+// the algorithms whose name contains the query or whose set is the query, ignoring cases
+pub fn search(query: &str) -> impl Iterator<Item = &'static NamedAlgorithm> {
+    let query = query.to_lowercase();
+    LIBRARY.iter().filter(move |alg| {
+        alg.name.to_lowercase().contains(&query) || alg.set.to_string().to_lowercase() == query
+    })
+}
+
+// the algorithms solving the next step from the state
+pub fn find_by_state(state: &CubeState) -> impl Iterator<Item = &'static NamedAlgorithm> + '_ {
+    LIBRARY.iter().filter(|alg| alg.matches(state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithms_solve_their_cases() {
+        for alg in LIBRARY {
+            let moves = alg.moves();
+            let (turns, orientation) = expand_moves(&moves, CubeOrientation::default());
+            assert_eq!(orientation, CubeOrientation::default(), "{}", alg.name);
+            if alg.set == AlgorithmSet::Trigger {
+                continue;
+            }
+            // the case is the solved cube scrambled by the inverse algorithm
+            let inverse = turns.iter().rev().map(|mv| mv.rev()).collect::<Vec<_>>();
+            let case = CubeState::from_moves(&inverse);
+            assert!(!case.is_solved(), "{}", alg.name);
+            assert!(alg.matches(&case), "{}", alg.name);
+            if alg.set != AlgorithmSet::F2l {
+                assert!(AlgorithmSet::F2l.is_goal(&case), "{}", alg.name);
+            }
+        }
+    }
+
+    #[test]
+    fn algorithms_are_searched_by_state() {
+        let t = search("t").find(|alg| alg.name == "T").unwrap();
+        let inverse = t
+            .turns()
+            .iter()
+            .rev()
+            .map(|mv| mv.rev())
+            .collect::<Vec<_>>();
+        let mut case = CubeState::from_moves(&inverse);
+        case.apply(CubeMove::U);
+        let found = find_by_state(&case).map(|alg| alg.name).collect::<Vec<_>>();
+        assert!(found.contains(&"T"), "{:?}", found);
+        assert!(
+            found.iter().all(|name| !name.starts_with("OLL")),
+            "{:?}",
+            found
+        );
+        assert_eq!(search("pll").count(), 21);
+    }
+
+    #[test]
+    fn f2l_pairs_are_inserted() {
+        assert_eq!(next_f2l_pair(&CubeState::default()), None);
+
+        // the back left pair taken out by the inverse of a rotated F2L 4
+        let (turns, _) = expand_moves(
+            &parse_algorithm("y2 R U R' y2").unwrap(),
+            CubeOrientation::default(),
+        );
+        let inverse = turns.iter().rev().map(|mv| mv.rev()).collect::<Vec<_>>();
+        let mut case = CubeState::from_moves(&inverse);
+        case.apply(CubeMove::U);
+        assert_eq!(next_f2l_pair(&case), Some(2));
+
+        let insertion = find_insertion(&case, 2).unwrap();
+        let (turns, _) = expand_moves(
+            &parse_algorithm(&insertion).unwrap(),
+            CubeOrientation::default(),
+        );
+        for mv in turns {
+            case.apply(mv);
+        }
+        assert_eq!(next_f2l_pair(&case), None, "{}", insertion);
+        assert_eq!(find_insertion(&CubeState::of_move(CubeMove::D), 0), None);
+    }
+}
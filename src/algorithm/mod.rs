@@ -0,0 +1,373 @@
+#![allow(dead_code)]
+
+pub mod library;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    iter::Sum,
+    ops::{Add, Neg},
+    str::FromStr,
+};
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString, FromRepr};
+
+use crate::cube::CubeMove;
+
+#[rustfmt::skip]
+#[allow(clippy::upper_case_acronyms)]
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default, Display, EnumIter, EnumString)]
+pub enum CubeOrientation {
+    // <A><B><C><D><E><F>: move <A>/<B>/<C>/<D>/<E>/<F> face to up/right/front/down/left/back
+    #[default]
+    URFDLB, UFLDBR, ULBDRF, UBRDFL, DFRUBL, DLFURB, DBLUFR, DRBULF, // CornerPosition
+    FURBDL, LUFRDB, BULFDR, RUBLDF, RDFLUB, FDLBUR, LDBRUF, BDRFUL, // CornerPosition.rotate_right()
+    RFULBD, FLUBRD, LBURFD, BRUFLD, FRDBLU, LFDRBU, BLDFRU, RBDLFU, // CornerPosition.rotate_left()
+    FRUBLD, LFURBD, BLUFRD, RBULFD, RFDLBU, FLDBRU, LBDRFU, BRDFLU, // CornerPosition.rev()
+    UFRDBL, ULFDRB, UBLDFR, URBDLF, DRFULB, DFLUBR, DLBURF, DBRUFL, // CornerPosition.rev().rotate_right()
+    RUFLDB, FULBDR, LUBRDF, BURFDL, FDRBUL, LDFRUB, BDLFUR, RDBLUF, // CornerPosition.rev().rotate_left()
+}
+
+impl CubeOrientation {
+    // no rotation
+    const I: CubeOrientation = CubeOrientation::URFDLB;
+
+    // rotate along face
+    const U: CubeOrientation = CubeOrientation::UBRDFL;
+    const D: CubeOrientation = CubeOrientation::UFLDBR;
+    const R: CubeOrientation = CubeOrientation::FRDBLU;
+    const L: CubeOrientation = CubeOrientation::BRUFLD;
+    const F: CubeOrientation = CubeOrientation::LUFRDB;
+    const B: CubeOrientation = CubeOrientation::RDFLUB;
+
+    // swap face
+    const UD: CubeOrientation = CubeOrientation::DRFULB;
+    const RL: CubeOrientation = CubeOrientation::ULFDRB;
+    const FB: CubeOrientation = CubeOrientation::URBDLF;
+}
+
+impl CubeOrientation {
+    pub fn repr(self) -> u8 {
+        self as u8
+    }
+
+    fn chars(self) -> [char; 6] {
+        self.to_string()
+            .chars()
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap()
+    }
+
+    fn perm(self) -> [usize; 6] {
+        let chars = Self::default().chars();
+        self.chars()
+            .map(|c| chars.iter().position(|&a| a == c).unwrap())
+    }
+
+    fn try_from_perm(perm: [usize; 6]) -> Option<Self> {
+        use std::str::FromStr;
+        let chars = Self::default().chars();
+        let name = perm.iter().map(|&i| chars[i]).collect::<String>();
+        Self::from_str(&name).ok()
+    }
+
+    pub fn is_mirror(self) -> bool {
+        self.repr() >= 24
+    }
+
+    pub fn as_map(&self) -> HashMap<CubeMove, CubeMove> {
+        CubeMove::iter()
+            .map(|mv| {
+                let repr = mv.repr();
+                let (ind, dir) = (repr / 2, repr % 2);
+                let ind_ = self.perm().iter().position(|&i| i == ind as usize).unwrap() as u8;
+                let dir_ = if self.is_mirror() { (dir + 1) % 2 } else { dir };
+                let repr_ = ind_ * 2 + dir_;
+                (mv, CubeMove::from_repr(repr_).unwrap())
+            })
+            .collect::<HashMap<_, _>>()
+    }
+}
+
+impl Add<CubeOrientation> for CubeOrientation {
+    type Output = CubeOrientation;
+
+    fn add(self, rhs: CubeOrientation) -> Self::Output {
+        let perm = self.perm();
+        let added_perm = rhs.perm().map(|i| perm[i]);
+        Self::try_from_perm(added_perm).unwrap()
+    }
+}
+
+impl Sum for CubeOrientation {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        let sum_perm = iter
+            .map(|r| r.perm())
+            .fold(Self::default().perm(), |lhs, rhs| rhs.map(|i| lhs[i]));
+        Self::try_from_perm(sum_perm).unwrap()
+    }
+}
+
+impl<'a> Sum<&'a CubeOrientation> for CubeOrientation {
+    fn sum<I: Iterator<Item = &'a CubeOrientation>>(iter: I) -> Self {
+        let sum_perm = iter
+            .map(|r| r.perm())
+            .fold(Self::default().perm(), |lhs, rhs| rhs.map(|i| lhs[i]));
+        Self::try_from_perm(sum_perm).unwrap()
+    }
+}
+
+impl Neg for CubeOrientation {
+    type Output = CubeOrientation;
+
+    fn neg(self) -> Self::Output {
+        let perm = self.perm();
+        let neg_perm = Self::default()
+            .perm()
+            .map(|i| perm.iter().position(|&j| j == i).unwrap());
+        Self::try_from_perm(neg_perm).unwrap()
+    }
+}
+
+#[rustfmt::skip]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, EnumIter, FromRepr)]
+#[repr(u8)]
+pub enum SliceMove {
+    // M follows L, E follows D, S follows F
+    M, Mp, E, Ep, S, Sp,
+}
+
+#[rustfmt::skip]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, EnumIter, FromRepr)]
+#[repr(u8)]
+pub enum CubeRotation {
+    // x follows R, y follows U, z follows F
+    X, Xp, Y, Yp, Z, Zp,
+}
+
+// a move of the standard notation; only face turns are reported by the cube, the others are
+// expanded into face turns followed by a rotation of the whole cube
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Move {
+    Face(CubeMove),
+    Wide(CubeMove),
+    Slice(SliceMove),
+    Rotation(CubeRotation),
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const SLICES: [&str; 6] = ["M", "M'", "E", "E'", "S", "S'"];
+        const ROTATIONS: [&str; 6] = ["x", "x'", "y", "y'", "z", "z'"];
+        match *self {
+            Move::Face(mv) => write!(f, "{}", mv),
+            Move::Wide(mv) => write!(f, "{}", mv.to_string().to_lowercase()),
+            Move::Slice(mv) => write!(f, "{}", SLICES[mv as usize]),
+            Move::Rotation(rot) => write!(f, "{}", ROTATIONS[rot as usize]),
+        }
+    }
+}
+
+impl FromStr for Move {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Move::iter()
+            .find(|mv| mv.to_string() == s)
+            .ok_or_else(|| format!("unknown move: {}", s))
+    }
+}
+
+impl Move {
+    pub fn iter() -> impl Iterator<Item = Move> {
+        CubeMove::iter()
+            .map(Move::Face)
+            .chain(CubeMove::iter().map(Move::Wide))
+            .chain(SliceMove::iter().map(Move::Slice))
+            .chain(CubeRotation::iter().map(Move::Rotation))
+    }
+
+    fn repr(self) -> u8 {
+        match self {
+            Move::Face(mv) | Move::Wide(mv) => mv.repr(),
+            Move::Slice(mv) => mv as u8,
+            Move::Rotation(rot) => rot as u8,
+        }
+    }
+
+    // replace the repr, keeping the kind of the move
+    fn with_repr(self, repr: u8) -> Self {
+        match self {
+            Move::Face(_) => Move::Face(CubeMove::from_repr(repr).unwrap()),
+            Move::Wide(_) => Move::Wide(CubeMove::from_repr(repr).unwrap()),
+            Move::Slice(_) => Move::Slice(SliceMove::from_repr(repr).unwrap()),
+            Move::Rotation(_) => Move::Rotation(CubeRotation::from_repr(repr).unwrap()),
+        }
+    }
+
+    pub fn is_clockwise(self) -> bool {
+        self.repr().is_multiple_of(2)
+    }
+
+    pub fn rev(self) -> Self {
+        self.with_repr(self.repr() ^ 1)
+    }
+
+    pub fn abs(self) -> Self {
+        self.with_repr(self.repr() / 2 * 2)
+    }
+
+    // 0 for U/D, 1 for R/L, 2 for F/B
+    pub fn axis(self) -> u8 {
+        match self {
+            Move::Face(mv) | Move::Wide(mv) => mv.repr() / 2 % 3,
+            Move::Slice(mv) => [1, 0, 2][mv as usize / 2],
+            Move::Rotation(rot) => [1, 0, 2][rot as usize / 2],
+        }
+    }
+
+    // moves around the same axis commute
+    pub fn commute(self, other: Self) -> bool {
+        self.axis() == other.axis()
+    }
+
+    // the face turns and the rotation of the whole cube performed afterward
+    pub fn expand(self) -> (Vec<CubeMove>, CubeOrientation) {
+        use CubeMove::*;
+
+        if let Move::Face(mv) = self {
+            return (vec![mv], CubeOrientation::I);
+        }
+        let (turns, rotation) = match self.abs() {
+            Move::Wide(U) => (vec![D], CubeOrientation::U),
+            Move::Wide(R) => (vec![L], CubeOrientation::R),
+            Move::Wide(F) => (vec![B], CubeOrientation::F),
+            Move::Wide(D) => (vec![U], CubeOrientation::D),
+            Move::Wide(L) => (vec![R], CubeOrientation::L),
+            Move::Wide(B) => (vec![F], CubeOrientation::B),
+            Move::Slice(SliceMove::M) => (vec![R, Lp], CubeOrientation::L),
+            Move::Slice(SliceMove::E) => (vec![U, Dp], CubeOrientation::D),
+            Move::Slice(SliceMove::S) => (vec![Fp, B], CubeOrientation::F),
+            Move::Rotation(CubeRotation::X) => (vec![], CubeOrientation::R),
+            Move::Rotation(CubeRotation::Y) => (vec![], CubeOrientation::U),
+            Move::Rotation(CubeRotation::Z) => (vec![], CubeOrientation::F),
+            _ => unreachable!(),
+        };
+        if self.is_clockwise() {
+            (turns, rotation)
+        } else {
+            (turns.into_iter().map(CubeMove::rev).collect(), -rotation)
+        }
+    }
+}
+
+// the face turns of the moves on the cube held at the orientation, and the final orientation
+pub fn expand_moves(
+    moves: &[Move],
+    orientation: CubeOrientation,
+) -> (Vec<CubeMove>, CubeOrientation) {
+    let mut orientation = orientation;
+    let mut res = Vec::new();
+    for mv in moves {
+        let (turns, rotation) = mv.expand();
+        // the face at each position of the held cube
+        let map = (-orientation).as_map();
+        res.extend(turns.iter().map(|turn| map[turn]));
+        orientation = orientation + rotation;
+    }
+    (res, orientation)
+}
+
+// moves separated by spaces, such as `R U R' U'` or `M2 U M2 U2`
+pub fn parse_algorithm(text: &str) -> Result<Vec<Move>, String> {
+    let mut res = Vec::new();
+    for token in text.split_whitespace() {
+        match token.strip_suffix('2') {
+            Some(token) => res.extend([token.parse::<Move>()?; 2]),
+            None => res.push(token.parse()?),
+        }
+    }
+    Ok(res)
+}
+
+// a pair of the same move is a half turn, such as `M M` into `M2`
+pub fn format_algorithm(moves: &[Move]) -> String {
+    let mut res = Vec::new();
+    let mut moves = moves.iter().peekable();
+    while let Some(&mv) = moves.next() {
+        if moves.next_if_eq(&&mv).is_some() {
+            res.push(format!("{}2", mv.abs()));
+        } else {
+            res.push(mv.to_string());
+        }
+    }
+    res.join(" ")
+}
+
+pub fn span<A>(gen: &[A]) -> Vec<(A, Vec<A>)>
+where
+    A: Add<A, Output = A> + Copy + Eq,
+{
+    let mut leaves = gen.to_vec();
+    let mut res = leaves.iter().map(|&a| (a, vec![a])).collect::<Vec<_>>();
+
+    while let Some(leaf) = leaves.pop() {
+        let path = res.iter().find(|(a, _)| a == &leaf).cloned().unwrap().1;
+        for &a in gen.iter() {
+            let next = leaf + a;
+            if res.iter().any(|(a, _)| a == &next) {
+                continue;
+            };
+            let mut next_path = path.clone();
+            next_path.push(a);
+            res.push((next, next_path));
+            leaves.push(next);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_are_parsed_and_formatted() {
+        let moves = parse_algorithm("r U' M2 x' S").unwrap();
+        assert_eq!(format_algorithm(&moves), "r U' M2 x' S");
+        assert_eq!(parse_algorithm(&format_algorithm(&moves)), Ok(moves));
+        assert!(parse_algorithm("R Q").is_err());
+        for mv in Move::iter() {
+            assert_eq!(mv.rev().rev(), mv);
+            assert_eq!(mv.to_string().parse::<Move>(), Ok(mv));
+        }
+    }
+
+    #[test]
+    fn moves_are_expanded_into_face_turns() {
+        use CubeMove::*;
+
+        // a rotation relabels the faces of the following turns
+        let moves = parse_algorithm("x U' y R").unwrap();
+        let (turns, orientation) = expand_moves(&moves, CubeOrientation::I);
+        assert_eq!(turns, vec![Fp, U]);
+        assert_eq!(orientation, CubeOrientation::R + CubeOrientation::U);
+
+        // a move followed by its reverse cancels out
+        for mv in Move::iter() {
+            let (turns, orientation) = expand_moves(&[mv, mv.rev()], CubeOrientation::I);
+            let (inverse, _) = expand_moves(&[mv], CubeOrientation::I);
+            assert_eq!(turns.len(), inverse.len() * 2, "{}", mv);
+            assert_eq!(orientation, CubeOrientation::I, "{}", mv);
+        }
+
+        // M2 U M2 U2 is a valid algorithm with no net rotation
+        let moves = parse_algorithm("M2 U M2 U2").unwrap();
+        let (turns, orientation) = expand_moves(&moves, CubeOrientation::I);
+        assert_eq!(orientation, CubeOrientation::I);
+        assert_eq!(turns, vec![R, Lp, R, Lp, D, R, Lp, R, Lp, U, U]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::{error::Error, time::Duration};
+
+use tokio::{sync::mpsc, time::timeout};
+
+use crate::{
+    algorithm::library::{self, AlgorithmSet, NamedAlgorithm, LIBRARY},
+    bluetooth::{connect_cube, gancubev2::ResponseMessage},
+    config::flag_value,
+    cube::CubeState,
+    shutdown::Shutdown,
+};
+
+const STATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// the state reported by the cube, or nothing if shutdown is requested
+async fn read_cube_state(shutdown: Shutdown) -> Result<Option<CubeState>, Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    // only moves are needed
+    gancube.message_filter().set_gyroscope(false);
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let handle = gancube
+        .register_handler(Box::new(move |msg| {
+            if let ResponseMessage::State {
+                count: _,
+                state: Some(state),
+            } = msg
+            {
+                let _ = sender.send(state);
+            }
+        }))
+        .await?;
+
+    gancube.subscribe_response().await?;
+    gancube.request_cube_state().await?;
+
+    let state = tokio::select! {
+        state = timeout(STATE_TIMEOUT, receiver.recv()) => state.ok().flatten(),
+        _ = shutdown.wait() => None,
+    };
+
+    shutdown.close(&gancube).await?;
+    handle.abort();
+
+    Ok(state)
+}
+
+// select algorithms by `--set=NAME`, `--search=QUERY` and `--match`, which keeps the algorithms
+// solving the next step from the state of the connected cube
+pub async fn select(
+    flags: &[String],
+    shutdown: Shutdown,
+) -> Result<Vec<&'static NamedAlgorithm>, Box<dyn Error>> {
+    let set = flag_value(flags, "set")
+        .map(str::parse::<AlgorithmSet>)
+        .transpose()?;
+    let mut algs = match flag_value(flags, "search") {
+        Some(query) => library::search(query).collect::<Vec<_>>(),
+        None => LIBRARY.iter().collect(),
+    };
+    algs.retain(|alg| set.is_none_or(|set| alg.set == set));
+
+    if flags.iter().any(|flag| flag == "--match") {
+        let Some(state) = read_cube_state(shutdown).await? else {
+            println!("no cube state received");
+            return Ok(Vec::new());
+        };
+        algs.retain(|alg| alg.matches(&state));
+    }
+
+    Ok(algs)
+}
+
+pub fn print(algs: &[&NamedAlgorithm]) {
+    if algs.is_empty() {
+        println!("no algorithm found");
+    }
+    for alg in algs {
+        println!(
+            "{:<8} {:<14} {:<48} {}",
+            alg.set.to_string(),
+            alg.name,
+            alg.notation,
+            alg.description
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+    path::Path,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+use crate::{
+    daemon::{DaemonEvent, TypedScreen},
+    i18n::{tr, Message},
+    shutdown::Shutdown,
+    terminal::{cells, Cell, Screen},
+    theme,
+};
+
+// the finished lines scroll away above the unfinished line, whose buffered text is underlined,
+// and the status of the cube is shown below
+fn draw<F: Write>(
+    terminal: &mut F,
+    frame: &mut Screen,
+    typed: &TypedScreen,
+    finished: &[String],
+    status: Option<&str>,
+) {
+    let above = finished
+        .iter()
+        .map(|line| cells(line, ""))
+        .collect::<Vec<Vec<Cell>>>();
+    let mut row = cells(&typed.line, "");
+    row.extend(cells(&typed.buffer, "\x1b[4m"));
+    let mut rows = vec![row];
+    if let Some(status) = status {
+        rows.push(cells(status, &theme::get().status));
+    }
+    frame.draw(terminal, &above, rows);
+}
+
+// follow the text typed through the daemon, from the screen kept by it; detaching leaves the cube
+// connected to the daemon, so that another terminal can attach later
+pub async fn run(path: impl AsRef<Path>, shutdown: Shutdown) -> Result<(), Box<dyn Error>> {
+    let stream = UnixStream::connect(path).await?;
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(b"{\"command\":\"attach\"}\n").await?;
+    let mut lines = BufReader::new(reader).lines();
+
+    let mut terminal = stdout();
+    let mut frame = Screen::new();
+    let mut typed = TypedScreen::default();
+    let mut status = None;
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = shutdown.wait() => None,
+        };
+        let Some(line) = line else {
+            break;
+        };
+        let event = serde_json::from_str::<DaemonEvent>(&line)?;
+        let finished = match &event {
+            DaemonEvent::Screen { lines, .. } => {
+                typed = TypedScreen::from_event(&event).unwrap_or_default();
+                lines.clone()
+            }
+            DaemonEvent::Error { message } => return Err(message.clone().into()),
+            // the daemon keeps reconnecting to the cube, and the typing goes on after that
+            DaemonEvent::Disconnect => {
+                status = Some(tr(Message::Reconnecting, &[]));
+                Vec::new()
+            }
+            DaemonEvent::State { .. } | DaemonEvent::Moves { .. } => {
+                status = None;
+                Vec::new()
+            }
+            _ => event
+                .engine_event()
+                .and_then(|event| typed.update(&event))
+                .into_iter()
+                .collect(),
+        };
+        draw(
+            &mut terminal,
+            &mut frame,
+            &typed,
+            &finished,
+            status.as_deref(),
+        );
+    }
+    println!();
+    Ok(())
+}
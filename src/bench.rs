@@ -0,0 +1,250 @@
+use std::{
+    error::Error,
+    hint::black_box,
+    io::stdout,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::time::sleep;
+
+use crate::{
+    bluetooth::{
+        connect_cube,
+        gancubev2::{NotificationTiming, ResponseMessage},
+    },
+    cube::{CubeMove, CubeState},
+    cuboard::{CuboardInput, CuboardKeymap},
+    engine::CuboardEngine,
+    idle::IdleMonitor,
+    shutdown::{restore_terminal, Shutdown},
+    solver::{self, CubieCoords},
+    terminal::Repaint,
+    train::CuboardInputPrinter,
+};
+
+#[derive(Default)]
+struct LatencySamples(Vec<Duration>);
+
+impl LatencySamples {
+    fn put(&mut self, duration: Duration) {
+        self.0.push(duration);
+    }
+
+    fn mean(&self) -> Duration {
+        if self.0.is_empty() {
+            return Duration::ZERO;
+        }
+        self.0.iter().sum::<Duration>() / self.0.len() as u32
+    }
+
+    fn percentile(sorted: &[Duration], p: f32) -> Duration {
+        let index = ((sorted.len() - 1) as f32 * p).round() as usize;
+        sorted[index]
+    }
+
+    fn summary(&self, name: &str) -> String {
+        fn ms(duration: Duration) -> f32 {
+            duration.as_secs_f32() * 1000.0
+        }
+
+        if self.0.is_empty() {
+            return format!("  {:<8} {:>8}", name, "-");
+        }
+        let mut sorted = self.0.clone();
+        sorted.sort();
+        format!(
+            "  {:<8} {:>8} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8.3}",
+            name,
+            sorted.len(),
+            ms(sorted[0]),
+            ms(self.mean()),
+            ms(Self::percentile(&sorted, 0.5)),
+            ms(Self::percentile(&sorted, 0.95)),
+            ms(sorted[sorted.len() - 1]),
+        )
+    }
+}
+
+#[derive(Default)]
+struct BenchRecorder {
+    decode: LatencySamples,
+    parse: LatencySamples,
+    render: LatencySamples,
+    total: LatencySamples,
+    moves: LatencySamples,
+    gyro_intervals: LatencySamples,
+    last_gyro: Option<Instant>,
+}
+
+impl BenchRecorder {
+    fn report(&self, elapsed: Duration) -> String {
+        let mut res = String::new();
+        res += &format!("benchmark for {:.1}s\n", elapsed.as_secs_f32());
+        res += "latency (ms):\n";
+        res += &format!(
+            "  {:<8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}\n",
+            "stage", "count", "min", "mean", "p50", "p95", "max"
+        );
+        res += &(self.decode.summary("decode") + "\n");
+        res += &(self.parse.summary("parse") + "\n");
+        res += &(self.render.summary("render") + "\n");
+        res += &(self.total.summary("total") + "\n");
+        res += &(self.moves.summary("moves") + "\n");
+
+        let intervals = &self.gyro_intervals.0;
+        if intervals.is_empty() {
+            res += "gyroscope: no message\n";
+            return res;
+        }
+        let mean = self.gyro_intervals.mean().as_secs_f32();
+        let var = intervals
+            .iter()
+            .map(|d| (d.as_secs_f32() - mean).powi(2))
+            .sum::<f32>()
+            / intervals.len() as f32;
+        let max = intervals.iter().max().unwrap().as_secs_f32();
+        res += &format!(
+            "gyroscope: {:.1} Hz, interval mean={:.1}ms, jitter={:.1}ms, max={:.1}ms\n",
+            1.0 / mean,
+            mean * 1000.0,
+            var.sqrt() * 1000.0,
+            max * 1000.0,
+        );
+        res
+    }
+}
+
+// the mean time of an operation repeated the given times
+fn time_per_op(count: u32, mut op: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..count {
+        op();
+    }
+    start.elapsed() / count
+}
+
+// time the cube model and the solver without a cube, so that their representations can be
+// compared
+pub fn run_engine(rounds: u32) -> String {
+    let mut rng = StdRng::seed_from_u64(0);
+    let moves = (0..1000)
+        .map(|_| CubeMove::from_repr(rng.gen_range(0..12)).unwrap())
+        .collect::<Vec<_>>();
+    let tables = solver::turn_tables();
+    let scrambles = (0..rounds)
+        .map(|_| solver::random_scramble(5, &mut rng))
+        .collect::<Vec<_>>();
+
+    let mut state = CubeState::default();
+    let mut i = 0;
+    let apply = time_per_op(rounds * 1000, || {
+        state.apply(moves[i % moves.len()]);
+        i += 1;
+    });
+    black_box(state);
+    let mut coords = CubieCoords::of(&CubeState::default());
+    let mut i = 0;
+    let turn = time_per_op(rounds * 1000, || {
+        coords = coords.then(&tables[i % tables.len()].1);
+        i += 1;
+    });
+    black_box(coords);
+    let scramble = time_per_op(rounds * 10, || {
+        black_box(solver::random_scramble(20, &mut rng));
+    });
+    let mut scrambles = scrambles.iter().cycle();
+    let solve = time_per_op(rounds, || {
+        let moves = scrambles
+            .next()
+            .unwrap()
+            .iter()
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        let state = CubeState::from_moves(&moves);
+        black_box(solver::solve(&state, &CubeState::default(), 5));
+    });
+
+    let mut res = String::new();
+    res += &format!("engine benchmark of {} rounds (per operation):\n", rounds);
+    res += &format!("  {:<16} {:>12?}\n", "state apply", apply);
+    res += &format!("  {:<16} {:>12?}\n", "coords turn", turn);
+    res += &format!("  {:<16} {:>12?}\n", "scramble of 20", scramble);
+    res += &format!("  {:<16} {:>12?}\n", "solve of 5", solve);
+    res
+}
+
+pub async fn run(
+    keymap: CuboardKeymap,
+    shutdown: Shutdown,
+    duration: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("connected! turn the cube to measure latency");
+    println!();
+
+    let recorder = Arc::new(Mutex::new(BenchRecorder::default()));
+    let recorder_handler = Arc::clone(&recorder);
+    let input = CuboardInput::new(keymap);
+    let engine = CuboardEngine::new(input);
+    let mut printer = CuboardInputPrinter::new(stdout(), engine, IdleMonitor::default());
+    let handler = Box::new(move |msg: ResponseMessage, timing: NotificationTiming| {
+        let Ok(mut recorder) = recorder_handler.lock() else {
+            return;
+        };
+
+        let is_gyroscope = matches!(msg, ResponseMessage::Gyroscope { .. });
+        let is_moves = matches!(msg, ResponseMessage::Moves { .. });
+        if matches!(msg, ResponseMessage::Disconnect) {
+            printer.handle_message(msg);
+            return;
+        }
+
+        let events = printer.engine.handle_message(msg);
+        let parsed = Instant::now();
+        printer.handle_events(events);
+        // measure a frame even if the frames are limited
+        printer.repaint();
+        let rendered = Instant::now();
+
+        recorder.decode.put(timing.decoded - timing.received);
+        recorder.parse.put(parsed - timing.decoded);
+        recorder.render.put(rendered - parsed);
+        recorder.total.put(rendered - timing.received);
+        if is_moves {
+            recorder.moves.put(rendered - timing.received);
+        }
+        if is_gyroscope {
+            if let Some(last_gyro) = recorder.last_gyro {
+                recorder.gyro_intervals.put(timing.received - last_gyro);
+            }
+            recorder.last_gyro = Some(timing.received);
+        }
+    });
+    let mut handle = gancube
+        .register_timed_handler(handler, Box::new(|_| {}))
+        .await?;
+
+    gancube.subscribe_response().await?;
+    gancube.request_cube_state().await?;
+
+    let start = Instant::now();
+    tokio::select! {
+        res = &mut handle => res?,
+        _ = sleep(duration) => {}
+        _ = shutdown.wait() => {}
+    }
+    let elapsed = start.elapsed();
+
+    shutdown.close(&gancube).await?;
+    handle.abort();
+    restore_terminal();
+
+    if let Ok(recorder) = recorder.lock() {
+        println!("{}", recorder.report(elapsed));
+        println!("notifications: {:?}", handle.stats());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,470 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::{format_turns, CubeMove, CubeState, CORNER_FACELETS, EDGE_FACELETS},
+    cuboard::{GyroGesture, GyroGestureRecognizer, BUFFER_SIZE},
+    idle::IdleMonitor,
+    scramble::ScrambleGuide,
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    solver::random_scramble,
+    train::handle_until_disconnected,
+};
+
+// the facelets lettered A to X by the Speffz scheme, face by face in the order U, L, F, R, B, D
+#[rustfmt::skip]
+const CORNER_LETTERS: [usize; 24] = [
+    0, 2, 8, 6, 36, 38, 44, 42, 18, 20, 26, 24,
+    9, 11, 17, 15, 45, 47, 53, 51, 27, 29, 35, 33,
+];
+#[rustfmt::skip]
+const EDGE_LETTERS: [usize; 24] = [
+    1, 5, 7, 3, 37, 41, 43, 39, 19, 23, 25, 21,
+    10, 14, 16, 12, 46, 50, 52, 48, 28, 32, 34, 30,
+];
+
+// the buffers of the Old Pochmann method, UBL and UR
+const CORNER_BUFFER: usize = 0;
+const EDGE_BUFFER: usize = 1;
+
+// the face of each facelet of the solved state
+fn face(facelet: usize) -> usize {
+    facelet / 9
+}
+
+fn letter(index: usize) -> char {
+    (b'A' + index as u8) as char
+}
+
+// the targets of the pieces, as the letters of the stickers shot to from the buffer, with cycle
+// breaks into the unsolved pieces in the order of the letters
+fn trace<const N: usize>(
+    faces: &[usize],
+    pieces: &[[usize; N]],
+    letters: &[usize; 24],
+    buffer: usize,
+) -> Vec<char> {
+    // the piece and the index of the sticker of a letter
+    let locate = |index: usize| {
+        let facelet = letters[index];
+        pieces.iter().enumerate().find_map(|(piece, facelets)| {
+            let k = facelets.iter().position(|&f| f == facelet)?;
+            Some((piece, k))
+        })
+    };
+    // the letter of where the sticker at the letter belongs
+    let target = |index: usize| {
+        let (piece, k) = locate(index)?;
+        let colors: [usize; N] = std::array::from_fn(|j| faces[pieces[piece][(k + j) % N]]);
+        let home = pieces.iter().find_map(|facelets| {
+            (0..N).find_map(|k| {
+                (0..N)
+                    .all(|j| face(facelets[(k + j) % N]) == colors[j])
+                    .then_some(facelets[k])
+            })
+        })?;
+        letters.iter().position(|&f| f == home)
+    };
+    let is_solved = |piece: &[usize; N]| piece.iter().all(|&f| faces[f] == face(f));
+
+    let Some((buffer_piece, _)) = locate(buffer) else {
+        return Vec::new();
+    };
+    let mut visited = pieces.iter().map(is_solved).collect::<Vec<_>>();
+    visited[buffer_piece] = true;
+    let mut res = Vec::new();
+    let mut start = buffer_piece;
+    let mut current = buffer;
+    // every step visits a piece or shoots to its start, so it ends in time
+    for _ in 0..3 * 24 {
+        let Some(next) = target(current) else {
+            break;
+        };
+        let Some((piece, _)) = locate(next) else {
+            break;
+        };
+        if piece != start {
+            res.push(letter(next));
+            visited[piece] = true;
+            current = next;
+            continue;
+        }
+        // the cycle is closed, back to the start of the cycle break
+        if start != buffer_piece {
+            res.push(letter(next));
+        }
+        let unvisited = (0..24).find(|&index| locate(index).is_some_and(|(p, _)| !visited[p]));
+        let Some(index) = unvisited else {
+            break;
+        };
+        let (piece, _) = locate(index).unwrap();
+        res.push(letter(index));
+        visited[piece] = true;
+        start = piece;
+        current = index;
+    }
+    res
+}
+
+// whether the piece of the letter is placed and oriented
+fn is_solved<const N: usize>(
+    faces: &[usize],
+    pieces: &[[usize; N]],
+    letters: &[usize; 24],
+    index: usize,
+) -> bool {
+    pieces
+        .iter()
+        .find(|facelets| facelets.contains(&letters[index]))
+        .is_some_and(|facelets| facelets.iter().all(|&f| faces[f] == face(f)))
+}
+
+fn faces_of(state: &CubeState) -> Vec<usize> {
+    state
+        .facelets()
+        .chars()
+        .map(|c| "URFDLB".find(c).unwrap())
+        .collect()
+}
+
+// the targets of a blindfolded solve of the state, in Speffz letters
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Memo {
+    pub corners: Vec<char>,
+    pub edges: Vec<char>,
+}
+
+impl Memo {
+    pub fn new(state: &CubeState) -> Self {
+        let faces = faces_of(state);
+        Memo {
+            corners: trace(&faces, &CORNER_FACELETS, &CORNER_LETTERS, CORNER_BUFFER),
+            edges: trace(&faces, &EDGE_FACELETS, &EDGE_LETTERS, EDGE_BUFFER),
+        }
+    }
+
+    // an odd number of corner targets, which swaps two edges too
+    pub fn parity(&self) -> bool {
+        self.corners.len() % 2 == 1
+    }
+
+    // the targets whose pieces are not solved in the state after the solve
+    pub fn wrong_targets(&self, state: &CubeState) -> Memo {
+        let faces = faces_of(state);
+        let index = |letter: char| (letter as u8 - b'A') as usize;
+        Memo {
+            corners: (self.corners.iter().copied())
+                .filter(|&c| !is_solved(&faces, &CORNER_FACELETS, &CORNER_LETTERS, index(c)))
+                .collect(),
+            edges: (self.edges.iter().copied())
+                .filter(|&c| !is_solved(&faces, &EDGE_FACELETS, &EDGE_LETTERS, index(c)))
+                .collect(),
+        }
+    }
+}
+
+impl Display for Memo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pairs = |letters: &[char]| {
+            letters
+                .chunks(2)
+                .map(|pair| pair.iter().collect::<String>())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        write!(
+            f,
+            "corners: {}  edges: {}",
+            pairs(&self.corners),
+            pairs(&self.edges)
+        )?;
+        if self.parity() {
+            write!(f, "  parity")?;
+        }
+        Ok(())
+    }
+}
+
+// show the memo of the tracked state, updated on every turn
+pub struct BlindAnalyzer<F: Write> {
+    terminal: F,
+    state: Option<CubeState>,
+    sequencer: MoveSequencer,
+}
+
+impl<F: Write> BlindAnalyzer<F> {
+    pub fn new(terminal: F) -> Self {
+        BlindAnalyzer {
+            terminal,
+            state: None,
+            sequencer: MoveSequencer::default(),
+        }
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) {
+        let sequenced = self.sequencer.handle_message(msg, Instant::now());
+        match msg {
+            ResponseMessage::State {
+                count: _,
+                state: Some(mut state),
+            } => {
+                state.reset_centers();
+                self.state = Some(state);
+                self.render();
+            }
+            ResponseMessage::Disconnect => {
+                let _ = writeln!(self.terminal);
+            }
+            _ => {}
+        }
+
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        if sequenced.moves.is_empty() {
+            return;
+        }
+        for (mv, _) in sequenced.moves {
+            state.apply(mv);
+        }
+        self.render();
+    }
+
+    fn render(&mut self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let _ = write!(self.terminal, "\r\x1b[2K{}", Memo::new(state));
+        let _ = self.terminal.flush();
+    }
+}
+
+const SCRAMBLE_LENGTH: usize = 20;
+
+enum TrainingPhase {
+    // follow the scramble from the solved state
+    Scrambling(ScrambleGuide),
+    Memorizing {
+        memo: Memo,
+        since: Instant,
+    },
+    Executing {
+        memo: Memo,
+        memo_time: Duration,
+        since: Instant,
+    },
+    Done,
+}
+
+// scramble the cube, show the targets until the first turn, then check the blindfolded solve,
+// which is finished by solving the cube or by turning it around
+pub struct BlindTrainer<F: Write> {
+    terminal: F,
+    state: Option<CubeState>,
+    sequencer: MoveSequencer,
+    phase: TrainingPhase,
+    recognizer: GyroGestureRecognizer<BUFFER_SIZE>,
+    shutdown: Shutdown,
+}
+
+impl<F: Write> BlindTrainer<F> {
+    pub fn new(terminal: F, shutdown: Shutdown) -> Self {
+        BlindTrainer {
+            terminal,
+            state: None,
+            sequencer: MoveSequencer::default(),
+            phase: TrainingPhase::Done,
+            recognizer: GyroGestureRecognizer::new(),
+            shutdown,
+        }
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) {
+        let now = Instant::now();
+        let sequenced = self.sequencer.handle_message(msg, now);
+        if !sequenced.moves.is_empty() {
+            self.turn(&sequenced.moves, now);
+        }
+        match msg {
+            ResponseMessage::State { count: _, state } => {
+                let (None, Some(mut state)) = (self.state, state) else {
+                    return;
+                };
+                // a scrambled cube is taken as it is
+                state.reset_centers();
+                self.state = Some(state);
+                self.phase = if state.is_solved() {
+                    let turns = random_scramble(SCRAMBLE_LENGTH, &mut rand::thread_rng());
+                    TrainingPhase::Scrambling(ScrambleGuide::new(turns))
+                } else {
+                    TrainingPhase::Memorizing {
+                        memo: Memo::new(&state),
+                        since: now,
+                    }
+                };
+                self.render();
+            }
+            ResponseMessage::Gyroscope { .. } => {
+                let gesture = self.recognizer.handle_message(msg);
+                if let (TrainingPhase::Executing { .. }, Some(GyroGesture::TurningAround)) =
+                    (&self.phase, gesture)
+                {
+                    self.finish(now);
+                }
+            }
+            ResponseMessage::Disconnect => {
+                let _ = writeln!(self.terminal);
+            }
+            _ => {}
+        }
+    }
+
+    fn turn(&mut self, moves: &[(CubeMove, Duration)], now: Instant) {
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        for &(mv, _) in moves {
+            state.apply(mv);
+            match &mut self.phase {
+                TrainingPhase::Scrambling(guide) => {
+                    guide.input(mv);
+                    if guide.is_finished() {
+                        self.phase = TrainingPhase::Memorizing {
+                            memo: Memo::new(state),
+                            since: now,
+                        };
+                    }
+                }
+                TrainingPhase::Memorizing { memo, since } => {
+                    self.phase = TrainingPhase::Executing {
+                        memo: memo.clone(),
+                        memo_time: now - *since,
+                        since: now,
+                    };
+                }
+                _ => {}
+            }
+        }
+        if matches!(self.phase, TrainingPhase::Executing { .. }) && state.is_solved() {
+            self.finish(now);
+        } else {
+            self.render();
+        }
+    }
+
+    fn finish(&mut self, now: Instant) {
+        let phase = std::mem::replace(&mut self.phase, TrainingPhase::Done);
+        let (
+            TrainingPhase::Executing {
+                memo,
+                memo_time,
+                since,
+            },
+            Some(state),
+        ) = (phase, self.state)
+        else {
+            return;
+        };
+        let times = format!(
+            "memo {:.1}s, execution {:.1}s",
+            memo_time.as_secs_f32(),
+            (now - since).as_secs_f32()
+        );
+        let _ = write!(self.terminal, "\r\x1b[2K");
+        if state.is_solved() {
+            let _ = writeln!(self.terminal, "solved! {}", times);
+        } else {
+            let wrong = memo.wrong_targets(&state);
+            let _ = writeln!(self.terminal, "not solved, {}", times);
+            let _ = writeln!(self.terminal, "targets: {}", memo);
+            let _ = writeln!(self.terminal, "wrong:   {}", wrong);
+        }
+        self.shutdown.request();
+    }
+
+    fn render(&mut self) {
+        let _ = write!(self.terminal, "\r\x1b[2K");
+        let _ = match &self.phase {
+            TrainingPhase::Scrambling(guide) => {
+                let (_, correction, rest) = guide.split();
+                let turns = [correction, rest].concat();
+                write!(self.terminal, "scramble: {}", format_turns(&turns))
+            }
+            TrainingPhase::Memorizing { memo, .. } => {
+                write!(self.terminal, "{}  (hidden by the first turn)", memo)
+            }
+            TrainingPhase::Executing { .. } => {
+                write!(
+                    self.terminal,
+                    "solve it blindfolded, or turn it around to give up"
+                )
+            }
+            TrainingPhase::Done => Ok(()),
+        };
+        let _ = self.terminal.flush();
+    }
+}
+
+// show the corner and edge targets of the cube for learning blindfolded solving, or train a
+// blindfolded solve
+pub async fn run(train: bool, shutdown: Shutdown, idle: IdleMonitor) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("connected! hold the cube white up and green front, buffers are UBL and UR");
+    println!();
+
+    let handler: Box<dyn FnMut(ResponseMessage) + Send> = if train {
+        let mut trainer = BlindTrainer::new(stdout(), shutdown.clone());
+        Box::new(move |msg| trainer.handle_message(msg))
+    } else {
+        // only moves are needed
+        gancube.message_filter().set_gyroscope(false);
+        let mut analyzer = BlindAnalyzer::new(stdout());
+        Box::new(move |msg| analyzer.handle_message(msg))
+    };
+    handle_until_disconnected(
+        &gancube,
+        handler,
+        shutdown,
+        idle,
+        SignalMonitor::default(),
+        None,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cube::CubeMove, pattern::Pattern};
+
+    #[test]
+    fn targets_are_lettered_by_speffz() {
+        let solved = Memo::new(&CubeState::default());
+        assert!(solved.corners.is_empty() && solved.edges.is_empty());
+
+        // every edge is flipped in place, where the buffer is ignored
+        let superflip = Memo::new(&Pattern::Superflip.state());
+        assert!(superflip.corners.is_empty());
+        assert_eq!(
+            superflip.to_string(),
+            "corners:   edges: AQ CI DE FL GX HR JP KU NT OV SW"
+        );
+
+        // U cycles UF to UL, UL to UB and UB to UR, the buffer
+        let u = Memo::new(&CubeState::of_move(CubeMove::U));
+        assert_eq!(u.edges, ['A', 'D', 'C']);
+        assert!(u.parity());
+
+        // only the edges are solved by U'
+        let wrong = u.wrong_targets(&CubeState::from_moves(&[CubeMove::U, CubeMove::Up]));
+        assert!(wrong.corners.is_empty() && wrong.edges.is_empty());
+        let wrong = u.wrong_targets(&CubeState::of_move(CubeMove::U));
+        assert_eq!(wrong, u);
+    }
+}
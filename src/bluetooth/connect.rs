@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use futures::future::try_join_all;
+use tokio::time::{sleep, timeout};
+
+use super::{
+    gancubev2::{Error, GanCubeV2, GanCubeV2Builder},
+    transport::Transport,
+};
+use crate::{config, shutdown::Shutdown};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_RETRIES: usize = 3;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectError {
+    #[error("no response from the GANCube in {timeout:?}, after {attempts} attempts")]
+    TimedOut { timeout: Duration, attempts: usize },
+    #[error("fail to connect to the GANCube after {attempts} attempts: {source}")]
+    Failed { source: Error, attempts: usize },
+    #[error("connecting is cancelled")]
+    Cancelled,
+}
+
+// connect to GANCubes, giving up an attempt after the timeout, and retrying with doubled backoff
+#[derive(Clone)]
+pub struct Connector {
+    timeout: Duration,
+    retries: usize,
+    backoff: Duration,
+    shutdown: Shutdown,
+}
+
+impl Connector {
+    pub fn new(shutdown: Shutdown) -> Self {
+        let device = &config::get().device;
+        Connector {
+            timeout: device
+                .connect_timeout
+                .and_then(|secs| Duration::try_from_secs_f32(secs).ok())
+                .unwrap_or(DEFAULT_TIMEOUT),
+            retries: device.connect_retries.unwrap_or(DEFAULT_RETRIES),
+            backoff: Duration::from_secs(1),
+            shutdown,
+        }
+    }
+
+    async fn attempt<P: Transport>(
+        &self,
+        builder: &GanCubeV2Builder<P>,
+        attempts: usize,
+    ) -> Result<GanCubeV2<P>, ConnectError> {
+        match timeout(self.timeout, builder.connect()).await {
+            Ok(Ok(gancube)) => Ok(gancube),
+            Ok(Err(source)) => Err(ConnectError::Failed { source, attempts }),
+            Err(_) => Err(ConnectError::TimedOut {
+                timeout: self.timeout,
+                attempts,
+            }),
+        }
+    }
+
+    async fn retry<P: Transport>(
+        &self,
+        builder: &GanCubeV2Builder<P>,
+    ) -> Result<GanCubeV2<P>, ConnectError> {
+        let mut backoff = self.backoff;
+        let mut attempts = 1;
+        loop {
+            let err = match self.attempt(builder, attempts).await {
+                Ok(gancube) => return Ok(gancube),
+                Err(err) if attempts > self.retries => return Err(err),
+                Err(err) => err,
+            };
+            println!("{}, retry in {:?}...", err, backoff);
+            // drop the half-made connection of the failed attempt
+            let _ = builder.device.disconnect().await;
+            sleep(backoff).await;
+            backoff *= 2;
+            attempts += 1;
+        }
+    }
+
+    pub async fn connect<P: Transport>(
+        &self,
+        builder: &GanCubeV2Builder<P>,
+    ) -> Result<GanCubeV2<P>, ConnectError> {
+        tokio::select! {
+            biased;
+            _ = self.shutdown.wait() => Err(ConnectError::Cancelled),
+            res = self.retry(builder) => res,
+        }
+    }
+
+    // connect to all GANCubes at the same time
+    pub async fn connect_all<P: Transport>(
+        &self,
+        builders: &[GanCubeV2Builder<P>],
+    ) -> Result<Vec<GanCubeV2<P>>, ConnectError> {
+        try_join_all(builders.iter().map(|builder| self.connect(builder))).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth::mock::MockTransport;
+
+    #[tokio::test]
+    async fn stalled_connections_are_retried() {
+        let mock = MockTransport::default();
+        let connector = Connector {
+            timeout: Duration::from_millis(50),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            shutdown: Shutdown::default(),
+        };
+
+        mock.stall_connections(2);
+        assert!(connector.connect(&mock.builder()).await.is_ok());
+
+        mock.drop_connection();
+        mock.stall_connections(3);
+        let res = connector.connect(&mock.builder()).await;
+        assert!(matches!(
+            res,
+            Err(ConnectError::TimedOut { attempts: 3, .. })
+        ));
+
+        let shutdown = Shutdown::default();
+        shutdown.request();
+        let res = Connector::new(shutdown).connect(&mock.builder()).await;
+        assert!(matches!(res, Err(ConnectError::Cancelled)));
+    }
+}
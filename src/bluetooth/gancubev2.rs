@@ -1,13 +1,27 @@
 #![allow(dead_code)]
 
-use btleplug::api::{Central, Characteristic, Peripheral, PeripheralProperties, WriteType};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use btleplug::api::{Characteristic, PeripheralProperties, WriteType};
 use futures::StreamExt;
 use thiserror;
 use uuid::{uuid, Uuid};
 
+use super::quirks::{lookup, CountWidener, SharedQuirks};
+use super::transport::Transport;
+use crate::config;
 use crate::cube::*;
 
-struct GanCubeV2Services {
+pub(crate) struct GanCubeV2Services {
     response: Characteristic,
     request: Characteristic,
     unknown1: Option<Characteristic>, // version?
@@ -19,8 +33,8 @@ struct GanCubeV2Services {
 
 #[rustfmt::skip]
 impl GanCubeV2Services {
-    const REQUEST_UUID  : Uuid = uuid!("28be4a4a-cd67-11e9-a32f-2a2ae2dbcce4");
-    const RESPONSE_UUID : Uuid = uuid!("28be4cb6-cd67-11e9-a32f-2a2ae2dbcce4");
+    pub(crate) const REQUEST_UUID  : Uuid = uuid!("28be4a4a-cd67-11e9-a32f-2a2ae2dbcce4");
+    pub(crate) const RESPONSE_UUID : Uuid = uuid!("28be4cb6-cd67-11e9-a32f-2a2ae2dbcce4");
     const UNKNOWN1_UUID : Uuid = uuid!("00002a05-0000-1000-8000-00805f9b34fb");
     const UNKNOWN2_UUID : Uuid = uuid!("ec4cff6d-81fc-4e5b-91e0-8103885c9ae3");
     const UNKNOWN3_UUID : Uuid = uuid!("f95a4b66-a721-11e9-a2a3-022ae2dbcce4");
@@ -28,7 +42,7 @@ impl GanCubeV2Services {
 }
 
 impl GanCubeV2Services {
-    async fn discover_services(device: &impl Peripheral) -> Result<Self, Error> {
+    async fn discover_services(device: &impl Transport) -> Result<Self, Error> {
         #[derive(Default)]
         struct GanCubeV2ServicesBuilder {
             response: Option<Characteristic>,
@@ -80,13 +94,15 @@ impl GanCubeV2Services {
     }
 }
 
-pub struct GanCubeV2<P: Peripheral> {
+pub struct GanCubeV2<P: Transport> {
     pub device: P,
     services: GanCubeV2Services,
-    cipher: cipher::GanCubeV2Cipher,
+    cipher: Arc<dyn cipher::GanCubeV2Cipher>,
+    filter: MessageFilter,
+    quirks: SharedQuirks,
 }
 
-pub struct GanCubeV2Builder<P: Peripheral> {
+pub struct GanCubeV2Builder<P: Transport> {
     pub device: P,
     pub properties: PeripheralProperties,
 }
@@ -99,6 +115,22 @@ pub enum Error {
     BluetoothConnectionFail(#[from] btleplug::Error),
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum NotificationError {
+    #[error("unknown notification from characteristic {0}")]
+    UnknownCharacteristic(Uuid),
+    #[error("fail to decode message: {0}")]
+    DecodeFail(#[from] codec::MessageParseError),
+}
+
+impl NotificationError {
+    // whether a response is lost, which may carry moves; notifications of other characteristics
+    // don't touch the move count
+    pub fn is_lost_response(&self) -> bool {
+        matches!(self, NotificationError::DecodeFail(_))
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DeviceError {
     #[error("cannot find required characteristics")]
@@ -107,25 +139,11 @@ pub enum DeviceError {
     NoDeviceIdentifier,
     #[error("device identifier data invalid")]
     InvalidDeviceIdentifier,
+    #[error("cipher key or iv in the config is not 16 bytes in hex")]
+    InvalidCipherKey,
 }
 
-impl<P: Peripheral> GanCubeV2Builder<P> {
-    pub async fn find_gancube_device<A>(adapter: &A) -> Result<Vec<Self>, Error>
-    where
-        A: Central<Peripheral = P>,
-    {
-        let mut res = vec![];
-        let peripherals = adapter.peripherals().await?;
-        for device in peripherals {
-            let Some(properties) = device.properties().await? else { continue; };
-            let Some(ref name) = properties.local_name else { continue; };
-            if name.starts_with("GAN") {
-                res.push(GanCubeV2Builder { device, properties });
-            }
-        }
-        Ok(res)
-    }
-
+impl<P: Transport> GanCubeV2Builder<P> {
     pub async fn connect(&self) -> Result<GanCubeV2<P>, Error> {
         if !self.device.is_connected().await? {
             self.device.connect().await?;
@@ -159,66 +177,139 @@ impl<P: Peripheral> GanCubeV2Builder<P> {
         //     println!("-------------------");
         // }
 
-        let cipher = cipher::GanCubeV2Cipher::make_cipher(&self.properties)?;
+        let cipher = cipher::make_cipher(&self.properties)?;
         Ok(GanCubeV2 {
             device: self.device.clone(),
             services,
             cipher,
+            filter: MessageFilter::default(),
+            quirks: SharedQuirks::default(),
         })
     }
 }
 
-impl<P: Peripheral> GanCubeV2<P> {
+impl<P: Transport> GanCubeV2<P> {
     pub async fn disconnect(&self) -> Result<(), btleplug::Error> {
         self.device.disconnect().await
     }
 
+    // messages dropped by the filter never wake the handlers
+    pub fn message_filter(&self) -> &MessageFilter {
+        &self.filter
+    }
+
+    // the cipher of the messages, for inspecting raw frames
+    pub(crate) fn cipher(&self) -> Arc<dyn cipher::GanCubeV2Cipher> {
+        Arc::clone(&self.cipher)
+    }
+
     pub async fn register_handler(
+        &self,
+        handler: Box<dyn FnMut(codec::ResponseMessage) + Send>,
+    ) -> Result<NotificationHandle, btleplug::Error> {
+        self.register_handler_with_errors(handler, Box::new(|_| {}))
+            .await
+    }
+
+    pub async fn register_handler_with_errors(
         &self,
         mut handler: Box<dyn FnMut(codec::ResponseMessage) + Send>,
-    ) -> Result<tokio::task::JoinHandle<()>, btleplug::Error> {
+        error_handler: Box<dyn FnMut(NotificationError) + Send>,
+    ) -> Result<NotificationHandle, btleplug::Error> {
+        self.register_timed_handler(Box::new(move |msg, _| handler(msg)), error_handler)
+            .await
+    }
+
+    pub async fn register_timed_handler(
+        &self,
+        mut handler: Box<dyn FnMut(codec::ResponseMessage, NotificationTiming) + Send>,
+        mut error_handler: Box<dyn FnMut(NotificationError) + Send>,
+    ) -> Result<NotificationHandle, btleplug::Error> {
         let mut notifications = self.device.notifications().await?;
-        let cipher = self.cipher.clone();
-        Ok(tokio::spawn(async move {
+        let cipher = Arc::clone(&self.cipher);
+        let filter = self.filter.clone();
+        let quirks = self.quirks.clone();
+        let mut counts = CountWidener::default();
+        let stats = Arc::new(NotificationCounters::default());
+        let counters = Arc::clone(&stats);
+        let task = tokio::spawn(async move {
             loop {
                 let Some(notification) = notifications.next().await else {
-                    continue;
+                    return;
                 };
+                let received = Instant::now();
+                counters.received.fetch_add(1, Ordering::Relaxed);
 
                 if notification.uuid != GanCubeV2Services::RESPONSE_UUID {
-                    eprintln!("unknown message: {:?}", notification);
+                    counters.unknown.fetch_add(1, Ordering::Relaxed);
+                    error_handler(NotificationError::UnknownCharacteristic(notification.uuid));
                     continue;
                 }
 
-                let message = match codec::ResponseMessage::decode(&notification.value, &cipher) {
-                    Ok(message) => message,
+                let protocol = quirks.get();
+                let message = match codec::ResponseMessage::decode(
+                    &notification.value,
+                    &*cipher,
+                    &protocol,
+                ) {
+                    Ok(mut message) => {
+                        if let codec::ResponseMessage::Moves { count, .. }
+                        | codec::ResponseMessage::State { count, .. } = &mut message
+                        {
+                            *count = counts.widen(*count, protocol.move_count_bits);
+                        }
+                        message
+                    }
                     Err(err) => {
-                        eprintln!("fail to decode message: {}", err);
+                        counters.failed.fetch_add(1, Ordering::Relaxed);
+                        error_handler(err.into());
                         continue;
                     }
                 };
+                counters.decoded.fetch_add(1, Ordering::Relaxed);
+                // the later messages are decoded by the quirks of the firmware
+                if let codec::ResponseMessage::Hardware(hardware) = message {
+                    quirks.set(lookup(&hardware.firmware(), &config::get().device.quirks));
+                }
+                if !filter.accepts(&message, received) {
+                    counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                let timing = NotificationTiming {
+                    received,
+                    decoded: Instant::now(),
+                };
 
-                let is_disconnected = codec::ResponseMessage::Disconnect == message;
-
-                handler(message);
+                handler(message, timing);
 
-                if is_disconnected {
+                if message == codec::ResponseMessage::Disconnect {
                     return;
                 }
             }
-        }))
+        });
+        Ok(NotificationHandle { task, stats })
     }
 
+    // the hardware info is requested at once, which chooses the quirks of the protocol
     pub async fn subscribe_response(&self) -> Result<(), btleplug::Error> {
-        self.device.subscribe(&self.services.response).await
+        self.device.subscribe(&self.services.response).await?;
+        let message = codec::RequestMessage::RequestHardwareInfo.encode(&*self.cipher);
+        self.device
+            .write(&self.services.request, &message, WriteType::WithResponse)
+            .await
     }
 
     pub async fn unsubscribe_response(&self) -> Result<(), btleplug::Error> {
         self.device.unsubscribe(&self.services.response).await
     }
 
+    // the signal strength in dBm, if reported by the bluetooth stack
+    pub async fn rssi(&self) -> Result<Option<i16>, Error> {
+        Ok(self.device.rssi().await?)
+    }
+
     pub async fn request_battery_state(&self) -> Result<(), Error> {
-        let message = codec::RequestMessage::RequestBatteryState.encode(&self.cipher);
+        let message = codec::RequestMessage::RequestBatteryState.encode(&*self.cipher);
         self.device
             .write(&self.services.request, &message, WriteType::WithResponse)
             .await?;
@@ -226,7 +317,7 @@ impl<P: Peripheral> GanCubeV2<P> {
     }
 
     pub async fn request_cube_state(&self) -> Result<(), Error> {
-        let message = codec::RequestMessage::RequestCubeState.encode(&self.cipher);
+        let message = codec::RequestMessage::RequestCubeState.encode(&*self.cipher);
         self.device
             .write(&self.services.request, &message, WriteType::WithResponse)
             .await?;
@@ -234,7 +325,7 @@ impl<P: Peripheral> GanCubeV2<P> {
     }
 
     pub async fn reset_cube_state(&self, state: CubeState) -> Result<(), Error> {
-        let message = codec::RequestMessage::ResetCubeState(state).encode(&self.cipher);
+        let message = codec::RequestMessage::ResetCubeState(state).encode(&*self.cipher);
         self.device
             .write(&self.services.request, &message, WriteType::WithResponse)
             .await?;
@@ -282,6 +373,7 @@ impl<P: Peripheral> GanCubeV2<P> {
 }
 
 mod codec {
+    use nalgebra::{self, UnitQuaternion, Vector3};
     use std::{io::prelude::*, time::Duration};
     use strum_macros::FromRepr;
     use thiserror::Error;
@@ -291,6 +383,7 @@ mod codec {
         cipher::GanCubeV2Cipher,
         util::{Biter, BiterMut},
     };
+    use crate::bluetooth::quirks::{Firmware, ProtocolQuirks};
     use crate::cube::*;
 
     #[derive(Debug, Error)]
@@ -308,6 +401,7 @@ mod codec {
         Gyroscope    = 0b0001,
         CubeMoves    = 0b0010,
         CubeState    = 0b0100,
+        HardwareInfo = 0b0101,
         BatteryState = 0b1001,
         Disconnect   = 0b1101,
     }
@@ -321,7 +415,31 @@ mod codec {
     type Quaternion = (f32, f32, f32, f32);
     type QuaternionP = (f32, f32, f32);
 
-    #[derive(PartialEq, PartialOrd)]
+    #[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+    pub struct HardwareInfo {
+        pub hardware: (u8, u8),
+        pub software: (u8, u8),
+        // ascii, padded with zeros
+        pub name: [u8; 8],
+        pub gyroscope: bool,
+    }
+
+    impl HardwareInfo {
+        pub fn firmware(&self) -> Firmware {
+            let name = self
+                .name
+                .iter()
+                .take_while(|&&c| c != 0)
+                .map(|&c| c as char);
+            Firmware {
+                name: name.collect(),
+                hardware: self.hardware,
+                software: self.software,
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
     pub enum ResponseMessage {
         Gyroscope {
             q1: Quaternion,
@@ -342,19 +460,42 @@ mod codec {
             charging: bool,
             percentage: u32,
         },
+        Hardware(HardwareInfo),
         Disconnect,
     }
 
     const CREL: &str = "\r\x1b[2K";
 
     impl ResponseMessage {
-        pub fn decode(data: &[u8], cipher: &GanCubeV2Cipher) -> Result<Self, MessageParseError> {
-            let Ok(mut data) = <[u8; 20]>::try_from(data) else {
-                return Err(MessageParseError::BadMessageLength(data.len()));
-            };
+        pub fn decode(
+            data: &[u8],
+            cipher: &dyn GanCubeV2Cipher,
+            quirks: &ProtocolQuirks,
+        ) -> Result<Self, MessageParseError> {
+            Self::decode_decrypted(Self::decrypt(data, cipher)?, quirks)
+        }
 
-            cipher.decrypt(&mut data);
+        // the decrypted frame, where a 19-byte frame is padded with zero, which only touches
+        // the last field of the gyroscope messages
+        pub fn decrypt(
+            data: &[u8],
+            cipher: &dyn GanCubeV2Cipher,
+        ) -> Result<[u8; 20], MessageParseError> {
+            if !matches!(data.len(), 19 | 20) {
+                return Err(MessageParseError::BadMessageLength(data.len()));
+            }
+            let mut frame = data.to_vec();
+            cipher.decrypt(&mut frame);
+            let mut res = [0; 20];
+            res[..frame.len()].copy_from_slice(&frame);
+            Ok(res)
+        }
 
+        // decode the message already decrypted, such as a captured frame
+        pub fn decode_decrypted(
+            data: [u8; 20],
+            quirks: &ProtocolQuirks,
+        ) -> Result<Self, MessageParseError> {
             let mut biter = Biter::new(&data);
 
             let Some(message_type) = ResponseMessageType::from_repr(biter.extract(4) as u8) else {
@@ -362,9 +503,10 @@ mod codec {
             };
 
             let message = match message_type {
-                ResponseMessageType::Gyroscope => Self::decode_gyroscope(&mut biter),
-                ResponseMessageType::CubeMoves => Self::decode_cube_moves(&mut biter),
-                ResponseMessageType::CubeState => Self::decode_cube_state(&mut biter),
+                ResponseMessageType::Gyroscope => Self::decode_gyroscope(&mut biter, quirks),
+                ResponseMessageType::CubeMoves => Self::decode_cube_moves(&mut biter, quirks),
+                ResponseMessageType::CubeState => Self::decode_cube_state(&mut biter, quirks),
+                ResponseMessageType::HardwareInfo => Self::decode_hardware_info(&mut biter),
                 ResponseMessageType::BatteryState => Self::decode_battery_state(&mut biter),
                 ResponseMessageType::Disconnect => Self::decode_disconnect(&mut biter),
             };
@@ -372,7 +514,7 @@ mod codec {
             Ok(message)
         }
 
-        fn decode_gyroscope(biter: &mut Biter) -> Self {
+        fn decode_gyroscope(biter: &mut Biter, quirks: &ProtocolQuirks) -> Self {
             fn from_signed_u3(e: u32) -> f32 {
                 const MAGNITUDE: f32 = (1 << 3) as f32;
                 const MASK: u8 = 0b0111;
@@ -398,6 +540,8 @@ mod codec {
             let redp = from_signed_u3(biter.extract(4));
             let bluep = from_signed_u3(biter.extract(4));
             let whitep = from_signed_u3(biter.extract(4));
+            let [red, blue, white] = quirks.gyro_axes.map(|i| [red, blue, white][i]);
+            let [redp, bluep, whitep] = quirks.gyro_axes.map(|i| [redp, bluep, whitep][i]);
             let q1 = (scalar, red, blue, white);
             let q1p = (redp, bluep, whitep);
 
@@ -408,6 +552,8 @@ mod codec {
             let redp_ = from_signed_u3(biter.extract(4));
             let bluep_ = from_signed_u3(biter.extract(4));
             let whitep_ = from_signed_u3(biter.extract(4));
+            let [red_, blue_, white_] = quirks.gyro_axes.map(|i| [red_, blue_, white_][i]);
+            let [redp_, bluep_, whitep_] = quirks.gyro_axes.map(|i| [redp_, bluep_, whitep_][i]);
             let q2 = (scalar_, red_, blue_, white_);
             let q2p = (redp_, bluep_, whitep_);
 
@@ -419,8 +565,8 @@ mod codec {
             Self::Gyroscope { q1, q1p, q2, q2p }
         }
 
-        fn decode_cube_moves(biter: &mut Biter) -> Self {
-            let count = biter.extract(8) as u8;
+        fn decode_cube_moves(biter: &mut Biter, quirks: &ProtocolQuirks) -> Self {
+            let count = biter.extract(quirks.move_count_bits) as u8;
 
             let mut moves = <[Option<CubeMove>; 7]>::default();
             for mv in moves.iter_mut() {
@@ -429,10 +575,11 @@ mod codec {
 
             let mut times = <[Duration; 7]>::default();
             for t in times.iter_mut() {
-                *t = Duration::from_millis(biter.extract(16) as u64);
+                *t = quirks.timestamp_unit * biter.extract(16);
             }
 
-            let remains = biter.extract(1);
+            // the bits left by a narrower move count
+            let remains = biter.extract(9 - quirks.move_count_bits);
             if remains != 0 {
                 eprintln!("bad remains data, possibly broken: {:1X}", remains);
             }
@@ -444,8 +591,8 @@ mod codec {
             }
         }
 
-        fn decode_cube_state(biter: &mut Biter) -> Self {
-            let count = biter.extract(8) as u8;
+        fn decode_cube_state(biter: &mut Biter, quirks: &ProtocolQuirks) -> Self {
+            let count = biter.extract(quirks.move_count_bits) as u8;
 
             let mut corners_position = [0, 1, 2, 3, 4, 5, 6, 7];
             let mut corners_orientation = [0; 8];
@@ -467,34 +614,32 @@ mod codec {
             }
             edges_position[11] = (0..12).find(|a| !edges_position[..11].contains(a)).unwrap();
 
-            for ori in edges_position.iter_mut().take(11) {
+            for ori in edges_orientation.iter_mut().take(11) {
                 *ori = biter.extract(1) as u8;
             }
             edges_orientation[11] = (2 - edges_orientation[..11].iter().sum::<u8>() % 2) % 2;
 
-            let _unknown = biter.extract(10);
+            let _unknown = biter.extract(18 - quirks.move_count_bits);
 
-            let remains = (0..6).map(|_| biter.extract(8) as u8).collect::<Vec<_>>();
+            let remains = biter.extract_array::<6>(8);
             if remains != [0; 6] {
                 eprintln!("bad remains data, possibly broken: {:02X?}", remains);
             }
 
             let state = try_block! {
-                let corners: [Corner; 8] = corners_position
-                    .into_iter()
-                    .zip(corners_orientation)
-                    .map(|v| v.try_into().ok())
-                    .collect::<Option<Vec<_>>>()?
-                    .try_into().unwrap();
-
-                let edges: [Edge; 12] = edges_position
-                    .into_iter()
-                    .zip(edges_orientation)
-                    .map(|v| v.try_into().ok())
-                    .collect::<Option<Vec<_>>>()?
-                    .try_into().unwrap();
-
-                Some(CubeState::new(corners, edges))
+                let mut state = CubeState::default();
+
+                let corners = corners_position.into_iter().zip(corners_orientation);
+                for (corner, value) in state.corners.iter_mut().zip(corners) {
+                    *corner = value.try_into().ok()?;
+                }
+
+                let edges = edges_position.into_iter().zip(edges_orientation);
+                for (edge, value) in state.edges.iter_mut().zip(edges) {
+                    *edge = value.try_into().ok()?;
+                }
+
+                Some(state)
             };
 
             Self::State {
@@ -503,10 +648,25 @@ mod codec {
             }
         }
 
+        fn decode_hardware_info(biter: &mut Biter) -> Self {
+            biter.skip(4);
+            let hardware = (biter.extract(8) as u8, biter.extract(8) as u8);
+            let software = (biter.extract(8) as u8, biter.extract(8) as u8);
+            let name = biter.extract_array::<8>(8).map(|c| c as u8);
+            let gyroscope = biter.extract(1) != 0;
+
+            Self::Hardware(HardwareInfo {
+                hardware,
+                software,
+                name,
+                gyroscope,
+            })
+        }
+
         fn decode_battery_state(biter: &mut Biter) -> Self {
             let charging = biter.extract(4) != 0;
             let percentage = biter.extract(8);
-            let remains = (0..18).map(|_| biter.extract(8) as u8).collect::<Vec<_>>();
+            let remains = biter.extract_array::<18>(8);
             if remains != [0; 18] {
                 eprintln!("bad remains data, possibly broken: {:02X?}", remains);
             }
@@ -518,8 +678,8 @@ mod codec {
         }
 
         fn decode_disconnect(biter: &mut Biter) -> Self {
-            let remains0 = biter.extract(4) as u8;
-            let remains = (0..19).map(|_| biter.extract(8) as u8).collect::<Vec<_>>();
+            let remains0 = biter.extract(4);
+            let remains = biter.extract_array::<19>(8);
             if remains0 != 0 || remains != [0; 19] {
                 eprintln!(
                     "bad remains data, possibly broken: {:02X?}, {:02X?}",
@@ -530,6 +690,102 @@ mod codec {
             Self::Disconnect
         }
 
+        // inverse of `decode`
+        pub fn encode(&self, cipher: &dyn GanCubeV2Cipher) -> [u8; 20] {
+            fn to_signed_u3(val: f32) -> u32 {
+                const MAGNITUDE: f32 = (1 << 3) as f32;
+                let sign = if val < 0.0 { 0b1000 } else { 0 };
+                sign | (val.abs() * MAGNITUDE).round().min(0b0111 as f32) as u32
+            }
+
+            fn to_signed_u15(val: f32) -> u32 {
+                const MAGNITUDE: f32 = (1 << 15) as f32;
+                let sign = if val < 0.0 { 0b1000_0000_0000_0000 } else { 0 };
+                sign | (val.abs() * MAGNITUDE)
+                    .round()
+                    .min(0b0111_1111_1111_1111 as f32) as u32
+            }
+
+            let mut message = [0; 20];
+            let mut biter = BiterMut::new(&mut message);
+
+            match self {
+                Self::Gyroscope { q1, q1p, q2, q2p } => {
+                    biter.assign(4, ResponseMessageType::Gyroscope.repr() as u32);
+                    for (q, qp) in [(q1, q1p), (q2, q2p)] {
+                        for val in [q.0, q.1, q.2, q.3] {
+                            biter.assign(16, to_signed_u15(val));
+                        }
+                        for val in [qp.0, qp.1, qp.2] {
+                            biter.assign(4, to_signed_u3(val));
+                        }
+                    }
+                    biter.assign(4, 0b1010);
+                }
+                Self::Moves {
+                    count,
+                    moves,
+                    times,
+                } => {
+                    biter.assign(4, ResponseMessageType::CubeMoves.repr() as u32);
+                    biter.assign(8, *count as u32);
+                    for mv in moves {
+                        biter.assign(5, mv.map_or(0b11111, |mv| mv.repr() as u32));
+                    }
+                    for t in times {
+                        biter.assign(16, t.as_millis() as u32);
+                    }
+                }
+                Self::State { count, state } => {
+                    let state = state.unwrap_or_default();
+                    biter.assign(4, ResponseMessageType::CubeState.repr() as u32);
+                    biter.assign(8, *count as u32);
+                    for corner in state.corners.iter().take(7) {
+                        biter.assign(3, corner.0.repr() as u32);
+                    }
+                    for corner in state.corners.iter().take(7) {
+                        biter.assign(2, corner.1.repr() as u32);
+                    }
+                    for edge in state.edges.iter().take(11) {
+                        biter.assign(4, edge.0.repr() as u32);
+                    }
+                    for edge in state.edges.iter().take(11) {
+                        biter.assign(1, edge.1.repr() as u32);
+                    }
+                }
+                Self::Battery {
+                    charging,
+                    percentage,
+                } => {
+                    biter.assign(4, ResponseMessageType::BatteryState.repr() as u32);
+                    biter.assign(4, *charging as u32);
+                    biter.assign(8, *percentage);
+                }
+                Self::Hardware(info) => {
+                    biter.assign(4, ResponseMessageType::HardwareInfo.repr() as u32);
+                    biter.assign(4, 0);
+                    for byte in [
+                        info.hardware.0,
+                        info.hardware.1,
+                        info.software.0,
+                        info.software.1,
+                    ] {
+                        biter.assign(8, byte as u32);
+                    }
+                    for byte in info.name {
+                        biter.assign(8, byte as u32);
+                    }
+                    biter.assign(1, info.gyroscope as u32);
+                }
+                Self::Disconnect => {
+                    biter.assign(4, ResponseMessageType::Disconnect.repr() as u32);
+                }
+            }
+
+            cipher.encrypt(&mut message);
+            message
+        }
+
         pub fn show(self) {
             match self {
                 Self::Gyroscope { q1, q1p, q2, q2p } => Self::show_gyroscope(q1, q1p, q2, q2p),
@@ -543,6 +799,7 @@ mod codec {
                     charging,
                     percentage,
                 } => Self::show_battery_state(charging, percentage),
+                Self::Hardware(info) => Self::show_hardware_info(info),
                 Self::Disconnect => Self::show_disconnect(),
             }
         }
@@ -644,18 +901,66 @@ mod codec {
             println!();
         }
 
+        fn show_hardware_info(info: HardwareInfo) {
+            let firmware = info.firmware();
+            print!("{}", CREL);
+            print!(
+                "hardware={} v{}.{} software=v{}.{}",
+                firmware.name, info.hardware.0, info.hardware.1, info.software.0, info.software.1
+            );
+            if info.gyroscope {
+                print!(" (gyroscope)");
+            }
+            println!();
+        }
+
         fn show_disconnect() {
             print!("{}", CREL);
             println!("auto disconnect");
         }
     }
 
+    // the orientation and the angular velocity of a gyroscope, in a right-handed world frame with
+    // x, y and z pointing to the R, U and F faces of the cube held white up and green front; the
+    // raw components (scalar, red, blue, white) are about the axes pointing to R, B and U
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct CubeOrientationFrame {
+        pub orientation: UnitQuaternion<f32>,
+        pub angular: Vector3<f32>,
+    }
+
+    impl CubeOrientationFrame {
+        pub fn from_raw(q: Quaternion, qp: QuaternionP) -> Self {
+            let (scalar, red, blue, white) = q;
+            let (redp, bluep, whitep) = qp;
+            let orientation = nalgebra::Quaternion::new(scalar, red, white, -blue);
+            CubeOrientationFrame {
+                orientation: UnitQuaternion::new_normalize(orientation),
+                angular: Vector3::new(redp, whitep, -bluep),
+            }
+        }
+    }
+
+    impl ResponseMessage {
+        // the frames of both gyroscopes
+        pub fn orientation_frames(&self) -> Option<(CubeOrientationFrame, CubeOrientationFrame)> {
+            let Self::Gyroscope { q1, q1p, q2, q2p } = *self else {
+                return None;
+            };
+            Some((
+                CubeOrientationFrame::from_raw(q1, q1p),
+                CubeOrientationFrame::from_raw(q2, q2p),
+            ))
+        }
+    }
+
     #[allow(clippy::enum_variant_names)]
     #[rustfmt::skip]
     #[repr(u8)]
     #[derive(PartialEq, Eq, PartialOrd, Ord)]
     enum RequestMessageType {
         RequestCubeState    = 0b_0000_0100,
+        RequestHardwareInfo = 0b_0000_0101,
         RequestBatteryState = 0b_0000_1001,
         ResetCubeState      = 0b_0000_1010,
     }
@@ -667,14 +972,16 @@ mod codec {
     }
 
     #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
     pub enum RequestMessage {
         RequestCubeState,
+        RequestHardwareInfo,
         RequestBatteryState,
         ResetCubeState(CubeState),
     }
 
     impl RequestMessage {
-        pub fn encode(&self, cipher: &GanCubeV2Cipher) -> [u8; 20] {
+        pub fn encode(&self, cipher: &dyn GanCubeV2Cipher) -> [u8; 20] {
             let mut message = [0; 20];
             let mut biter = BiterMut::new(&mut message);
 
@@ -682,6 +989,9 @@ mod codec {
                 Self::RequestCubeState => {
                     biter.assign(8, RequestMessageType::RequestCubeState.repr() as u32);
                 }
+                Self::RequestHardwareInfo => {
+                    biter.assign(8, RequestMessageType::RequestHardwareInfo.repr() as u32);
+                }
                 Self::RequestBatteryState => {
                     biter.assign(8, RequestMessageType::RequestBatteryState.repr() as u32);
                 }
@@ -706,22 +1016,210 @@ mod codec {
             message
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::bluetooth::{gancubev2::cipher::GanCipher, mock::MockTransport};
+
+        #[test]
+        fn messages_are_decoded_by_quirks() {
+            let cipher = GanCipher::new(&MockTransport::properties()).unwrap();
+            let moves = ResponseMessage::Moves {
+                count: 3,
+                moves: [None; 7],
+                times: [Duration::from_millis(12); 7],
+            };
+            let quirks = ProtocolQuirks {
+                timestamp_unit: Duration::from_millis(10),
+                ..Default::default()
+            };
+            let Ok(ResponseMessage::Moves { count, times, .. }) =
+                ResponseMessage::decode(&moves.encode(&cipher), &cipher, &quirks)
+            else {
+                panic!("moves are not decoded");
+            };
+            assert_eq!(count, 3);
+            assert_eq!(times[0], Duration::from_millis(120));
+
+            let info = HardwareInfo {
+                hardware: (0, 1),
+                software: (7, 76),
+                name: *b"GANi3\0\0\0",
+                gyroscope: true,
+            };
+            let message = ResponseMessage::Hardware(info).encode(&cipher);
+            let decoded = ResponseMessage::decode(&message, &cipher, &ProtocolQuirks::default());
+            assert!(matches!(decoded, Ok(ResponseMessage::Hardware(decoded)) if decoded == info));
+            assert_eq!(info.firmware().name, "GANi3");
+        }
+
+        #[test]
+        fn cube_state_is_decoded_with_flipped_edges() {
+            // the cube state after F, which flips four edges
+            let frame = [
+                0x40, 0x53, 0x53, 0x13, 0x30, 0x48, 0x12, 0x46, 0x90, 0xCE, 0x2B, 0x48, 0x98, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ];
+            let mut biter = Biter::new(&frame);
+            biter.skip(4);
+            let ResponseMessage::State {
+                count,
+                state: Some(state),
+            } = ResponseMessage::decode_cube_state(&mut biter, &ProtocolQuirks::default())
+            else {
+                panic!("cube state is not decoded");
+            };
+            assert_eq!(count, 5);
+            let corners = [(1, 1), (5, 2), (2, 0), (3, 0), (0, 2), (4, 1), (6, 0), (7, 0)];
+            assert_eq!(state.corners, corners.map(|c| Corner::try_from(c).unwrap()));
+            #[rustfmt::skip]
+            let edges = [
+                (0, 0), (9, 1), (2, 0), (3, 0), (4, 0), (8, 1),
+                (6, 0), (7, 0), (1, 1), (5, 1), (10, 0), (11, 0),
+            ];
+            assert_eq!(state.edges, edges.map(|e| Edge::try_from(e).unwrap()));
+        }
+    }
+}
+
+pub use codec::{CubeOrientationFrame, ResponseMessage};
+
+#[derive(Default)]
+struct NotificationCounters {
+    received: AtomicUsize,
+    decoded: AtomicUsize,
+    failed: AtomicUsize,
+    unknown: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct NotificationStats {
+    pub received: usize,
+    pub decoded: usize,
+    pub failed: usize,
+    pub unknown: usize,
+    // dropped by the message filter
+    pub dropped: usize,
+}
+
+impl NotificationStats {
+    pub fn errors(&self) -> usize {
+        self.failed + self.unknown
+    }
+}
+
+// the kinds of messages handed to the handlers; gyroscope notifications dominate the bandwidth, so
+// they can be dropped in the notification task when only moves are needed, or thinned out to the
+// configured rate
+#[derive(Clone)]
+pub struct MessageFilter {
+    gyroscope: Arc<AtomicBool>,
+    gyroscope_interval: Duration,
+    last_gyroscope: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Default for MessageFilter {
+    fn default() -> Self {
+        MessageFilter::new(config::get().device.gyro_rate)
+    }
 }
 
-pub use codec::ResponseMessage;
+impl MessageFilter {
+    // at most `gyro_rate` gyroscope messages per second
+    pub fn new(gyro_rate: Option<f32>) -> Self {
+        let interval = gyro_rate
+            .filter(|&rate| rate > 0.0)
+            .and_then(|rate| Duration::try_from_secs_f32(1.0 / rate).ok())
+            .unwrap_or(Duration::ZERO);
+        MessageFilter {
+            gyroscope: Arc::new(AtomicBool::new(true)),
+            gyroscope_interval: interval,
+            last_gyroscope: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn set_gyroscope(&self, enabled: bool) {
+        self.gyroscope.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_gyroscope_enabled(&self) -> bool {
+        self.gyroscope.load(Ordering::Relaxed)
+    }
+
+    pub fn accepts(&self, message: &codec::ResponseMessage, received: Instant) -> bool {
+        if !matches!(message, codec::ResponseMessage::Gyroscope { .. }) {
+            return true;
+        }
+        if !self.gyroscope.load(Ordering::Relaxed) {
+            return false;
+        }
+        let Ok(mut last) = self.last_gyroscope.lock() else {
+            return true;
+        };
+        match *last {
+            Some(last) if received.duration_since(last) < self.gyroscope_interval => false,
+            _ => {
+                *last = Some(received);
+                true
+            }
+        }
+    }
+}
+
+// time points of a notification in the notification task
+#[derive(Clone, Copy, Debug)]
+pub struct NotificationTiming {
+    pub received: Instant,
+    pub decoded: Instant,
+}
+
+// handle of the notification task, which can be awaited until the cube disconnects
+pub struct NotificationHandle {
+    task: tokio::task::JoinHandle<()>,
+    stats: Arc<NotificationCounters>,
+}
+
+impl NotificationHandle {
+    pub fn stats(&self) -> NotificationStats {
+        NotificationStats {
+            received: self.stats.received.load(Ordering::Relaxed),
+            decoded: self.stats.decoded.load(Ordering::Relaxed),
+            failed: self.stats.failed.load(Ordering::Relaxed),
+            unknown: self.stats.unknown.load(Ordering::Relaxed),
+            dropped: self.stats.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn abort(&self) {
+        self.task.abort();
+    }
+}
+
+impl Future for NotificationHandle {
+    type Output = Result<(), tokio::task::JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task).poll(cx)
+    }
+}
+
+pub(crate) mod cipher {
+    use std::sync::Arc;
 
-mod cipher {
     use aes::cipher::{generic_array::GenericArray, BlockDecrypt, BlockEncrypt, KeyInit};
     use aes::{Aes128, Block};
     use btleplug::api::PeripheralProperties;
 
     use super::DeviceError;
+    use crate::config::{self, CipherConfig};
 
-    #[derive(Clone)]
-    pub struct GanCubeV2Cipher {
-        key: Block,
-        iv: Block,
-        aes: Aes128,
+    // the encryption of the messages, which differs between GAN and some clones
+    pub trait GanCubeV2Cipher: Send + Sync {
+        // the frames are 20 bytes, or 19 bytes by some firmwares
+        fn encrypt(&self, value: &mut [u8]);
+        fn decrypt(&self, value: &mut [u8]);
     }
 
     const KEY: [u8; 16] = [
@@ -734,39 +1232,48 @@ mod cipher {
         0x20, 0x95, 0x78, 0x14, 0x32, 0x12, 0x02, 0x43,
     ];
 
-    impl GanCubeV2Cipher {
-        pub(super) fn make_cipher(
-            device_props: &PeripheralProperties,
-        ) -> Result<Self, DeviceError> {
-            let Some(manufacturer_data) = device_props.manufacturer_data.get(&1) else {
-                return Err(DeviceError::NoDeviceIdentifier);
-            };
-            let Ok(device_id) = <&[u8; 9]>::try_from(&manufacturer_data[..]) else {
-                return Err(DeviceError::InvalidDeviceIdentifier);
-            };
-
-            let device_key: [u8; 6] = device_id[3..9].try_into().unwrap();
-            let mut key = KEY;
-            let mut iv = IV;
+    // the device identifier in the manufacturer data, which is also used to find GANCubes
+    pub(crate) fn device_identifier(
+        device_props: &PeripheralProperties,
+    ) -> Result<&[u8; 9], DeviceError> {
+        let Some(manufacturer_data) = device_props.manufacturer_data.get(&1) else {
+            return Err(DeviceError::NoDeviceIdentifier);
+        };
+        <&[u8; 9]>::try_from(&manufacturer_data[..])
+            .map_err(|_| DeviceError::InvalidDeviceIdentifier)
+    }
 
-            fn add_device_key(secret: &mut [u8; 16], device_key: &[u8; 6]) {
-                secret
-                    .iter_mut()
-                    .zip(device_key)
-                    .for_each(|(a, b)| *a = ((*a as u16 + *b as u16) % 255) as u8);
-            }
+    // add the last 6 bytes of the device identifier to the secret
+    fn salt(secret: &mut [u8; 16], device_props: &PeripheralProperties) -> Result<(), DeviceError> {
+        let device_id = device_identifier(device_props)?;
+        let device_key: [u8; 6] = device_id[3..9].try_into().unwrap();
+        secret
+            .iter_mut()
+            .zip(device_key)
+            .for_each(|(a, b)| *a = ((*a as u16 + b as u16) % 255) as u8);
+        Ok(())
+    }
 
-            add_device_key(&mut key, &device_key);
-            add_device_key(&mut iv, &device_key);
+    // AES-128 applied to the first and the last 16 bytes of a message, each xored with the iv,
+    // where the blocks overlap by the bytes a frame is shorter than 32 bytes
+    #[derive(Clone)]
+    struct AesCipher {
+        iv: Block,
+        aes: Aes128,
+    }
 
+    impl AesCipher {
+        fn new(key: [u8; 16], iv: [u8; 16]) -> Self {
             let key = GenericArray::from(key);
             let iv = GenericArray::from(iv);
-            let aes = Aes128::new(&key);
-            Ok(GanCubeV2Cipher { key, iv, aes })
+            AesCipher {
+                iv,
+                aes: Aes128::new(&key),
+            }
         }
 
-        pub(super) fn encrypt(&self, value: &mut [u8; 20]) {
-            fn encrypt_block(cipher: &GanCubeV2Cipher, block: &mut [u8]) {
+        fn encrypt(&self, value: &mut [u8]) {
+            fn encrypt_block(cipher: &AesCipher, block: &mut [u8]) {
                 let block = GenericArray::from_mut_slice(block);
                 block.iter_mut().zip(cipher.iv).for_each(|(a, b)| *a ^= b);
                 cipher.aes.encrypt_block(block);
@@ -777,8 +1284,8 @@ mod cipher {
             encrypt_block(self, &mut value[offset..]);
         }
 
-        pub(super) fn decrypt(&self, value: &mut [u8; 20]) {
-            fn decrypt_block(cipher: &GanCubeV2Cipher, block: &mut [u8]) {
+        fn decrypt(&self, value: &mut [u8]) {
+            fn decrypt_block(cipher: &AesCipher, block: &mut [u8]) {
                 let block = GenericArray::from_mut_slice(block);
                 cipher.aes.decrypt_block(block);
                 block.iter_mut().zip(cipher.iv).for_each(|(a, b)| *a ^= b);
@@ -789,10 +1296,165 @@ mod cipher {
             decrypt_block(self, &mut value[..16]);
         }
     }
+
+    // the keys of GAN salted by the device identifier
+    #[derive(Clone)]
+    pub struct GanCipher(AesCipher);
+
+    impl GanCipher {
+        pub(crate) fn new(device_props: &PeripheralProperties) -> Result<Self, DeviceError> {
+            let mut key = KEY;
+            let mut iv = IV;
+            salt(&mut key, device_props)?;
+            salt(&mut iv, device_props)?;
+            Ok(GanCipher(AesCipher::new(key, iv)))
+        }
+    }
+
+    impl GanCubeV2Cipher for GanCipher {
+        fn encrypt(&self, value: &mut [u8]) {
+            self.0.encrypt(value)
+        }
+
+        fn decrypt(&self, value: &mut [u8]) {
+            self.0.decrypt(value)
+        }
+    }
+
+    // the keys given by the config, for clones and custom firmwares
+    #[derive(Clone)]
+    pub struct CustomCipher(AesCipher);
+
+    impl CustomCipher {
+        pub(crate) fn new(
+            config: &CipherConfig,
+            device_props: &PeripheralProperties,
+        ) -> Result<Self, DeviceError> {
+            let parse = |hex: &str| -> Option<[u8; 16]> {
+                if hex.len() != 32 || !hex.is_ascii() {
+                    return None;
+                }
+                let bytes = (0..16)
+                    .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+                    .collect::<Option<Vec<_>>>()?;
+                bytes.try_into().ok()
+            };
+            let mut key = parse(&config.key).ok_or(DeviceError::InvalidCipherKey)?;
+            let mut iv = parse(&config.iv).ok_or(DeviceError::InvalidCipherKey)?;
+            if config.salted {
+                salt(&mut key, device_props)?;
+                salt(&mut iv, device_props)?;
+            }
+            Ok(CustomCipher(AesCipher::new(key, iv)))
+        }
+    }
+
+    impl GanCubeV2Cipher for CustomCipher {
+        fn encrypt(&self, value: &mut [u8]) {
+            self.0.encrypt(value)
+        }
+
+        fn decrypt(&self, value: &mut [u8]) {
+            self.0.decrypt(value)
+        }
+    }
+
+    // the first configured cipher accepting the address of the device, or the one of GAN
+    pub(crate) fn make_cipher(
+        device_props: &PeripheralProperties,
+    ) -> Result<Arc<dyn GanCubeV2Cipher>, DeviceError> {
+        let address = device_props.address.to_string();
+        let custom = config::get().device.ciphers.iter().find(|cipher| {
+            (cipher.address.as_ref()).is_none_or(|expected| expected.eq_ignore_ascii_case(&address))
+        });
+        Ok(match custom {
+            Some(custom) => Arc::new(CustomCipher::new(custom, device_props)?),
+            None => Arc::new(GanCipher::new(device_props)?),
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::bluetooth::mock::MockTransport;
+
+        #[test]
+        fn custom_cipher_takes_the_keys() {
+            let props = MockTransport::properties();
+            let hex = |bytes: [u8; 16]| bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let config = CipherConfig {
+                address: None,
+                key: hex(KEY),
+                iv: hex(IV),
+                salted: true,
+            };
+            let custom = CustomCipher::new(&config, &props).unwrap();
+            let gan = GanCipher::new(&props).unwrap();
+
+            let message: [u8; 20] = std::array::from_fn(|i| i as u8);
+            let mut encrypted = message;
+            gan.encrypt(&mut encrypted);
+            assert_ne!(encrypted, message);
+            custom.decrypt(&mut encrypted);
+            assert_eq!(encrypted, message);
+
+            let unsalted = CipherConfig {
+                salted: false,
+                ..config.clone()
+            };
+            let unsalted = CustomCipher::new(&unsalted, &props).unwrap();
+            unsalted.encrypt(&mut encrypted);
+            gan.decrypt(&mut encrypted);
+            assert_ne!(encrypted, message);
+
+            // the blocks overlap more in a short frame
+            let mut short: [u8; 19] = std::array::from_fn(|i| i as u8);
+            gan.encrypt(&mut short);
+            custom.decrypt(&mut short);
+            assert_eq!(short[..], message[..19]);
+
+            let invalid = CipherConfig {
+                key: "0102".to_string(),
+                ..config
+            };
+            assert!(CustomCipher::new(&invalid, &props).is_err());
+        }
+    }
 }
 
 mod util {
-    const FIRST_BIT: u8 = 1 << 7;
+    // extract bits in big-endian order by loading the covered bytes into one word
+    fn extract_bits(data: &[u8], index: usize, count: usize) -> u32 {
+        debug_assert!(count <= 32);
+        if count == 0 {
+            return 0;
+        }
+        let start = index / 8;
+        let end = (index + count).div_ceil(8);
+        let word = data[start..end]
+            .iter()
+            .fold(0u64, |word, &byte| (word << 8) | byte as u64);
+        let offset = end * 8 - (index + count);
+        ((word >> offset) & ((1u64 << count) - 1)) as u32
+    }
+
+    fn assign_bits(data: &mut [u8], index: usize, count: usize, value: u32) {
+        debug_assert!(count <= 32);
+        if count == 0 {
+            return;
+        }
+        let start = index / 8;
+        let end = (index + count).div_ceil(8);
+        let offset = end * 8 - (index + count);
+        let mask = ((1u64 << count) - 1) << offset;
+        let word = data[start..end]
+            .iter()
+            .fold(0u64, |word, &byte| (word << 8) | byte as u64);
+        let word = (word & !mask) | (((value as u64) << offset) & mask);
+        for (i, byte) in data[start..end].iter_mut().rev().enumerate() {
+            *byte = (word >> (i * 8)) as u8;
+        }
+    }
 
     // big-endian bit iterator
     pub struct Biter<'a> {
@@ -819,16 +1481,15 @@ mod util {
         }
 
         pub fn extract(&mut self, count: usize) -> u32 {
-            let mut result = 0;
-            for bit in (self.index..).take(count) {
-                result <<= 1;
-                if self.data[bit / 8] & (FIRST_BIT >> (bit % 8)) != 0 {
-                    result |= 1;
-                }
-            }
+            let result = extract_bits(self.data, self.index, count);
             self.index += count;
             result
         }
+
+        // extract the same number of bits for each element
+        pub fn extract_array<const N: usize>(&mut self, count: usize) -> [u32; N] {
+            core::array::from_fn(|_| self.extract(count))
+        }
     }
 
     impl<'a> BiterMut<'a> {
@@ -845,24 +1506,13 @@ mod util {
         }
 
         pub fn extract(&mut self, count: usize) -> u32 {
-            let mut result = 0;
-            for bit in (self.index..).take(count) {
-                result <<= 1;
-                if self.data[bit / 8] & (FIRST_BIT >> (bit % 8)) != 0 {
-                    result |= 1;
-                }
-            }
+            let result = extract_bits(self.data, self.index, count);
             self.index += count;
             result
         }
+
         pub fn assign(&mut self, count: usize, value: u32) {
-            for (bit, i) in (self.index..).take(count).zip((0..count).rev()) {
-                if value & (1 << i) != 0 {
-                    self.data[bit / 8] |= FIRST_BIT >> (bit % 8);
-                } else {
-                    self.data[bit / 8] &= !(FIRST_BIT >> (bit % 8));
-                }
-            }
+            assign_bits(self.data, self.index, count, value);
             self.index += count;
         }
     }
@@ -0,0 +1,439 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use btleplug::api::{
+    CharPropFlags, Characteristic, PeripheralProperties, ValueNotification, WriteType,
+};
+use futures::{future, stream};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::{
+    gancubev2::{
+        cipher::{GanCipher, GanCubeV2Cipher},
+        GanCubeV2Builder, GanCubeV2Services, ResponseMessage,
+    },
+    scan::GAN_SERVICE_UUID,
+    transport::{NotificationStream, Transport},
+};
+use crate::cube::CubeMove;
+
+#[derive(Default)]
+struct MockState {
+    connected: bool,
+    subscribed: bool,
+    listeners: Vec<mpsc::UnboundedSender<ValueNotification>>,
+    written: Vec<Vec<u8>>,
+    // number of the next connection attempts which never finish
+    stalled: usize,
+    rssi: Option<i16>,
+}
+
+// in-memory GANCube serving canned frames, for testing without hardware
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    const DEVICE_ID: [u8; 9] = [0x00, 0x00, 0x00, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+
+    pub fn properties() -> PeripheralProperties {
+        PeripheralProperties {
+            local_name: Some("GANMock".to_string()),
+            manufacturer_data: HashMap::from([(1, Self::DEVICE_ID.to_vec())]),
+            ..PeripheralProperties::default()
+        }
+    }
+
+    pub fn cipher() -> GanCipher {
+        GanCipher::new(&Self::properties()).unwrap()
+    }
+
+    pub fn builder(&self) -> GanCubeV2Builder<Self> {
+        GanCubeV2Builder {
+            device: self.clone(),
+            properties: Self::properties(),
+        }
+    }
+
+    // send a raw frame to all notification streams if subscribed
+    pub fn notify(&self, uuid: Uuid, value: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        if !state.connected || !state.subscribed {
+            return;
+        }
+        let notification = ValueNotification { uuid, value };
+        state
+            .listeners
+            .retain(|listener| listener.send(notification.clone()).is_ok());
+    }
+
+    pub fn notify_message(&self, message: ResponseMessage) {
+        let frame = message.encode(&Self::cipher());
+        self.notify(GanCubeV2Services::RESPONSE_UUID, frame.to_vec());
+    }
+
+    // frames written to the request characteristic
+    pub fn written(&self) -> Vec<Vec<u8>> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    // simulate an unresponsive cube for the next connection attempts
+    pub fn stall_connections(&self, attempts: usize) {
+        self.state.lock().unwrap().stalled = attempts;
+    }
+
+    pub fn set_rssi(&self, rssi: Option<i16>) {
+        self.state.lock().unwrap().rssi = rssi;
+    }
+
+    // simulate a lost connection, which ends all notification streams
+    pub fn drop_connection(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.connected = false;
+        state.subscribed = false;
+        state.listeners.clear();
+    }
+
+    fn characteristic(uuid: Uuid, properties: CharPropFlags) -> Characteristic {
+        Characteristic {
+            uuid,
+            service_uuid: GAN_SERVICE_UUID,
+            properties,
+        }
+    }
+
+    fn check_connected(&self) -> btleplug::Result<()> {
+        if self.state.lock().unwrap().connected {
+            Ok(())
+        } else {
+            Err(btleplug::Error::NotConnected)
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn is_connected(&self) -> impl Future<Output = btleplug::Result<bool>> + Send {
+        let connected = self.state.lock().unwrap().connected;
+        async move { Ok(connected) }
+    }
+
+    fn connect(&self) -> impl Future<Output = btleplug::Result<()>> + Send {
+        let mut state = self.state.lock().unwrap();
+        let stalled = state.stalled > 0;
+        if stalled {
+            state.stalled -= 1;
+        } else {
+            state.connected = true;
+        }
+        async move {
+            if stalled {
+                future::pending::<()>().await;
+            }
+            Ok(())
+        }
+    }
+
+    fn disconnect(&self) -> impl Future<Output = btleplug::Result<()>> + Send {
+        self.drop_connection();
+        async { Ok(()) }
+    }
+
+    fn discover_services(&self) -> impl Future<Output = btleplug::Result<()>> + Send {
+        let res = self.check_connected();
+        async move { res }
+    }
+
+    fn characteristics(&self) -> BTreeSet<Characteristic> {
+        BTreeSet::from([
+            Self::characteristic(GanCubeV2Services::REQUEST_UUID, CharPropFlags::WRITE),
+            Self::characteristic(GanCubeV2Services::RESPONSE_UUID, CharPropFlags::NOTIFY),
+        ])
+    }
+
+    fn rssi(&self) -> impl Future<Output = btleplug::Result<Option<i16>>> + Send {
+        let rssi = self.state.lock().unwrap().rssi;
+        async move { Ok(rssi) }
+    }
+
+    fn write<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+        data: &'a [u8],
+        _write_type: WriteType,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a {
+        let res = self.check_connected().map(|_| {
+            if characteristic.uuid == GanCubeV2Services::REQUEST_UUID {
+                self.state.lock().unwrap().written.push(data.to_vec());
+            }
+        });
+        async move { res }
+    }
+
+    fn read<'a>(
+        &'a self,
+        _characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<Vec<u8>>> + Send + 'a {
+        let res = self.check_connected().map(|_| vec![]);
+        async move { res }
+    }
+
+    fn subscribe<'a>(
+        &'a self,
+        _characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a {
+        let res = self.check_connected();
+        if res.is_ok() {
+            self.state.lock().unwrap().subscribed = true;
+        }
+        async move { res }
+    }
+
+    fn unsubscribe<'a>(
+        &'a self,
+        _characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a {
+        let res = self.check_connected();
+        if res.is_ok() {
+            self.state.lock().unwrap().subscribed = false;
+        }
+        async move { res }
+    }
+
+    fn notifications(&self) -> impl Future<Output = btleplug::Result<NotificationStream>> + Send {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.state.lock().unwrap().listeners.push(sender);
+        let notifications = stream::unfold(receiver, |mut receiver| async move {
+            let notification = receiver.recv().await?;
+            Some((notification, receiver))
+        });
+        async move { Ok(Box::pin(notifications) as NotificationStream) }
+    }
+}
+
+// the last 7 moves reported by the cube, the latest first, counted from a state of count 0
+#[derive(Default)]
+pub struct MoveWindow {
+    count: u8,
+    moves: [Option<CubeMove>; 7],
+    times: [Duration; 7],
+}
+
+impl MoveWindow {
+    pub fn turn(&mut self, mv: CubeMove) -> ResponseMessage {
+        self.turn_after(mv, Duration::ZERO)
+    }
+
+    // the move made the pause after the previous one
+    pub fn turn_after(&mut self, mv: CubeMove, pause: Duration) -> ResponseMessage {
+        self.count = self.count.wrapping_add(1);
+        self.moves.rotate_right(1);
+        self.times.rotate_right(1);
+        self.moves[0] = Some(mv);
+        self.times[0] = pause;
+        ResponseMessage::Moves {
+            count: self.count,
+            moves: self.moves,
+            times: self.times,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::bluetooth::gancubev2::MessageFilter;
+    use crate::cube::{CubeMove, CubeState};
+
+    async fn connect(mock: &MockTransport) -> super::super::gancubev2::GanCubeV2<MockTransport> {
+        mock.builder().connect().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn handler_receives_canned_messages() {
+        let mock = MockTransport::default();
+        let gancube = connect(&mock).await;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let handle = gancube
+            .register_handler(Box::new(move |msg| {
+                let _ = sender.send(msg);
+            }))
+            .await
+            .unwrap();
+        gancube.subscribe_response().await.unwrap();
+
+        let messages = [
+            ResponseMessage::Battery {
+                charging: true,
+                percentage: 87,
+            },
+            ResponseMessage::Moves {
+                count: 42,
+                moves: [
+                    Some(CubeMove::R),
+                    Some(CubeMove::Up),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                ],
+                times: [Duration::from_millis(120); 7],
+            },
+            ResponseMessage::State {
+                count: 42,
+                state: Some(CubeState::default()),
+            },
+            ResponseMessage::Disconnect,
+        ];
+        for message in messages {
+            mock.notify_message(message);
+        }
+
+        handle.await.unwrap();
+        for message in messages {
+            assert_eq!(receiver.recv().await, Some(message));
+        }
+    }
+
+    #[tokio::test]
+    async fn short_frames_are_decoded() {
+        let mock = MockTransport::default();
+        let gancube = connect(&mock).await;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let handle = gancube
+            .register_handler(Box::new(move |msg| {
+                let _ = sender.send(msg);
+            }))
+            .await
+            .unwrap();
+        gancube.subscribe_response().await.unwrap();
+
+        // the frame without its last byte, encrypted as 19 bytes
+        let message = ResponseMessage::Battery {
+            charging: false,
+            percentage: 42,
+        };
+        let cipher = MockTransport::cipher();
+        let mut frame = message.encode(&cipher);
+        cipher.decrypt(&mut frame);
+        let mut short = frame[..19].to_vec();
+        cipher.encrypt(&mut short);
+        mock.notify(GanCubeV2Services::RESPONSE_UUID, short);
+        mock.notify_message(ResponseMessage::Disconnect);
+
+        handle.await.unwrap();
+        assert_eq!(receiver.recv().await, Some(message));
+    }
+
+    #[tokio::test]
+    async fn errors_are_counted() {
+        let mock = MockTransport::default();
+        let gancube = connect(&mock).await;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let handle = gancube
+            .register_handler_with_errors(
+                Box::new(|_| {}),
+                Box::new(move |err| {
+                    let _ = sender.send(err.to_string());
+                }),
+            )
+            .await
+            .unwrap();
+        gancube.subscribe_response().await.unwrap();
+
+        mock.notify(GanCubeV2Services::RESPONSE_UUID, vec![0; 18]);
+        mock.notify(GanCubeV2Services::REQUEST_UUID, vec![0; 20]);
+        mock.notify_message(ResponseMessage::Disconnect);
+
+        handle.await.unwrap();
+        assert!(receiver.recv().await.is_some());
+        assert!(receiver.recv().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn gyroscope_messages_are_filtered() {
+        let mock = MockTransport::default();
+        let gancube = connect(&mock).await;
+        gancube.message_filter().set_gyroscope(false);
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut handle = gancube
+            .register_handler(Box::new(move |msg| {
+                let _ = sender.send(msg);
+            }))
+            .await
+            .unwrap();
+        gancube.subscribe_response().await.unwrap();
+
+        let gyroscope = ResponseMessage::Gyroscope {
+            q1: (1.0, 0.0, 0.0, 0.0),
+            q1p: (0.0, 0.0, 0.0),
+            q2: (1.0, 0.0, 0.0, 0.0),
+            q2p: (0.0, 0.0, 0.0),
+        };
+        mock.notify_message(gyroscope);
+        mock.notify_message(ResponseMessage::Disconnect);
+
+        (&mut handle).await.unwrap();
+        assert_eq!(receiver.recv().await, Some(ResponseMessage::Disconnect));
+        assert_eq!(handle.stats().dropped, 1);
+
+        // thinned out to 10 messages per second
+        let filter = MessageFilter::new(Some(10.0));
+        let now = Instant::now();
+        let accepted = (0..10)
+            .map(|i| filter.accepts(&gyroscope, now + Duration::from_millis(i * 30)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accepted,
+            [true, false, false, false, true, false, false, false, true, false]
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_are_encrypted() {
+        let mock = MockTransport::default();
+        let gancube = connect(&mock).await;
+
+        gancube.request_battery_state().await.unwrap();
+        gancube.request_cube_state().await.unwrap();
+
+        let cipher = MockTransport::cipher();
+        let types = mock
+            .written()
+            .into_iter()
+            .map(|frame| {
+                let mut frame = <[u8; 20]>::try_from(frame).unwrap();
+                cipher.decrypt(&mut frame);
+                frame[0]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(types, [0x09, 0x04]);
+    }
+
+    #[tokio::test]
+    async fn reconnect_after_connection_lost() {
+        let mock = MockTransport::default();
+        let gancube = connect(&mock).await;
+        let handle = gancube.register_handler(Box::new(|_| {})).await.unwrap();
+        gancube.subscribe_response().await.unwrap();
+
+        mock.drop_connection();
+        handle.await.unwrap();
+        assert!(gancube.request_cube_state().await.is_err());
+
+        let gancube = connect(&mock).await;
+        assert!(gancube.request_cube_state().await.is_ok());
+    }
+}
@@ -1 +1,80 @@
-pub mod gancubev2;
\ No newline at end of file
+pub mod connect;
+pub mod gancubev2;
+#[cfg(test)]
+pub mod mock;
+pub mod quirks;
+pub mod scan;
+pub mod sequencer;
+pub mod session;
+pub mod transport;
+
+use btleplug::{
+    api::{Central, Manager as _},
+    platform,
+};
+
+use self::{
+    connect::{ConnectError, Connector},
+    gancubev2::{GanCubeV2, GanCubeV2Builder},
+    scan::{ScanError, Scanner},
+};
+use crate::{
+    config,
+    i18n::{tr, Message},
+    shutdown::Shutdown,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdapterError {
+    #[error("no bluetooth adapter found")]
+    NotFound,
+    #[error("something wrong with the bluetooth adapter")]
+    Bluetooth(#[from] btleplug::Error),
+}
+
+// the first adapter accepted by the config
+pub async fn select_adapter<A: Central>(adapters: Vec<A>) -> Result<A, AdapterError> {
+    for adapter in adapters {
+        if config::get()
+            .device
+            .accepts_adapter(&adapter.adapter_info().await?)
+        {
+            return Ok(adapter);
+        }
+    }
+    Err(AdapterError::NotFound)
+}
+
+// the adapter accepted by the config among the adapters of this platform
+pub async fn open_adapter() -> Result<platform::Adapter, AdapterError> {
+    let manager = platform::Manager::new().await?;
+    select_adapter(manager.adapters().await?).await
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CubeError {
+    #[error(transparent)]
+    Adapter(#[from] AdapterError),
+    #[error(transparent)]
+    Scan(#[from] ScanError),
+    #[error(transparent)]
+    Connect(#[from] ConnectError),
+}
+
+// scan for a GANCube with the configured adapter
+pub async fn find_cube(
+    shutdown: &Shutdown,
+) -> Result<GanCubeV2Builder<platform::Peripheral>, CubeError> {
+    let adapter = open_adapter().await?;
+    Ok(Scanner::new(shutdown.clone()).scan_one(&adapter).await?)
+}
+
+// scan for a GANCube with the configured adapter and connect to it
+pub async fn connect_cube(
+    shutdown: Shutdown,
+) -> Result<GanCubeV2<platform::Peripheral>, CubeError> {
+    let builder = find_cube(&shutdown).await?;
+
+    println!("{}", tr(Message::Connecting, &[]));
+    Ok(Connector::new(shutdown).connect(&builder).await?)
+}
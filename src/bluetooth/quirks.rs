@@ -0,0 +1,157 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::config::QuirksConfig;
+
+// the details of the protocol which differ between firmwares
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ProtocolQuirks {
+    // the unit of the times between moves
+    pub timestamp_unit: Duration,
+    // the sent axes of the gyroscope taken as the x, y and z axes
+    pub gyro_axes: [usize; 3],
+    // the width of the move count, at most 8 bits
+    pub move_count_bits: usize,
+}
+
+impl Default for ProtocolQuirks {
+    fn default() -> Self {
+        ProtocolQuirks {
+            timestamp_unit: Duration::from_millis(1),
+            gyro_axes: [0, 1, 2],
+            move_count_bits: 8,
+        }
+    }
+}
+
+// the firmware reported by the hardware info response
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Firmware {
+    pub name: String,
+    pub hardware: (u8, u8),
+    pub software: (u8, u8),
+}
+
+// the order of the axes such as `yxz`, as the sent axis of each of x, y and z
+fn parse_axes(order: &str) -> Option<[usize; 3]> {
+    let axes = ['x', 'y', 'z'].map(|axis| order.find(axis));
+    let axes = [axes[0]?, axes[1]?, axes[2]?];
+    (order.len() == 3).then_some(axes)
+}
+
+impl QuirksConfig {
+    fn matches(&self, firmware: &Firmware) -> bool {
+        let software = format!("{}.{}", firmware.software.0, firmware.software.1);
+        (self.name.as_ref()).is_none_or(|name| firmware.name.starts_with(name.as_str()))
+            && (self.software.as_ref()).is_none_or(|version| *version == software)
+    }
+
+    // invalid values are ignored, keeping the protocol of GAN
+    fn apply(&self, mut quirks: ProtocolQuirks) -> ProtocolQuirks {
+        if let Some(micros) = self.timestamp_unit {
+            quirks.timestamp_unit = Duration::from_micros(micros);
+        }
+        if let Some(axes) = self.gyro_axes.as_deref().and_then(parse_axes) {
+            quirks.gyro_axes = axes;
+        }
+        if let Some(bits @ 1..=8) = self.move_count_bits {
+            quirks.move_count_bits = bits;
+        }
+        quirks
+    }
+}
+
+// the quirks of the first entry of the table matching the firmware
+pub fn lookup(firmware: &Firmware, table: &[QuirksConfig]) -> ProtocolQuirks {
+    table
+        .iter()
+        .find(|entry| entry.matches(firmware))
+        .map_or(ProtocolQuirks::default(), |entry| {
+            entry.apply(ProtocolQuirks::default())
+        })
+}
+
+// the quirks shared with the notification task, which updates them when the hardware info arrives
+#[derive(Clone, Default)]
+pub struct SharedQuirks(Arc<Mutex<ProtocolQuirks>>);
+
+impl SharedQuirks {
+    pub fn get(&self) -> ProtocolQuirks {
+        self.0.lock().map(|quirks| *quirks).unwrap_or_default()
+    }
+
+    pub fn set(&self, quirks: ProtocolQuirks) {
+        if let Ok(mut shared) = self.0.lock() {
+            *shared = quirks;
+        }
+    }
+}
+
+// widen the move counts of fewer bits to 8 bits, as the consumers compare them modulo 256
+#[derive(Default)]
+pub struct CountWidener {
+    last: Option<u8>,
+}
+
+impl CountWidener {
+    pub fn widen(&mut self, count: u8, bits: usize) -> u8 {
+        if bits >= 8 {
+            return count;
+        }
+        let modulus = 1u16 << bits;
+        let widened = match self.last {
+            Some(last) => {
+                let diff = (count as u16 + modulus - last as u16 % modulus) % modulus;
+                last.wrapping_add(diff as u8)
+            }
+            None => count,
+        };
+        self.last = Some(widened);
+        widened
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quirks_are_looked_up_by_firmware() {
+        let firmware = Firmware {
+            name: "GANi3".to_string(),
+            hardware: (0, 1),
+            software: (7, 76),
+        };
+        let entry = |name: &str, software: &str| QuirksConfig {
+            name: Some(name.to_string()),
+            software: Some(software.to_string()),
+            timestamp_unit: Some(10_000),
+            gyro_axes: Some("yxz".to_string()),
+            move_count_bits: Some(7),
+        };
+        assert_eq!(lookup(&firmware, &[]), ProtocolQuirks::default());
+        assert_eq!(
+            lookup(&firmware, &[entry("GANi3", "7.75")]),
+            ProtocolQuirks::default()
+        );
+        assert_eq!(
+            lookup(&firmware, &[entry("MG", "7.76"), entry("GAN", "7.76")]),
+            ProtocolQuirks {
+                timestamp_unit: Duration::from_millis(10),
+                gyro_axes: [1, 0, 2],
+                move_count_bits: 7,
+            }
+        );
+        assert_eq!(parse_axes("xzz"), None);
+    }
+
+    #[test]
+    fn narrow_counts_are_widened() {
+        let mut widener = CountWidener::default();
+        let counts = [126, 127, 0, 1, 5].map(|count| widener.widen(count, 7));
+        assert_eq!(counts, [126, 127, 128, 129, 133]);
+        assert_eq!(widener.widen(42, 8), 42);
+    }
+}
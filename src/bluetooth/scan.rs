@@ -0,0 +1,222 @@
+use std::{
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use btleplug::api::{Central, Peripheral, PeripheralProperties, ScanFilter};
+use tokio::time::sleep;
+use uuid::{uuid, Uuid};
+
+use super::{
+    gancubev2::{cipher::device_identifier, GanCubeV2Builder},
+    session::GanCubeV2Session,
+};
+use crate::{
+    config,
+    devices::{Devices, DevicesError},
+    shutdown::Shutdown,
+    signal,
+};
+
+// the primary service advertised by GANCubes
+pub const GAN_SERVICE_UUID: Uuid = uuid!("6e400001-b5a3-f393-e0a9-e50e24dc4179");
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScanError {
+    #[error("no GANCube found in {0:?}")]
+    Timeout(Duration),
+    #[error("something wrong with the bluetooth connection")]
+    Bluetooth(#[from] btleplug::Error),
+    #[error("cannot read the remembered cubes: {0}")]
+    Devices(#[from] DevicesError),
+    #[error("scanning is cancelled")]
+    Cancelled,
+}
+
+// advertised the GAN service, or the manufacturer data of GANCubes, or the name of older firmwares
+pub fn is_gancube(properties: &PeripheralProperties) -> bool {
+    properties.services.contains(&GAN_SERVICE_UUID)
+        || device_identifier(properties).is_ok()
+        || properties
+            .local_name
+            .as_ref()
+            .is_some_and(|name| name.starts_with("GAN"))
+}
+
+// scan for GANCubes until enough of them are found
+#[derive(Clone)]
+pub struct Scanner {
+    interval: Duration,
+    timeout: Option<Duration>,
+    // connect to the remembered cubes known by the adapter without scanning
+    remembered: bool,
+    shutdown: Shutdown,
+}
+
+impl Scanner {
+    pub fn new(shutdown: Shutdown) -> Self {
+        let timeout = config::get().device.scan_timeout;
+        Scanner {
+            interval: Duration::from_secs(1),
+            timeout: timeout.and_then(|secs| Duration::try_from_secs_f32(secs).ok()),
+            remembered: true,
+            shutdown,
+        }
+    }
+
+    pub fn with_remembered(mut self, remembered: bool) -> Self {
+        self.remembered = remembered;
+        self
+    }
+
+    pub fn filter() -> ScanFilter {
+        ScanFilter {
+            services: vec![GAN_SERVICE_UUID],
+        }
+    }
+
+    pub async fn find<A: Central>(
+        adapter: &A,
+    ) -> Result<Vec<GanCubeV2Builder<A::Peripheral>>, ScanError> {
+        let mut res = vec![];
+        for device in adapter.peripherals().await? {
+            let Some(properties) = device.properties().await? else {
+                continue;
+            };
+            let address = properties.address.to_string();
+            if is_gancube(&properties) && config::get().device.accepts_address(&address) {
+                res.push(GanCubeV2Builder { device, properties });
+            }
+        }
+        Ok(res)
+    }
+
+    // the remembered cubes already known by the adapter, in the order of the devices file
+    pub async fn find_remembered<A: Central>(
+        adapter: &A,
+    ) -> Result<Vec<GanCubeV2Builder<A::Peripheral>>, ScanError> {
+        let devices = Devices::remembered()?;
+        let mut res = vec![];
+        for device in adapter.peripherals().await? {
+            let Some(mut properties) = device.properties().await? else {
+                continue;
+            };
+            let address = properties.address.to_string();
+            if !config::get().device.accepts_address(&address) {
+                continue;
+            }
+            let Some(index) = devices.devices.iter().position(|d| d.matches(&address)) else {
+                continue;
+            };
+            devices.devices[index].restore(&mut properties);
+            res.push((index, GanCubeV2Builder { device, properties }));
+        }
+        res.sort_by_key(|(index, _)| *index);
+        Ok(res.into_iter().map(|(_, builder)| builder).collect())
+    }
+
+    async fn find_enough<A: Central>(
+        &self,
+        adapter: &A,
+        count: usize,
+    ) -> Result<Vec<GanCubeV2Builder<A::Peripheral>>, ScanError> {
+        if self.remembered {
+            let found = Self::find_remembered(adapter).await?;
+            if let Some(builders) = GanCubeV2Session::pick(found, count) {
+                return Ok(builders);
+            }
+        }
+
+        adapter.start_scan(Self::filter()).await?;
+        print!("scan devices");
+
+        let res = tokio::select! {
+            biased;
+            _ = self.shutdown.wait() => Err(ScanError::Cancelled),
+            res = self.poll(adapter, count) => res,
+        };
+        println!();
+
+        adapter.stop_scan().await?;
+        res
+    }
+
+    async fn poll<A: Central>(
+        &self,
+        adapter: &A,
+        count: usize,
+    ) -> Result<Vec<GanCubeV2Builder<A::Peripheral>>, ScanError> {
+        let start = Instant::now();
+        loop {
+            print!(".");
+            let _ = stdout().flush();
+
+            let found = Self::find(adapter).await?;
+            if let Some(builders) = GanCubeV2Session::pick(found, count) {
+                return Ok(builders);
+            }
+            if let Some(timeout) = self.timeout {
+                if start.elapsed() >= timeout {
+                    return Err(ScanError::Timeout(timeout));
+                }
+            }
+
+            sleep(self.interval).await;
+        }
+    }
+
+    // the given number of different GANCubes, shown with their signal strength
+    pub async fn scan<A: Central>(
+        &self,
+        adapter: &A,
+        count: usize,
+    ) -> Result<Vec<GanCubeV2Builder<A::Peripheral>>, ScanError> {
+        let builders = self.find_enough(adapter, count).await?;
+        for builder in &builders {
+            let properties = &builder.properties;
+            println!(
+                "found {} [{}], {}",
+                properties.local_name.as_deref().unwrap_or("GANCube"),
+                properties.address,
+                signal::describe(properties.rssi),
+            );
+        }
+        Ok(builders)
+    }
+
+    pub async fn scan_one<A: Central>(
+        &self,
+        adapter: &A,
+    ) -> Result<GanCubeV2Builder<A::Peripheral>, ScanError> {
+        let mut builders = self.scan(adapter, 1).await?;
+        Ok(builders.remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth::mock::MockTransport;
+
+    #[test]
+    fn gancubes_are_found_by_service_or_manufacturer_data() {
+        assert!(is_gancube(&MockTransport::properties()));
+
+        let by_service = PeripheralProperties {
+            local_name: Some("cube".to_string()),
+            services: vec![GAN_SERVICE_UUID],
+            ..PeripheralProperties::default()
+        };
+        assert!(is_gancube(&by_service));
+
+        let mut by_manufacturer_data = MockTransport::properties();
+        by_manufacturer_data.local_name = None;
+        assert!(is_gancube(&by_manufacturer_data));
+
+        let mut other = MockTransport::properties();
+        other.local_name = Some("keyboard".to_string());
+        other.manufacturer_data.insert(1, vec![0x00; 4]);
+        assert!(!is_gancube(&other));
+        assert!(!is_gancube(&PeripheralProperties::default()));
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use super::gancubev2::ResponseMessage;
+use crate::cube::CubeMove;
+
+// a move with the pause before it
+type SequencedMove = (CubeMove, Duration);
+
+// the moves released by the sequencer, in the order of their counts
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sequenced {
+    pub moves: Vec<SequencedMove>,
+    // moves never received, which are skipped
+    pub lost: usize,
+}
+
+// order the moves of `Moves` messages by their counts; each message repeats the last 7 moves, so
+// re-delivered messages are dropped, and moves after a missing one wait for a late message
+// carrying it, until the gap times out
+#[derive(Clone, Debug, Default)]
+pub struct MoveSequencer {
+    // the count of the last released move
+    count: Option<u8>,
+    // the moves after the count, where `None` is not received yet
+    pending: VecDeque<Option<SequencedMove>>,
+    gap_since: Option<Instant>,
+}
+
+impl MoveSequencer {
+    pub const GAP_TIMEOUT: Duration = Duration::from_millis(100);
+    // counts more than this ahead are seen as re-delivered old messages
+    const MAX_AHEAD: usize = 128;
+
+    pub fn count(&self) -> Option<u8> {
+        self.count
+    }
+
+    // synchronize by the count of the cube state, dropping the pending moves
+    pub fn reset(&mut self, count: u8) {
+        self.count = Some(count);
+        self.pending.clear();
+        self.gap_since = None;
+    }
+
+    pub fn push(
+        &mut self,
+        count: u8,
+        moves: [Option<CubeMove>; 7],
+        times: [Duration; 7],
+        now: Instant,
+    ) -> Sequenced {
+        let Some(prev_count) = self.count else {
+            return Sequenced::default();
+        };
+        let ahead = count.wrapping_sub(prev_count) as usize;
+        if ahead == 0 || ahead > Self::MAX_AHEAD {
+            return self.flush(now);
+        }
+
+        if self.pending.len() < ahead {
+            self.pending.resize(ahead, None);
+        }
+        // the i-th move of the message is the move of `count - i`
+        for (i, (&mv, &pause)) in moves.iter().zip(&times).enumerate().take(ahead) {
+            let slot = &mut self.pending[ahead - 1 - i];
+            if slot.is_none() {
+                *slot = mv.map(|mv| (mv, pause));
+            }
+        }
+        self.flush(now)
+    }
+
+    // the cube state synchronizes the count, the moves are pushed, and other messages only release
+    // the moves waiting too long for a lost one
+    pub fn handle_message(&mut self, msg: ResponseMessage, now: Instant) -> Sequenced {
+        match msg {
+            ResponseMessage::State { count, state: _ } => {
+                self.reset(count);
+                Sequenced::default()
+            }
+            ResponseMessage::Moves {
+                count,
+                moves,
+                times,
+            } => self.push(count, moves, times, now),
+            _ => self.flush(now),
+        }
+    }
+
+    // release the received moves, and skip the gap if it waits too long
+    pub fn flush(&mut self, now: Instant) -> Sequenced {
+        let mut res = Sequenced::default();
+        loop {
+            match self.pending.front() {
+                None => {
+                    self.gap_since = None;
+                    break;
+                }
+                Some(Some(_)) => {
+                    self.gap_since = None;
+                }
+                Some(None) => {
+                    let since = *self.gap_since.get_or_insert(now);
+                    if now.duration_since(since) < Self::GAP_TIMEOUT {
+                        break;
+                    }
+                }
+            }
+            match self.pending.pop_front().flatten() {
+                Some(mv) => res.moves.push(mv),
+                None => res.lost += 1,
+            }
+            self.count = self.count.map(|count| count.wrapping_add(1));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_are_deduplicated_and_reordered() {
+        use CubeMove::*;
+
+        let now = Instant::now();
+        let mut sequencer = MoveSequencer::default();
+        // moves before the count is known are dropped
+        let moves = [Some(R), None, None, None, None, None, None];
+        let res = sequencer.push(1, moves, [Duration::ZERO; 7], now);
+        assert_eq!(res, Sequenced::default());
+
+        sequencer.reset(0);
+        // the i-th move of the message of `count` is the move of `count - i`
+        let mut push = |count: u8, moves: &[Option<CubeMove>]| {
+            let mut all = [None; 7];
+            all[..moves.len()].copy_from_slice(moves);
+            let res = sequencer.push(count, all, [Duration::ZERO; 7], now);
+            res.moves.iter().map(|&(mv, _)| mv).collect::<Vec<_>>()
+        };
+        assert_eq!(push(1, &[Some(R)]), vec![R]);
+        // re-delivered
+        assert_eq!(push(1, &[Some(R)]), vec![]);
+        // the message 3 comes before the message 2, but it repeats the move 2
+        assert_eq!(push(3, &[Some(F), Some(U), Some(R)]), vec![U, F]);
+        assert_eq!(push(2, &[Some(U), Some(R)]), vec![]);
+        // the move 4 is missing from the message 5, which waits for the late message 4
+        assert_eq!(push(5, &[Some(L), None, Some(F)]), vec![]);
+        assert_eq!(push(4, &[Some(B), Some(F)]), vec![B, L]);
+        // a gap not filled in time is skipped
+        assert_eq!(push(8, &[Some(R), None, None, Some(L)]), vec![]);
+        let res = sequencer.flush(now + MoveSequencer::GAP_TIMEOUT);
+        assert_eq!(res.moves, vec![(R, Duration::ZERO)]);
+        assert_eq!(res.lost, 2);
+        assert_eq!(sequencer.count(), Some(8));
+    }
+
+    #[test]
+    fn messages_synchronize_and_release_moves() {
+        use CubeMove::*;
+
+        let now = Instant::now();
+        let mut sequencer = MoveSequencer::default();
+        let state = ResponseMessage::State {
+            count: 3,
+            state: None,
+        };
+        assert_eq!(sequencer.handle_message(state, now), Sequenced::default());
+        assert_eq!(sequencer.count(), Some(3));
+
+        // the move 4 is lost, so the move 5 waits until another message comes after the timeout
+        let moves = ResponseMessage::Moves {
+            count: 5,
+            moves: [Some(U), None, None, None, None, None, None],
+            times: [Duration::ZERO; 7],
+        };
+        assert!(sequencer.handle_message(moves, now).moves.is_empty());
+        let battery = ResponseMessage::Battery {
+            charging: false,
+            percentage: 50,
+        };
+        let res = sequencer.handle_message(battery, now + MoveSequencer::GAP_TIMEOUT);
+        assert_eq!(res.moves, vec![(U, Duration::ZERO)]);
+        assert_eq!(res.lost, 1);
+    }
+}
@@ -0,0 +1,40 @@
+use btleplug::api::Peripheral;
+
+use super::{
+    connect::{ConnectError, Connector},
+    gancubev2::{GanCubeV2, GanCubeV2Builder},
+    transport::Transport,
+};
+
+// multiple GANCubes connected at the same time
+pub struct GanCubeV2Session<P: Transport> {
+    pub cubes: Vec<GanCubeV2<P>>,
+}
+
+impl<P: Peripheral> GanCubeV2Session<P> {
+    // pick the given number of different devices from the found devices
+    pub fn pick(found: Vec<GanCubeV2Builder<P>>, count: usize) -> Option<Vec<GanCubeV2Builder<P>>> {
+        let mut picked: Vec<GanCubeV2Builder<P>> = Vec::new();
+        for builder in found {
+            if picked.iter().all(|b| b.device.id() != builder.device.id()) {
+                picked.push(builder);
+            }
+        }
+        if picked.len() < count {
+            return None;
+        }
+        picked.truncate(count);
+        Some(picked)
+    }
+}
+
+impl<P: Transport> GanCubeV2Session<P> {
+    // connect to all devices at the same time
+    pub async fn connect(
+        builders: &[GanCubeV2Builder<P>],
+        connector: &Connector,
+    ) -> Result<Self, ConnectError> {
+        let cubes = connector.connect_all(builders).await?;
+        Ok(GanCubeV2Session { cubes })
+    }
+}
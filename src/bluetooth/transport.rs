@@ -0,0 +1,108 @@
+use std::{collections::BTreeSet, future::Future, pin::Pin};
+
+use btleplug::api::{Characteristic, Peripheral, ValueNotification, WriteType};
+use futures::Stream;
+
+pub type NotificationStream = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
+
+// the part of `Peripheral` used to communicate with a GANCube, which can be implemented without
+// the bluetooth stack
+pub trait Transport: Send + Sync + Clone {
+    fn is_connected(&self) -> impl Future<Output = btleplug::Result<bool>> + Send;
+
+    fn connect(&self) -> impl Future<Output = btleplug::Result<()>> + Send;
+
+    fn disconnect(&self) -> impl Future<Output = btleplug::Result<()>> + Send;
+
+    fn discover_services(&self) -> impl Future<Output = btleplug::Result<()>> + Send;
+
+    fn characteristics(&self) -> BTreeSet<Characteristic>;
+
+    fn rssi(&self) -> impl Future<Output = btleplug::Result<Option<i16>>> + Send;
+
+    fn write<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+        data: &'a [u8],
+        write_type: WriteType,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a;
+
+    fn read<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<Vec<u8>>> + Send + 'a;
+
+    fn subscribe<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a;
+
+    fn unsubscribe<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a;
+
+    fn notifications(&self) -> impl Future<Output = btleplug::Result<NotificationStream>> + Send;
+}
+
+impl<P: Peripheral> Transport for P {
+    fn is_connected(&self) -> impl Future<Output = btleplug::Result<bool>> + Send {
+        Peripheral::is_connected(self)
+    }
+
+    fn connect(&self) -> impl Future<Output = btleplug::Result<()>> + Send {
+        Peripheral::connect(self)
+    }
+
+    fn disconnect(&self) -> impl Future<Output = btleplug::Result<()>> + Send {
+        Peripheral::disconnect(self)
+    }
+
+    fn discover_services(&self) -> impl Future<Output = btleplug::Result<()>> + Send {
+        Peripheral::discover_services(self)
+    }
+
+    fn characteristics(&self) -> BTreeSet<Characteristic> {
+        Peripheral::characteristics(self)
+    }
+
+    async fn rssi(&self) -> btleplug::Result<Option<i16>> {
+        Ok(Peripheral::properties(self)
+            .await?
+            .and_then(|props| props.rssi))
+    }
+
+    fn write<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+        data: &'a [u8],
+        write_type: WriteType,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a {
+        Peripheral::write(self, characteristic, data, write_type)
+    }
+
+    fn read<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<Vec<u8>>> + Send + 'a {
+        Peripheral::read(self, characteristic)
+    }
+
+    fn subscribe<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a {
+        Peripheral::subscribe(self, characteristic)
+    }
+
+    fn unsubscribe<'a>(
+        &'a self,
+        characteristic: &'a Characteristic,
+    ) -> impl Future<Output = btleplug::Result<()>> + Send + 'a {
+        Peripheral::unsubscribe(self, characteristic)
+    }
+
+    fn notifications(&self) -> impl Future<Output = btleplug::Result<NotificationStream>> + Send {
+        Peripheral::notifications(self)
+    }
+}
@@ -0,0 +1,28 @@
+use tokio::sync::broadcast;
+
+use crate::daemon::DaemonEvent;
+
+#[cfg(feature = "dbus")]
+mod service;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BusError {
+    #[cfg(not(feature = "dbus"))]
+    #[error("built without D-Bus support, enable the feature `dbus`")]
+    Unsupported,
+    #[cfg(feature = "dbus")]
+    #[error("D-Bus error: {0}")]
+    Connection(String),
+}
+
+// publish the battery and the connection as properties, and the moves and the typing as signals,
+// until the events end
+#[cfg(feature = "dbus")]
+pub async fn publish(events: broadcast::Receiver<DaemonEvent>) -> Result<(), BusError> {
+    service::publish(events).await
+}
+
+#[cfg(not(feature = "dbus"))]
+pub async fn publish(_events: broadcast::Receiver<DaemonEvent>) -> Result<(), BusError> {
+    Err(BusError::Unsupported)
+}
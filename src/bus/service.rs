@@ -0,0 +1,164 @@
+use tokio::sync::broadcast;
+
+use super::BusError;
+use crate::daemon::DaemonEvent;
+
+// the properties published on the bus
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CubeStatus {
+    pub connected: bool,
+    pub charging: bool,
+    pub battery: u32,
+}
+
+impl CubeStatus {
+    pub fn new() -> Self {
+        CubeStatus {
+            connected: true,
+            charging: false,
+            battery: 0,
+        }
+    }
+
+    // tell whether the properties are changed by the event
+    pub fn update(&mut self, event: &DaemonEvent) -> bool {
+        let prev = *self;
+        match *event {
+            DaemonEvent::Battery {
+                charging,
+                percentage,
+            } => {
+                self.charging = charging;
+                self.battery = percentage;
+            }
+            DaemonEvent::Disconnect => self.connected = false,
+            // the daemon reconnects to the cube after it is lost
+            DaemonEvent::State { .. } | DaemonEvent::Moves { .. } => self.connected = true,
+            _ => {}
+        }
+        *self != prev
+    }
+}
+
+pub async fn publish(mut events: broadcast::Receiver<DaemonEvent>) -> Result<(), BusError> {
+    use std::sync::{Arc, Mutex};
+
+    use dbus::{
+        arg::{PropMap, RefArg, Variant},
+        channel::{MatchingReceiver, Sender},
+        message::{MatchRule, SignalArgs},
+        nonblock::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged,
+        Message, Path,
+    };
+    use dbus_crossroads::{Crossroads, IfaceBuilder};
+
+    const BUS_NAME: &str = "org.cuboard";
+    const OBJECT_PATH: &str = "/org/cuboard/Cube";
+    const INTERFACE: &str = "org.cuboard.Cube";
+
+    type SharedStatus = Arc<Mutex<CubeStatus>>;
+
+    let error = |err: dbus::Error| BusError::Connection(err.to_string());
+    let (resource, conn) = dbus_tokio::connection::new_session_sync().map_err(error)?;
+    tokio::spawn(async move {
+        let err = resource.await;
+        println!("lost connection to D-Bus: {}", err);
+    });
+
+    let status = Arc::new(Mutex::new(CubeStatus::new()));
+    let mut cr = Crossroads::new();
+    let iface = cr.register(INTERFACE, |b: &mut IfaceBuilder<SharedStatus>| {
+        let get = |status: &SharedStatus| *status.lock().unwrap();
+        b.property("Connected")
+            .get(move |_, status| Ok(get(status).connected));
+        b.property("Charging")
+            .get(move |_, status| Ok(get(status).charging));
+        b.property("Battery")
+            .get(move |_, status| Ok(get(status).battery));
+        b.signal::<(Vec<String>,), _>("Moves", ("moves",));
+        b.signal::<(String, String), _>("Input", ("accept", "buffer"));
+        b.signal::<(String,), _>("Finish", ("text",));
+        b.signal::<(), _>("Cancel", ());
+    });
+    cr.insert(OBJECT_PATH, &[iface], Arc::clone(&status));
+    conn.request_name(BUS_NAME, false, true, false)
+        .await
+        .map_err(error)?;
+    conn.start_receive(
+        MatchRule::new_method_call(),
+        Box::new(move |msg, conn| {
+            let _ = cr.handle_message(msg, conn);
+            true
+        }),
+    );
+
+    let path = Path::from(OBJECT_PATH);
+    let signal = |name: &str| Message::signal(&path, &INTERFACE.into(), &name.into());
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+
+        let changed = {
+            let mut status = status.lock().unwrap();
+            status.update(&event).then_some(*status)
+        };
+        if let Some(status) = changed {
+            let mut changed_properties = PropMap::new();
+            let mut put = |name: &str, value: Box<dyn RefArg>| {
+                changed_properties.insert(name.to_string(), Variant(value));
+            };
+            put("Connected", Box::new(status.connected));
+            put("Charging", Box::new(status.charging));
+            put("Battery", Box::new(status.battery));
+            let changed = PropertiesPropertiesChanged {
+                interface_name: INTERFACE.to_string(),
+                changed_properties,
+                invalidated_properties: Vec::new(),
+            };
+            let _ = conn.send(changed.to_emit_message(&path));
+        }
+
+        let msg = match event {
+            DaemonEvent::Moves { moves } => signal("Moves").append1(moves),
+            DaemonEvent::Input { accept, buffer } => signal("Input").append2(accept, buffer),
+            DaemonEvent::Finish { text } => signal("Finish").append1(text),
+            DaemonEvent::Cancel => signal("Cancel"),
+            _ => continue,
+        };
+        let _ = conn.send(msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_follows_battery_and_connection() {
+        let mut status = CubeStatus::new();
+        let battery = DaemonEvent::Battery {
+            charging: true,
+            percentage: 80,
+        };
+        assert!(status.update(&battery));
+        assert!(!status.update(&battery));
+        assert!(!status.update(&DaemonEvent::Cancel));
+        assert!(status.update(&DaemonEvent::Disconnect));
+        assert_eq!(
+            status,
+            CubeStatus {
+                connected: false,
+                charging: true,
+                battery: 80,
+            }
+        );
+        let moves = DaemonEvent::Moves {
+            moves: vec!["U".to_string()],
+        };
+        assert!(status.update(&moves));
+        assert!(status.connected);
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+    mem,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::sleep;
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::CubeOrientationFrame},
+    config::{self, Config, GestureConfig},
+    cuboard::{gyro_sample, torque_diversity, turning_deviation, BUFFER_SIZE},
+    shutdown::Shutdown,
+};
+
+type GyroSample = CubeOrientationFrame;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CalibrationError {
+    #[error("no gyroscope message received")]
+    NoSamples,
+    #[error("shaking cannot be told apart from holding still, shake harder and try again")]
+    WeakShaking,
+    #[error("no turning around found in attempt {0}, turn the cube a full round and try again")]
+    NoTurning(usize),
+}
+
+const STILL_DURATION: Duration = Duration::from_secs(3);
+const SHAKE_DURATION: Duration = Duration::from_secs(3);
+const TURN_DURATION: Duration = Duration::from_secs(4);
+const TURN_ATTEMPTS: usize = 3;
+
+// the same statistics as the recognizer, for every window of samples
+fn window_torques(samples: &[GyroSample]) -> Vec<f32> {
+    samples
+        .windows(BUFFER_SIZE)
+        .map(|window| torque_diversity(&window.iter().map(|s| s.angular).collect::<Vec<_>>()))
+        .collect()
+}
+
+fn window_deviations(samples: &[GyroSample]) -> Vec<f32> {
+    // the recognizer sees the filtered orientations
+    let mut filter = config::get().gesture.filter.filter();
+    let orientations = samples
+        .iter()
+        .map(|sample| {
+            filter.put(sample.orientation);
+            filter.get()
+        })
+        .collect::<Vec<_>>();
+    orientations
+        .windows(BUFFER_SIZE)
+        .map(|window| turning_deviation(window[0], window[BUFFER_SIZE - 1]))
+        .collect()
+}
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+// halfway between the strongest torque while holding still and the typical torque while shaking
+fn calibrate_shaking(still: &[GyroSample], shake: &[GyroSample]) -> Result<f32, CalibrationError> {
+    let still = window_torques(still)
+        .into_iter()
+        .max_by(f32::total_cmp)
+        .ok_or(CalibrationError::NoSamples)?;
+    let shake = median(window_torques(shake)).ok_or(CalibrationError::NoSamples)?;
+    if shake <= still {
+        return Err(CalibrationError::WeakShaking);
+    }
+    Ok((still + shake) / 2.0)
+}
+
+// loose enough to accept the sloppiest attempt, with some margin
+fn calibrate_turning(attempts: &[Vec<GyroSample>]) -> Result<f32, CalibrationError> {
+    const MARGIN: f32 = 1.5;
+    const MIN_TOLERANCE: f32 = 0.05;
+    const MAX_TOLERANCE: f32 = 0.3;
+
+    let mut worst: f32 = 0.0;
+    for (i, samples) in attempts.iter().enumerate() {
+        let best = window_deviations(samples)
+            .into_iter()
+            .min_by(f32::total_cmp)
+            .ok_or(CalibrationError::NoSamples)?;
+        if best > MAX_TOLERANCE {
+            return Err(CalibrationError::NoTurning(i + 1));
+        }
+        worst = worst.max(best);
+    }
+    Ok((worst * MARGIN).clamp(MIN_TOLERANCE, MAX_TOLERANCE))
+}
+
+fn calibrate(
+    still: &[GyroSample],
+    shake: &[GyroSample],
+    turns: &[Vec<GyroSample>],
+) -> Result<GestureConfig, CalibrationError> {
+    Ok(GestureConfig {
+        shaking_torque: calibrate_shaking(still, shake)?,
+        turning_tolerance: calibrate_turning(turns)?,
+        ..GestureConfig::default()
+    })
+}
+
+pub async fn run(config_path: PathBuf, shutdown: Shutdown) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("connected! follow the instructions to calibrate the gestures");
+    println!();
+
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    let samples_handler = Arc::clone(&samples);
+    let handle = gancube
+        .register_handler(Box::new(move |msg| {
+            let Some(sample) = gyro_sample(msg) else {
+                return;
+            };
+            if let Ok(mut samples) = samples_handler.lock() {
+                samples.push(sample);
+            }
+        }))
+        .await?;
+
+    gancube.subscribe_response().await?;
+
+    // collect samples during the countdown, or nothing if shutdown is requested
+    let record = |instruction: String, duration: Duration| {
+        let samples = Arc::clone(&samples);
+        let shutdown = shutdown.clone();
+        async move {
+            println!("{}", instruction);
+            let _ = mem::take(&mut *samples.lock().ok()?);
+            for remain in (1..=duration.as_secs()).rev() {
+                print!("\r{}s ", remain);
+                let _ = stdout().flush();
+                tokio::select! {
+                    _ = sleep(Duration::from_secs(1)) => {}
+                    _ = shutdown.wait() => return None,
+                }
+            }
+            println!("\rdone");
+            Some(mem::take(&mut *samples.lock().ok()?))
+        }
+    };
+
+    let result: Option<Result<GestureConfig, CalibrationError>> = async {
+        let still = record("hold the cube still".to_string(), STILL_DURATION).await?;
+        let shake = record("shake the cube".to_string(), SHAKE_DURATION).await?;
+        let mut turns = Vec::new();
+        for i in 1..=TURN_ATTEMPTS {
+            let instruction = format!("turn the cube around once ({}/{})", i, TURN_ATTEMPTS);
+            turns.push(record(instruction, TURN_DURATION).await?);
+        }
+        Some(calibrate(&still, &shake, &turns))
+    }
+    .await;
+
+    shutdown.close(&gancube).await?;
+    handle.abort();
+
+    let Some(gesture) = result.transpose()? else {
+        return Ok(());
+    };
+    println!();
+    println!("shaking_torque = {:.3}", gesture.shaking_torque);
+    println!("turning_tolerance = {:.3}", gesture.turning_tolerance);
+
+    // keep the other settings in the config file, rather than the ones overridden by flags
+    let mut config = Config::load(&config_path)?;
+    config.gesture.shaking_torque = gesture.shaking_torque;
+    config.gesture.turning_tolerance = gesture.turning_tolerance;
+    config.save(&config_path)?;
+    println!("saved to {}", config_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use nalgebra::{UnitQuaternion, Vector3};
+
+    use super::*;
+
+    fn turning(turns: f32) -> Vec<GyroSample> {
+        (0..BUFFER_SIZE * 2)
+            .map(|i| {
+                let angle = 2.0 * PI * turns * i as f32 / (BUFFER_SIZE - 1) as f32;
+                let orientation = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), angle);
+                CubeOrientationFrame {
+                    orientation,
+                    angular: Vector3::new(0.0, 0.0, 1.0),
+                }
+            })
+            .collect()
+    }
+
+    fn shaking(amplitude: f32) -> Vec<GyroSample> {
+        (0..BUFFER_SIZE * 2)
+            .map(|i| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                CubeOrientationFrame {
+                    orientation: UnitQuaternion::identity(),
+                    angular: Vector3::new(sign * amplitude, 0.0, 0.0),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shaking_threshold_is_between_still_and_shaking() {
+        let torque = calibrate_shaking(&shaking(0.05), &shaking(1.0)).unwrap();
+        assert!(0.05 < torque && torque < 1.0, "{}", torque);
+        assert!(matches!(
+            calibrate_shaking(&shaking(1.0), &shaking(0.05)),
+            Err(CalibrationError::WeakShaking)
+        ));
+    }
+
+    #[test]
+    fn turning_tolerance_accepts_every_attempt() {
+        let tolerance = calibrate_turning(&[turning(1.0), turning(0.9)]).unwrap();
+        assert!(tolerance >= 0.1, "{}", tolerance);
+        assert!(matches!(
+            calibrate_turning(&[turning(1.0), turning(0.25)]),
+            Err(CalibrationError::NoTurning(2))
+        ));
+    }
+}
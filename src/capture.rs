@@ -0,0 +1,163 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::bluetooth::{gancubev2::ResponseMessage, quirks::ProtocolQuirks};
+
+// a notification of the cube with the time since the capture started, written
+// as `SECS ENCRYPTED DECRYPTED` in hex, where the decrypted frame is `-` if it has a bad length
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedFrame {
+    pub time: Duration,
+    pub encrypted: Vec<u8>,
+    pub decrypted: Option<[u8; 20]>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+impl Display for CapturedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let decrypted = self.decrypted.map_or("-".to_string(), |data| hex(&data));
+        write!(
+            f,
+            "{:.6} {} {}",
+            self.time.as_secs_f64(),
+            hex(&self.encrypted),
+            decrypted
+        )
+    }
+}
+
+impl FromStr for CapturedFrame {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("invalid captured frame: {}", s);
+        let [time, encrypted, decrypted] = s.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(error());
+        };
+        let time = time.parse::<f64>().map_err(|_| error())?;
+        let decrypted = match decrypted {
+            "-" => None,
+            text => {
+                let data = parse_hex(text).ok_or_else(error)?;
+                Some(data.try_into().map_err(|_| error())?)
+            }
+        };
+        Ok(CapturedFrame {
+            time: Duration::try_from_secs_f64(time).map_err(|_| error())?,
+            encrypted: parse_hex(encrypted).ok_or_else(error)?,
+            decrypted,
+        })
+    }
+}
+
+// write the frames as they are received, so that nothing is lost if the cube misbehaves
+pub struct CaptureWriter {
+    file: fs::File,
+}
+
+impl CaptureWriter {
+    // start the file with the header as `#` comments
+    pub fn create(path: impl AsRef<Path>, header: &str) -> io::Result<Self> {
+        let mut file = fs::File::create(path)?;
+        for line in header.lines() {
+            writeln!(file, "# {}", line)?;
+        }
+        Ok(CaptureWriter { file })
+    }
+
+    pub fn write(&mut self, frame: &CapturedFrame) -> io::Result<()> {
+        writeln!(self.file, "{}", frame)
+    }
+}
+
+pub fn parse_capture(text: &str) -> Result<Vec<CapturedFrame>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+// the decrypted frame of the capture, and its message or why it cannot be decoded
+pub fn describe(frame: &CapturedFrame) -> String {
+    let Some(data) = frame.decrypted else {
+        return format!("bad message length: {}", frame.encrypted.len());
+    };
+    // the type of the message is the first 4 bits
+    let decoded = match ResponseMessage::decode_decrypted(data, &ProtocolQuirks::default()) {
+        Ok(message) => format!("{:?}", message),
+        Err(_) => format!("unknown type 0x{:X}", data[0] >> 4),
+    };
+    format!("{} {}", hex(&data), decoded)
+}
+
+// run the codec over the captured frames again
+pub fn decode(path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    for frame in parse_capture(&fs::read_to_string(path)?)? {
+        println!("{:>12.6} {}", frame.time.as_secs_f64(), describe(&frame));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_written_and_decoded() {
+        let mut battery = [0; 20];
+        battery[0] = 0x90;
+        battery[1] = 0x06;
+        let mut unknown = [0; 20];
+        unknown[..4].copy_from_slice(&[0x60, 0x00, 0x01, 0x07]);
+        let frames = vec![
+            CapturedFrame {
+                time: Duration::from_micros(1_500),
+                encrypted: vec![0xAB; 20],
+                decrypted: Some(battery),
+            },
+            CapturedFrame {
+                time: Duration::from_secs(2),
+                encrypted: vec![0xCD; 20],
+                decrypted: Some(unknown),
+            },
+            CapturedFrame {
+                time: Duration::from_secs(3),
+                encrypted: vec![0x01, 0x02],
+                decrypted: None,
+            },
+        ];
+        let text = frames
+            .iter()
+            .map(|frame| frame.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let text = format!("# GANMock\n{}\n", text);
+        assert_eq!(parse_capture(&text).unwrap(), frames);
+        assert!(parse_capture("0.1 ABC -").is_err());
+
+        assert!(describe(&frames[0]).contains("Battery"));
+        assert!(describe(&frames[1]).ends_with("unknown type 0x6"));
+        assert_eq!(describe(&frames[2]), "bad message length: 2");
+    }
+}
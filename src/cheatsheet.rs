@@ -0,0 +1,244 @@
+use crate::{
+    cube::CubeMove,
+    cuboard::{CuboardInput, CuboardKey, CuboardKeymap},
+    terminal::{cells, Cell},
+    theme,
+};
+
+const STYLED_TEMPLATE: &str = "
+     {B}  {B.3}  {/}     
+     {B}{B.2}   {B.0}{/}     
+     {B}  {B.1}  {/}     
+     {U}  {U.1}  {/}     
+     {U}{U.0}   {U.2}{/}     
+     {U}  {U.3}  {/}     
+{L}  {L.3}  {F}  {F.0}  {R}  {R.2}  {/}
+{L}{L.2}   {L.0}{F}{F.3}   {F.1}{R}{R.1}   {R.3}{/}
+{L}  {L.1}  {F}  {F.2}  {R}  {R.0}  {/}
+     {D}  {D.2}  {/}     
+     {D}{D.1}   {D.3}{/}     
+     {D}  {D.0}  {/}     
+";
+const STYLED_TEMPLATE_BAR: &str = "CHEAT SHEET:
+     double     |      single     |     single      |     double
+    clockwise   |     clockwise   |counter-clockwise|counter-clockwise
+----------------|-----------------|-----------------|-----------------
+";
+const SEPARATOR: &str = " | ";
+
+// columns of the whole sheet
+const SHEET_WIDTH: usize = 15 * 4 + SEPARATOR.len() * 3;
+
+const FACES: [CubeMove; 6] = [
+    CubeMove::U,
+    CubeMove::R,
+    CubeMove::F,
+    CubeMove::D,
+    CubeMove::L,
+    CubeMove::B,
+];
+
+fn key_symbol(key: &str) -> String {
+    key.replace('\n', "↵").replace(' ', "⌴")
+}
+
+fn face_style(side: CubeMove) -> String {
+    theme::get().faces[side as u8 as usize / 2].clone()
+}
+
+// the turns of each column of the sheet: double and single clockwise, single and double
+// counter-clockwise
+fn columns(keymap: &CuboardKeymap, side: CubeMove) -> [[&'static str; 4]; 4] {
+    [
+        keymap[1][side as u8 as usize],
+        keymap[0][side as u8 as usize],
+        keymap[0][side.rev() as u8 as usize],
+        keymap[1][side.rev() as u8 as usize],
+    ]
+}
+
+// the rows of a panel of the sheet, filled by the keys of the column
+fn panel(keymap: &CuboardKeymap, column: usize) -> Vec<Vec<Cell>> {
+    STYLED_TEMPLATE
+        .trim_matches('\n')
+        .split('\n')
+        .map(|line| {
+            let mut row = Vec::new();
+            let mut style = String::new();
+            let mut rest = line;
+            while !rest.is_empty() {
+                let Some(tag) = rest.strip_prefix('{').and_then(|tag| tag.split_once('}')) else {
+                    let mut chars = rest.chars();
+                    let c = chars.next().unwrap();
+                    row.push((c.to_string(), style.clone()));
+                    rest = chars.as_str();
+                    continue;
+                };
+                let (tag, after) = tag;
+                rest = after;
+                let side = |name: &str| FACES.into_iter().find(|m| m.to_string() == name);
+                match tag.split_once('.') {
+                    None if tag == "/" => style.clear(),
+                    None => style = side(tag).map_or(String::new(), face_style),
+                    Some((name, i)) => {
+                        let (Some(side), Ok(i)) = (side(name), i.parse::<usize>()) else {
+                            continue;
+                        };
+                        let key = columns(keymap, side)[column][i];
+                        row.push((key_symbol(key), style.clone()));
+                    }
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+// the four panels side by side
+fn sheet_rows(keymap: &CuboardKeymap) -> Vec<Vec<Cell>> {
+    let panels = (0..4)
+        .map(|column| panel(keymap, column))
+        .collect::<Vec<_>>();
+    (0..panels[0].len())
+        .map(|i| {
+            let mut row = Vec::new();
+            for (column, panel) in panels.iter().enumerate() {
+                if column > 0 {
+                    row.extend(cells(SEPARATOR, ""));
+                }
+                row.extend(panel[i].iter().cloned());
+            }
+            row
+        })
+        .collect()
+}
+
+fn to_text(row: &[Cell]) -> String {
+    let mut text = String::new();
+    let mut current = "";
+    for (g, style) in row {
+        if style != current {
+            text += "\x1b[m";
+            text += style;
+            current = style;
+        }
+        text += g;
+    }
+    if !current.is_empty() {
+        text += "\x1b[m";
+    }
+    text
+}
+
+pub fn make_cheatsheet(keymap: &CuboardKeymap) -> String {
+    STYLED_TEMPLATE_BAR.to_string()
+        + &sheet_rows(keymap)
+            .iter()
+            .map(|row| to_text(row))
+            .collect::<Vec<_>>()
+            .join("\n")
+}
+
+// the keys of a face, a row for each column of the sheet with the adjacent faces of the keys
+fn zoomed_rows(keymap: &CuboardKeymap, side: CubeMove) -> Vec<Vec<Cell>> {
+    const LABELS: [&str; 4] = ["double ", "single ", "single ", "double "];
+    let moves = [side, side, side.rev(), side.rev()];
+    let adjacents = (0..4)
+        .map(|i| {
+            FACES
+                .into_iter()
+                .find(|&adj| CuboardKey::num_of(side, adj) == Some(i))
+        })
+        .collect::<Vec<_>>();
+    columns(keymap, side)
+        .iter()
+        .enumerate()
+        .map(|(column, keys)| {
+            let mut row = cells(LABELS[column], "");
+            row.extend(cells(&format!("{:<3}", moves[column].to_string()), ""));
+            for (key, adj) in keys.iter().zip(&adjacents) {
+                row.extend(cells("  ", ""));
+                if let Some(adj) = adj {
+                    row.extend(cells(&format!(" {} ", adj), &face_style(*adj)));
+                }
+                row.extend(cells(&format!(" {}", key_symbol(key)), ""));
+            }
+            row
+        })
+        .collect()
+}
+
+// the cheatsheet shown above the line being typed, toggled by shaking the cube with nothing to
+// cancel; it pages to the keymap of the current layer, and zooms to the face of the key being
+// typed
+#[derive(Default)]
+pub struct CheatsheetWidget {
+    visible: bool,
+}
+
+impl CheatsheetWidget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn rows(&self, input: &CuboardInput, width: usize) -> Vec<Vec<Cell>> {
+        if !self.visible {
+            return Vec::new();
+        }
+        let layer = input.buffer.layer();
+        let keymap = input.keymap_of(layer);
+        let zoom = input.buffer.remains().first().map(|mv| mv.abs());
+        let status = &theme::get().status;
+        let mut header = format!("layer {}/{}", layer + 1, input.layers.len() + 1);
+        let mut rows = match zoom {
+            Some(side) => {
+                header += &format!(", face {}", side);
+                zoomed_rows(keymap, side)
+            }
+            // the last column is left empty, so that the cursor never wraps by itself
+            None if SHEET_WIDTH < width => sheet_rows(keymap),
+            None => {
+                header += ", start a key to zoom to its face";
+                Vec::new()
+            }
+        };
+        rows.insert(0, cells(&header, status));
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::cuboard::DEFAULT_KEYMAP;
+
+    fn text(row: &[Cell]) -> String {
+        row.iter().map(|(g, _)| g.as_str()).collect()
+    }
+
+    #[test]
+    fn cheatsheet_is_zoomed_to_the_face_of_the_key() {
+        let rows = sheet_rows(&DEFAULT_KEYMAP);
+        assert_eq!(rows.len(), 12);
+        assert!(rows.iter().all(|row| row.len() == SHEET_WIDTH));
+        assert_eq!(text(&rows[7]).matches('a').count(), 1);
+
+        let mut input = CuboardInput::new(DEFAULT_KEYMAP);
+        let mut widget = CheatsheetWidget::new();
+        assert!(widget.rows(&input, 80).is_empty());
+        widget.toggle();
+        assert_eq!(widget.rows(&input, 80).len(), 13);
+        assert_eq!(widget.rows(&input, 40).len(), 1);
+
+        input.input(&[(CubeMove::R, Duration::ZERO)]);
+        let rows = widget.rows(&input, 40);
+        assert_eq!(rows.len(), 5);
+        assert_eq!(text(&rows[2]), "single R     D  g   F  a   U  s   B  p");
+    }
+}
@@ -0,0 +1,600 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cuboard::InputMode,
+    hooks::HookKind,
+    i18n::Locale,
+    orientation::FilterStrategy,
+    solver::SolverBackend,
+    theme::{Color, Palette},
+    train::{Correction, HintLevel},
+    view::options::{CameraMode, FacePalette},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("cannot write config: {0}")]
+    Format(#[from] toml::ser::Error),
+    #[error("cannot find the config directory, set $HOME or $XDG_CONFIG_HOME")]
+    NoConfigDir,
+    #[error("invalid value of --{name}: {value}")]
+    Flag { name: String, value: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    // `builtin`, or the url of a kociemba server; before the tables, as toml requires
+    pub solver: SolverBackend,
+    pub device: DeviceConfig,
+    pub input: InputConfig,
+    pub trainer: TrainerConfig,
+    pub gesture: GestureConfig,
+    pub theme: ThemeConfig,
+    pub view: ViewConfig,
+    pub switch: SwitchConfig,
+    pub mouse: MouseConfig,
+    pub ui: UiConfig,
+    pub goal: GoalConfig,
+    // commands run on events, as `[[hooks]]` tables
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct DeviceConfig {
+    // connect only to the cube with this bluetooth address
+    pub address: Option<String>,
+    // use the first bluetooth adapter whose description contains this
+    pub adapter: Option<String>,
+    // give up scanning for cubes after this many seconds
+    pub scan_timeout: Option<f32>,
+    // give up a connection attempt after this many seconds, 10 by default
+    pub connect_timeout: Option<f32>,
+    // attempts to connect again after the first one fails, 3 by default
+    pub connect_retries: Option<usize>,
+    // handle at most this many gyroscope messages per second, all of them by default
+    pub gyro_rate: Option<f32>,
+    // compare the state of the cube with the moves every this many seconds, never by default
+    pub verify_interval: Option<f32>,
+    // the ciphers of clones not using the keys of GAN, as `[[device.ciphers]]` tables
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ciphers: Vec<CipherConfig>,
+    // the protocol details of firmwares differing from GAN, as `[[device.quirks]]` tables
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quirks: Vec<QuirksConfig>,
+}
+
+impl DeviceConfig {
+    pub fn accepts_address(&self, address: &str) -> bool {
+        self.address
+            .as_ref()
+            .is_none_or(|expected| expected.eq_ignore_ascii_case(address))
+    }
+
+    pub fn accepts_adapter(&self, info: &str) -> bool {
+        self.adapter
+            .as_ref()
+            .is_none_or(|expected| info.contains(expected.as_str()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CipherConfig {
+    // used for the cube with this bluetooth address, or for any cube
+    pub address: Option<String>,
+    // 16 bytes in hex
+    pub key: String,
+    pub iv: String,
+    // whether the key and the iv are salted by the device identifier, as GAN does
+    #[serde(default)]
+    pub salted: bool,
+}
+
+// the first entry matching the hardware info reported by the cube is used
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QuirksConfig {
+    // the hardware name starts with this
+    pub name: Option<String>,
+    // the software version, such as `7.76`
+    pub software: Option<String>,
+    // microseconds per unit of the times between moves, 1000 by default
+    pub timestamp_unit: Option<u64>,
+    // the order of the gyroscope axes as sent, such as `yxz`, `xyz` by default
+    pub gyro_axes: Option<String>,
+    // the width of the move count, 8 bits by default
+    pub move_count_bits: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct InputConfig {
+    pub keymap: Option<PathBuf>,
+    pub mode: InputMode,
+    // the chords of the steno mode, or the words of the t9 mode
+    pub dictionary: Option<PathBuf>,
+    // columns of the prompt line showing the turns of the current key
+    pub prompt_width: usize,
+    // how many times per second the terminal is redrawn at most, or 0 to redraw on every message
+    pub frame_rate: f32,
+    // print the text as appended lines, without moving the cursor
+    pub plain: bool,
+    // show the turns finishing the cube when it is within this many turns of solved, or 0 not to
+    pub near_solved: usize,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            keymap: None,
+            mode: InputMode::default(),
+            dictionary: None,
+            prompt_width: 12,
+            frame_rate: 30.0,
+            plain: false,
+            near_solved: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct TrainerConfig {
+    // number of lines of the text shown ahead
+    pub margin: usize,
+    // when to show the moves of the next character under the prompt
+    pub hints: HintLevel,
+    // what to do with a mistyped character, and the seconds before a held mistake is skipped
+    pub correction: Correction,
+    pub skip_after: f32,
+    // append the result of each session to this file, under the name of the user
+    pub results: Option<PathBuf>,
+    pub name: Option<String>,
+    // type source code, with the indentation repeated by shaking the cube and the symbols scored
+    // apart
+    pub code: bool,
+    // continue the text files and the urls where the last session stopped, unless the line to
+    // start from is given
+    pub resume: bool,
+    pub start_line: Option<usize>,
+}
+
+impl Default for TrainerConfig {
+    fn default() -> Self {
+        TrainerConfig {
+            margin: 3,
+            hints: HintLevel::default(),
+            correction: Correction::default(),
+            skip_after: 2.0,
+            results: None,
+            name: None,
+            code: false,
+            resume: false,
+            start_line: None,
+        }
+    }
+}
+
+// the daily goals of the trainer, a goal of zero is ignored
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct GoalConfig {
+    // minutes practiced
+    pub minutes: u64,
+    // characters typed
+    pub keys: usize,
+    // the best speed of a session
+    pub wpm: f32,
+}
+
+impl Default for GoalConfig {
+    fn default() -> Self {
+        GoalConfig {
+            minutes: 10,
+            keys: 0,
+            wpm: 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct GestureConfig {
+    // standard deviation of the angular velocity above which the cube is shaking
+    pub shaking_torque: f32,
+    // how far from a full turn, in turns, the cube is still turning around
+    pub turning_tolerance: f32,
+    // cosine of the angle within which a face of the tilted cube points up
+    pub tilt_threshold: f32,
+    // smoothing of the orientations before recognizing turning around
+    pub filter: FilterStrategy,
+    // angular speed above which the cube turning no face is being re-gripped, or 0 to disable
+    pub grip_speed: f32,
+    // seconds the cube lies still before the input is paused, or 0 to never pause
+    pub pause_after: f32,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            shaking_torque: 0.25,
+            turning_tolerance: 0.1,
+            tilt_threshold: 0.8,
+            filter: FilterStrategy::None,
+            grip_speed: 0.5,
+            pause_after: 5.0,
+        }
+    }
+}
+
+// colors of the terminal UI, which override the colors of the palette
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub palette: Palette,
+    pub prompt: Option<Color>,
+    pub status: Option<Color>,
+    pub error: Option<Color>,
+}
+
+// face colors and the camera of the 3D view
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ViewConfig {
+    pub camera: CameraMode,
+    pub palette: FacePalette,
+    // colors of U, R, F, D, L, B for the custom palette
+    pub custom: [Color; 6],
+    // smoothing of the orientation of the shown cube
+    pub filter: FilterStrategy,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        let palette = FacePalette::default();
+        ViewConfig {
+            camera: CameraMode::default(),
+            palette,
+            custom: palette.colors(&[Color::Ansi(15); 6]),
+            filter: FilterStrategy::Average,
+        }
+    }
+}
+
+// moves of the switch events, separated by spaces, such as `R R'`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SwitchConfig {
+    pub next: String,
+    pub previous: String,
+    pub select: String,
+}
+
+impl Default for SwitchConfig {
+    fn default() -> Self {
+        SwitchConfig {
+            next: "R R'".to_string(),
+            previous: "L L'".to_string(),
+            select: "U U'".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct MouseConfig {
+    // pixels moved by turning the cube a degree
+    pub sensitivity: f32,
+    // degrees per second below which the turning is ignored
+    pub dead_zone: f32,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        MouseConfig {
+            sensitivity: 20.0,
+            dead_zone: 5.0,
+        }
+    }
+}
+
+// the language of the messages
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct UiConfig {
+    pub locale: Locale,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HookConfig {
+    pub event: HookKind,
+    // run by `sh -c`, with the event as json in the standard input
+    pub command: String,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// `$XDG_CONFIG_HOME/cuboard/config.toml`, or `~/.config/cuboard/config.toml`
+pub fn config_path() -> Result<PathBuf, ConfigError> {
+    let dir = match (env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME")) {
+        (Some(dir), _) if !dir.is_empty() => PathBuf::from(dir),
+        (_, Some(home)) if !home.is_empty() => PathBuf::from(home).join(".config"),
+        _ => return Err(ConfigError::NoConfigDir),
+    };
+    Ok(dir.join("cuboard").join("config.toml"))
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(text)?)
+    }
+
+    // a missing config file is the default config
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    // flags override the config file
+    pub fn apply_flags(&mut self, flags: &[String]) -> Result<(), ConfigError> {
+        fn parse<T: FromStr>(flags: &[String], name: &str) -> Result<Option<T>, ConfigError> {
+            let Some(value) = flag_value(flags, name) else {
+                return Ok(None);
+            };
+            let res = value.parse().map_err(|_| ConfigError::Flag {
+                name: name.to_string(),
+                value: value.to_string(),
+            })?;
+            Ok(Some(res))
+        }
+
+        if let Some(solver) = parse(flags, "solver")? {
+            self.solver = solver;
+        }
+        if let Some(address) = parse(flags, "device")? {
+            self.device.address = Some(address);
+        }
+        if let Some(adapter) = parse(flags, "adapter")? {
+            self.device.adapter = Some(adapter);
+        }
+        if let Some(timeout) = flag_secs(flags, "scan-timeout", 1.0)? {
+            self.device.scan_timeout = Some(timeout.as_secs_f32());
+        }
+        if let Some(timeout) = flag_secs(flags, "connect-timeout", 1.0)? {
+            self.device.connect_timeout = Some(timeout.as_secs_f32());
+        }
+        if let Some(retries) = parse(flags, "connect-retries")? {
+            self.device.connect_retries = Some(retries);
+        }
+        if let Some(rate) = parse::<f32>(flags, "gyro-rate")? {
+            // the interval between gyroscope messages must fit in a duration
+            if rate > 0.0 && Duration::try_from_secs_f32(1.0 / rate).is_err() {
+                return Err(ConfigError::Flag {
+                    name: "gyro-rate".to_string(),
+                    value: rate.to_string(),
+                });
+            }
+            self.device.gyro_rate = Some(rate);
+        }
+        if let Some(secs) = parse(flags, "verify")? {
+            self.device.verify_interval = Some(secs);
+        }
+        if let Some(keymap) = parse(flags, "keymap")? {
+            self.input.keymap = Some(keymap);
+        }
+        if let Some(mode) = parse(flags, "input-mode")? {
+            self.input.mode = mode;
+        }
+        if let Some(dictionary) = parse(flags, "dictionary")? {
+            self.input.dictionary = Some(dictionary);
+        }
+        if let Some(width) = parse(flags, "prompt-width")? {
+            self.input.prompt_width = width;
+        }
+        if let Some(rate) = parse(flags, "frame-rate")? {
+            self.input.frame_rate = rate;
+        }
+        if flags.iter().any(|flag| flag == "--plain") {
+            self.input.plain = true;
+        }
+        if let Some(depth) = parse(flags, "near-solved")? {
+            self.input.near_solved = depth;
+        }
+        if let Some(margin) = parse(flags, "margin")? {
+            self.trainer.margin = margin;
+        }
+        if let Some(hints) = parse(flags, "hints")? {
+            self.trainer.hints = hints;
+        }
+        if let Some(correction) = parse(flags, "correction")? {
+            self.trainer.correction = correction;
+        }
+        if let Some(secs) = parse(flags, "skip-after")? {
+            self.trainer.skip_after = secs;
+        }
+        if let Some(results) = parse(flags, "results")? {
+            self.trainer.results = Some(results);
+        }
+        if let Some(name) = parse(flags, "name")? {
+            self.trainer.name = Some(name);
+        }
+        if flags.iter().any(|flag| flag == "--code") {
+            self.trainer.code = true;
+        }
+        if flags.iter().any(|flag| flag == "--resume") {
+            self.trainer.resume = true;
+        }
+        if let Some(line) = parse(flags, "line")? {
+            self.trainer.start_line = Some(line);
+        }
+        if let Some(torque) = parse(flags, "shaking-torque")? {
+            self.gesture.shaking_torque = torque;
+        }
+        if let Some(tolerance) = parse(flags, "turning-tolerance")? {
+            self.gesture.turning_tolerance = tolerance;
+        }
+        if let Some(sensitivity) = parse(flags, "mouse-sensitivity")? {
+            self.mouse.sensitivity = sensitivity;
+        }
+        if let Some(dead_zone) = parse(flags, "mouse-dead-zone")? {
+            self.mouse.dead_zone = dead_zone;
+        }
+        if let Some(palette) = parse(flags, "theme")? {
+            self.theme.palette = palette;
+        }
+        if let Some(palette) = parse(flags, "face-colors")? {
+            self.view.palette = palette;
+        }
+        if let Some(camera) = parse(flags, "camera")? {
+            self.view.camera = camera;
+        }
+        if let Some(filter) = parse(flags, "view-filter")? {
+            self.view.filter = filter;
+        }
+        if let Some(filter) = parse(flags, "gesture-filter")? {
+            self.gesture.filter = filter;
+        }
+        if let Some(locale) = parse(flags, "locale")? {
+            self.ui.locale = locale;
+        }
+        Ok(())
+    }
+}
+
+// find the value of the flag `--<name>=<value>`
+pub fn flag_value<'a>(flags: &'a [String], name: &str) -> Option<&'a str> {
+    flags.iter().find_map(|flag| {
+        flag.strip_prefix("--")?
+            .strip_prefix(name)?
+            .strip_prefix('=')
+    })
+}
+
+// find the duration of the flag `--<name>=<value>`, in `unit` seconds
+pub fn flag_secs(flags: &[String], name: &str, unit: f32) -> Result<Option<Duration>, ConfigError> {
+    let Some(value) = flag_value(flags, name) else {
+        return Ok(None);
+    };
+    value
+        .parse::<f32>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f32(value * unit).ok())
+        .map(Some)
+        .ok_or_else(|| ConfigError::Flag {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+}
+
+// the config is loaded once at startup and shared by everything
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_are_default() {
+        let config = Config::parse("[gesture]\nshaking_torque = 0.5\n").unwrap();
+        assert_eq!(config.gesture.shaking_torque, 0.5);
+        assert_eq!(
+            config.gesture.turning_tolerance,
+            GestureConfig::default().turning_tolerance
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn hooks_are_tables() {
+        let text = "[[hooks]]\nevent = \"line\"\ncommand = \"notify-send cuboard\"\n";
+        let config = Config::parse(text).unwrap();
+        assert_eq!(
+            config.hooks,
+            [HookConfig {
+                event: HookKind::Line,
+                command: "notify-send cuboard".to_string(),
+            }]
+        );
+        assert_eq!(
+            Config::parse(&toml::to_string(&config).unwrap()).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn flags_override_config() {
+        let mut config =
+            Config::parse("[trainer]\nmargin = 5\n[input]\nprompt_width = 20\n").unwrap();
+        let flags = ["--margin=2".to_string(), "--device=AA:BB".to_string()];
+        config.apply_flags(&flags).unwrap();
+        assert_eq!(config.trainer.margin, 2);
+        assert_eq!(config.input.prompt_width, 20);
+        assert!(config.device.accepts_address("aa:bb"));
+        assert!(!config.device.accepts_address("aa:cc"));
+
+        let flags = ["--margin=many".to_string()];
+        assert!(matches!(
+            config.apply_flags(&flags),
+            Err(ConfigError::Flag { .. })
+        ));
+        for flag in [
+            "--scan-timeout=-1",
+            "--connect-timeout=NaN",
+            "--gyro-rate=1e-40",
+        ] {
+            assert!(matches!(
+                config.apply_flags(&[flag.to_string()]),
+                Err(ConfigError::Flag { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn custom_face_colors_are_parsed() {
+        let text = "[view]\npalette = \"custom\"\ncustom = [\"white\", \"#d55e00\", \"green\", \"yellow\", \"#cc79a7\", \"blue\"]\n";
+        let config = Config::parse(text).unwrap();
+        let colors = config.view.palette.colors(&config.view.custom);
+        assert_eq!(colors[1].rgb(), (0xd5, 0x5e, 0x00));
+        assert_eq!(colors[5], Color::Ansi(4));
+        assert!(Config::parse("[view]\npalette = \"sepia\"\n").is_err());
+    }
+
+    #[test]
+    fn format_and_parse_roundtrip() {
+        let mut config = Config::default();
+        config.gesture.turning_tolerance = 0.15;
+        let text = toml::to_string(&config).unwrap();
+        assert_eq!(Config::parse(&text).unwrap(), config);
+    }
+}
@@ -1,18 +1,28 @@
 use std::{
     error::Error,
-    io::{stdout, Read, Write},
+    fs,
+    io::{Read, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
-use btleplug::{
-    api::{Central, Manager, Peripheral, ScanFilter},
-    platform,
-};
-use tokio::time::sleep;
+use btleplug::api::{Central, Peripheral};
+use futures::StreamExt;
+use tokio::sync::mpsc;
 
 use crate::{
-    bluetooth::gancubev2::{GanCubeV2Builder, ResponseMessage},
+    bluetooth::{
+        connect::Connector,
+        gancubev2::{GanCubeV2, NotificationHandle, ResponseMessage},
+        open_adapter,
+        scan::Scanner,
+        transport::Transport,
+    },
+    capture::{CaptureWriter, CapturedFrame},
     cube::CubeState,
+    i18n::{tr, Message},
+    shutdown::Shutdown,
 };
 
 fn direct_input_mode() -> impl Drop {
@@ -36,106 +46,281 @@ fn read_char() -> std::io::Result<u8> {
     Ok(input[0])
 }
 
-pub async fn run() -> Result<(), Box<dyn Error>> {
-    let _input_handle = direct_input_mode();
+// read stdin in a dedicated thread, so that waiting for a key doesn't block the shutdown
+fn spawn_char_reader() -> mpsc::UnboundedReceiver<u8> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(ch) = read_char() {
+            if sender.send(ch).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
 
-    // get the first bluetooth adapter
-    let manager = platform::Manager::new().await.unwrap();
-    let adapters = manager.adapters().await?;
-    let adapter = adapters.into_iter().next().unwrap();
-    let info = adapter.adapter_info().await?;
-    println!("adapter: {}", info);
+// names of commands with their usages
+const COMMANDS: [(&str, Message); 13] = [
+    ("help", Message::HelpHelp),
+    ("quit", Message::HelpQuit),
+    ("disconnect", Message::HelpDisconnect),
+    ("sub", Message::HelpSubscribe),
+    ("unsub", Message::HelpUnsubscribe),
+    ("battery", Message::RequestBattery),
+    ("state", Message::RequestState),
+    ("reset [FACELETS]", Message::HelpReset),
+    ("stats", Message::HelpStats),
+    ("watch gyro on|off", Message::HelpWatch),
+    ("sleep SECS", Message::HelpSleep),
+    ("raw HEX", Message::HelpRaw),
+    ("unknown 1|2|3|4", Message::HelpUnknown),
+];
 
-    // start scanning for devices
-    adapter.start_scan(ScanFilter::default()).await?;
-    print!("scan devices");
+#[derive(Clone, Debug, PartialEq)]
+enum ConsoleCommand {
+    Help,
+    Quit,
+    Disconnect,
+    Subscribe,
+    Unsubscribe,
+    Battery,
+    State,
+    Reset(CubeState),
+    Stats,
+    WatchGyro(bool),
+    Sleep(Duration),
+    Raw([u8; 20]),
+    Unknown(u8),
+}
 
-    let builder = 'a: loop {
-        print!(".");
-        let _ = stdout().flush();
+impl FromStr for ConsoleCommand {
+    type Err = String;
 
-        let found = GanCubeV2Builder::find_gancube_device(&adapter).await?;
-        if found.is_empty() {
-            sleep(Duration::from_secs(1)).await;
-            continue;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("unknown command: {}", s);
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        match words[..] {
+            ["help"] => Ok(ConsoleCommand::Help),
+            ["quit"] => Ok(ConsoleCommand::Quit),
+            ["disconnect"] => Ok(ConsoleCommand::Disconnect),
+            ["sub"] => Ok(ConsoleCommand::Subscribe),
+            ["unsub"] => Ok(ConsoleCommand::Unsubscribe),
+            ["battery"] => Ok(ConsoleCommand::Battery),
+            ["state"] => Ok(ConsoleCommand::State),
+            ["reset"] => Ok(ConsoleCommand::Reset(CubeState::default())),
+            ["reset", facelets] => CubeState::from_facelets(facelets).map(ConsoleCommand::Reset),
+            ["stats"] => Ok(ConsoleCommand::Stats),
+            ["watch", "gyro", "on"] => Ok(ConsoleCommand::WatchGyro(true)),
+            ["watch", "gyro", "off"] => Ok(ConsoleCommand::WatchGyro(false)),
+            ["sleep", secs] => {
+                let secs = secs.parse::<f32>().map_err(|_| error())?;
+                Duration::try_from_secs_f32(secs)
+                    .map(ConsoleCommand::Sleep)
+                    .map_err(|_| error())
+            }
+            ["raw", ..] => {
+                let hex = words[1..].concat();
+                if hex.is_empty() || hex.len() > 40 || hex.len() % 2 != 0 {
+                    return Err(error());
+                }
+                let mut message = [0; 20];
+                for (byte, i) in message.iter_mut().zip((0..hex.len()).step_by(2)) {
+                    *byte = u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| error())?;
+                }
+                Ok(ConsoleCommand::Raw(message))
+            }
+            ["unknown", n @ ("1" | "2" | "3" | "4")] => {
+                Ok(ConsoleCommand::Unknown(n.parse().unwrap()))
+            }
+            _ => Err(error()),
         }
+    }
+}
 
-        println!();
-        for builder in found.iter() {
-            println!("===================================================");
-            let name = builder.properties.local_name.clone().unwrap();
-            println!("name: {} [{}]", name, builder.device.address());
-            println!("{:#?}", builder.device);
-            println!("{:#?}", builder.properties);
-        }
-        println!("===================================================");
+// the words which can follow the given words
+fn completions(words: &[&str]) -> Vec<&'static str> {
+    match words {
+        [] => COMMANDS
+            .iter()
+            .map(|(usage, _)| usage.split(' ').next().unwrap())
+            .collect(),
+        ["watch"] => vec!["gyro"],
+        ["watch", "gyro"] => vec!["on", "off"],
+        ["unknown"] => vec!["1", "2", "3", "4"],
+        _ => vec![],
+    }
+}
 
-        break 'a found.into_iter().next().unwrap();
-    };
-    println!();
+// the prompt with the edited line, drawn again after notifications are printed
+#[derive(Clone, Default)]
+struct Prompt {
+    line: Arc<Mutex<Option<String>>>,
+}
 
-    println!("connect to GANCube...");
-    let gancube = builder.connect().await?;
-    println!("connected! have fun~");
-    println!();
+impl Prompt {
+    fn set(&self, line: Option<&str>) {
+        if let Ok(mut prompt) = self.line.lock() {
+            *prompt = line.map(str::to_string);
+        }
+        self.redraw();
+    }
 
-    // handle notifications
-    println!("Instructions:");
-    println!("  q: exit");
-    println!("  Q: disconnect GANCube and exit");
-    println!("  s: subscribe/unsubscribe response characteristic");
-    println!("  b: request battery state");
-    println!("  c: request cube state");
-    println!("  r: reset cube state");
-    println!();
+    fn redraw(&self) {
+        if let Ok(line) = self.line.lock() {
+            if let Some(line) = &*line {
+                print!("{}> {}", CREL, line);
+                let _ = std::io::stdout().flush();
+            }
+        }
+    }
+}
 
-    // println!("Experimental instructions (may destroy your device):");
-    // println!("  a: arbitrary request");
-    // println!("  1: unkown characteristic 1");
-    // println!("  2: unkown characteristic 2");
-    // println!("  3: unkown characteristic 3");
-    // println!("  4: unkown characteristic 4");
-    // println!();
-
-    let mut handler = ConsoleMessageHandler::new();
-    gancube
-        .register_handler(Box::new(move |msg| handler.handle_message(msg)))
-        .await?;
-    gancube.subscribe_response().await?;
-    let mut is_subscribed = true;
-    loop {
-        match read_char()? {
+// a line editor with history and tab completion, fed by keys
+#[derive(Default)]
+struct LineEditor {
+    line: String,
+    history: Vec<String>,
+    // the browsed entry of the history
+    index: usize,
+    escape: Option<Vec<u8>>,
+}
+
+impl LineEditor {
+    // return the line when it is entered
+    fn key(&mut self, ch: u8) -> Option<String> {
+        if let Some(escape) = &mut self.escape {
+            escape.push(ch);
+            match escape[..] {
+                [b'['] => return None,
+                [b'[', b'A'] => self.browse(-1),
+                [b'[', b'B'] => self.browse(1),
+                _ => {}
+            }
+            self.escape = None;
+            return None;
+        }
+        match ch {
             b'\n' => {
-                println!("{}", CREL);
+                let line = std::mem::take(&mut self.line);
+                if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                self.index = self.history.len();
+                return Some(line);
+            }
+            0x1b => self.escape = Some(Vec::new()),
+            0x08 | 0x7f => {
+                self.line.pop();
+            }
+            b'\t' => self.complete(),
+            ch if ch.is_ascii_graphic() || ch == b' ' => self.line.push(ch as char),
+            _ => {}
+        }
+        None
+    }
+
+    fn browse(&mut self, offset: isize) {
+        let index = self.index.saturating_add_signed(offset);
+        if index < self.history.len() {
+            self.index = index;
+            self.line = self.history[index].clone();
+        } else if index == self.history.len() {
+            self.index = index;
+            self.line.clear();
+        }
+    }
+
+    // complete the last word, or list the candidates if it is ambiguous
+    fn complete(&mut self) {
+        let mut words = self.line.split_whitespace().collect::<Vec<_>>();
+        let prefix = if self.line.ends_with(' ') || self.line.is_empty() {
+            ""
+        } else {
+            words.pop().unwrap()
+        };
+        let candidates = completions(&words)
+            .into_iter()
+            .filter(|word| word.starts_with(prefix))
+            .collect::<Vec<_>>();
+        match candidates[..] {
+            [] => {}
+            [word] => self.line += &format!("{} ", &word[prefix.len()..]),
+            _ => println!("{}{}", CREL, candidates.join("  ")),
+        }
+    }
+}
+
+struct Console<'a, P: Transport> {
+    gancube: &'a GanCubeV2<P>,
+    handle: &'a NotificationHandle,
+    is_subscribed: bool,
+}
+
+impl<P: Transport> Console<'_, P> {
+    // return false to exit
+    async fn execute(&mut self, line: &str) -> Result<bool, Box<dyn Error>> {
+        if line.trim().is_empty() || line.starts_with('#') {
+            return Ok(true);
+        }
+        let command = match line.parse::<ConsoleCommand>() {
+            Ok(command) => command,
+            Err(err) => {
+                println!("{}{}, see `help`", CREL, err);
+                return Ok(true);
             }
-            b'q' => break,
-            b'Q' => {
-                println!("{}disconnect GANCube...", CREL);
+        };
+        let gancube = self.gancube;
+        match command {
+            ConsoleCommand::Help => {
+                for (usage, description) in COMMANDS {
+                    println!("{}  {:<20}{}", CREL, usage, tr(description, &[]));
+                }
+            }
+            ConsoleCommand::Quit => return Ok(false),
+            ConsoleCommand::Disconnect => {
+                println!("{}{}", CREL, tr(Message::Disconnecting, &[]));
                 gancube.device.disconnect().await?;
-                break;
+                return Ok(false);
             }
-            b's' => {
-                if is_subscribed {
-                    gancube.unsubscribe_response().await?;
-                } else {
+            ConsoleCommand::Subscribe => {
+                if !self.is_subscribed {
                     gancube.subscribe_response().await?;
+                    self.is_subscribed = true;
+                }
+            }
+            ConsoleCommand::Unsubscribe => {
+                if self.is_subscribed {
+                    gancube.unsubscribe_response().await?;
+                    self.is_subscribed = false;
                 }
-                is_subscribed = !is_subscribed;
-                println!("{}", CREL);
             }
-            b'b' => {
+            ConsoleCommand::Battery => {
                 gancube.request_battery_state().await?;
-                println!("{}request battery state", CREL);
+                println!("{}{}", CREL, tr(Message::RequestBattery, &[]));
             }
-            b'c' => {
+            ConsoleCommand::State => {
                 gancube.request_cube_state().await?;
-                println!("{}request cube state", CREL);
+                println!("{}{}", CREL, tr(Message::RequestState, &[]));
+            }
+            ConsoleCommand::Reset(state) => {
+                gancube.reset_cube_state(state).await?;
+                println!("{}{}", CREL, tr(Message::ResetState, &[]));
             }
-            b'r' => {
-                gancube.reset_cube_state(CubeState::default()).await?;
-                println!("{}reset cube state", CREL);
+            ConsoleCommand::Stats => {
+                let stats = self.handle.stats();
+                println!(
+                    "{}notifications: received={}, decoded={}, failed={}, unknown={}, dropped={}",
+                    CREL, stats.received, stats.decoded, stats.failed, stats.unknown, stats.dropped
+                );
             }
-            b'a' => {
+            ConsoleCommand::WatchGyro(watch) => {
+                gancube.message_filter().set_gyroscope(watch);
+                if !watch {
+                    println!("{}", CREL);
+                }
+            }
+            ConsoleCommand::Sleep(duration) => tokio::time::sleep(duration).await,
+            ConsoleCommand::Raw(message) => {
                 // 04 -> RequestCubeState
                 // 09 -> RequestBatteryState
                 // 0A -> ResetCubeState
@@ -147,33 +332,158 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
                 // 0E -> [E0, 00, ...]
                 // 0F -> [F0, 00, ...]
                 // 10 -> destroy GANCube...
-                println!("{}input message type (02X)", CREL);
-                let s = String::from_utf8([read_char()?; 40].to_vec())?;
-                let message_type = u8::from_str_radix(&s, 16)?;
-                let mut message = [0; 20];
-                message[0] = message_type;
                 gancube.arbitrary_request(message, false).await?;
-                println!("{}arbitrary request <= {:02X?}", CREL, message_type);
-            }
-            b'1' => {
-                let res = gancube.unknown1().await?;
-                println!("{}unknown characteristic 1 => {:02X?}", CREL, res);
+                println!("{}arbitrary request <= {:02X?}", CREL, message);
             }
-            b'2' => {
-                let res = gancube.unknown2().await?;
-                println!("{}unknown characteristic 2 => {:02X?}", CREL, res);
+            ConsoleCommand::Unknown(n) => {
+                let res = match n {
+                    1 => gancube.unknown1().await?,
+                    2 => gancube.unknown2().await?,
+                    3 => gancube.unknown3().await?,
+                    _ => {
+                        let data = [0; 20];
+                        gancube.unknown4(data).await?;
+                        println!("{}unknown characteristic 4 <= {:02X?}", CREL, data);
+                        return Ok(true);
+                    }
+                };
+                println!("{}unknown characteristic {} => {:02X?}", CREL, n, res);
             }
-            b'3' => {
-                let res = gancube.unknown3().await?;
-                println!("{}unknown characteristic 3 => {:02X?}", CREL, res);
+        }
+        Ok(true)
+    }
+}
+
+// write the raw frames of all notifications to the file, beside the handlers which see only the
+// decoded messages
+async fn capture_frames<P: Transport>(
+    gancube: &GanCubeV2<P>,
+    path: &str,
+    header: &str,
+) -> Result<tokio::task::JoinHandle<()>, Box<dyn Error>> {
+    let mut writer = CaptureWriter::create(path, header)?;
+    let mut notifications = gancube.device.notifications().await?;
+    let cipher = gancube.cipher();
+    let start = Instant::now();
+    Ok(tokio::spawn(async move {
+        while let Some(notification) = notifications.next().await {
+            let decrypted = ResponseMessage::decrypt(&notification.value, &*cipher).ok();
+            let frame = CapturedFrame {
+                time: start.elapsed(),
+                encrypted: notification.value,
+                decrypted,
+            };
+            if writer.write(&frame).is_err() {
+                println!("{}<!> error: fail to write captured frames", CREL);
+                return;
             }
-            b'4' => {
-                let data = [0; 20];
-                gancube.unknown4(data).await?;
-                println!("{}unknown characteristic 4 <= {:02X?}", CREL, data);
+        }
+    }))
+}
+
+pub async fn run(
+    script: Option<String>,
+    capture: Option<String>,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn Error>> {
+    let script = script.map(fs::read_to_string).transpose()?;
+
+    // get the configured bluetooth adapter
+    let adapter = open_adapter().await?;
+    let info = adapter.adapter_info().await?;
+    println!("adapter: {}", info);
+
+    // scan for the GANCube
+    let builder = Scanner::new(shutdown.clone()).scan_one(&adapter).await?;
+    println!("===================================================");
+    let name = builder.properties.local_name.clone().unwrap_or_default();
+    println!("name: {} [{}]", name, builder.device.address());
+    println!("{:#?}", builder.device);
+    println!("{:#?}", builder.properties);
+    println!("===================================================");
+    println!();
+
+    println!("{}", tr(Message::Connecting, &[]));
+    let gancube = Connector::new(shutdown.clone()).connect(&builder).await?;
+    println!("{}", tr(Message::Connected, &[]));
+    println!("{}", tr(Message::ConsoleHint, &[]));
+    let capture = match &capture {
+        Some(path) => {
+            let header = format!("{} [{}]", name, builder.device.address());
+            let task = capture_frames(&gancube, path, &header).await?;
+            println!(
+                "capture frames to {}, read them by `cuboard decode {}`",
+                path, path
+            );
+            Some(task)
+        }
+        None => None,
+    };
+    println!();
+
+    // handle notifications, where gyroscope notifications are dropped until watched
+    gancube.message_filter().set_gyroscope(false);
+    let prompt = Prompt::default();
+    let mut handler = ConsoleMessageHandler::new(prompt.clone());
+    let error_prompt = prompt.clone();
+    let handle = gancube
+        .register_handler_with_errors(
+            Box::new(move |msg| handler.handle_message(msg)),
+            Box::new(move |err| {
+                println!("{}<!> {}", CREL, tr(Message::Error, &[&err]));
+                error_prompt.redraw();
+            }),
+        )
+        .await?;
+    gancube.subscribe_response().await?;
+    let mut console = Console {
+        gancube: &gancube,
+        handle: &handle,
+        is_subscribed: true,
+    };
+
+    // run the script line by line, as if typed
+    let mut running = true;
+    for line in script.iter().flat_map(|script| script.lines()) {
+        println!("{}> {}", CREL, line);
+        running = tokio::select! {
+            res = console.execute(line) => res?,
+            _ = shutdown.wait() => false,
+        };
+        if !running {
+            break;
+        }
+    }
+
+    if running {
+        let _input_handle = direct_input_mode();
+        let mut chars = spawn_char_reader();
+        let mut editor = LineEditor::default();
+        while running {
+            prompt.set(Some(&editor.line));
+            let ch = tokio::select! {
+                ch = chars.recv() => ch,
+                _ = shutdown.wait() => None,
+            };
+            let Some(ch) = ch else {
+                break;
+            };
+            if let Some(line) = editor.key(ch) {
+                prompt.set(None);
+                println!("{}> {}", CREL, line);
+                running = tokio::select! {
+                    res = console.execute(&line) => res?,
+                    _ = shutdown.wait() => false,
+                };
             }
-            _ => {}
         }
+        prompt.set(None);
+    }
+
+    shutdown.close(&gancube).await?;
+    handle.abort();
+    if let Some(capture) = capture {
+        capture.abort();
     }
 
     println!();
@@ -192,12 +502,14 @@ fn draw_bar(value: f32, width: usize) -> String {
 
 struct ConsoleMessageHandler {
     prev_time: Instant,
+    prompt: Prompt,
 }
 
 impl ConsoleMessageHandler {
-    fn new() -> Self {
+    fn new(prompt: Prompt) -> Self {
         ConsoleMessageHandler {
             prev_time: Instant::now(),
+            prompt,
         }
     }
 
@@ -251,6 +563,7 @@ impl ConsoleMessageHandler {
                 );
                 print!("({:0.3}s)", duration);
                 let _ = std::io::stdout().flush();
+                return;
             }
             ResponseMessage::Moves {
                 count,
@@ -300,10 +613,56 @@ impl ConsoleMessageHandler {
                 }
                 println!();
             }
+            ResponseMessage::Hardware(info) => {
+                let firmware = info.firmware();
+                print!("{}<!> ", CREL);
+                print!(
+                    "hardware={} v{}.{}, software=v{}.{}",
+                    firmware.name,
+                    info.hardware.0,
+                    info.hardware.1,
+                    info.software.0,
+                    info.software.1
+                );
+                if info.gyroscope {
+                    print!(" (gyroscope)");
+                }
+                println!();
+            }
             ResponseMessage::Disconnect => {
                 print!("{}<!> ", CREL);
-                println!("cube auto-disconnect");
+                println!("{}", tr(Message::AutoDisconnect, &[]));
             }
         }
+        self.prompt.redraw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_edited_and_parsed() {
+        assert_eq!("state".parse(), Ok(ConsoleCommand::State));
+        assert_eq!("watch gyro on".parse(), Ok(ConsoleCommand::WatchGyro(true)));
+        let mut message = [0; 20];
+        message[..2].copy_from_slice(&[0x04, 0xAB]);
+        assert_eq!("raw 04 ab".parse(), Ok(ConsoleCommand::Raw(message)));
+        assert!("raw 4".parse::<ConsoleCommand>().is_err());
+        assert!("reset UUU".parse::<ConsoleCommand>().is_err());
+
+        let mut editor = LineEditor::default();
+        let mut type_keys = |keys: &[u8]| {
+            keys.iter()
+                .filter_map(|&ch| editor.key(ch))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(type_keys(b"wa\tgy\to\t"), Vec::<String>::new());
+        assert_eq!(type_keys(b"n\n"), ["watch gyro on"]);
+        assert_eq!(type_keys(b"stx\x7fats\n"), ["stats"]);
+        // browse the history
+        assert_eq!(type_keys(b"\x1b[A\x1b[A\x1b[B\n"), ["stats"]);
+        assert_eq!(type_keys(b"\x1b[A\x1b[A\n"), ["watch gyro on"]);
     }
 }
@@ -0,0 +1,138 @@
+use std::{error::Error, fs, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::stats::{self, Penalty, SolveRecord, Stats};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CsTimerError {
+    #[error("invalid csTimer file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("invalid solve in csTimer file: {0}")]
+    InvalidSolve(String),
+}
+
+// a solve is `[[penalty, millis], scramble, comment, date]`, where the penalty is 0, 2000 for +2
+// or -1 for DNF, and more fields may follow
+fn parse_solve(solve: &Value) -> Option<SolveRecord> {
+    let solve = solve.as_array()?;
+    let time = solve.first()?.as_array()?;
+    let penalty = match time.first()?.as_i64()? {
+        0 => Penalty::None,
+        2000 => Penalty::PlusTwo,
+        -1 => Penalty::Dnf,
+        _ => return None,
+    };
+    let text = |i: usize| solve.get(i).and_then(Value::as_str).unwrap_or_default();
+    Some(SolveRecord {
+        millis: time.get(1)?.as_u64()?,
+        penalty,
+        scramble: text(1).to_string(),
+        comment: text(2).to_string(),
+        date: solve.get(3).and_then(Value::as_u64).unwrap_or_default(),
+    })
+}
+
+// the solves of all sessions of an exported file, oldest first
+pub fn parse_sessions(text: &str) -> Result<Vec<SolveRecord>, CsTimerError> {
+    let file = serde_json::from_str::<serde_json::Map<String, Value>>(text)?;
+    let mut solves = Vec::new();
+    for (name, session) in file {
+        // other fields, such as `properties`, are not sessions
+        let is_session = name
+            .strip_prefix("session")
+            .is_some_and(|n| n.parse::<u32>().is_ok());
+        if !is_session {
+            continue;
+        }
+        // older versions keep sessions as strings of json
+        let session = match session {
+            Value::String(text) => serde_json::from_str(&text)?,
+            session => session,
+        };
+        let Value::Array(session) = session else {
+            return Err(CsTimerError::InvalidSolve(session.to_string()));
+        };
+        for solve in &session {
+            let parsed = parse_solve(solve);
+            solves.push(parsed.ok_or_else(|| CsTimerError::InvalidSolve(solve.to_string()))?);
+        }
+    }
+    solves.sort_by_key(|solve| solve.date);
+    Ok(solves)
+}
+
+// a file of one session named `cuboard`, which can be imported by csTimer
+pub fn format_sessions(solves: &[SolveRecord]) -> String {
+    let session = solves
+        .iter()
+        .map(|solve| {
+            let penalty = match solve.penalty {
+                Penalty::None => 0,
+                Penalty::PlusTwo => 2000,
+                Penalty::Dnf => -1,
+            };
+            json!([
+                [penalty, solve.millis],
+                solve.scramble,
+                solve.comment,
+                solve.date
+            ])
+        })
+        .collect::<Vec<_>>();
+    let session_data = json!({ "1": { "name": "cuboard", "opt": {}, "rank": 1 } });
+    let file = json!({
+        "session1": session,
+        "properties": { "sessionData": session_data.to_string() },
+    });
+    file.to_string()
+}
+
+// merge the solves of the file into the stats, and tell how many are added
+pub fn import(path: impl AsRef<Path>) -> Result<usize, Box<dyn Error>> {
+    let solves = parse_sessions(&fs::read_to_string(path)?)?;
+    let stats_path = stats::stats_path()?;
+    let mut stats = Stats::load(&stats_path)?;
+    let added = stats.merge_solves(solves);
+    stats.save(&stats_path)?;
+    Ok(added)
+}
+
+pub fn export(path: impl AsRef<Path>) -> Result<usize, Box<dyn Error>> {
+    let stats = Stats::load(stats::stats_path()?)?;
+    fs::write(path, format_sessions(&stats.solves))?;
+    Ok(stats.solves.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_are_read_and_written() {
+        let text = r#"{
+            "session1": [
+                [[0, 12345], "R U R' U'", "", 1600000100],
+                [[2000, 23456], "F2 D", "oops", 1600000000, ["R U", "333"]]
+            ],
+            "session2": "[[[-1, 9999], \"B\", \"\", 1600000200]]",
+            "properties": { "sessionN": 2 }
+        }"#;
+        let solves = parse_sessions(text).unwrap();
+        assert_eq!(
+            solves[0],
+            SolveRecord {
+                millis: 23456,
+                penalty: Penalty::PlusTwo,
+                scramble: "F2 D".to_string(),
+                comment: "oops".to_string(),
+                date: 1600000000,
+            }
+        );
+        assert_eq!(solves[1].result(), Some(12345));
+        assert_eq!(solves[2].penalty, Penalty::Dnf);
+
+        assert_eq!(parse_sessions(&format_sessions(&solves)).unwrap(), solves);
+        assert!(parse_sessions(r#"{"session1": [[[1, 100], ""]]}"#).is_err());
+    }
+}
@@ -4,8 +4,10 @@ use std::{
     fmt::Display,
     iter::Sum,
     ops::{Add, Neg},
+    str::FromStr,
 };
 
+use strum::IntoEnumIterator;
 use strum_macros::{Display, EnumIter, FromRepr};
 
 #[rustfmt::skip]
@@ -28,7 +30,7 @@ pub struct Corner(pub CornerPosition, pub PieceOrientation<3>);
 impl Display for Corner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = self.0.to_string();
-        let mut name = name.chars().collect::<Box<_>>();
+        let mut name = name.chars().collect::<Box<[char]>>();
         name.rotate_left(self.1.repr() as usize);
         let name = name.iter().collect::<String>();
         write!(f, "{}", name)
@@ -64,7 +66,7 @@ pub struct Edge(pub EdgePosition, pub PieceOrientation<2>);
 impl Display for Edge {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = self.0.to_string();
-        let mut name = name.chars().collect::<Box<_>>();
+        let mut name = name.chars().collect::<Box<[char]>>();
         name.rotate_left(self.1.repr() as usize);
         let name = name.iter().collect::<String>();
         write!(f, "{}", name)
@@ -144,7 +146,7 @@ impl<const N: u8> Neg for PieceOrientation<N> {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct CubeState {
     pub corners: [Corner; 8],
     pub edges: [Edge; 12],
@@ -154,13 +156,11 @@ pub struct CubeState {
 impl Default for CubeState {
     fn default() -> Self {
         let corners: [Corner; 8] = (0..8)
-            .into_iter()
             .map(|i| (i, 0).try_into().unwrap())
             .collect::<Vec<Corner>>()
             .try_into()
             .unwrap();
         let edges: [Edge; 12] = (0..12)
-            .into_iter()
             .map(|i| (i, 0).try_into().unwrap())
             .collect::<Vec<Edge>>()
             .try_into()
@@ -169,6 +169,23 @@ impl Default for CubeState {
     }
 }
 
+// the facelets of each position, starting from the U or D facelet and going clockwise
+#[rustfmt::skip]
+pub const CORNER_FACELETS: [[usize; 3]; 8] = [
+    [8, 9, 20], [6, 18, 38], [0, 36, 47], [2, 45, 11],
+    [29, 26, 15], [27, 44, 24], [33, 53, 42], [35, 17, 51],
+];
+#[rustfmt::skip]
+pub const EDGE_FACELETS: [[usize; 2]; 12] = [
+    [5, 10], [7, 19], [3, 37], [1, 46], [32, 16], [28, 25],
+    [30, 43], [34, 52], [23, 12], [21, 41], [50, 39], [48, 14],
+];
+
+// the face of each facelet of the solved state
+fn face(facelet: usize) -> usize {
+    facelet / 9
+}
+
 impl CubeState {
     pub fn new(corners: [Corner; 8], edges: [Edge; 12]) -> Self {
         CubeState {
@@ -181,6 +198,243 @@ impl CubeState {
     pub fn reset_centers(&mut self) {
         self.centers = [0.try_into().unwrap(); 6];
     }
+
+    pub fn is_solved(&self) -> bool {
+        *self == CubeState::default()
+    }
+
+    // whether the state can be reached by turns: every piece appears once, the twists and flips
+    // cancel out, and corners and edges are permuted with the same parity
+    pub fn is_valid(&self) -> bool {
+        fn parity(perm: &[usize]) -> Option<bool> {
+            let mut sorted = perm.to_vec();
+            sorted.sort();
+            if sorted.into_iter().ne(0..perm.len()) {
+                return None;
+            }
+            let mut seen = vec![false; perm.len()];
+            let mut odd = false;
+            for start in 0..perm.len() {
+                let mut i = start;
+                let mut len = 0;
+                while !seen[i] {
+                    seen[i] = true;
+                    i = perm[i];
+                    len += 1;
+                }
+                odd ^= len > 0 && len % 2 == 0;
+            }
+            Some(odd)
+        }
+        let corners = self.corners.map(|c| c.0.repr() as usize);
+        let edges = self.edges.map(|e| e.0.repr() as usize);
+        let (Some(corners), Some(edges)) = (parity(&corners), parity(&edges)) else {
+            return false;
+        };
+        let twist: PieceOrientation<3> = self.corners.iter().map(|c| c.1).sum();
+        let flip: PieceOrientation<2> = self.edges.iter().map(|e| e.1).sum();
+        corners == edges && twist.repr() == 0 && flip.repr() == 0
+    }
+
+    // the piece at each position after the pieces of this state are moved by `other`
+    pub fn then(&self, other: &CubeState) -> CubeState {
+        let corners = other.corners.map(|Corner(pos, ori)| {
+            let Corner(pos_, ori_) = self.corners[pos as usize];
+            Corner(pos_, ori_ + ori)
+        });
+        let edges = other.edges.map(|Edge(pos, ori)| {
+            let Edge(pos_, ori_) = self.edges[pos as usize];
+            Edge(pos_, ori_ + ori)
+        });
+        CubeState::new(corners, edges)
+    }
+
+    pub fn inverse(&self) -> CubeState {
+        let mut res = *self;
+        for (i, &Corner(pos, ori)) in self.corners.iter().enumerate() {
+            res.corners[pos as usize] = (i as u8, (-ori).repr()).try_into().unwrap();
+        }
+        for (i, &Edge(pos, ori)) in self.edges.iter().enumerate() {
+            res.edges[pos as usize] = (i as u8, (-ori).repr()).try_into().unwrap();
+        }
+        res
+    }
+
+    // the state after the move from the solved state
+    pub fn of_move(mv: CubeMove) -> CubeState {
+        // the piece moved to each position and its twist, by clockwise turns of U, R, F, D, L, B
+        #[rustfmt::skip]
+        const CORNER_FACELETS: [[(u8, u8); 8]; 6] = [
+            [(3, 0), (0, 0), (1, 0), (2, 0), (4, 0), (5, 0), (6, 0), (7, 0)],
+            [(4, 2), (1, 0), (2, 0), (0, 1), (7, 1), (5, 0), (6, 0), (3, 2)],
+            [(1, 1), (5, 2), (2, 0), (3, 0), (0, 2), (4, 1), (6, 0), (7, 0)],
+            [(0, 0), (1, 0), (2, 0), (3, 0), (5, 0), (6, 0), (7, 0), (4, 0)],
+            [(0, 0), (2, 1), (6, 2), (3, 0), (4, 0), (1, 2), (5, 1), (7, 0)],
+            [(0, 0), (1, 0), (3, 1), (7, 2), (4, 0), (5, 0), (2, 2), (6, 1)],
+        ];
+        #[rustfmt::skip]
+        const EDGE_FACELETS: [[(u8, u8); 12]; 6] = [
+            [(3, 0), (0, 0), (1, 0), (2, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0), (11, 0)],
+            [(8, 0), (1, 0), (2, 0), (3, 0), (11, 0), (5, 0), (6, 0), (7, 0), (4, 0), (9, 0), (10, 0), (0, 0)],
+            [(0, 0), (9, 1), (2, 0), (3, 0), (4, 0), (8, 1), (6, 0), (7, 0), (1, 1), (5, 1), (10, 0), (11, 0)],
+            [(0, 0), (1, 0), (2, 0), (3, 0), (5, 0), (6, 0), (7, 0), (4, 0), (8, 0), (9, 0), (10, 0), (11, 0)],
+            [(0, 0), (1, 0), (10, 0), (3, 0), (4, 0), (5, 0), (9, 0), (7, 0), (8, 0), (2, 0), (6, 0), (11, 0)],
+            [(0, 0), (1, 0), (2, 0), (11, 1), (4, 0), (5, 0), (6, 0), (10, 1), (8, 0), (9, 0), (3, 1), (7, 1)],
+        ];
+
+        let face = mv.repr() as usize / 2;
+        let corners = CORNER_FACELETS[face].map(|c| c.try_into().unwrap());
+        let edges = EDGE_FACELETS[face].map(|e| e.try_into().unwrap());
+        let turn = CubeState::new(corners, edges);
+        if mv.is_clockwise() {
+            turn
+        } else {
+            turn.inverse()
+        }
+    }
+
+    pub fn apply(&mut self, mv: CubeMove) {
+        *self = self.then(&CubeState::of_move(mv));
+    }
+
+    pub fn from_moves(moves: &[CubeMove]) -> CubeState {
+        let mut state = CubeState::default();
+        for &mv in moves {
+            state.apply(mv);
+        }
+        state
+    }
+
+    // the 54 facelets of faces U, R, F, D, L, B in reading order, such as
+    // `UUUUUUUUURRRRRRRRRFFFFFFFFFDDDDDDDDDLLLLLLLLLBBBBBBBBB` for the solved state
+    pub fn from_facelets(facelets: &str) -> Result<CubeState, String> {
+        let error = || format!("unknown facelets: {}", facelets);
+        let faces = facelets
+            .chars()
+            .map(|c| "URFDLB".find(c).ok_or_else(error))
+            .collect::<Result<Vec<_>, _>>()?;
+        if faces.len() != 54 {
+            return Err(error());
+        }
+
+        let mut state = CubeState::default();
+        for (corner, facelets) in state.corners.iter_mut().zip(CORNER_FACELETS) {
+            let colors = facelets.map(|i| faces[i]);
+            let ori = colors
+                .iter()
+                .position(|&c| c == 0 || c == 3)
+                .ok_or_else(error)?;
+            let pos = CORNER_FACELETS
+                .iter()
+                .position(|solved| (0..3).all(|k| face(solved[k]) == colors[(ori + k) % 3]))
+                .ok_or_else(error)?;
+            *corner = (pos as u8, ori as u8).try_into().unwrap();
+        }
+        for (edge, facelets) in state.edges.iter_mut().zip(EDGE_FACELETS) {
+            let colors = facelets.map(|i| faces[i]);
+            let (pos, ori) = EDGE_FACELETS
+                .iter()
+                .enumerate()
+                .find_map(|(pos, solved)| {
+                    let solved = solved.map(face);
+                    (0..2)
+                        .find(|&ori| solved == [colors[ori], colors[(ori + 1) % 2]])
+                        .map(|ori| (pos, ori))
+                })
+                .ok_or_else(error)?;
+            *edge = (pos as u8, ori as u8).try_into().unwrap();
+        }
+        if !state.is_valid() {
+            return Err(error());
+        }
+        Ok(state)
+    }
+
+    // the inverse of `from_facelets`
+    pub fn facelets(&self) -> String {
+        let mut faces: Vec<usize> = (0..54).map(face).collect();
+        for (facelets, &Corner(pos, ori)) in CORNER_FACELETS.iter().zip(&self.corners) {
+            let solved = CORNER_FACELETS[pos as usize];
+            for (k, &facelet) in solved.iter().enumerate() {
+                faces[facelets[(ori.repr() as usize + k) % 3]] = face(facelet);
+            }
+        }
+        for (facelets, &Edge(pos, ori)) in EDGE_FACELETS.iter().zip(&self.edges) {
+            let solved = EDGE_FACELETS[pos as usize];
+            for (k, &facelet) in solved.iter().enumerate() {
+                faces[facelets[(ori.repr() as usize + k) % 2]] = face(facelet);
+            }
+        }
+        faces
+            .iter()
+            .map(|&f| "URFDLB".as_bytes()[f] as char)
+            .collect()
+    }
+
+    // the pieces placed differently in the other state, ignoring the centers
+    pub fn diff(&self, other: &CubeState) -> CubeDiff {
+        let corners = CornerPosition::iter()
+            .zip(self.corners.iter().zip(&other.corners))
+            .filter(|(_, (this, other))| this != other)
+            .map(|(position, (&this, &other))| PieceDiff::Corner(position, this, other));
+        let edges = EdgePosition::iter()
+            .zip(self.edges.iter().zip(&other.edges))
+            .filter(|(_, (this, other))| this != other)
+            .map(|(position, (&this, &other))| PieceDiff::Edge(position, this, other));
+        CubeDiff(corners.chain(edges).collect())
+    }
+}
+
+// a position holding different pieces in two states, or the same piece twisted or flipped
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PieceDiff {
+    Corner(CornerPosition, Corner, Corner),
+    Edge(EdgePosition, Edge, Edge),
+}
+
+impl PieceDiff {
+    // the same piece is in place, but twisted or flipped
+    pub fn is_rotated(&self) -> bool {
+        match self {
+            PieceDiff::Corner(_, this, other) => this.0 == other.0,
+            PieceDiff::Edge(_, this, other) => this.0 == other.0,
+        }
+    }
+}
+
+// such as `UR: FR/UR`, the position and the pieces at it in both states
+impl Display for PieceDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PieceDiff::Corner(position, this, other) => {
+                write!(f, "{}: {}/{}", position, this, other)
+            }
+            PieceDiff::Edge(position, this, other) => write!(f, "{}: {}/{}", position, this, other),
+        }
+    }
+}
+
+// the differences of two states, corners first
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CubeDiff(pub Vec<PieceDiff>);
+
+impl CubeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // the numbers of the positions holding other pieces, and of the pieces rotated in place
+    pub fn counts(&self) -> (usize, usize) {
+        let rotated = self.0.iter().filter(|diff| diff.is_rotated()).count();
+        (self.0.len() - rotated, rotated)
+    }
+}
+
+impl Display for CubeDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let diffs = self.0.iter().map(PieceDiff::to_string).collect::<Vec<_>>();
+        write!(f, "{}", diffs.join(", "))
+    }
 }
 
 #[rustfmt::skip]
@@ -218,7 +472,7 @@ impl CubeMove {
     }
 
     pub fn is_clockwise(self) -> bool {
-        self.repr() % 2 == 0
+        self.repr().is_multiple_of(2)
     }
 
     pub fn rev(self) -> Self {
@@ -238,6 +492,132 @@ impl CubeMove {
     }
 }
 
+// a face turned by quarter turns clockwise: 1 for `U`, 2 for `U2` and 3 for `U'`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct FaceTurn {
+    face: CubeMove,
+    amount: u8,
+}
+
+impl FaceTurn {
+    // none if the face is not turned at all
+    pub fn new(face: CubeMove, amount: u8) -> Option<Self> {
+        let amount = if face.is_clockwise() {
+            amount % 4
+        } else {
+            (4 - amount % 4) % 4
+        };
+        (amount != 0).then_some(FaceTurn {
+            face: face.abs(),
+            amount,
+        })
+    }
+
+    pub fn face(self) -> CubeMove {
+        self.face
+    }
+
+    pub fn amount(self) -> u8 {
+        self.amount
+    }
+
+    pub fn is_half_turn(self) -> bool {
+        self.amount == 2
+    }
+
+    pub fn rev(self) -> Self {
+        FaceTurn {
+            face: self.face,
+            amount: 4 - self.amount,
+        }
+    }
+
+    // half turns are performed clockwise
+    pub fn moves(self) -> Vec<CubeMove> {
+        match self.amount {
+            3 => vec![self.face.rev()],
+            n => vec![self.face; n as usize],
+        }
+    }
+}
+
+impl Display for FaceTurn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.amount {
+            2 => write!(f, "{}2", self.face),
+            3 => write!(f, "{}", self.face.rev()),
+            _ => write!(f, "{}", self.face),
+        }
+    }
+}
+
+impl FromStr for FaceTurn {
+    type Err = String;
+
+    // `U`, `U'`, `U2` or `U2'`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("unknown turn: {}", s);
+        let (name, prime) = match s.strip_suffix('\'') {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        let (name, amount) = match name.strip_suffix('2') {
+            Some(name) => (name, 2),
+            None => (name, 1),
+        };
+        let face = CubeMove::iter()
+            .find(|mv| mv.is_clockwise() && mv.to_string() == name)
+            .ok_or_else(error)?;
+        let face = if prime { face.rev() } else { face };
+        FaceTurn::new(face, amount).ok_or_else(error)
+    }
+}
+
+// turns of the same face separated only by commuting turns are merged, such as `U D U` into
+// `U2 D`, and cancelled turns are removed
+pub fn merge_turns(moves: &[CubeMove]) -> Vec<FaceTurn> {
+    let mut res = Vec::new();
+    for &mv in moves {
+        merge_turn(&mut res, mv);
+    }
+    res
+}
+
+// merge the move into the merged turns before it
+pub fn merge_turn(turns: &mut Vec<FaceTurn>, mv: CubeMove) {
+    let Some(turn) = FaceTurn::new(mv, 1) else {
+        return;
+    };
+    let same_face = turns
+        .iter()
+        .rev()
+        .take_while(|other| other.face.commute(turn.face))
+        .position(|other| other.face == turn.face)
+        .map(|i| turns.len() - 1 - i);
+    match same_face {
+        Some(i) => match FaceTurn::new(turn.face, turns[i].amount + turn.amount) {
+            Some(merged) => turns[i] = merged,
+            None => {
+                turns.remove(i);
+            }
+        },
+        None => turns.push(turn),
+    }
+}
+
+// turns separated by spaces, such as `R U2 R' U'`
+pub fn parse_turns(text: &str) -> Result<Vec<FaceTurn>, String> {
+    text.split_whitespace().map(str::parse).collect()
+}
+
+pub fn format_turns(turns: &[FaceTurn]) -> String {
+    turns
+        .iter()
+        .map(FaceTurn::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 pub fn format_moves(moves: &[CubeMove]) -> String {
     fn group<T: Eq>(slice: &[T]) -> Vec<&[T]> {
         let mut res = Vec::new();
@@ -271,3 +651,91 @@ pub fn format_moves(moves: &[CubeMove]) -> String {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_are_parsed_from_facelets() {
+        let solved = "UUUUUUUUURRRRRRRRRFFFFFFFFFDDDDDDDDDLLLLLLLLLBBBBBBBBB";
+        assert_eq!(CubeState::from_facelets(solved), Ok(CubeState::default()));
+        let r = "UUFUUFUUFRRRRRRRRRFFDFFDFFDDDBDDBDDBLLLLLLLLLUBBUBBUBB";
+        assert_eq!(
+            CubeState::from_facelets(r),
+            Ok(CubeState::of_move(CubeMove::R))
+        );
+        let f = "UUUUUULLLURRURRURRFFFFFFFFFRRRDDDDDDLLDLLDLLDBBBBBBBBB";
+        assert_eq!(
+            CubeState::from_facelets(f),
+            Ok(CubeState::of_move(CubeMove::F))
+        );
+        let state = CubeState::from_moves(&[CubeMove::R, CubeMove::Up, CubeMove::Fp, CubeMove::B]);
+        assert_eq!(CubeState::from_facelets(&state.facelets()), Ok(state));
+        assert_eq!(CubeState::of_move(CubeMove::F).facelets(), f);
+        assert!(CubeState::from_facelets(&solved[1..]).is_err());
+        assert!(CubeState::from_facelets(&solved.replace("UUUUUUUUU", "UUUUUUUUD")).is_err());
+    }
+
+    #[test]
+    fn diffs_tell_moved_and_rotated_pieces() {
+        let solved = CubeState::default();
+        assert!(solved.diff(&solved).is_empty());
+
+        // R moves 4 corners and 4 edges to other positions
+        let r = CubeState::of_move(CubeMove::R);
+        let diff = solved.diff(&r);
+        assert_eq!(diff.counts(), (8, 0));
+        assert!(diff.to_string().contains("UR: UR/FR"));
+
+        let mut twisted = solved;
+        twisted.corners[0].1 = PieceOrientation(1);
+        let diff = solved.diff(&twisted);
+        assert_eq!(diff.counts(), (0, 1));
+        assert_eq!(diff.to_string(), "URF: URF/RFU");
+    }
+
+    #[test]
+    fn turns_are_merged_and_formatted() {
+        use CubeMove::*;
+
+        let turns = merge_turns(&[U, U, R, Rp, Rp, F, F, F, B, B, B, B]);
+        assert_eq!(format_turns(&turns), "U2 R' F'");
+        assert_eq!(parse_turns("U2 R' F'"), Ok(turns.clone()));
+        assert_eq!(parse_turns("U2'"), parse_turns("U2"));
+        assert_eq!(turns[0].moves(), vec![U, U]);
+        assert_eq!(turns[1].rev().to_string(), "R");
+        assert!(parse_turns("U3").is_err());
+    }
+
+    #[test]
+    fn moves_are_applied_to_states() {
+        use CubeMove::*;
+
+        for mv in CubeMove::iter() {
+            assert!(CubeState::from_moves(&[mv; 4]).is_solved(), "{}", mv);
+            assert!(CubeState::from_moves(&[mv, mv.rev()]).is_solved(), "{}", mv);
+        }
+        let sexy = [R, U, Rp, Up];
+        assert!(!CubeState::from_moves(&sexy).is_solved());
+        assert!(CubeState::from_moves(&sexy).is_valid());
+        assert!(CubeState::from_moves(&[R, F, Dp, B, L]).is_valid());
+        assert!(CubeState::from_moves(&sexy.repeat(6)).is_solved());
+
+        // T permutation swaps UR with UL and UBR with URF
+        let moves = [R, U, Rp, Up, Rp, F, R, R, Up, Rp, Up, R, U, Rp, Fp];
+        let state = CubeState::from_moves(&moves);
+        assert_eq!(state.edges[0], Edge(EdgePosition::UL, PieceOrientation(0)));
+        let ubr = Corner(CornerPosition::UBR, PieceOrientation(0));
+        assert_eq!(state.corners[0], ubr);
+        assert_eq!(state.then(&state.inverse()), CubeState::default());
+
+        // a single twisted corner or swapped pair of edges can't be made by turns
+        let mut twisted = CubeState::default();
+        twisted.corners[0].1 = PieceOrientation(1);
+        assert!(!twisted.is_valid());
+        let mut swapped = state;
+        swapped.edges.swap(0, 2);
+        assert!(!swapped.is_valid());
+    }
+}
@@ -1,12 +1,25 @@
 #![allow(dead_code)]
 
-use std::{f32::consts::PI, ops::Range};
+use std::{
+    collections::BTreeMap,
+    f32::consts::PI,
+    ops::{Bound, Range},
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use kiss3d::nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use nalgebra::{UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    bluetooth::gancubev2::ResponseMessage,
-    cube::{format_moves, CubeMove},
+    bluetooth::{
+        gancubev2::{CubeOrientationFrame, ResponseMessage},
+        sequencer::{MoveSequencer, Sequenced},
+    },
+    config,
+    cube::{format_moves, merge_turn, merge_turns, CubeMove, FaceTurn},
+    orientation::OrientationFilter,
+    steno::{self, StenoDecoder},
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -15,11 +28,12 @@ pub enum CuboardInputError {
     UnfinishedInput,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CuboardKey {
     pub main: CubeMove,
     pub num: usize, // 0..4
     pub is_shifted: bool,
+    pub layer: usize,
 }
 
 impl CuboardKey {
@@ -41,53 +55,135 @@ impl CuboardKey {
         ]
     };
 
-    fn parse(value: &[CubeMove], mut start: usize) -> Vec<(Self, Range<usize>)> {
+    // the index of the adjacent face in the key order of the main move
+    pub fn num_of(main: CubeMove, adj: CubeMove) -> Option<usize> {
+        Self::KEYS[main as u8 as usize]
+            .iter()
+            .position(|a| adj.abs() == *a)
+    }
+}
+
+// chunking strategy turning buffered moves into keys, which can split the moves by the pauses
+// between them
+pub trait KeyParser {
+    // parse keys greedily from `start`, stop at the first incomplete chunk; `pauses` are the
+    // pauses before the moves
+    fn parse(
+        &self,
+        moves: &[CubeMove],
+        pauses: &[Duration],
+        start: usize,
+        layer: usize,
+    ) -> Vec<(CuboardKey, Range<usize>)>;
+}
+
+// a key is a main move repeated up to `N` times followed by an adjacent move, the repeated main
+// move means shifted, and each repeat after the second moves the key to the next layer
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RepeatTurnParser<const N: usize>;
+
+pub type DoubleTurnParser = RepeatTurnParser<2>;
+pub type TripleTurnParser = RepeatTurnParser<3>;
+
+impl<const N: usize> KeyParser for RepeatTurnParser<N> {
+    fn parse(
+        &self,
+        moves: &[CubeMove],
+        _pauses: &[Duration],
+        mut start: usize,
+        layer: usize,
+    ) -> Vec<(CuboardKey, Range<usize>)> {
         let mut res = Vec::new();
-        loop {
-            let (main, adj, is_shifted) = match value[start..] {
-                [a, a_, b, ..] if a == a_ && a != b => (a, b.abs(), true),
-                [a, b, ..] if a != b => (a, b.abs(), false),
-                _ => return res,
+        while let Some(&main) = moves.get(start) {
+            let repeat = moves[start..]
+                .iter()
+                .take(N)
+                .take_while(|&&mv| mv == main)
+                .count();
+            let Some(&adj) = moves.get(start + repeat).filter(|&&adj| adj != main) else {
+                break;
             };
-            let order = &Self::KEYS[main as u8 as usize];
-            let Some(num) = order.iter().position(|a| adj == *a) else {
-                return res;
+            let Some(num) = CuboardKey::num_of(main, adj) else {
+                break;
             };
-            let end = if is_shifted { start + 3 } else { start + 2 };
+            let end = start + repeat + 1;
             res.push((
                 CuboardKey {
                     main,
                     num,
-                    is_shifted,
+                    is_shifted: repeat > 1,
+                    layer: layer + repeat.saturating_sub(2),
                 },
                 start..end,
             ));
             start = end;
         }
+        res
     }
 }
 
 pub struct CuboardBuffer {
     moves: Vec<CubeMove>,
+    // the pause before each move
+    pauses: Vec<Duration>,
+    // the moves with the turns of a face merged into half turns
+    turns: Vec<FaceTurn>,
     keys: Vec<(CuboardKey, Range<usize>)>,
+    layer: usize,
+    parser: Box<dyn KeyParser + Send>,
 }
 
 impl CuboardBuffer {
     pub fn new() -> Self {
+        CuboardBuffer::with_parser(Box::new(DoubleTurnParser::default()))
+    }
+
+    pub fn with_parser(parser: Box<dyn KeyParser + Send>) -> Self {
         CuboardBuffer {
             moves: Vec::new(),
+            pauses: Vec::new(),
+            turns: Vec::new(),
             keys: Vec::new(),
+            layer: 0,
+            parser,
         }
     }
 
+    // replay the moves from an empty buffer
+    pub fn from_moves(moves: &[CubeMove]) -> Self {
+        let mut buffer = CuboardBuffer::new();
+        for &mv in moves {
+            buffer.input(mv, Duration::ZERO);
+        }
+        buffer
+    }
+
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
+    // the layer is assigned to the keys parsed afterward
+    pub fn set_layer(&mut self, layer: usize) {
+        self.layer = layer;
+    }
+
     pub fn moves(&self) -> &[CubeMove] {
         &self.moves
     }
 
+    pub fn pauses(&self) -> &[Duration] {
+        &self.pauses
+    }
+
     pub fn keys(&self) -> &[(CuboardKey, Range<usize>)] {
         &self.keys
     }
 
+    // the collapsed moves with the turns of a face merged into half turns
+    pub fn turns(&self) -> &[FaceTurn] {
+        &self.turns
+    }
+
     pub fn remains(&self) -> &[CubeMove] {
         let chunk_end = self.keys.last().map_or(0, |k| k.1.end);
         &self.moves[chunk_end..]
@@ -95,6 +191,8 @@ impl CuboardBuffer {
 
     pub fn cancel(&mut self) {
         self.moves.clear();
+        self.pauses.clear();
+        self.turns.clear();
         self.keys.clear();
     }
 
@@ -106,10 +204,13 @@ impl CuboardBuffer {
         let chunk_end = self.keys.last().map_or(0, |k| k.1.end);
         let res = self.keys.drain(..).map(|k| k.0).collect();
         self.moves.drain(..chunk_end);
+        self.pauses.drain(..chunk_end);
+        self.turns = merge_turns(&self.moves);
         res
     }
 
-    pub fn input(&mut self, mv: CubeMove) -> bool {
+    // input the move turned after the pause
+    pub fn input(&mut self, mv: CubeMove, pause: Duration) -> bool {
         trait FirstAndLast: Iterator {
             fn first_and_last(self) -> Option<(Self::Item, Self::Item)>;
         }
@@ -145,13 +246,21 @@ impl CuboardBuffer {
             .take_while(|(_, c)| c.end > collapsed_range.start)
             .count();
 
-        let mut subseq = self.moves.drain(collapsed_range).collect::<Vec<_>>();
+        let mut subseq = self
+            .moves
+            .drain(collapsed_range.clone())
+            .collect::<Vec<_>>();
+        let mut subpauses = self.pauses.drain(collapsed_range).collect::<Vec<_>>();
         if subseq.is_empty() || subseq.last().unwrap() == &mv {
             subseq.push(mv);
+            subpauses.push(pause);
         } else {
             subseq.pop();
+            subpauses.pop();
         }
         self.moves.extend(subseq);
+        self.pauses.extend(subpauses);
+        merge_turn(&mut self.turns, mv);
 
         let mut key_changed = false;
 
@@ -161,7 +270,9 @@ impl CuboardBuffer {
         }
 
         let chunk_end = self.keys.last().map_or(0, |k| k.1.end);
-        let new_keys = CuboardKey::parse(&self.moves, chunk_end);
+        let new_keys = self
+            .parser
+            .parse(&self.moves, &self.pauses, chunk_end, self.layer);
         if !new_keys.is_empty() {
             self.keys.extend(new_keys);
             key_changed = true;
@@ -171,17 +282,185 @@ impl CuboardBuffer {
     }
 }
 
-const BUFFER_SIZE: usize = 20;
+pub const BUFFER_SIZE: usize = 20;
+
+// how turns are decoded into text
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputMode {
+    // keys of the keymap, each made of a main move and an adjacent move
+    #[default]
+    Keys,
+    // clockwise and counterclockwise turns as dots and dashes
+    Morse,
+    // moves turned together as chords of the steno dictionary
+    Steno,
+    // faces as letter groups, whose words are chosen from the dictionary by the composer
+    T9,
+}
+
+impl FromStr for InputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keys" => Ok(InputMode::Keys),
+            "morse" => Ok(InputMode::Morse),
+            "steno" => Ok(InputMode::Steno),
+            "t9" => Ok(InputMode::T9),
+            _ => Err(format!("unknown input mode: {}", s)),
+        }
+    }
+}
+
+// decoding strategy replacing the keys of the keymap, which can split the moves by the pauses
+// between them
+pub trait TurnDecoder {
+    // input the move turned after the pause, and return the accepted text
+    fn input(&mut self, mv: CubeMove, pause: Duration) -> String;
+    // the text of the moves not accepted yet
+    fn pending(&self) -> String;
+    // the moves not accepted yet, in the notation of the decoder
+    fn symbols(&self) -> String;
+    fn cancel(&mut self);
+}
+
+const MORSE_CODE: [(&str, char); 36] = [
+    (".-", 'a'),
+    ("-...", 'b'),
+    ("-.-.", 'c'),
+    ("-..", 'd'),
+    (".", 'e'),
+    ("..-.", 'f'),
+    ("--.", 'g'),
+    ("....", 'h'),
+    ("..", 'i'),
+    (".---", 'j'),
+    ("-.-", 'k'),
+    (".-..", 'l'),
+    ("--", 'm'),
+    ("-.", 'n'),
+    ("---", 'o'),
+    (".--.", 'p'),
+    ("--.-", 'q'),
+    (".-.", 'r'),
+    ("...", 's'),
+    ("-", 't'),
+    ("..-", 'u'),
+    ("...-", 'v'),
+    (".--", 'w'),
+    ("-..-", 'x'),
+    ("-.--", 'y'),
+    ("--..", 'z'),
+    ("-----", '0'),
+    (".----", '1'),
+    ("..---", '2'),
+    ("...--", '3'),
+    ("....-", '4'),
+    (".....", '5'),
+    ("-....", '6'),
+    ("--...", '7'),
+    ("---..", '8'),
+    ("----.", '9'),
+];
+
+// decode clockwise turns as dots and counterclockwise turns as dashes, where a pause before a
+// move ends the letter, and a longer pause also ends the word
+#[derive(Clone, Debug, Default)]
+pub struct MorseDecoder {
+    symbols: String,
+}
+
+impl MorseDecoder {
+    pub const LETTER_GAP: Duration = Duration::from_millis(600);
+    pub const WORD_GAP: Duration = Duration::from_millis(1500);
+
+    // the letter of the symbols so far, if any
+    pub fn letter(&self) -> Option<char> {
+        MORSE_CODE
+            .iter()
+            .find(|(code, _)| *code == self.symbols)
+            .map(|&(_, letter)| letter)
+    }
+}
+
+impl TurnDecoder for MorseDecoder {
+    fn input(&mut self, mv: CubeMove, pause: Duration) -> String {
+        let mut res = String::new();
+        if !self.symbols.is_empty() && pause >= Self::LETTER_GAP {
+            res += &self.pending();
+            self.cancel();
+            if pause >= Self::WORD_GAP {
+                res.push(' ');
+            }
+        }
+        self.symbols.push(if mv.is_clockwise() { '.' } else { '-' });
+        res
+    }
+
+    // unknown codes are dropped
+    fn pending(&self) -> String {
+        self.letter().map_or(String::new(), String::from)
+    }
+
+    fn symbols(&self) -> String {
+        self.symbols.clone()
+    }
+
+    fn cancel(&mut self) {
+        self.symbols.clear();
+    }
+}
+
+// the letters of the faces U, R, F, D, L, B in the t9 mode
+const T9_GROUPS: [&str; 6] = ["abcd", "efgh", "ijklm", "nopq", "rstu", "vwxyz"];
+
+// the digit of the group of the letter, 1 to 6 for the faces U, R, F, D, L, B
+fn t9_digit(letter: char) -> Option<char> {
+    let index = T9_GROUPS.iter().position(|group| group.contains(letter))?;
+    char::from_digit(index as u32 + 1, 10)
+}
+
+// type clockwise turns as the digits of the letter groups, and counterclockwise turns as spaces;
+// the words are disambiguated by the t9 table of the composer
+#[derive(Clone, Debug, Default)]
+pub struct T9Decoder;
+
+impl TurnDecoder for T9Decoder {
+    fn input(&mut self, mv: CubeMove, _pause: Duration) -> String {
+        if !mv.is_clockwise() {
+            return " ".to_string();
+        }
+        char::from_digit(mv.repr() as u32 / 2 + 1, 10)
+            .unwrap()
+            .to_string()
+    }
+
+    fn pending(&self) -> String {
+        String::new()
+    }
+
+    fn symbols(&self) -> String {
+        String::new()
+    }
+
+    fn cancel(&mut self) {}
+}
 
 pub struct CuboardInput {
     pub buffer: CuboardBuffer,
     pub keymap: CuboardKeymap,
+    // keymaps of the layers 1, 2, ..., the layer 0 is `keymap`
+    pub layers: Vec<CuboardKeymap>,
+    // decode the moves by another strategy instead of the keys of the keymap
+    pub decoder: Option<Box<dyn TurnDecoder + Send>>,
     handler: CuboardInputMessageHandler,
 }
 
 pub struct CuboardInputMessageHandler {
-    count: Option<u8>,
+    sequencer: MoveSequencer,
     recognizer: GyroGestureRecognizer<BUFFER_SIZE>,
+    grip: GripDetector,
 }
 
 pub type CuboardKeymap = [[[&'static str; 4]; 12]; 2];
@@ -223,7 +502,22 @@ pub enum CuboardInputEvent {
     Init,
     Cancel,
     Finish(String),
+    // skip is the number of moves lost between notifications
     Input { accept: String, skip: usize },
+    // the cube is set down, and nothing is typed until it is picked up
+    Pause,
+    Resume,
+}
+
+impl CuboardInputEvent {
+    // the gesture acknowledged by this event
+    pub fn gesture(&self) -> Option<GyroGesture> {
+        match self {
+            CuboardInputEvent::Finish(_) => Some(GyroGesture::TurningAround),
+            CuboardInputEvent::Cancel => Some(GyroGesture::Shaking),
+            _ => None,
+        }
+    }
 }
 
 impl CuboardInput {
@@ -231,45 +525,101 @@ impl CuboardInput {
         CuboardInput {
             buffer: CuboardBuffer::new(),
             keymap,
+            layers: Vec::new(),
+            decoder: match config::get().input.mode {
+                InputMode::Keys => None,
+                InputMode::Morse => Some(Box::new(MorseDecoder::default())),
+                InputMode::Steno => steno::get().map(|dictionary| {
+                    Box::new(StenoDecoder::new(dictionary)) as Box<dyn TurnDecoder + Send>
+                }),
+                InputMode::T9 => Some(Box::new(T9Decoder)),
+            },
             handler: CuboardInputMessageHandler {
-                count: None,
+                sequencer: MoveSequencer::default(),
                 recognizer: GyroGestureRecognizer::new(),
+                grip: GripDetector::new(),
             },
         }
     }
 
+    pub fn with_layers(mut self, layers: Vec<CuboardKeymap>) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn with_parser(mut self, parser: Box<dyn KeyParser + Send>) -> Self {
+        self.buffer = CuboardBuffer::with_parser(parser);
+        self
+    }
+
+    pub fn with_decoder(mut self, decoder: Box<dyn TurnDecoder + Send>) -> Self {
+        self.decoder = Some(decoder);
+        self
+    }
+
+    // fall back to the base keymap for undefined layers
+    pub fn keymap_of(&self, layer: usize) -> &CuboardKeymap {
+        match layer {
+            0 => &self.keymap,
+            _ => self.layers.get(layer - 1).unwrap_or(&self.keymap),
+        }
+    }
+
+    pub fn set_layer(&mut self, layer: usize) {
+        self.buffer.set_layer(layer);
+    }
+
+    pub fn grip(&self) -> &GripDetector {
+        &self.handler.grip
+    }
+
     pub fn buffered_text(&self) -> String {
+        if let Some(decoder) = &self.decoder {
+            return decoder.pending();
+        }
         self.buffer
             .keys()
             .iter()
-            .map(|k| self.keymap[k.0.is_shifted as usize][k.0.main as u8 as usize][k.0.num])
+            .map(|k| {
+                self.keymap_of(k.0.layer)[k.0.is_shifted as usize][k.0.main as u8 as usize][k.0.num]
+            })
             .collect::<String>()
     }
 
     pub fn complete_part(&self) -> String {
+        if self.decoder.is_some() {
+            return String::new();
+        }
         let moves = self.buffer.moves();
         let complete = &moves[..moves.len() - self.buffer.remains().len()];
         format_moves(complete)
     }
 
     pub fn remain_part(&self) -> String {
-        format_moves(self.buffer.remains())
+        match &self.decoder {
+            Some(decoder) => decoder.symbols(),
+            None => format_moves(self.buffer.remains()),
+        }
     }
 
     pub fn cancel(&mut self) {
         self.buffer.cancel();
+        if let Some(decoder) = &mut self.decoder {
+            decoder.cancel();
+        }
     }
 
     pub fn finish(&mut self) -> String {
         let accepted_text = self.buffered_text();
-        self.buffer.cancel();
+        self.cancel();
         accepted_text
     }
 
-    pub fn input(&mut self, mvs: &[CubeMove]) -> String {
+    // input the moves with the pauses before them
+    pub fn input(&mut self, mvs: &[(CubeMove, Duration)]) -> String {
         let mut res = String::new();
-        for mv in mvs {
-            self.buffer.input(*mv);
+        for &(mv, pause) in mvs {
+            self.buffer.input(mv, pause);
             if self.buffered_text().contains('\n') {
                 res += &self.finish();
             }
@@ -279,62 +629,89 @@ impl CuboardInput {
 
     pub fn handle_message(&mut self, msg: ResponseMessage) -> Option<CuboardInputEvent> {
         // ignore messages until the current count is known
-        if self.handler.count.is_none() {
+        if self.handler.sequencer.count().is_none() {
             if let ResponseMessage::State { count, state: _ } = msg {
-                self.handler.count = Some(count);
+                self.handler.sequencer.reset(count);
                 return Some(CuboardInputEvent::Init);
             } else {
                 return Some(CuboardInputEvent::Uninit);
             }
         }
 
-        if let ResponseMessage::Gyroscope {
-            q1,
-            q1p,
-            q2: _,
-            q2p: _,
-        } = msg
-        {
-            let orientation =
-                UnitQuaternion::new_normalize(Quaternion::new(q1.0, q1.1, q1.2, q1.3));
-            let angular = Vector3::new(q1p.0, q1p.1, q1p.2);
-            let gesture = self.handler.recognizer.put(orientation, angular);
-            match gesture {
-                Some(GyroGesture::TurningAround) => {
-                    let accept = self.finish();
-                    return Some(CuboardInputEvent::Finish(accept));
-                }
-                Some(GyroGesture::Shaking) => {
-                    self.cancel();
-                    return Some(CuboardInputEvent::Cancel);
-                }
-                _ => {}
-            }
+        // resynchronize the count by the requested cube state
+        if let ResponseMessage::State { count, state: _ } = msg {
+            self.handler.sequencer.reset(count);
+            return None;
         }
 
-        let ResponseMessage::Moves { count, moves, times: _ } = msg else {
-            return None;
-        };
+        let now = Instant::now();
+        let paused = self.handler.grip.is_paused();
+        let accepted = self.handler.grip.handle_message(msg, now);
+        let is_paused = self.handler.grip.is_paused();
+        if paused || is_paused {
+            // the turn waking the cube is sequenced to keep the count, but dropped
+            self.handler.sequencer.handle_message(msg, now);
+        }
+        match (paused, is_paused) {
+            (false, true) => return Some(CuboardInputEvent::Pause),
+            // the gestures begin with the cube picked up
+            (true, false) => {
+                self.handler.recognizer = GyroGestureRecognizer::new();
+                return Some(CuboardInputEvent::Resume);
+            }
+            (true, true) => return None,
+            (false, false) => {}
+        }
 
-        let prev_count = self.handler.count.unwrap();
-        self.handler.count = Some(count);
+        match self.handler.recognizer.handle_message(msg) {
+            Some(GyroGesture::TurningAround) => {
+                let accept = self.finish();
+                return Some(CuboardInputEvent::Finish(accept));
+            }
+            Some(GyroGesture::Shaking) => {
+                self.cancel();
+                return Some(CuboardInputEvent::Cancel);
+            }
+            None => {}
+        }
 
-        let diff = count.wrapping_sub(prev_count) as usize;
-        let mut skip = 7usize.saturating_sub(diff);
-        let mut accept_moves = vec![];
-        for &mv in moves[..diff.clamp(0, 7)].iter().rev() {
-            if let Some(mv) = mv {
-                accept_moves.push(mv);
-            } else {
-                skip += 1;
+        // moves after a lost notification wait for it in the sequencer, and are released by
+        // later messages
+        let sequenced = match msg {
+            ResponseMessage::Moves {
+                count,
+                moves,
+                times,
+            } => self.handler.sequencer.push(count, moves, times, now),
+            _ => {
+                let sequenced = self.handler.sequencer.flush(now);
+                if sequenced == Sequenced::default() {
+                    return None;
+                }
+                sequenced
             }
+        };
+
+        // the moves are still sequenced to keep the count, but not typed
+        if !accepted {
+            return None;
         }
-        let accept = self.input(&accept_moves);
+
+        let skip = sequenced.lost;
+        let accept = match &mut self.decoder {
+            Some(decoder) => sequenced
+                .moves
+                .iter()
+                .map(|&(mv, pause)| decoder.input(mv, pause))
+                .collect(),
+            None => self.input(&sequenced.moves),
+        };
         Some(CuboardInputEvent::Input { accept, skip })
     }
 }
 
-struct GyroGestureRecognizer<const N: usize> {
+pub struct GyroGestureRecognizer<const N: usize> {
+    filter: Box<dyn OrientationFilter + Send>,
     orientations: [UnitQuaternion<f32>; N],
     angulars: [Vector3<f32>; N],
     index: usize,
@@ -344,35 +721,45 @@ struct GyroGestureRecognizer<const N: usize> {
     debounce: usize,
 }
 
-#[derive(Clone, Copy, Debug)]
-enum GyroGesture {
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GyroGesture {
     TurningAround,
     Shaking,
 }
 
+impl GyroGesture {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            GyroGesture::TurningAround => "⏎",
+            GyroGesture::Shaking => "✗",
+        }
+    }
+}
+
 impl<const N: usize> GyroGestureRecognizer<N> {
-    fn new() -> Self {
-        const SHAKING_TORQUE: f32 = 0.25f32;
-        const TOLERANCE: f32 = 0.1;
+    pub fn new() -> Self {
+        let config = config::get().gesture;
         let orientation = UnitQuaternion::identity();
         let angular = Vector3::default();
         GyroGestureRecognizer {
+            filter: config.filter.filter(),
             orientations: [orientation; N],
             angulars: [angular; N],
             index: 0,
-            shaking_diversity: SHAKING_TORQUE,
-            turning_tolerance: TOLERANCE,
+            shaking_diversity: config.shaking_torque,
+            turning_tolerance: config.turning_tolerance,
             debounce: 0,
         }
     }
 
-    fn put(
-        &mut self,
-        orientation: UnitQuaternion<f32>,
-        angular: Vector3<f32>,
-    ) -> Option<GyroGesture> {
-        self.orientations[self.index] = orientation;
-        self.angulars[self.index] = angular;
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> Option<GyroGesture> {
+        self.put(gyro_sample(msg)?)
+    }
+
+    fn put(&mut self, frame: CubeOrientationFrame) -> Option<GyroGesture> {
+        self.filter.put(frame.orientation);
+        self.orientations[self.index] = self.filter.get();
+        self.angulars[self.index] = frame.angular;
         self.index = (self.index + 1) % N;
 
         if self.debounce > 0 {
@@ -394,25 +781,773 @@ impl<const N: usize> GyroGestureRecognizer<N> {
     }
 
     fn is_turning_around(&self) -> bool {
-        fn half_angle(q: UnitQuaternion<f32>) -> f32 {
-            (q.i.powi(2) + q.j.powi(2) + q.k.powi(2)).sqrt().atan2(q.w)
-        }
-
         let first_ori = self.orientations[self.index];
         let last_ori = self.orientations[(self.index + N - 1) % N];
-        let angle = half_angle(last_ori * first_ori.conjugate()) * 2.0;
-
-        (angle / (2.0 * PI) - 1.0).abs() < self.turning_tolerance
+        turning_deviation(first_ori, last_ori) < self.turning_tolerance
     }
 
     fn is_shaking(&self) -> bool {
-        let mean = self.angulars.iter().sum::<Vector3<f32>>() / N as f32;
-        let var = self
-            .angulars
+        torque_diversity(&self.angulars) > self.shaking_diversity
+    }
+}
+
+// the orientation and the angular velocity of the cube
+pub fn gyro_sample(msg: ResponseMessage) -> Option<CubeOrientationFrame> {
+    let (frame, _) = msg.orientation_frames()?;
+    Some(frame)
+}
+
+// how far the rotation between two orientations is from a full turn, in turns
+pub fn turning_deviation(first: UnitQuaternion<f32>, last: UnitQuaternion<f32>) -> f32 {
+    fn half_angle(q: UnitQuaternion<f32>) -> f32 {
+        (q.i.powi(2) + q.j.powi(2) + q.k.powi(2)).sqrt().atan2(q.w)
+    }
+
+    let angle = half_angle(last * first.conjugate()) * 2.0;
+    (angle / (2.0 * PI) - 1.0).abs()
+}
+
+// standard deviation of the angular velocities
+pub fn torque_diversity(angulars: &[Vector3<f32>]) -> f32 {
+    let n = angulars.len() as f32;
+    let mean = angulars.iter().sum::<Vector3<f32>>() / n;
+    let var = angulars
+        .iter()
+        .map(|p| (p - mean).norm_squared())
+        .sum::<f32>()
+        / n;
+    var.sqrt()
+}
+
+// remember the last gesture for a moment, so that it can be acknowledged by a flash
+#[derive(Default)]
+pub struct GestureFlash {
+    last: Option<(GyroGesture, Instant)>,
+}
+
+impl GestureFlash {
+    const DURATION: Duration = Duration::from_millis(200);
+
+    pub fn put(&mut self, gesture: Option<GyroGesture>, now: Instant) {
+        if let Some(gesture) = gesture {
+            self.last = Some((gesture, now));
+        }
+    }
+
+    // the gesture being flashed
+    pub fn get(&self, now: Instant) -> Option<GyroGesture> {
+        let (gesture, time) = self.last?;
+        (now.duration_since(time) < Self::DURATION).then_some(gesture)
+    }
+}
+
+// use the orientation of another cube as the modifier of keymap layers: the layer is selected by
+// the face pointing to the same direction as the U face at the reference orientation
+pub struct CuboardModifier {
+    reference: Option<UnitQuaternion<f32>>,
+    layer: usize,
+    threshold: f32,
+}
+
+impl CuboardModifier {
+    // U, R, F, D, L, B in the world frame of `CubeOrientationFrame`
+    const FACES: [Vector3<f32>; 6] = [
+        Vector3::new(0.0, 1.0, 0.0),
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, 1.0),
+        Vector3::new(0.0, -1.0, 0.0),
+        Vector3::new(-1.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, -1.0),
+    ];
+
+    pub fn new() -> Self {
+        CuboardModifier {
+            reference: None,
+            layer: 0,
+            threshold: config::get().gesture.tilt_threshold,
+        }
+    }
+
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
+    // the current orientation becomes the reference orientation (layer 0)
+    pub fn recalibrate(&mut self) {
+        self.reference = None;
+        self.layer = 0;
+    }
+
+    // return the new layer if changed
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> Option<usize> {
+        let (frame, _) = msg.orientation_frames()?;
+        let orientation = frame.orientation;
+        let reference = *self.reference.get_or_insert(orientation);
+        let up = (orientation.conjugate() * reference) * Self::FACES[0];
+
+        // keep the current layer until the orientation is held clearly
+        let layer = Self::FACES
+            .iter()
+            .position(|face| face.dot(&up) > self.threshold)?;
+        if layer == self.layer {
+            return None;
+        }
+        self.layer = layer;
+        Some(layer)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum GripState {
+    Held,
+    // the cube is steady since then, if so
+    Regripping(Option<Instant>),
+}
+
+// tell when the cube is picked up, put down or held differently: the orientation changes rapidly
+// while no face is turned, and the moves are ignored until the cube is held steady again; and
+// when the cube lies still, the input is paused until it is moved
+pub struct GripDetector {
+    // the angular speed above which the cube is moved, or 0 to never detect it
+    speed: f32,
+    last_move: Option<Instant>,
+    state: GripState,
+    // the face pointing to the user, as the index of U, R, F, D, L, B
+    facing: Option<usize>,
+    // how long the cube lies still before the input is paused, if ever
+    pause_after: Option<Duration>,
+    // since when the cube lies still, and its orientation then
+    still: Option<(Instant, UnitQuaternion<f32>)>,
+    paused: bool,
+}
+
+impl GripDetector {
+    // the rotation just after turning faces comes from the turns
+    const QUIET: Duration = Duration::from_millis(500);
+    const SETTLE: Duration = Duration::from_millis(300);
+    // a cube in hand drifts more than this, in radians
+    const STILL_ANGLE: f32 = 0.02;
+    // a cube on the desk turns slower than this by the noise, in radians per second
+    const STILL_SPEED: f32 = 0.1;
+
+    pub fn new() -> Self {
+        let pause_after = config::get().gesture.pause_after;
+        GripDetector {
+            speed: config::get().gesture.grip_speed,
+            last_move: None,
+            state: GripState::Held,
+            facing: None,
+            pause_after: (pause_after > 0.0).then(|| Duration::from_secs_f32(pause_after)),
+            still: None,
+            paused: false,
+        }
+    }
+
+    pub fn is_regripping(&self) -> bool {
+        matches!(self.state, GripState::Regripping(_))
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn facing(&self) -> Option<usize> {
+        self.facing
+    }
+
+    // tell whether the moves of the message should be typed
+    pub fn handle_message(&mut self, msg: ResponseMessage, now: Instant) -> bool {
+        if let ResponseMessage::Moves { .. } = msg {
+            // the turn wakes the cube up, but it may be knocked on the desk
+            self.still = None;
+            if std::mem::take(&mut self.paused) || self.is_regripping() {
+                return false;
+            }
+            self.last_move = Some(now);
+            return true;
+        }
+        let Some(frame) = gyro_sample(msg) else {
+            return true;
+        };
+
+        let still = frame.angular.norm() < Self::STILL_SPEED;
+        match self.still {
+            Some((since, orientation))
+                if still && orientation.angle_to(&frame.orientation) < Self::STILL_ANGLE =>
+            {
+                if self.pause_after.is_some_and(|after| now - since >= after) {
+                    self.paused = true;
+                }
+            }
+            _ if still => self.still = Some((now, frame.orientation)),
+            _ => {
+                self.still = None;
+                self.paused = false;
+            }
+        }
+
+        // the face toward the user is the one pointing to the F direction of the world frame
+        let toward = frame.orientation.conjugate() * Vector3::z();
+        let threshold = config::get().gesture.tilt_threshold;
+        let mut faces = CuboardModifier::FACES.iter();
+        if let Some(face) = faces.position(|face| face.dot(&toward) > threshold) {
+            self.facing = Some(face);
+        }
+
+        if self.speed <= 0.0 {
+            return true;
+        }
+        let moving = frame.angular.norm() > self.speed;
+        self.state = match self.state {
+            GripState::Held => {
+                let quiet = self.last_move.is_none_or(|time| now - time > Self::QUIET);
+                if moving && quiet {
+                    GripState::Regripping(None)
+                } else {
+                    GripState::Held
+                }
+            }
+            GripState::Regripping(_) if moving => GripState::Regripping(None),
+            GripState::Regripping(steady) => {
+                let since = steady.unwrap_or(now);
+                if now - since >= Self::SETTLE {
+                    GripState::Held
+                } else {
+                    GripState::Regripping(Some(since))
+                }
+            }
+        };
+        true
+    }
+}
+
+// table of composed characters, each sequence of typed characters maps to candidates
+#[derive(Clone, Default)]
+pub struct ComposeTable {
+    entries: BTreeMap<String, Vec<String>>,
+}
+
+impl ComposeTable {
+    pub fn new(entries: impl IntoIterator<Item = (String, Vec<String>)>) -> Self {
+        ComposeTable {
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    // accents typed as dead keys followed by the letter, such as "`e" for "è"
+    pub fn dead_keys() -> Self {
+        const ACCENTS: [(char, &str, &str); 5] = [
+            ('`', "aeiou", "àèìòù"),
+            ('\'', "aeiouy", "áéíóúý"),
+            ('^', "aeiou", "âêîôû"),
+            ('"', "aeiouy", "äëïöüÿ"),
+            ('~', "ano", "ãñõ"),
+        ];
+        let mut entries = Vec::new();
+        for (dead, letters, accented) in ACCENTS {
+            for (letter, composed) in letters.chars().zip(accented.chars()) {
+                entries.push((format!("{}{}", dead, letter), vec![composed.to_string()]));
+                let upper = letter.to_ascii_uppercase();
+                let composed_upper = composed.to_uppercase().to_string();
+                entries.push((format!("{}{}", dead, upper), vec![composed_upper]));
+            }
+        }
+        ComposeTable::new(entries)
+    }
+
+    // romaji to hiragana
+    pub fn kana() -> Self {
+        const VOWELS: &str = "aiueo";
+        #[rustfmt::skip]
+        const KANA: [(&str, &str); 15] = [
+            ("", "あいうえお"), ("k", "かきくけこ"), ("g", "がぎぐげご"), ("s", "さしすせそ"),
+            ("z", "ざじずぜぞ"), ("t", "たちつてと"), ("d", "だぢづでど"), ("n", "なにぬねの"),
+            ("h", "はひふへほ"), ("b", "ばびぶべぼ"), ("p", "ぱぴぷぺぽ"), ("m", "まみむめも"),
+            ("y", "や_ゆ_よ"), ("r", "らりるれろ"), ("w", "わ__を"),
+        ];
+        #[rustfmt::skip]
+        const IRREGULAR: [(&str, &str); 5] = [
+            ("shi", "し"), ("chi", "ち"), ("tsu", "つ"), ("fu", "ふ"), ("ji", "じ"),
+        ];
+        let mut entries = Vec::new();
+        for (consonant, kana) in KANA {
+            for (vowel, kana) in VOWELS.chars().zip(kana.chars()) {
+                if kana != '_' {
+                    entries.push((format!("{}{}", consonant, vowel), vec![kana.to_string()]));
+                }
+            }
+        }
+        for (romaji, kana) in IRREGULAR {
+            entries.push((romaji.to_string(), vec![kana.to_string()]));
+        }
+        entries.push(("nn".to_string(), vec!["ん".to_string()]));
+        entries.push(("n".to_string(), vec!["ん".to_string()]));
+        entries.push(("-".to_string(), vec!["ー".to_string()]));
+        ComposeTable::new(entries)
+    }
+
+    // words of the list by their digits of the t9 mode, in the order of the list, so that common
+    // words listed first are selected first; words with other letters are skipped
+    pub fn t9(words: &str) -> Self {
+        let mut entries = BTreeMap::<String, Vec<String>>::new();
+        for word in words.lines().map(str::trim) {
+            if word.is_empty() || word.starts_with('#') {
+                continue;
+            }
+            let word = word.to_lowercase();
+            let Some(digits) = word.chars().map(t9_digit).collect::<Option<String>>() else {
+                continue;
+            };
+            let candidates = entries.entry(digits).or_default();
+            if !candidates.contains(&word) {
+                candidates.push(word);
+            }
+        }
+        ComposeTable::new(entries)
+    }
+
+    // each line is a sequence followed by candidates separated by whitespace
+    pub fn parse(text: &str) -> Self {
+        ComposeTable::new(text.lines().filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            let sequence = tokens.next()?;
+            if sequence.starts_with('#') {
+                return None;
+            }
+            Some((sequence.to_string(), tokens.map(str::to_string).collect()))
+        }))
+    }
+
+    fn candidates(&self, sequence: &str) -> &[String] {
+        self.entries.get(sequence).map_or(&[], |c| &c[..])
+    }
+
+    // keys starting with the sequence are sorted right from the sequence itself
+    fn is_prefix(&self, sequence: &str) -> bool {
+        self.entries
+            .range::<str, _>((Bound::Included(sequence), Bound::Unbounded))
+            .next()
+            .is_some_and(|(key, _)| key.starts_with(sequence))
+    }
+
+    fn is_extendable(&self, sequence: &str) -> bool {
+        self.entries
+            .range::<str, _>((Bound::Excluded(sequence), Bound::Unbounded))
+            .next()
+            .is_some_and(|(key, _)| key.starts_with(sequence))
+    }
+}
+
+// compose the accepted text by the table, the pending sequence is kept as preedit until it
+// cannot be extended, and the space confirms the selected candidate
+pub struct CuboardComposer {
+    table: ComposeTable,
+    preedit: String,
+    selected: usize,
+}
+
+impl CuboardComposer {
+    pub fn new(table: ComposeTable) -> Self {
+        CuboardComposer {
+            table,
+            preedit: String::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn preedit(&self) -> &str {
+        &self.preedit
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        self.table.candidates(&self.preedit)
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.candidates().len() {
+            self.selected = index;
+        }
+    }
+
+    // commit the preedit, or the selected candidate if any
+    pub fn flush(&mut self) -> String {
+        let res = match self.candidates().get(self.selected) {
+            Some(candidate) => candidate.clone(),
+            None => self.preedit.clone(),
+        };
+        self.preedit.clear();
+        self.selected = 0;
+        res
+    }
+
+    pub fn input(&mut self, text: &str) -> String {
+        let mut res = String::new();
+        for c in text.chars() {
+            if c == ' ' && !self.candidates().is_empty() {
+                res += &self.flush();
+                continue;
+            }
+
+            let sequence = format!("{}{}", self.preedit, c);
+            if !self.table.is_prefix(&sequence) {
+                res += &self.flush();
+                if self.table.is_prefix(&c.to_string()) {
+                    self.preedit.push(c);
+                } else {
+                    res.push(c);
+                }
+            } else {
+                self.preedit = sequence;
+                self.selected = 0;
+            }
+
+            // commit at once if nothing to choose
+            if !self.table.is_extendable(&self.preedit) && self.candidates().len() == 1 {
+                res += &self.flush();
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::{
+        bluetooth::mock::MoveWindow,
+        cube::{format_turns, parse_turns, CubeState},
+    };
+
+    fn moves_strategy() -> impl Strategy<Value = Vec<CubeMove>> {
+        let moves = CubeMove::iter().collect::<Vec<_>>();
+        prop::collection::vec(prop::sample::select(moves), 0..64)
+    }
+
+    // split the moves into maximal runs of mutually commuting moves
+    fn commuting_runs(moves: &[CubeMove]) -> Vec<&[CubeMove]> {
+        let mut res = Vec::new();
+        let mut start = 0;
+        for i in 1..=moves.len() {
+            if i == moves.len() || !moves[i].commute(moves[start]) {
+                res.push(&moves[start..i]);
+                start = i;
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn full_turns_are_merged_away() {
+        use CubeMove::*;
+
+        let moves = [R, B, B, B, B, L, R, U];
+        let buffer = CuboardBuffer::from_moves(&moves);
+        assert_eq!(buffer.moves(), moves);
+        assert_eq!(format_turns(buffer.turns()), "R2 L U");
+        let moves = parse_turns("R2 L U")
+            .unwrap()
             .iter()
-            .map(|p| (p - mean).norm_squared())
-            .sum::<f32>()
-            / N as f32;
-        var > self.shaking_diversity.powi(2)
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            format_turns(CuboardBuffer::from_moves(&moves).turns()),
+            "R2 L U"
+        );
+    }
+
+    #[test]
+    fn composer_handles_dead_keys_and_kana() {
+        let mut composer = CuboardComposer::new(ComposeTable::dead_keys());
+        assert_eq!(composer.input("caf'e `x"), "café `x");
+
+        let mut composer = CuboardComposer::new(ComposeTable::kana());
+        assert_eq!(composer.input("konnnichiha"), "こんにちは");
+        assert_eq!(composer.input("n"), "");
+        assert_eq!(composer.preedit(), "n");
+        assert_eq!(composer.flush(), "ん");
+    }
+
+    #[test]
+    fn composer_selects_candidates() {
+        let table = ComposeTable::parse("ma 妈 麻 马 骂\nmao 猫");
+        let mut composer = CuboardComposer::new(table);
+        assert_eq!(composer.input("ma"), "");
+        assert_eq!(composer.candidates().len(), 4);
+        composer.select(2);
+        assert_eq!(composer.input(" "), "马");
+        assert_eq!(composer.input("mao"), "猫");
+    }
+
+    #[test]
+    fn compose_table_finds_prefixes_among_neighbors() {
+        let table = ComposeTable::parse("a A\nab AB\nb B\nbcd BCD");
+        assert!(table.is_prefix("a") && table.is_prefix("bc"));
+        assert!(!table.is_prefix("aa") && !table.is_prefix("c"));
+        assert!(table.is_extendable("a") && table.is_extendable("b"));
+        assert!(!table.is_extendable("ab") && !table.is_extendable("bcd"));
+    }
+
+    #[test]
+    fn t9_words_are_chosen_by_digits() {
+        use CubeMove::*;
+
+        let mut decoder = T9Decoder;
+        let typed = [D, D, Up, L, R, R, Bp]
+            .into_iter()
+            .map(|mv| decoder.input(mv, Duration::ZERO))
+            .collect::<String>();
+        assert_eq!(typed, "44 522 ");
+
+        let table = ComposeTable::t9("on\nno\nThe\nit's\n");
+        let mut composer = CuboardComposer::new(table);
+        assert_eq!(composer.input("44"), "");
+        assert_eq!(composer.candidates(), ["on", "no"]);
+        composer.select(1);
+        assert_eq!(composer.input(" 522"), "nothe");
+        // words with other letters are skipped
+        assert_eq!(composer.input("3"), "3");
+    }
+
+    #[test]
+    fn morse_letters_are_separated_by_pauses() {
+        use CubeMove::*;
+
+        let mut input =
+            CuboardInput::new(DEFAULT_KEYMAP).with_decoder(Box::new(MorseDecoder::default()));
+        input.handle_message(ResponseMessage::State {
+            count: 0,
+            state: None,
+        });
+        let short = Duration::from_millis(200);
+        let letter = MorseDecoder::LETTER_GAP;
+        let word = MorseDecoder::WORD_GAP;
+        // `.-` then `..`, then `-` after a word gap
+        let mut accepted = String::new();
+        let mut window = MoveWindow::default();
+        for (mv, pause) in [(R, short), (Up, short), (F, letter), (D, short), (Lp, word)] {
+            let msg = window.turn_after(mv, pause);
+            if let Some(CuboardInputEvent::Input { accept, skip: _ }) = input.handle_message(msg) {
+                accepted += &accept;
+            }
+        }
+        assert_eq!(accepted, "ai ");
+        assert_eq!(input.remain_part(), "-");
+        assert_eq!(input.finish(), "t");
+    }
+
+    #[test]
+    fn gesture_flash_expires() {
+        let now = Instant::now();
+        let mut flash = GestureFlash::default();
+        flash.put(CuboardInputEvent::Cancel.gesture(), now);
+        flash.put(None, now);
+        assert_eq!(flash.get(now), Some(GyroGesture::Shaking));
+        assert_eq!(flash.get(now + GestureFlash::DURATION), None);
+    }
+
+    #[test]
+    fn moves_are_ignored_while_regripping() {
+        fn gyroscope(angular: f32) -> ResponseMessage {
+            let q = (1.0, 0.0, 0.0, 0.0);
+            let qp = (angular, 0.0, 0.0);
+            ResponseMessage::Gyroscope {
+                q1: q,
+                q1p: qp,
+                q2: q,
+                q2p: qp,
+            }
+        }
+        let moves = ResponseMessage::Moves {
+            count: 0,
+            moves: [Some(CubeMove::R); 7],
+            times: [Duration::ZERO; 7],
+        };
+
+        let mut grip = GripDetector::new();
+        grip.speed = 0.5;
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        // turning faces shakes the cube
+        assert!(grip.handle_message(moves, at(0)));
+        assert!(grip.handle_message(gyroscope(0.75), at(100)));
+        assert!(!grip.is_regripping());
+        assert_eq!(grip.facing(), Some(2));
+
+        // moving it without turns picks it up
+        assert!(grip.handle_message(gyroscope(0.75), at(1000)));
+        assert!(grip.is_regripping());
+        assert!(!grip.handle_message(moves, at(1100)));
+        assert!(grip.handle_message(gyroscope(0.0), at(1200)));
+        assert!(grip.is_regripping());
+        assert!(grip.handle_message(gyroscope(0.0), at(1500)));
+        assert!(!grip.is_regripping());
+        assert!(grip.handle_message(moves, at(1600)));
+    }
+
+    #[test]
+    fn input_is_paused_while_the_cube_lies_still() {
+        fn gyroscope(scalar: f32, angular: f32) -> ResponseMessage {
+            let q = (scalar, (1.0 - scalar * scalar).sqrt(), 0.0, 0.0);
+            let qp = (angular, 0.0, 0.0);
+            ResponseMessage::Gyroscope {
+                q1: q,
+                q1p: qp,
+                q2: q,
+                q2p: qp,
+            }
+        }
+
+        let mut grip = GripDetector::new();
+        grip.pause_after = Some(Duration::from_secs(5));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // held in hand, the orientation drifts
+        grip.handle_message(gyroscope(1.0, 0.0), at(0));
+        grip.handle_message(gyroscope(0.99, 0.0), at(3));
+        grip.handle_message(gyroscope(0.99, 0.0), at(6));
+        assert!(!grip.is_paused());
+
+        // set on the desk, where the sensor is still noisy
+        grip.handle_message(gyroscope(0.99, 0.05), at(9));
+        assert!(grip.is_paused());
+
+        // picked up
+        grip.handle_message(gyroscope(0.99, 0.125), at(10));
+        assert!(!grip.is_paused());
+    }
+
+    #[test]
+    fn turn_waking_the_cube_is_not_typed() {
+        use CubeMove::*;
+
+        let still = ResponseMessage::Gyroscope {
+            q1: (1.0, 0.0, 0.0, 0.0),
+            q1p: (0.0, 0.0, 0.0),
+            q2: (1.0, 0.0, 0.0, 0.0),
+            q2p: (0.0, 0.0, 0.0),
+        };
+        let mut input = CuboardInput::new(DEFAULT_KEYMAP);
+        input.handler.grip.pause_after = Some(Duration::ZERO);
+        input.handle_message(ResponseMessage::State {
+            count: 0,
+            state: None,
+        });
+        input.handle_message(still);
+        assert!(matches!(
+            input.handle_message(still),
+            Some(CuboardInputEvent::Pause)
+        ));
+
+        let mut window = MoveWindow::default();
+        assert!(matches!(
+            input.handle_message(window.turn(R)),
+            Some(CuboardInputEvent::Resume)
+        ));
+        input.handle_message(window.turn(U));
+        assert_eq!(input.buffer.moves(), [U]);
+    }
+
+    #[test]
+    fn modifier_follows_the_face_pointing_up() {
+        fn gyroscope(q: (f32, f32, f32, f32)) -> ResponseMessage {
+            let qp = (0.0, 0.0, 0.0);
+            ResponseMessage::Gyroscope {
+                q1: q,
+                q1p: qp,
+                q2: q,
+                q2p: qp,
+            }
+        }
+
+        // a quarter turn about the blue axis, which points to B, tilts R up
+        let c = std::f32::consts::FRAC_1_SQRT_2;
+        let tilted = gyroscope((c, 0.0, -c, 0.0));
+        let frame = gyro_sample(tilted).unwrap();
+        assert!((frame.orientation * Vector3::x() - Vector3::y()).norm() < 1e-4);
+
+        let mut modifier = CuboardModifier::new();
+        let still = gyroscope((1.0, 0.0, 0.0, 0.0));
+        assert_eq!(modifier.handle_message(still), None);
+        assert_eq!(modifier.handle_message(tilted), Some(1));
+    }
+
+    proptest! {
+        #[test]
+        fn keys_are_reconstructible(moves in moves_strategy()) {
+            let buffer = CuboardBuffer::from_moves(&moves);
+            let keys = DoubleTurnParser::default().parse(
+                buffer.moves(),
+                buffer.pauses(),
+                0,
+                buffer.layer(),
+            );
+            prop_assert_eq!(buffer.keys(), &keys[..]);
+            prop_assert_eq!(buffer.pauses().len(), buffer.moves().len());
+            prop_assert_eq!(buffer.is_completed(), buffer.remains().is_empty());
+        }
+
+        #[test]
+        fn triple_turn_keys_are_reconstructible(moves in moves_strategy()) {
+            let mut buffer = CuboardBuffer::with_parser(Box::new(TripleTurnParser::default()));
+            for &mv in &moves {
+                buffer.input(mv, Duration::ZERO);
+            }
+            let keys = TripleTurnParser::default().parse(
+                buffer.moves(),
+                buffer.pauses(),
+                0,
+                buffer.layer(),
+            );
+            prop_assert_eq!(buffer.keys(), &keys[..]);
+        }
+
+        #[test]
+        fn cancel_pairs_are_removed(moves in moves_strategy()) {
+            let buffer = CuboardBuffer::from_moves(&moves);
+            for run in commuting_runs(buffer.moves()) {
+                for mv in run {
+                    prop_assert!(!run.contains(&mv.rev()), "{:?} in {:?}", mv, run);
+                }
+            }
+        }
+
+        #[test]
+        fn turns_are_reconstructible(moves in moves_strategy()) {
+            let buffer = CuboardBuffer::from_moves(&moves);
+            let turns = buffer.turns().to_vec();
+            let turn_moves = turns.iter().flat_map(|turn| turn.moves()).collect::<Vec<_>>();
+            prop_assert_eq!(CubeState::from_moves(&turn_moves), CubeState::from_moves(buffer.moves()));
+            let parsed = parse_turns(&format_turns(&turns)).unwrap();
+            prop_assert_eq!(&parsed, &turns);
+            let buffer = CuboardBuffer::from_moves(&turn_moves);
+            prop_assert_eq!(buffer.turns(), &turns[..]);
+        }
+
+        #[test]
+        fn inverse_sequence_cancels(moves in moves_strategy()) {
+            let inverse = moves.iter().rev().map(|mv| mv.rev()).collect::<Vec<_>>();
+            let buffer = CuboardBuffer::from_moves(&[moves, inverse].concat());
+            prop_assert!(buffer.moves().is_empty());
+            prop_assert!(buffer.keys().is_empty());
+        }
+
+        #[test]
+        fn flush_keeps_remains(moves in moves_strategy()) {
+            let mut buffer = CuboardBuffer::from_moves(&moves);
+            let remains = buffer.remains().to_vec();
+            let keys = buffer.keys().iter().map(|k| k.0.clone()).collect::<Vec<_>>();
+            prop_assert_eq!(buffer.flush(), keys);
+            prop_assert_eq!(buffer.moves(), &remains[..]);
+            prop_assert!(buffer.keys().is_empty());
+        }
     }
 }
@@ -0,0 +1,707 @@
+use std::{
+    collections::VecDeque,
+    env,
+    error::Error,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use nalgebra::UnitQuaternion;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, UnixListener, UnixStream},
+    sync::{broadcast, watch},
+    time::{interval, timeout},
+};
+
+use crate::{
+    bluetooth::{
+        connect::{ConnectError, Connector},
+        find_cube,
+        gancubev2::{GanCubeV2, NotificationHandle, ResponseMessage},
+        sequencer::MoveSequencer,
+        transport::Transport,
+    },
+    bus, config,
+    cube::CubeState,
+    cuboard::{CuboardInput, CuboardKeymap, GyroGesture, DEFAULT_KEYMAP},
+    engine::{CuboardEngine, EngineEvent},
+    i18n::{tr, Message},
+    keymap,
+    metrics::{self, Metrics},
+    orientation::OrientationFilter,
+    shutdown::Shutdown,
+    train::BATTERY_CHECK_INTERVAL,
+    webview,
+};
+
+// the socket in the runtime directory, or in the temporary directory if there is none
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("cuboard.sock"),
+        _ => env::temp_dir().join(format!("cuboard-{}.sock", unsafe { libc::getuid() })),
+    }
+}
+
+// a request is a line of json, such as `{"command":"keymap","path":"dvorak.txt"}`
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum DaemonRequest {
+    // send the events of the cube from now on
+    Subscribe,
+    // read the state of the cube
+    State,
+    // reset the state tracked by the cube to the solved state
+    Reset,
+    // switch to the keymap file, or to the default keymap
+    Keymap { path: Option<String> },
+    // send the screen of the typed text, then the events as subscribed
+    Attach,
+}
+
+// every line sent to the clients is an event, including the replies to the requests
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum DaemonEvent {
+    Moves {
+        moves: Vec<String>,
+    },
+    Input {
+        accept: String,
+        buffer: String,
+    },
+    Finish {
+        text: String,
+    },
+    Cancel,
+    // the events of the engine, which the screens of the attached clients follow
+    Commit {
+        text: String,
+    },
+    Line {
+        line: String,
+    },
+    Buffer {
+        buffer: String,
+    },
+    State {
+        corners: Vec<String>,
+        edges: Vec<String>,
+        facelets: String,
+    },
+    // the orientation of the cube as a unit quaternion
+    Orientation {
+        x: f32,
+        y: f32,
+        z: f32,
+        w: f32,
+    },
+    Battery {
+        charging: bool,
+        percentage: u32,
+    },
+    Disconnect,
+    // the recent lines typed, the unfinished line and the buffered text, for attaching clients
+    Screen {
+        lines: Vec<String>,
+        line: String,
+        buffer: String,
+    },
+    Ok,
+    Error {
+        message: String,
+    },
+}
+
+impl DaemonEvent {
+    pub fn state(state: &CubeState) -> Self {
+        DaemonEvent::State {
+            corners: state.corners.iter().map(|c| c.to_string()).collect(),
+            edges: state.edges.iter().map(|e| e.to_string()).collect(),
+            facelets: state.facelets(),
+        }
+    }
+
+    fn engine(event: &EngineEvent) -> Option<Self> {
+        match event {
+            EngineEvent::TextCommitted(text) => Some(DaemonEvent::Commit { text: text.clone() }),
+            EngineEvent::LineCompleted(line) => Some(DaemonEvent::Line { line: line.clone() }),
+            EngineEvent::BufferChanged { buffered, .. } => Some(DaemonEvent::Buffer {
+                buffer: buffered.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    // the event of the engine sent by the daemon, for the screens of the attached clients
+    pub fn engine_event(&self) -> Option<EngineEvent> {
+        match self {
+            DaemonEvent::Commit { text } => Some(EngineEvent::TextCommitted(text.clone())),
+            DaemonEvent::Line { line } => Some(EngineEvent::LineCompleted(line.clone())),
+            DaemonEvent::Buffer { buffer } => Some(EngineEvent::BufferChanged {
+                preedit: String::new(),
+                buffered: buffer.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// the text typed through the daemon, kept for the clients attaching later, and followed by the
+// clients from the events of the engine
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypedScreen {
+    pub lines: VecDeque<String>,
+    pub line: String,
+    pub buffer: String,
+}
+
+impl TypedScreen {
+    // the number of finished lines kept
+    const HISTORY: usize = 100;
+
+    // tell the line finished by the event
+    pub fn update(&mut self, event: &EngineEvent) -> Option<String> {
+        match event {
+            // the lines finished by the text follow it
+            EngineEvent::TextCommitted(text) => {
+                match text.rfind('\n') {
+                    Some(i) => self.line = text[i + 1..].to_string(),
+                    None => self.line += text,
+                }
+                None
+            }
+            EngineEvent::LineCompleted(line) => {
+                self.lines.push_back(line.clone());
+                if self.lines.len() > Self::HISTORY {
+                    self.lines.pop_front();
+                }
+                Some(line.clone())
+            }
+            EngineEvent::BufferChanged { buffered, .. } => {
+                self.buffer = buffered.clone();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    pub fn event(&self) -> DaemonEvent {
+        DaemonEvent::Screen {
+            lines: self.lines.iter().cloned().collect(),
+            line: self.line.clone(),
+            buffer: self.buffer.clone(),
+        }
+    }
+
+    // the screen sent to an attaching client
+    pub fn from_event(event: &DaemonEvent) -> Option<Self> {
+        match event {
+            DaemonEvent::Screen {
+                lines,
+                line,
+                buffer,
+            } => Some(TypedScreen {
+                lines: lines.iter().cloned().collect(),
+                line: line.clone(),
+                buffer: buffer.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// decode the messages of the cube into events, typing by the keymap
+pub struct DaemonInput {
+    pub screen: TypedScreen,
+    engine: CuboardEngine,
+    sequencer: MoveSequencer,
+    orientation: Box<dyn OrientationFilter + Send>,
+}
+
+impl DaemonInput {
+    pub fn new(keymap: CuboardKeymap) -> Self {
+        DaemonInput {
+            screen: TypedScreen::default(),
+            engine: CuboardEngine::new(CuboardInput::new(keymap)),
+            sequencer: MoveSequencer::default(),
+            orientation: config::get().view.filter.filter(),
+        }
+    }
+
+    pub fn set_keymap(&mut self, keymap: CuboardKeymap) {
+        self.engine.input.cancel();
+        self.engine.input.keymap = keymap;
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> Vec<DaemonEvent> {
+        let mut events = Vec::new();
+        let sequenced = self.sequencer.handle_message(msg, Instant::now());
+        if !sequenced.moves.is_empty() {
+            let moves = sequenced
+                .moves
+                .iter()
+                .map(|(mv, _)| mv.to_string())
+                .collect();
+            events.push(DaemonEvent::Moves { moves });
+        }
+        match msg {
+            ResponseMessage::Moves { .. } => {}
+            ResponseMessage::State { count: _, state } => {
+                if let Some(state) = state {
+                    events.push(DaemonEvent::state(&state));
+                }
+            }
+            ResponseMessage::Battery {
+                charging,
+                percentage,
+            } => events.push(DaemonEvent::Battery {
+                charging,
+                percentage,
+            }),
+            ResponseMessage::Disconnect => events.push(DaemonEvent::Disconnect),
+            ResponseMessage::Hardware(_) => {}
+            ResponseMessage::Gyroscope { .. } => {
+                if let Some((frame1, frame2)) = msg.orientation_frames() {
+                    // average the orientations of both gyroscopes
+                    let q = frame1.orientation.into_inner() + frame2.orientation.into_inner();
+                    self.orientation.put(UnitQuaternion::new_normalize(q));
+                    let q = self.orientation.get();
+                    events.push(DaemonEvent::Orientation {
+                        x: q.i,
+                        y: q.j,
+                        z: q.k,
+                        w: q.w,
+                    });
+                }
+            }
+        }
+
+        // the input events for the subscribers, gathered from the events of the engine
+        let mut accept = String::new();
+        let mut buffered = false;
+        let mut gesture = None;
+        for event in self.engine.handle_message(msg) {
+            self.screen.update(&event);
+            match &event {
+                EngineEvent::TextCommitted(text) => accept += text,
+                EngineEvent::BufferChanged { .. } => buffered = true,
+                EngineEvent::GestureFired(fired) => gesture = Some(*fired),
+                _ => {}
+            }
+            events.extend(DaemonEvent::engine(&event));
+        }
+        match gesture {
+            Some(GyroGesture::TurningAround) => events.push(DaemonEvent::Finish { text: accept }),
+            Some(GyroGesture::Shaking) => events.push(DaemonEvent::Cancel),
+            None if !accept.is_empty() || buffered => events.push(DaemonEvent::Input {
+                accept,
+                buffer: self.engine.buffered_text().to_string(),
+            }),
+            None => {}
+        }
+        events
+    }
+}
+
+const STATE_TIMEOUT: Duration = Duration::from_secs(5);
+const EVENT_CAPACITY: usize = 256;
+
+async fn send_event<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    event: &DaemonEvent,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+async fn handle_request<P: Transport>(
+    request: DaemonRequest,
+    gancube: &GanCubeV2<P>,
+    input: &Mutex<DaemonInput>,
+    events: &broadcast::Sender<DaemonEvent>,
+) -> Result<DaemonEvent, Box<dyn Error>> {
+    match request {
+        DaemonRequest::Subscribe => Ok(DaemonEvent::Ok),
+        DaemonRequest::Attach => Ok(input.lock().map_err(|err| err.to_string())?.screen.event()),
+        DaemonRequest::State => {
+            // the reply is the next state reported by the cube
+            let mut receiver = events.subscribe();
+            gancube.request_cube_state().await?;
+            let state = timeout(STATE_TIMEOUT, async {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event @ DaemonEvent::State { .. }) => return Some(event),
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            });
+            Ok(state.await.ok().flatten().ok_or("no cube state received")?)
+        }
+        DaemonRequest::Reset => {
+            gancube.reset_cube_state(CubeState::default()).await?;
+            Ok(DaemonEvent::Ok)
+        }
+        DaemonRequest::Keymap { path } => {
+            let keymap = match path {
+                Some(path) => keymap::load_keymap(path)?,
+                None => DEFAULT_KEYMAP,
+            };
+            input
+                .lock()
+                .map_err(|err| err.to_string())?
+                .set_keymap(keymap);
+            Ok(DaemonEvent::Ok)
+        }
+    }
+}
+
+// answer the requests of a client with the cube connected at that time, and forward the events
+// after it subscribes
+async fn serve_client<P: Transport>(
+    stream: UnixStream,
+    cube: watch::Receiver<Arc<GanCubeV2<P>>>,
+    input: Arc<Mutex<DaemonInput>>,
+    events: broadcast::Sender<DaemonEvent>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut subscription: Option<broadcast::Receiver<DaemonEvent>> = None;
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+                let reply = match serde_json::from_str::<DaemonRequest>(&line) {
+                    Ok(DaemonRequest::Attach) => {
+                        // the events are sent while the input is locked, so that none is missed
+                        // or repeated after the screen
+                        match input.lock() {
+                            Ok(input) => {
+                                subscription = Some(events.subscribe());
+                                Ok(input.screen.event())
+                            }
+                            Err(err) => Err(err.to_string().into()),
+                        }
+                    }
+                    Ok(request) => {
+                        if request == DaemonRequest::Subscribe {
+                            subscription = Some(events.subscribe());
+                        }
+                        let gancube = Arc::clone(&cube.borrow());
+                        handle_request(request, &gancube, &input, &events).await
+                    }
+                    Err(err) => Err(err.into()),
+                };
+                let reply = reply.unwrap_or_else(|err| DaemonEvent::Error {
+                    message: err.to_string(),
+                });
+                send_event(&mut writer, &reply).await?;
+            }
+            event = async { subscription.as_mut()?.recv().await.ok() }, if subscription.is_some() => {
+                // lagging behind drops some events, but keeps the subscription
+                if let Some(event) = event {
+                    send_event(&mut writer, &event).await?;
+                }
+            }
+        }
+    }
+}
+
+async fn serve<P: Transport + 'static>(
+    listener: UnixListener,
+    cube: watch::Receiver<Arc<GanCubeV2<P>>>,
+    input: Arc<Mutex<DaemonInput>>,
+    events: broadcast::Sender<DaemonEvent>,
+) -> std::io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let client = serve_client(stream, cube.clone(), Arc::clone(&input), events.clone());
+        tokio::spawn(async move {
+            if let Err(err) = client.await {
+                println!("client error: {}", err);
+            }
+        });
+    }
+}
+
+// register the handlers of the messages of the cube, and start receiving them
+async fn attach<P: Transport>(
+    gancube: &GanCubeV2<P>,
+    input: &Arc<Mutex<DaemonInput>>,
+    events: &broadcast::Sender<DaemonEvent>,
+    metrics: &Arc<Mutex<Metrics>>,
+) -> Result<NotificationHandle, Box<dyn Error>> {
+    if let Ok(mut metrics) = metrics.lock() {
+        metrics.connect();
+    }
+    let input_handler = Arc::clone(input);
+    let sender = events.clone();
+    let metrics_handler = Arc::clone(metrics);
+    let metrics_error_handler = Arc::clone(metrics);
+    let handle = gancube
+        .register_timed_handler(
+            Box::new(move |msg, timing| {
+                let Ok(mut input) = input_handler.lock() else {
+                    return;
+                };
+                let events = input.handle_message(msg);
+                let mut metrics = metrics_handler.lock().ok();
+                for event in events {
+                    if let Some(metrics) = &mut metrics {
+                        metrics.observe_event(&event);
+                    }
+                    let _ = sender.send(event);
+                }
+                if let Some(metrics) = &mut metrics {
+                    metrics.observe_message(&msg);
+                    metrics.observe_latency(timing);
+                }
+            }),
+            Box::new(move |err| {
+                if let Ok(mut metrics) = metrics_error_handler.lock() {
+                    metrics.observe_error(&err);
+                }
+            }),
+        )
+        .await?;
+
+    gancube.subscribe_response().await?;
+    gancube.request_cube_state().await?;
+    Ok(handle)
+}
+
+// keep the cube connected, and serve other processes through the socket, and through D-Bus, the
+// metrics endpoint and the web viewer if asked
+pub async fn run(
+    path: PathBuf,
+    keymap: CuboardKeymap,
+    dbus: bool,
+    metrics_address: Option<String>,
+    web_address: Option<String>,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn Error>> {
+    // a socket left by a crashed daemon is replaced, but not the one of a running daemon
+    if UnixStream::connect(&path).await.is_ok() {
+        return Err(format!("a daemon is already listening on {}", path.display()).into());
+    }
+
+    let builder = find_cube(&shutdown).await?;
+    println!("{}", tr(Message::Connecting, &[]));
+    let connector = Connector::new(shutdown.clone());
+    let mut gancube = Arc::new(connector.connect(&builder).await?);
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+
+    let input = Arc::new(Mutex::new(DaemonInput::new(keymap)));
+    let (events, _) = broadcast::channel(EVENT_CAPACITY);
+    let mut handle = attach(&gancube, &input, &events, &metrics).await?;
+    let (cube, cube_receiver) = watch::channel(Arc::clone(&gancube));
+
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    println!("listening on {}", path.display());
+
+    if dbus {
+        let events = events.subscribe();
+        tokio::spawn(async move {
+            if let Err(err) = bus::publish(events).await {
+                println!("{}", err);
+            }
+        });
+    }
+
+    if let Some(address) = metrics_address {
+        let listener = TcpListener::bind(&address).await?;
+        println!("metrics on http://{}/metrics", address);
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(listener, metrics).await {
+                println!("{}", err);
+            }
+        });
+    }
+
+    if let Some(address) = web_address {
+        let events = events.subscribe();
+        tokio::spawn(async move {
+            if let Err(err) = webview::serve(address, events).await {
+                println!("{}", err);
+            }
+        });
+    }
+
+    let serving = serve(listener, cube_receiver, Arc::clone(&input), events.clone());
+    tokio::pin!(serving);
+    let mut battery_check = interval(BATTERY_CHECK_INTERVAL);
+    let res = loop {
+        let lost = tokio::select! {
+            res = &mut serving => break res.map_err(Into::into),
+            _ = &mut handle => true,
+            _ = battery_check.tick() => gancube.request_battery_state().await.is_err(),
+            _ = shutdown.wait() => break Ok(()),
+        };
+        if !lost {
+            continue;
+        }
+
+        // keep reconnecting to the same cube, while the clients stay connected to the daemon
+        println!("{}", tr(Message::Reconnecting, &[]));
+        handle.abort();
+        let attached = loop {
+            let reconnected = match connector.connect(&builder).await {
+                Ok(gancube) => Arc::new(gancube),
+                Err(ConnectError::Cancelled) => break None,
+                Err(err) => {
+                    println!("{}", err);
+                    continue;
+                }
+            };
+            match attach(&reconnected, &input, &events, &metrics).await {
+                Ok(handle) => break Some((reconnected, handle)),
+                Err(err) => println!("{}", err),
+            }
+        };
+        let Some((reconnected, reattached)) = attached else {
+            break Ok(());
+        };
+        println!("{}", tr(Message::Connected, &[]));
+        gancube = reconnected;
+        handle = reattached;
+        cube.send_replace(Arc::clone(&gancube));
+    };
+
+    let _ = std::fs::remove_file(&path);
+    shutdown.close(&gancube).await?;
+    handle.abort();
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bluetooth::mock::{MockTransport, MoveWindow},
+        cube::CubeMove,
+    };
+
+    #[test]
+    fn requests_are_parsed_from_lines() {
+        let parse = |line| serde_json::from_str::<DaemonRequest>(line).unwrap();
+        assert_eq!(
+            parse(r#"{"command":"subscribe"}"#),
+            DaemonRequest::Subscribe
+        );
+        assert_eq!(
+            parse(r#"{"command":"keymap","path":"a.txt"}"#),
+            DaemonRequest::Keymap {
+                path: Some("a.txt".to_string())
+            }
+        );
+        assert_eq!(
+            parse(r#"{"command":"keymap"}"#),
+            DaemonRequest::Keymap { path: None }
+        );
+        assert!(serde_json::from_str::<DaemonRequest>(r#"{"command":"quit"}"#).is_err());
+    }
+
+    #[test]
+    fn attached_screens_follow_the_events() {
+        let mut screen = TypedScreen::default();
+        let commit = |text: &str| EngineEvent::TextCommitted(text.to_string());
+        let completed = |line: &str| EngineEvent::LineCompleted(line.to_string());
+        let buffer = |buffered: &str| EngineEvent::BufferChanged {
+            preedit: String::new(),
+            buffered: buffered.to_string(),
+        };
+        assert_eq!(screen.update(&commit("ab")), None);
+        screen.update(&buffer("c"));
+        screen.update(&commit("c\nd"));
+        assert_eq!(screen.update(&completed("abc")), Some("abc".to_string()));
+        // turning around ends no line
+        screen.update(&commit("e"));
+        screen.update(&EngineEvent::GestureFired(GyroGesture::TurningAround));
+        screen.update(&buffer(""));
+        assert_eq!(screen.line, "de");
+        screen.update(&commit("\nf"));
+        screen.update(&completed("de"));
+        screen.update(&buffer("g"));
+
+        // a client attaching later starts from the same screen
+        let event = screen.event();
+        let line = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            line,
+            r#"{"event":"screen","lines":["abc","de"],"line":"f","buffer":"g"}"#
+        );
+        let mut attached = TypedScreen::from_event(&serde_json::from_str(&line).unwrap()).unwrap();
+        assert_eq!(attached, screen);
+
+        // the events of the engine are sent to the clients
+        let event = DaemonEvent::engine(&commit("h\n")).unwrap();
+        let line = serde_json::to_string(&event).unwrap();
+        assert_eq!(line, r#"{"event":"commit","text":"h\n"}"#);
+        let event = serde_json::from_str::<DaemonEvent>(&line).unwrap();
+        attached.update(&event.engine_event().unwrap());
+        assert_eq!(attached.line, "");
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_typed_text() {
+        let mock = MockTransport::default();
+        let gancube = Arc::new(mock.builder().connect().await.unwrap());
+        let (_cube, cube_receiver) = watch::channel(gancube);
+        let input = Arc::new(Mutex::new(DaemonInput::new(DEFAULT_KEYMAP)));
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = serve_client(server, cube_receiver, Arc::clone(&input), events.clone());
+        tokio::spawn(server);
+        let (reader, mut writer) = client.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        writer
+            .write_all(b"{\"command\":\"subscribe\"}\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            r#"{"event":"ok"}"#
+        );
+
+        // `U R` types "c" by the default keymap
+        let mut messages = vec![ResponseMessage::State {
+            count: 0,
+            state: None,
+        }];
+        let mut window = MoveWindow::default();
+        messages.extend([window.turn(CubeMove::U), window.turn(CubeMove::R)]);
+        for msg in messages {
+            for event in input.lock().unwrap().handle_message(msg) {
+                events.send(event).unwrap();
+            }
+        }
+
+        let mut received = Vec::new();
+        while received.len() < 4 {
+            received.push(lines.next_line().await.unwrap().unwrap());
+        }
+        assert_eq!(received[0], r#"{"event":"moves","moves":["U"]}"#);
+        assert_eq!(received[3], r#"{"event":"input","accept":"","buffer":"c"}"#);
+
+        writer
+            .write_all(b"{\"command\":\"reset\"}\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            r#"{"event":"ok"}"#
+        );
+        assert_eq!(mock.written().len(), 1);
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use btleplug::api::PeripheralProperties;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bluetooth::{gancubev2::cipher::device_identifier, open_adapter, scan::Scanner},
+    config::DeviceConfig,
+    shutdown::Shutdown,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DevicesError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid devices: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("cannot find the data directory, set $HOME or $XDG_DATA_HOME")]
+    NoDataDir,
+    #[error("no remembered cube with the address {0}")]
+    NotFound(String),
+}
+
+// a cube used before, which is connected without scanning
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RememberedDevice {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // the device identifier in the manufacturer data, which the cipher is made from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<[u8; 9]>,
+    // used when no keymap is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keymap: Option<PathBuf>,
+}
+
+impl RememberedDevice {
+    pub fn new(properties: &PeripheralProperties, keymap: Option<PathBuf>) -> Self {
+        RememberedDevice {
+            address: properties.address.to_string(),
+            name: properties.local_name.clone(),
+            identifier: device_identifier(properties).ok().copied(),
+            keymap,
+        }
+    }
+
+    pub fn matches(&self, address: &str) -> bool {
+        self.address.eq_ignore_ascii_case(address)
+    }
+
+    // fill in the manufacturer data missing from the cached properties
+    pub fn restore(&self, properties: &mut PeripheralProperties) {
+        if let Some(identifier) = self.identifier {
+            properties
+                .manufacturer_data
+                .entry(1)
+                .or_insert_with(|| identifier.to_vec());
+        }
+    }
+}
+
+// the remembered cubes, the most recently added first
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Devices {
+    pub devices: Vec<RememberedDevice>,
+}
+
+// `$XDG_DATA_HOME/cuboard/devices.json`, or `~/.local/share/cuboard/devices.json`
+pub fn devices_path() -> Result<PathBuf, DevicesError> {
+    let dir = match (env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) {
+        (Some(dir), _) if !dir.is_empty() => PathBuf::from(dir),
+        (_, Some(home)) if !home.is_empty() => PathBuf::from(home).join(".local").join("share"),
+        _ => return Err(DevicesError::NoDataDir),
+    };
+    Ok(dir.join("cuboard").join("devices.json"))
+}
+
+impl Devices {
+    // a missing devices file has no remembered cubes
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DevicesError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Devices::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), DevicesError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // the remembered cubes of the user, or none without the data directory
+    pub fn remembered() -> Result<Self, DevicesError> {
+        match devices_path() {
+            Ok(path) => Self::load(path),
+            Err(_) => Ok(Devices::default()),
+        }
+    }
+
+    // remember the cube, replacing the old record of the same address
+    pub fn add(&mut self, device: RememberedDevice) {
+        self.devices.retain(|d| !d.matches(&device.address));
+        self.devices.insert(0, device);
+    }
+
+    pub fn remove(&mut self, address: &str) -> Result<RememberedDevice, DevicesError> {
+        let Some(index) = self.devices.iter().position(|d| d.matches(address)) else {
+            return Err(DevicesError::NotFound(address.to_string()));
+        };
+        Ok(self.devices.remove(index))
+    }
+
+    // the cube connected by default, the first one accepted by the config
+    pub fn preferred(&self, config: &DeviceConfig) -> Option<&RememberedDevice> {
+        self.devices
+            .iter()
+            .find(|d| config.accepts_address(&d.address))
+    }
+}
+
+pub fn list() -> Result<(), DevicesError> {
+    let devices = Devices::load(devices_path()?)?;
+    if devices.devices.is_empty() {
+        println!("no remembered cube, add one by `cuboard devices add`");
+    }
+    for device in &devices.devices {
+        print!("{}", device.address);
+        if let Some(name) = &device.name {
+            print!(" {}", name);
+        }
+        if let Some(keymap) = &device.keymap {
+            print!(" (keymap: {})", keymap.display());
+        }
+        println!();
+    }
+    Ok(())
+}
+
+// scan for a cube and remember it
+pub async fn add(keymap: Option<PathBuf>, shutdown: Shutdown) -> Result<(), Box<dyn Error>> {
+    let path = devices_path()?;
+    let mut devices = Devices::load(&path)?;
+
+    // scan for the GANCube with the configured bluetooth adapter, even if some cubes are remembered
+    let adapter = open_adapter().await?;
+    let builder = Scanner::new(shutdown)
+        .with_remembered(false)
+        .scan_one(&adapter)
+        .await?;
+
+    let device = RememberedDevice::new(&builder.properties, keymap);
+    println!(
+        "remember {} {}",
+        device.address,
+        device.name.as_deref().unwrap_or_default()
+    );
+    devices.add(device);
+    devices.save(&path)?;
+    Ok(())
+}
+
+pub fn remove(address: &str) -> Result<(), DevicesError> {
+    let path = devices_path()?;
+    let mut devices = Devices::load(&path)?;
+    let device = devices.remove(address)?;
+    println!("forget {}", device.address);
+    devices.save(&path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth::{
+        gancubev2::cipher::{GanCipher, GanCubeV2Cipher},
+        mock::MockTransport,
+    };
+
+    #[test]
+    fn remembered_devices_restore_the_cipher() {
+        let mut properties = MockTransport::properties();
+        let device = RememberedDevice::new(&properties, Some(PathBuf::from("keymap.txt")));
+        let address = device.address.clone();
+
+        let mut devices = Devices::default();
+        devices.add(device.clone());
+        devices.add(device.clone());
+        assert_eq!(devices.devices, vec![device.clone()]);
+
+        let text = serde_json::to_string(&devices).unwrap();
+        assert_eq!(serde_json::from_str::<Devices>(&text).unwrap(), devices);
+
+        let other = DeviceConfig {
+            address: Some("AB:12:34:56:78:9A".to_string()),
+            ..DeviceConfig::default()
+        };
+        assert_eq!(devices.preferred(&DeviceConfig::default()), Some(&device));
+        assert_eq!(devices.preferred(&other), None);
+
+        properties.manufacturer_data.clear();
+        device.restore(&mut properties);
+        let cipher = GanCipher::new(&properties).unwrap();
+        let mut frame = [0x42; 20];
+        cipher.encrypt(&mut frame);
+        MockTransport::cipher().decrypt(&mut frame);
+        assert_eq!(frame, [0x42; 20]);
+
+        assert_eq!(devices.remove(&address.to_lowercase()).unwrap(), device);
+        assert!(matches!(
+            devices.remove(&address),
+            Err(DevicesError::NotFound(_))
+        ));
+    }
+}
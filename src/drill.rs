@@ -0,0 +1,310 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    algorithm::{expand_moves, library::NamedAlgorithm, parse_algorithm, CubeOrientation},
+    bluetooth::{connect_cube, gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::{CubeMove, CubeState},
+    idle::IdleMonitor,
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    theme,
+    train::handle_until_disconnected,
+};
+
+// one way to execute the algorithm: the cube held at an orientation, and the last layer turned
+// before starting
+struct Execution {
+    // the states after each turn of the algorithm, from the start of the drill
+    states: Vec<CubeState>,
+    // the last layer, which can be turned before and after the algorithm
+    auf: CubeMove,
+    progress: usize,
+}
+
+impl Execution {
+    fn new(turns: &[CubeMove], auf: CubeMove, setup: usize) -> Self {
+        let mut state = CubeState::default();
+        for _ in 0..setup {
+            state.apply(auf);
+        }
+        let mut states = vec![state];
+        for &mv in turns {
+            state.apply(mv);
+            states.push(state);
+        }
+        Execution {
+            states,
+            auf,
+            progress: 0,
+        }
+    }
+
+    // move to the state reached, or tell if the state is one turn before the next but one state,
+    // such as the first half of a half turn or of two commuting turns
+    fn update(&mut self, state: &CubeState) -> bool {
+        if let Some(i) = self.states.iter().rposition(|s| s == state) {
+            self.progress = i;
+            return true;
+        }
+        let Some(target) = self.states.get(self.progress + 2) else {
+            return false;
+        };
+        CubeMove::iter().any(|mv| {
+            let mut next = *state;
+            next.apply(mv);
+            &next == target
+        })
+    }
+
+    fn is_finished(&self, state: &CubeState) -> bool {
+        let mut last = *self.states.last().unwrap();
+        (0..4).any(|_| {
+            last.apply(self.auf);
+            &last == state
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DrillStatus {
+    // waiting for the first turn
+    Ready,
+    // number of turns of the algorithm executed
+    Executing(usize),
+    // the last turn doesn't follow the algorithm, undo it to continue
+    Mistake,
+    Finished(Duration),
+}
+
+// check the turns of the cube against an algorithm, however the cube is held
+pub struct AlgorithmDrill {
+    turns: usize,
+    executions: Vec<Execution>,
+    state: CubeState,
+    start: Option<Instant>,
+    status: DrillStatus,
+}
+
+impl AlgorithmDrill {
+    pub fn new(alg: &NamedAlgorithm) -> Self {
+        let moves = alg.moves();
+        let mut executions = Vec::new();
+        for orientation in CubeOrientation::iter().filter(|o| !o.is_mirror()) {
+            let (turns, _) = expand_moves(&moves, orientation);
+            let (auf, _) = expand_moves(&parse_algorithm("U").unwrap(), orientation);
+            for setup in 0..4 {
+                executions.push(Execution::new(&turns, auf[0], setup));
+            }
+        }
+        AlgorithmDrill {
+            turns: alg.turns().len(),
+            executions,
+            state: CubeState::default(),
+            start: None,
+            status: DrillStatus::Ready,
+        }
+    }
+
+    pub fn status(&self) -> DrillStatus {
+        self.status
+    }
+
+    pub fn input(&mut self, mv: CubeMove) -> DrillStatus {
+        if matches!(self.status, DrillStatus::Finished(_)) {
+            return self.status;
+        }
+        let start = *self.start.get_or_insert_with(Instant::now);
+        self.state.apply(mv);
+
+        let state = self.state;
+        let mut consistent = Vec::new();
+        for execution in self.executions.iter_mut() {
+            if execution.update(&state) {
+                consistent.push(execution.progress);
+            }
+        }
+        let finished = self
+            .executions
+            .iter()
+            .any(|e| e.progress == self.turns && e.is_finished(&state));
+        self.status = if finished {
+            DrillStatus::Finished(start.elapsed())
+        } else {
+            match consistent.into_iter().max() {
+                Some(progress) => DrillStatus::Executing(progress),
+                None => DrillStatus::Mistake,
+            }
+        };
+        self.status
+    }
+}
+
+// show the algorithms one by one, and time the executions
+pub struct DrillRunner<F: Write> {
+    terminal: F,
+    algs: Vec<&'static NamedAlgorithm>,
+    index: usize,
+    drill: AlgorithmDrill,
+    sequencer: MoveSequencer,
+    times: Vec<Duration>,
+}
+
+impl<F: Write> DrillRunner<F> {
+    pub fn new(terminal: F, algs: Vec<&'static NamedAlgorithm>) -> Self {
+        let drill = AlgorithmDrill::new(algs[0]);
+        DrillRunner {
+            terminal,
+            algs,
+            index: 0,
+            drill,
+            sequencer: MoveSequencer::default(),
+            times: Vec::new(),
+        }
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) {
+        let uninit = self.sequencer.count().is_none();
+        for (mv, _) in self.sequencer.handle_message(msg, Instant::now()).moves {
+            self.input(mv);
+        }
+        match msg {
+            ResponseMessage::State { .. } if uninit => self.render(),
+            ResponseMessage::Disconnect => {
+                let _ = writeln!(self.terminal);
+                self.print_summary();
+            }
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, mv: CubeMove) {
+        if let DrillStatus::Finished(time) = self.drill.input(mv) {
+            self.render();
+            let _ = writeln!(self.terminal);
+            self.times.push(time);
+            self.index = (self.index + 1) % self.algs.len();
+            self.drill = AlgorithmDrill::new(self.algs[self.index]);
+        }
+        self.render();
+    }
+
+    // the tokens of the notation fully executed are highlighted
+    fn render(&mut self) {
+        let alg = self.algs[self.index];
+        let progress = match self.drill.status() {
+            DrillStatus::Executing(progress) => progress,
+            DrillStatus::Finished(_) => usize::MAX,
+            _ => 0,
+        };
+        let mut turns = 0;
+        let mut done = Vec::new();
+        let mut remains = Vec::new();
+        for token in alg.notation.split_whitespace() {
+            let moves = parse_algorithm(token).unwrap();
+            turns += expand_moves(&moves, CubeOrientation::default()).0.len();
+            if turns <= progress && remains.is_empty() {
+                done.push(token);
+            } else {
+                remains.push(token);
+            }
+        }
+        let status = match self.drill.status() {
+            DrillStatus::Ready => "ready".to_string(),
+            DrillStatus::Executing(_) => String::new(),
+            DrillStatus::Mistake => format!("{}undo the last turn\x1b[m", theme::get().error),
+            DrillStatus::Finished(time) => format!("{:.2}s", time.as_secs_f32()),
+        };
+        let _ = write!(
+            self.terminal,
+            "\r\x1b[2K{} {}: {}\x1b[2m {}\x1b[m  {}",
+            alg.set,
+            alg.name,
+            done.join(" "),
+            remains.join(" "),
+            status,
+        );
+        let _ = self.terminal.flush();
+    }
+
+    fn print_summary(&mut self) {
+        if self.times.is_empty() {
+            return;
+        }
+        let total = self.times.iter().sum::<Duration>();
+        let _ = writeln!(
+            self.terminal,
+            "{} executions, mean {:.2}s, best {:.2}s",
+            self.times.len(),
+            (total / self.times.len() as u32).as_secs_f32(),
+            self.times.iter().min().unwrap().as_secs_f32(),
+        );
+    }
+}
+
+pub async fn run(
+    algs: Vec<&'static NamedAlgorithm>,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    // only moves are needed
+    gancube.message_filter().set_gyroscope(false);
+    println!("connected! execute the algorithms, holding the cube in any way");
+    println!();
+
+    let mut runner = DrillRunner::new(stdout(), algs);
+    let handler: Box<dyn FnMut(ResponseMessage) + Send> =
+        Box::new(move |msg| runner.handle_message(msg));
+    handle_until_disconnected(
+        &gancube,
+        handler,
+        shutdown,
+        idle,
+        SignalMonitor::default(),
+        None,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::library::LIBRARY;
+
+    fn drill(name: &str) -> AlgorithmDrill {
+        AlgorithmDrill::new(LIBRARY.iter().find(|alg| alg.name == name).unwrap())
+    }
+
+    #[test]
+    fn drill_accepts_any_orientation_and_auf() {
+        // T permutation held with F up, after a pre-AUF
+        let t = LIBRARY.iter().find(|alg| alg.name == "T").unwrap();
+        let moves = parse_algorithm(&format!("x U {}", t.notation)).unwrap();
+        let (turns, _) = expand_moves(&moves, CubeOrientation::default());
+        let mut drill = AlgorithmDrill::new(t);
+        for mv in turns {
+            assert_ne!(drill.input(mv), DrillStatus::Mistake);
+        }
+        assert!(matches!(drill.status(), DrillStatus::Finished(_)));
+    }
+
+    #[test]
+    fn drill_reports_mistakes_until_undone() {
+        use CubeMove::*;
+
+        // a half turn in either direction
+        let mut sune = drill("sune");
+        for mv in [R, U, Rp, U, R, Up, Up] {
+            assert_ne!(sune.input(mv), DrillStatus::Mistake);
+        }
+        assert_eq!(sune.input(D), DrillStatus::Mistake);
+        assert_eq!(sune.input(Dp), DrillStatus::Executing(7));
+        assert!(matches!(sune.input(Rp), DrillStatus::Finished(_)));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{
+    bluetooth::gancubev2::ResponseMessage,
+    cuboard::{CuboardComposer, CuboardInput, CuboardInputEvent, CuboardModifier, GyroGesture},
+    hooks::Hooks,
+    transcript::{Gesture, TranscriptRecorder},
+};
+
+// what happens to the input, for front-ends to show
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineEvent {
+    // the cube state is known, and typing begins
+    Started,
+    // text accepted by the input, out of the composer
+    TextCommitted(String),
+    // the text not accepted yet, or the selected candidate, is changed
+    BufferChanged { preedit: String, buffered: String },
+    // a line finished by a newline
+    LineCompleted(String),
+    // after the text committed by it
+    GestureFired(GyroGesture),
+    // the cube is set down or picked up
+    Paused,
+    Resumed,
+}
+
+// turn the messages of the cube into the text typed by it, without showing anything
+pub struct CuboardEngine {
+    pub input: CuboardInput,
+    composer: Option<CuboardComposer>,
+    // select candidates of the composer by tilting the cube
+    selector: CuboardModifier,
+    hooks: Hooks,
+    transcript: Option<TranscriptRecorder>,
+    started: bool,
+    // the committed text of the unfinished line
+    line: String,
+    // the preedit, the buffered text and the selected candidate
+    buffer: (String, String, usize),
+}
+
+impl CuboardEngine {
+    pub fn new(input: CuboardInput) -> Self {
+        CuboardEngine {
+            input,
+            composer: None,
+            selector: CuboardModifier::new(),
+            hooks: Hooks::from_config(),
+            transcript: None,
+            started: false,
+            line: String::new(),
+            buffer: Default::default(),
+        }
+    }
+
+    pub fn with_composer(mut self, composer: CuboardComposer) -> Self {
+        self.composer = Some(composer);
+        self
+    }
+
+    pub fn with_transcript(mut self, transcript: TranscriptRecorder) -> Self {
+        self.transcript = Some(transcript);
+        self
+    }
+
+    pub fn composer(&self) -> Option<&CuboardComposer> {
+        self.composer.as_ref()
+    }
+
+    // messages before the cube state is known are ignored
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    // commit the text as if it were typed, such as the indentation repeated by the trainer
+    pub fn type_text(&mut self, text: &str) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+        self.commit(text, &mut events);
+        events
+    }
+
+    // drop the end of the unfinished line, such as the text typed after a mistake held by the
+    // trainer
+    pub fn truncate_line(&mut self, len: usize) {
+        self.line.truncate(len);
+    }
+
+    pub fn preedit(&self) -> &str {
+        &self.buffer.0
+    }
+
+    pub fn buffered_text(&self) -> &str {
+        &self.buffer.1
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.input.buffer.keys().len()
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> Vec<EngineEvent> {
+        if let Some(composer) = &mut self.composer {
+            if composer.candidates().len() > 1 {
+                if let Some(index) = self.selector.handle_message(msg) {
+                    composer.select(index);
+                }
+            } else {
+                self.selector.recalibrate();
+            }
+        }
+
+        let event = self.input.handle_message(msg);
+        self.hooks.handle_message(msg, &event);
+        self.handle_event(event)
+    }
+
+    pub fn handle_event(&mut self, event: Option<CuboardInputEvent>) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+        match event {
+            Some(CuboardInputEvent::Uninit) => {
+                return events;
+            }
+            Some(CuboardInputEvent::Init) => {
+                self.started = true;
+                events.push(EngineEvent::Started);
+            }
+            None => {}
+            Some(CuboardInputEvent::Cancel) => {
+                self.input.cancel();
+                if let Some(transcript) = &self.transcript {
+                    transcript.gesture(Gesture::Cancel);
+                }
+                events.push(EngineEvent::GestureFired(GyroGesture::Shaking));
+            }
+            Some(CuboardInputEvent::Finish(accept)) => {
+                if let Some(transcript) = &self.transcript {
+                    transcript.gesture(Gesture::Finish);
+                }
+                self.accept(&accept, &mut events);
+                if let Some(composer) = &mut self.composer {
+                    let text = composer.flush();
+                    self.commit(&text, &mut events);
+                }
+                events.push(EngineEvent::GestureFired(GyroGesture::TurningAround));
+            }
+            Some(CuboardInputEvent::Input { accept, skip: _ }) => {
+                self.accept(&accept, &mut events);
+            }
+            Some(CuboardInputEvent::Pause) => events.push(EngineEvent::Paused),
+            Some(CuboardInputEvent::Resume) => events.push(EngineEvent::Resumed),
+        }
+
+        // the decoder may keep a newline in the buffer
+        if self.input.buffered_text().contains('\n') {
+            let accept = self.input.finish();
+            self.accept(&accept, &mut events);
+        }
+
+        let buffer = (
+            self.composer
+                .as_ref()
+                .map_or(String::new(), |c| c.preedit().to_string()),
+            self.input.buffered_text(),
+            self.composer.as_ref().map_or(0, CuboardComposer::selected),
+        );
+        if let Some(transcript) = &self.transcript {
+            transcript.buffer(&buffer.1);
+        }
+        if buffer != self.buffer {
+            self.buffer = buffer;
+            events.push(EngineEvent::BufferChanged {
+                preedit: self.buffer.0.clone(),
+                buffered: self.buffer.1.clone(),
+            });
+        }
+        events
+    }
+
+    fn accept(&mut self, text: &str, events: &mut Vec<EngineEvent>) {
+        let text = match &mut self.composer {
+            Some(composer) => composer.input(text),
+            None => text.to_string(),
+        };
+        self.commit(&text, events);
+    }
+
+    fn commit(&mut self, text: &str, events: &mut Vec<EngineEvent>) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(transcript) = &self.transcript {
+            transcript.accept(text);
+        }
+        events.push(EngineEvent::TextCommitted(text.to_string()));
+        self.line += text;
+        while let Some(i) = self.line.find('\n') {
+            let rest = self.line.split_off(i + 1);
+            let mut line = std::mem::replace(&mut self.line, rest);
+            line.pop();
+            events.push(EngineEvent::LineCompleted(line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cuboard::{ComposeTable, DEFAULT_KEYMAP};
+
+    #[test]
+    fn input_events_are_turned_into_text() {
+        let input = CuboardInput::new(DEFAULT_KEYMAP);
+        let composer = CuboardComposer::new(ComposeTable::dead_keys());
+        let mut engine = CuboardEngine::new(input).with_composer(composer);
+        let text = |accept: &str| {
+            Some(CuboardInputEvent::Input {
+                accept: accept.to_string(),
+                skip: 0,
+            })
+        };
+
+        assert_eq!(engine.handle_event(Some(CuboardInputEvent::Uninit)), []);
+        assert!(!engine.is_started());
+        assert_eq!(
+            engine.handle_event(Some(CuboardInputEvent::Init)),
+            [EngineEvent::Started]
+        );
+
+        assert_eq!(
+            engine.handle_event(text("ab\ncd'")),
+            [
+                EngineEvent::TextCommitted("ab\ncd".to_string()),
+                EngineEvent::LineCompleted("ab".to_string()),
+                EngineEvent::BufferChanged {
+                    preedit: "'".to_string(),
+                    buffered: String::new(),
+                },
+            ]
+        );
+        assert_eq!(engine.line(), "cd");
+        assert_eq!(engine.handle_event(None), []);
+
+        assert_eq!(
+            engine.handle_event(Some(CuboardInputEvent::Finish("e".to_string()))),
+            [
+                EngineEvent::TextCommitted("é".to_string()),
+                EngineEvent::GestureFired(GyroGesture::TurningAround),
+                EngineEvent::BufferChanged {
+                    preedit: String::new(),
+                    buffered: String::new(),
+                },
+            ]
+        );
+        assert_eq!(engine.line(), "cdé");
+        assert_eq!(
+            engine.handle_event(Some(CuboardInputEvent::Cancel)),
+            [EngineEvent::GestureFired(GyroGesture::Shaking)]
+        );
+    }
+}
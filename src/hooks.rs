@@ -0,0 +1,247 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    thread,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bluetooth::{gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    config::{self, HookConfig},
+    cube::CubeState,
+    cuboard::CuboardInputEvent,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookKind {
+    Key,
+    Line,
+    Gesture,
+    Solved,
+}
+
+// the payload passed to the hooks as json
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum HookEvent {
+    // text accepted by the input
+    Key { text: String },
+    // a line finished by enter or by turning around
+    Line { text: String },
+    // `finish` for turning around, `cancel` for shaking
+    Gesture { gesture: String },
+    // the cube is solved again, counted from when it was last solved
+    Solved { moves: usize, seconds: f32 },
+}
+
+impl HookEvent {
+    pub fn kind(&self) -> HookKind {
+        match self {
+            HookEvent::Key { .. } => HookKind::Key,
+            HookEvent::Line { .. } => HookKind::Line,
+            HookEvent::Gesture { .. } => HookKind::Gesture,
+            HookEvent::Solved { .. } => HookKind::Solved,
+        }
+    }
+}
+
+// run `sh -c COMMAND` with the payload in the standard input and the kind of the event in
+// `$CUBOARD_EVENT`, without waiting for it
+fn run_hook(command: &str, event: &HookEvent) {
+    let payload = serde_json::to_string(event).unwrap();
+    let kind = serde_json::to_value(event.kind()).unwrap();
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("CUBOARD_EVENT", kind.as_str().unwrap_or_default())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return;
+    };
+    thread::spawn(move || {
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = writeln!(stdin, "{}", payload);
+        }
+        let _ = child.wait();
+    });
+}
+
+// the turns since the cube was last solved, from the state reported by the cube
+#[derive(Default)]
+struct SolveTracker {
+    state: Option<CubeState>,
+    sequencer: MoveSequencer,
+    moves: usize,
+    start: Option<Instant>,
+}
+
+impl SolveTracker {
+    fn handle_message(&mut self, msg: ResponseMessage) -> Option<HookEvent> {
+        let now = Instant::now();
+        let sequenced = self.sequencer.handle_message(msg, now);
+        if let ResponseMessage::State {
+            count: _,
+            state: Some(mut state),
+        } = msg
+        {
+            state.reset_centers();
+            self.state = Some(state);
+        }
+
+        let state = self.state.as_mut()?;
+        if sequenced.moves.is_empty() {
+            return None;
+        }
+        let start = *self.start.get_or_insert(now);
+        for (mv, _) in sequenced.moves {
+            state.apply(mv);
+            self.moves += 1;
+        }
+        if !state.is_solved() {
+            return None;
+        }
+        let event = HookEvent::Solved {
+            moves: self.moves,
+            seconds: start.elapsed().as_secs_f32(),
+        };
+        self.moves = 0;
+        self.start = None;
+        Some(event)
+    }
+}
+
+// run the configured hooks on the events of the input and of the cube
+pub struct Hooks {
+    hooks: Vec<HookConfig>,
+    line: String,
+    solve: SolveTracker,
+}
+
+impl Hooks {
+    pub fn new(hooks: Vec<HookConfig>) -> Self {
+        Hooks {
+            hooks,
+            line: String::new(),
+            solve: SolveTracker::default(),
+        }
+    }
+
+    pub fn from_config() -> Self {
+        Self::new(config::get().hooks.clone())
+    }
+
+    // the events of the hooks caused by the message and its input event
+    fn events(
+        &mut self,
+        msg: ResponseMessage,
+        event: &Option<CuboardInputEvent>,
+    ) -> Vec<HookEvent> {
+        let mut events = Vec::new();
+        let accept = match event {
+            Some(CuboardInputEvent::Input { accept, skip: _ }) => accept.as_str(),
+            Some(CuboardInputEvent::Finish(accept)) => accept.as_str(),
+            _ => "",
+        };
+        if !accept.is_empty() {
+            events.push(HookEvent::Key {
+                text: accept.to_string(),
+            });
+        }
+        self.line += accept;
+        while let Some(i) = self.line.find('\n') {
+            let text = self.line[..i].to_string();
+            self.line.drain(..=i);
+            events.push(HookEvent::Line { text });
+        }
+        let gesture = match event {
+            Some(CuboardInputEvent::Finish(_)) => {
+                let text = std::mem::take(&mut self.line);
+                events.push(HookEvent::Line { text });
+                Some("finish")
+            }
+            Some(CuboardInputEvent::Cancel) => Some("cancel"),
+            _ => None,
+        };
+        if let Some(gesture) = gesture {
+            events.push(HookEvent::Gesture {
+                gesture: gesture.to_string(),
+            });
+        }
+        events.extend(self.solve.handle_message(msg));
+        events
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage, event: &Option<CuboardInputEvent>) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        for event in self.events(msg, event) {
+            for hook in self.hooks.iter().filter(|hook| hook.event == event.kind()) {
+                run_hook(&hook.command, &event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bluetooth::mock::MoveWindow, cube::CubeMove};
+
+    #[test]
+    fn lines_are_finished_by_enter_and_turning_around() {
+        let mut hooks = Hooks::new(Vec::new());
+        let input = |accept: &str| {
+            Some(CuboardInputEvent::Input {
+                accept: accept.to_string(),
+                skip: 0,
+            })
+        };
+        let events = hooks.events(ResponseMessage::Disconnect, &input("ab\ncd"));
+        assert_eq!(
+            events,
+            [
+                HookEvent::Key {
+                    text: "ab\ncd".to_string()
+                },
+                HookEvent::Line {
+                    text: "ab".to_string()
+                },
+            ]
+        );
+        let finish = Some(CuboardInputEvent::Finish("e".to_string()));
+        let events = hooks.events(ResponseMessage::Disconnect, &finish);
+        assert_eq!(
+            events[1..],
+            [
+                HookEvent::Line {
+                    text: "cde".to_string()
+                },
+                HookEvent::Gesture {
+                    gesture: "finish".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn solves_are_counted_from_the_reported_state() {
+        let mut tracker = SolveTracker::default();
+        let state = ResponseMessage::State {
+            count: 0,
+            state: Some(CubeState::default()),
+        };
+        assert_eq!(tracker.handle_message(state), None);
+        let mut window = MoveWindow::default();
+        let mut solved = Vec::new();
+        for mv in [CubeMove::R, CubeMove::U, CubeMove::Up, CubeMove::Rp] {
+            solved.extend(tracker.handle_message(window.turn(mv)));
+        }
+        assert!(matches!(solved[..], [HookEvent::Solved { moves: 4, .. }]));
+    }
+}
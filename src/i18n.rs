@@ -0,0 +1,290 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::config;
+
+// the language of the user-facing strings
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Locale {
+    #[default]
+    En,
+    Ja,
+    // Mandarin in simplified characters
+    Zh,
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Locale::En),
+            "ja" => Ok(Locale::Ja),
+            "zh" => Ok(Locale::Zh),
+            _ => Err(format!("unknown locale: {}", s)),
+        }
+    }
+}
+
+// the user-facing strings, where `{}` is replaced by the arguments in order
+#[derive(Clone, Copy, PartialEq, Eq, Debug, EnumIter)]
+pub enum Message {
+    Connecting,
+    ConnectingCubes,
+    Connected,
+    ChordingHint,
+    ConsoleHint,
+    ReplayHint,
+    RecordedTo,
+    Error,
+    AutoDisconnect,
+    Disconnecting,
+    Reconnecting,
+    RequestBattery,
+    RequestState,
+    ResetState,
+    IdleFinish,
+    WeakSignal,
+    StateDesync,
+    StateTrusted,
+    StateReset,
+    PausedHint,
+    Regripping,
+    RegrippingFacing,
+    MistakeHint,
+    NearSolved,
+    Started,
+    Ready,
+    Cancelled,
+    Paused,
+    Resumed,
+    Typed,
+    LineCommitted,
+    Space,
+    Tab,
+    HelpHelp,
+    HelpQuit,
+    HelpDisconnect,
+    HelpSubscribe,
+    HelpUnsubscribe,
+    HelpReset,
+    HelpStats,
+    HelpWatch,
+    HelpSleep,
+    HelpRaw,
+    HelpUnknown,
+}
+
+impl Message {
+    fn english(self) -> &'static str {
+        match self {
+            Message::Connecting => "connect to GANCube...",
+            Message::ConnectingCubes => "connect to GANCubes...",
+            Message::Connected => "connected! have fun~",
+            Message::ChordingHint => {
+                "type with the first cube, and tilt the second cube to shift layers"
+            }
+            Message::ConsoleHint => {
+                "type `help` for commands, TAB to complete and UP/DOWN for history"
+            }
+            Message::ReplayHint => "space: pause, [ ]: speed, , .: scrub, home: restart",
+            Message::RecordedTo => "recorded to {}",
+            Message::Error => "error: {}",
+            Message::AutoDisconnect => "cube auto-disconnect",
+            Message::Disconnecting => "disconnect GANCube...",
+            Message::Reconnecting => "the GANCube is lost, reconnect to it...",
+            Message::RequestBattery => "request battery state",
+            Message::RequestState => "request cube state",
+            Message::ResetState => "reset cube state",
+            Message::IdleFinish => "idle: finish in {}s",
+            Message::WeakSignal => "weak signal {}dBm, moves may be lost",
+            Message::StateDesync => {
+                "the cube state differs from the moves ({}), turn the cube around to trust it, or \
+                 shake it to reset it"
+            }
+            Message::StateTrusted => "the cube state is trusted",
+            Message::StateReset => "the cube state is reset to the moves",
+            Message::PausedHint => "paused, pick up the cube to resume",
+            Message::Regripping => "re-gripping",
+            Message::RegrippingFacing => "re-gripping, {} face toward you",
+            Message::MistakeHint => "shake to erase the mistake",
+            Message::NearSolved => "{} from solved: {}",
+            Message::Started => "started",
+            Message::Ready => "ready",
+            Message::Cancelled => "cancelled",
+            Message::Paused => "paused",
+            Message::Resumed => "resumed",
+            Message::Typed => "typed: {}",
+            Message::LineCommitted => "line committed",
+            Message::Space => "space",
+            Message::Tab => "tab",
+            Message::HelpHelp => "show this help",
+            Message::HelpQuit => "exit",
+            Message::HelpDisconnect => "disconnect GANCube and exit",
+            Message::HelpSubscribe => "subscribe response characteristic",
+            Message::HelpUnsubscribe => "unsubscribe response characteristic",
+            Message::HelpReset => "reset cube state, to the solved state by default",
+            Message::HelpStats => "show notification statistics",
+            Message::HelpWatch => "show or hide gyroscope notifications",
+            Message::HelpSleep => "wait for notifications, useful in scripts",
+            Message::HelpRaw => "arbitrary request, such as `raw 04` (may destroy your device)",
+            Message::HelpUnknown => "access unknown characteristic (may destroy your device)",
+        }
+    }
+
+    fn japanese(self) -> &'static str {
+        match self {
+            Message::Connecting => "GANCube に接続中...",
+            Message::ConnectingCubes => "GANCube 2台に接続中...",
+            Message::Connected => "接続しました!楽しんでね~",
+            Message::ChordingHint => "1台目のキューブで入力し、2台目を傾けてレイヤーを切り替えます",
+            Message::ConsoleHint => "`help` でコマンド一覧、TAB で補完、UP/DOWN で履歴",
+            Message::ReplayHint => "space: 一時停止, [ ]: 速度, , .: シーク, home: 最初から",
+            Message::RecordedTo => "{} に記録しました",
+            Message::Error => "エラー: {}",
+            Message::AutoDisconnect => "キューブが自動的に切断されました",
+            Message::Disconnecting => "GANCube を切断中...",
+            Message::Reconnecting => "GANCube との接続が切れました、再接続中...",
+            Message::RequestBattery => "バッテリー状態を要求",
+            Message::RequestState => "キューブの状態を要求",
+            Message::ResetState => "キューブの状態をリセット",
+            Message::IdleFinish => "待機中: あと{}秒で終了",
+            Message::WeakSignal => "電波が弱い {}dBm、回転が失われるかも",
+            Message::StateDesync => {
+                "キューブの状態が回転と異なります ({})、キューブを裏返すと信頼、振るとリセット"
+            }
+            Message::StateTrusted => "キューブの状態を信頼しました",
+            Message::StateReset => "キューブの状態を回転に合わせてリセットしました",
+            Message::PausedHint => "一時停止中、キューブを持ち上げると再開",
+            Message::Regripping => "持ち直し中",
+            Message::RegrippingFacing => "持ち直し中、{} 面が手前",
+            Message::MistakeHint => "振ってミスを消す",
+            Message::NearSolved => "あと{}手で完成: {}",
+            Message::Started => "開始",
+            Message::Ready => "準備完了",
+            Message::Cancelled => "キャンセル",
+            Message::Paused => "一時停止",
+            Message::Resumed => "再開",
+            Message::Typed => "入力: {}",
+            Message::LineCommitted => "行を確定",
+            Message::Space => "スペース",
+            Message::Tab => "タブ",
+            Message::HelpHelp => "このヘルプを表示",
+            Message::HelpQuit => "終了",
+            Message::HelpDisconnect => "GANCube を切断して終了",
+            Message::HelpSubscribe => "応答キャラクタリスティックを購読",
+            Message::HelpUnsubscribe => "応答キャラクタリスティックの購読を解除",
+            Message::HelpReset => "キューブの状態をリセット、既定は完成状態",
+            Message::HelpStats => "通知の統計を表示",
+            Message::HelpWatch => "ジャイロスコープの通知を表示/非表示",
+            Message::HelpSleep => "通知を待つ、スクリプト向け",
+            Message::HelpRaw => "任意のリクエスト、例えば `raw 04` (デバイスを壊すおそれあり)",
+            Message::HelpUnknown => {
+                "未知のキャラクタリスティックにアクセス (デバイスを壊すおそれあり)"
+            }
+        }
+    }
+
+    fn mandarin(self) -> &'static str {
+        match self {
+            Message::Connecting => "正在连接 GANCube...",
+            Message::ConnectingCubes => "正在连接两个 GANCube...",
+            Message::Connected => "已连接!玩得开心~",
+            Message::ChordingHint => "用第一个魔方输入,倾斜第二个魔方切换层",
+            Message::ConsoleHint => "输入 `help` 查看命令,TAB 补全,UP/DOWN 浏览历史",
+            Message::ReplayHint => "space: 暂停, [ ]: 速度, , .: 拖动, home: 重新开始",
+            Message::RecordedTo => "已记录到 {}",
+            Message::Error => "错误: {}",
+            Message::AutoDisconnect => "魔方已自动断开",
+            Message::Disconnecting => "正在断开 GANCube...",
+            Message::Reconnecting => "GANCube 已断开,正在重新连接...",
+            Message::RequestBattery => "请求电池状态",
+            Message::RequestState => "请求魔方状态",
+            Message::ResetState => "重置魔方状态",
+            Message::IdleFinish => "空闲: {}秒后结束",
+            Message::WeakSignal => "信号弱 {}dBm,转动可能丢失",
+            Message::StateDesync => "魔方状态与转动不一致 ({}),翻转魔方以信任它,摇动魔方以重置它",
+            Message::StateTrusted => "已信任魔方状态",
+            Message::StateReset => "魔方状态已按转动重置",
+            Message::PausedHint => "已暂停,拿起魔方以继续",
+            Message::Regripping => "正在换手",
+            Message::RegrippingFacing => "正在换手,{} 面朝向你",
+            Message::MistakeHint => "摇动魔方以删除错误",
+            Message::NearSolved => "还差{}步复原: {}",
+            Message::Started => "开始",
+            Message::Ready => "就绪",
+            Message::Cancelled => "已取消",
+            Message::Paused => "已暂停",
+            Message::Resumed => "已继续",
+            Message::Typed => "输入: {}",
+            Message::LineCommitted => "行已提交",
+            Message::Space => "空格",
+            Message::Tab => "制表符",
+            Message::HelpHelp => "显示此帮助",
+            Message::HelpQuit => "退出",
+            Message::HelpDisconnect => "断开 GANCube 并退出",
+            Message::HelpSubscribe => "订阅响应特征",
+            Message::HelpUnsubscribe => "取消订阅响应特征",
+            Message::HelpReset => "重置魔方状态,默认为复原状态",
+            Message::HelpStats => "显示通知统计",
+            Message::HelpWatch => "显示或隐藏陀螺仪通知",
+            Message::HelpSleep => "等待通知,适用于脚本",
+            Message::HelpRaw => "任意请求,例如 `raw 04`(可能损坏设备)",
+            Message::HelpUnknown => "访问未知特征(可能损坏设备)",
+        }
+    }
+
+    pub fn text_in(self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::En => self.english(),
+            Locale::Ja => self.japanese(),
+            Locale::Zh => self.mandarin(),
+        }
+    }
+}
+
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut parts = template.split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            text += &arg.to_string();
+        }
+        text += part;
+    }
+    text
+}
+
+// the message in the configured locale
+pub fn tr(message: Message, args: &[&dyn Display]) -> String {
+    fill(message.text_in(config::get().ui.locale), args)
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    #[test]
+    fn translations_keep_the_arguments() {
+        for message in Message::iter() {
+            let count = message.english().matches("{}").count();
+            for locale in [Locale::Ja, Locale::Zh] {
+                assert_eq!(
+                    message.text_in(locale).matches("{}").count(),
+                    count,
+                    "{:?} in {:?}",
+                    message,
+                    locale
+                );
+            }
+        }
+        let text = fill(Message::RegrippingFacing.text_in(Locale::Ja), &[&"F"]);
+        assert_eq!(text, "持ち直し中、F 面が手前");
+        assert_eq!(fill("{} of {}", &[&1, &2]), "1 of 2");
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bluetooth::{
+        gancubev2::{Error, GanCubeV2},
+        transport::Transport,
+    },
+    i18n::{tr, Message},
+    shutdown::Shutdown,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum IdlePolicy {
+    // let the cube auto-disconnect by itself
+    #[default]
+    Ignore,
+    // send a request to the cube to keep the connection alive
+    Keepalive,
+    // finish the session cleanly
+    Finish,
+}
+
+impl FromStr for IdlePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(IdlePolicy::Ignore),
+            "keepalive" => Ok(IdlePolicy::Keepalive),
+            "finish" => Ok(IdlePolicy::Finish),
+            _ => Err(format!("unknown idle policy: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct IdleMonitor {
+    last_move: Arc<Mutex<Instant>>,
+    pub policy: IdlePolicy,
+    pub timeout: Duration,
+}
+
+impl IdleMonitor {
+    const WARNING_TIME: Duration = Duration::from_secs(30);
+    pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new(policy: IdlePolicy, timeout: Duration) -> Self {
+        IdleMonitor {
+            last_move: Arc::new(Mutex::new(Instant::now())),
+            policy,
+            timeout,
+        }
+    }
+
+    pub fn touch(&self) {
+        if let Ok(mut last_move) = self.last_move.lock() {
+            *last_move = Instant::now();
+        }
+    }
+
+    pub fn idle_time(&self) -> Duration {
+        self.last_move
+            .lock()
+            .map_or(Duration::ZERO, |last_move| last_move.elapsed())
+    }
+
+    pub fn remaining_time(&self) -> Duration {
+        self.timeout.saturating_sub(self.idle_time())
+    }
+
+    // message shown in the status line when the session is about to finish
+    pub fn warning(&self) -> Option<String> {
+        if self.policy != IdlePolicy::Finish {
+            return None;
+        }
+        let remaining = self.remaining_time();
+        if remaining > Self::WARNING_TIME {
+            return None;
+        }
+        Some(tr(Message::IdleFinish, &[&remaining.as_secs()]))
+    }
+
+    pub async fn check<P: Transport>(
+        &self,
+        gancube: &GanCubeV2<P>,
+        shutdown: &Shutdown,
+    ) -> Result<(), Error> {
+        if self.idle_time() < self.timeout {
+            return Ok(());
+        }
+
+        match self.policy {
+            IdlePolicy::Ignore => {}
+            IdlePolicy::Keepalive => {
+                gancube.request_battery_state().await?;
+                self.touch();
+            }
+            IdlePolicy::Finish => {
+                shutdown.request();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for IdleMonitor {
+    fn default() -> Self {
+        const TIMEOUT: Duration = Duration::from_secs(5 * 60);
+        IdleMonitor::new(IdlePolicy::default(), TIMEOUT)
+    }
+}
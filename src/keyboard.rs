@@ -0,0 +1,356 @@
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyboardError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[cfg_attr(target_os = "linux", allow(dead_code))]
+    #[error("the virtual keyboard needs uinput, which is only on linux")]
+    Unsupported,
+}
+
+// keys of the virtual keyboard, by the codes of linux input events
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[repr(u16)]
+pub enum Key {
+    Num1 = 2,
+    Num2 = 3,
+    Num3 = 4,
+    Num4 = 5,
+    Num5 = 6,
+    Num6 = 7,
+    Num7 = 8,
+    Num8 = 9,
+    Num9 = 10,
+    Num0 = 11,
+    Minus = 12,
+    Equal = 13,
+    Tab = 15,
+    Q = 16,
+    W = 17,
+    E = 18,
+    R = 19,
+    T = 20,
+    Y = 21,
+    U = 22,
+    I = 23,
+    O = 24,
+    P = 25,
+    LeftBrace = 26,
+    RightBrace = 27,
+    Enter = 28,
+    A = 30,
+    S = 31,
+    D = 32,
+    F = 33,
+    G = 34,
+    H = 35,
+    J = 36,
+    K = 37,
+    L = 38,
+    Semicolon = 39,
+    Apostrophe = 40,
+    Grave = 41,
+    LeftShift = 42,
+    Backslash = 43,
+    Z = 44,
+    X = 45,
+    C = 46,
+    V = 47,
+    B = 48,
+    N = 49,
+    M = 50,
+    Comma = 51,
+    Dot = 52,
+    Slash = 53,
+    Space = 57,
+    Mute = 113,
+    VolumeDown = 114,
+    VolumeUp = 115,
+    NextSong = 163,
+    PlayPause = 164,
+    PreviousSong = 165,
+    Rewind = 168,
+    FastForward = 208,
+    LeftButton = 0x110,
+    RightButton = 0x111,
+    MiddleButton = 0x112,
+}
+
+impl Key {
+    pub const ALL: [Key; 62] = [
+        Key::Num1,
+        Key::Num2,
+        Key::Num3,
+        Key::Num4,
+        Key::Num5,
+        Key::Num6,
+        Key::Num7,
+        Key::Num8,
+        Key::Num9,
+        Key::Num0,
+        Key::Minus,
+        Key::Equal,
+        Key::Tab,
+        Key::Q,
+        Key::W,
+        Key::E,
+        Key::R,
+        Key::T,
+        Key::Y,
+        Key::U,
+        Key::I,
+        Key::O,
+        Key::P,
+        Key::LeftBrace,
+        Key::RightBrace,
+        Key::Enter,
+        Key::A,
+        Key::S,
+        Key::D,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::Semicolon,
+        Key::Apostrophe,
+        Key::Grave,
+        Key::LeftShift,
+        Key::Backslash,
+        Key::Z,
+        Key::X,
+        Key::C,
+        Key::V,
+        Key::B,
+        Key::N,
+        Key::M,
+        Key::Comma,
+        Key::Dot,
+        Key::Slash,
+        Key::Space,
+        Key::Mute,
+        Key::VolumeDown,
+        Key::VolumeUp,
+        Key::NextSong,
+        Key::PlayPause,
+        Key::PreviousSong,
+        Key::Rewind,
+        Key::FastForward,
+        Key::LeftButton,
+        Key::RightButton,
+        Key::MiddleButton,
+    ];
+
+    // the characters typed by the keys without and with shift, on the US layout
+    #[rustfmt::skip]
+    const LAYOUT: [(Key, char, char); 50] = [
+        (Key::Num1, '1', '!'), (Key::Num2, '2', '@'), (Key::Num3, '3', '#'),
+        (Key::Num4, '4', '$'), (Key::Num5, '5', '%'), (Key::Num6, '6', '^'),
+        (Key::Num7, '7', '&'), (Key::Num8, '8', '*'), (Key::Num9, '9', '('),
+        (Key::Num0, '0', ')'), (Key::Minus, '-', '_'), (Key::Equal, '=', '+'),
+        (Key::Tab, '\t', '\t'), (Key::Q, 'q', 'Q'), (Key::W, 'w', 'W'), (Key::E, 'e', 'E'),
+        (Key::R, 'r', 'R'), (Key::T, 't', 'T'), (Key::Y, 'y', 'Y'), (Key::U, 'u', 'U'),
+        (Key::I, 'i', 'I'), (Key::O, 'o', 'O'), (Key::P, 'p', 'P'), (Key::LeftBrace, '[', '{'),
+        (Key::RightBrace, ']', '}'), (Key::Enter, '\n', '\n'), (Key::A, 'a', 'A'),
+        (Key::S, 's', 'S'), (Key::D, 'd', 'D'), (Key::F, 'f', 'F'), (Key::G, 'g', 'G'),
+        (Key::H, 'h', 'H'), (Key::J, 'j', 'J'), (Key::K, 'k', 'K'), (Key::L, 'l', 'L'),
+        (Key::Semicolon, ';', ':'), (Key::Apostrophe, '\'', '"'), (Key::Grave, '`', '~'),
+        (Key::Backslash, '\\', '|'), (Key::Z, 'z', 'Z'), (Key::X, 'x', 'X'), (Key::C, 'c', 'C'),
+        (Key::V, 'v', 'V'), (Key::B, 'b', 'B'), (Key::N, 'n', 'N'), (Key::M, 'm', 'M'),
+        (Key::Comma, ',', '<'), (Key::Dot, '.', '>'), (Key::Slash, '/', '?'),
+        (Key::Space, ' ', ' '),
+    ];
+
+    // the key typing the character, and whether shift is needed
+    pub fn for_char(c: char) -> Option<(Key, bool)> {
+        Self::LAYOUT.iter().find_map(|&(key, lower, upper)| {
+            if c == lower {
+                Some((key, false))
+            } else if c == upper {
+                Some((key, true))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+// keys tapped together on the virtual keyboard, named for the terminal
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyAction {
+    pub name: &'static str,
+    // pressed in order and released in reverse order
+    pub keys: &'static [Key],
+}
+
+// the output of the profiles
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Keys(KeyAction),
+    // relative motion of the pointer in pixels, where y goes down
+    Pointer(i32, i32),
+    // clicks of the wheel, positive for scrolling up
+    Scroll(i32),
+}
+
+impl Action {
+    // the name shown in the terminal, the pointer motion is too frequent to show
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Action::Keys(action) => Some(action.name),
+            Action::Pointer(..) => None,
+            Action::Scroll(clicks) if *clicks > 0 => Some("scroll up"),
+            Action::Scroll(_) => Some("scroll down"),
+        }
+    }
+}
+
+// a keyboard with a pointer created by uinput, which is seen by the OS as a real device
+#[cfg(target_os = "linux")]
+pub struct VirtualKeyboard {
+    device: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+impl VirtualKeyboard {
+    const EV_SYN: u16 = 0x00;
+    const EV_KEY: u16 = 0x01;
+    const EV_REL: u16 = 0x02;
+    const REL_X: u16 = 0x00;
+    const REL_Y: u16 = 0x01;
+    const REL_WHEEL: u16 = 0x08;
+    // _IOW('U', 100, int), _IOW('U', 101, int), _IOW('U', 102, int), _IO('U', 1) and _IO('U', 2)
+    const UI_SET_EVBIT: libc::c_ulong = 0x40045564;
+    const UI_SET_KEYBIT: libc::c_ulong = 0x40045565;
+    const UI_SET_RELBIT: libc::c_ulong = 0x40045566;
+    const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+    const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+
+    pub fn new() -> Result<Self, KeyboardError> {
+        use std::os::fd::AsRawFd;
+
+        let device = std::fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/uinput")?;
+        let fd = device.as_raw_fd();
+        let check = |res: libc::c_int| {
+            if res < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        };
+        for kind in [Self::EV_KEY, Self::EV_REL] {
+            check(unsafe { libc::ioctl(fd, Self::UI_SET_EVBIT as _, kind as libc::c_int) })?;
+        }
+        for key in Key::ALL {
+            check(unsafe { libc::ioctl(fd, Self::UI_SET_KEYBIT as _, key as libc::c_int) })?;
+        }
+        for axis in [Self::REL_X, Self::REL_Y, Self::REL_WHEEL] {
+            check(unsafe { libc::ioctl(fd, Self::UI_SET_RELBIT as _, axis as libc::c_int) })?;
+        }
+
+        let mut setup: libc::uinput_user_dev = unsafe { std::mem::zeroed() };
+        for (dst, src) in setup.name.iter_mut().zip(b"cuboard") {
+            *dst = *src as libc::c_char;
+        }
+        setup.id.bustype = 0x06; // BUS_VIRTUAL
+        setup.id.version = 1;
+        let mut keyboard = VirtualKeyboard { device };
+        keyboard.write_raw(&setup)?;
+        check(unsafe { libc::ioctl(fd, Self::UI_DEV_CREATE as _) })?;
+        Ok(keyboard)
+    }
+
+    fn write_raw<T>(&mut self, value: &T) -> io::Result<()> {
+        use std::io::Write;
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>())
+        };
+        self.device.write_all(bytes)
+    }
+
+    fn send(&mut self, kind: u16, code: u16, value: i32) -> io::Result<()> {
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = kind;
+        event.code = code;
+        event.value = value;
+        self.write_raw(&event)
+    }
+
+    pub fn perform(&mut self, action: Action) -> io::Result<()> {
+        match action {
+            Action::Keys(action) => self.tap(action),
+            Action::Pointer(dx, dy) => {
+                self.send(Self::EV_REL, Self::REL_X, dx)?;
+                self.send(Self::EV_REL, Self::REL_Y, dy)?;
+                self.send(Self::EV_SYN, 0, 0)
+            }
+            Action::Scroll(clicks) => {
+                self.send(Self::EV_REL, Self::REL_WHEEL, clicks)?;
+                self.send(Self::EV_SYN, 0, 0)
+            }
+        }
+    }
+
+    // type the text on the US layout, characters without a key are dropped
+    pub fn type_text(&mut self, text: &str) -> io::Result<()> {
+        for (key, shift) in text.chars().filter_map(Key::for_char) {
+            let keys: &[Key] = if shift {
+                &[Key::LeftShift, key]
+            } else {
+                &[key]
+            };
+            for &key in keys {
+                self.send(Self::EV_KEY, key as u16, 1)?;
+            }
+            self.send(Self::EV_SYN, 0, 0)?;
+            for &key in keys.iter().rev() {
+                self.send(Self::EV_KEY, key as u16, 0)?;
+            }
+            self.send(Self::EV_SYN, 0, 0)?;
+        }
+        Ok(())
+    }
+
+    fn tap(&mut self, action: KeyAction) -> io::Result<()> {
+        for &key in action.keys {
+            self.send(Self::EV_KEY, key as u16, 1)?;
+        }
+        self.send(Self::EV_SYN, 0, 0)?;
+        for &key in action.keys.iter().rev() {
+            self.send(Self::EV_KEY, key as u16, 0)?;
+        }
+        self.send(Self::EV_SYN, 0, 0)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for VirtualKeyboard {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        unsafe { libc::ioctl(self.device.as_raw_fd(), Self::UI_DEV_DESTROY as _) };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct VirtualKeyboard;
+
+#[cfg(not(target_os = "linux"))]
+impl VirtualKeyboard {
+    pub fn new() -> Result<Self, KeyboardError> {
+        Err(KeyboardError::Unsupported)
+    }
+
+    pub fn perform(&mut self, _action: Action) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn type_text(&mut self, _text: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    cube::CubeMove,
+    cuboard::{CuboardKey, CuboardKeymap, DEFAULT_KEYMAP},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeymapError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: {reason}")]
+    Parse { line: usize, reason: String },
+    #[error("missing keys of {0}")]
+    MissingKeys(String),
+}
+
+// the keymap file has one line per first symbol, such as `U d u c k` or `U'2 ) ] } >`, where
+// the double turn indicates the shifted keys; `\s`, `\n` and `\\` escape whitespace, enter and
+// backslash, and lines starting with `#` are comments
+pub fn format_keymap(keymap: &CuboardKeymap) -> String {
+    fn escape(key: &str) -> String {
+        key.replace('\\', "\\\\")
+            .replace(' ', "\\s")
+            .replace('\n', "\\n")
+    }
+
+    let mut res = String::new();
+    for mv in CubeMove::iter() {
+        for (shifted, prefix) in [(0, ""), (1, "2")] {
+            let keys = keymap[shifted][mv as u8 as usize].map(escape);
+            res += &format!("{}{} {}\n", mv, prefix, keys.join(" "));
+        }
+    }
+    res
+}
+
+pub fn parse_keymap(text: &str) -> Result<CuboardKeymap, KeymapError> {
+    fn unescape(token: &str) -> Option<String> {
+        let mut res = String::new();
+        let mut chars = token.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                res.push(c);
+                continue;
+            }
+            match chars.next()? {
+                's' => res.push(' '),
+                'n' => res.push('\n'),
+                '\\' => res.push('\\'),
+                _ => return None,
+            }
+        }
+        Some(res)
+    }
+
+    let mut keymap = [[[""; 4]; 12]; 2];
+    let mut defined = [[false; 12]; 2];
+    for (i, line) in text.lines().enumerate() {
+        let error = |reason: &str| KeymapError::Parse {
+            line: i + 1,
+            reason: reason.to_string(),
+        };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        let [symbol, keys @ ..] = &tokens[..] else {
+            unreachable!();
+        };
+        let (symbol, shifted) = match symbol.strip_suffix('2') {
+            Some(symbol) => (symbol, 1),
+            None => (*symbol, 0),
+        };
+        let Some(mv) = CubeMove::iter().find(|mv| mv.to_string() == symbol) else {
+            return Err(error("unknown first symbol"));
+        };
+        if keys.len() != 4 {
+            return Err(error("expect 4 keys"));
+        }
+        for (j, key) in keys.iter().enumerate() {
+            let key = unescape(key).ok_or_else(|| error("invalid escape"))?;
+            // keymaps are loaded once and live until exit
+            keymap[shifted][mv as u8 as usize][j] = Box::leak(key.into_boxed_str());
+        }
+        defined[shifted][mv as u8 as usize] = true;
+    }
+
+    for mv in CubeMove::iter() {
+        for (shifted, prefix) in [(0, ""), (1, "2")] {
+            if !defined[shifted][mv as u8 as usize] {
+                return Err(KeymapError::MissingKeys(format!("{}{}", mv, prefix)));
+            }
+        }
+    }
+    Ok(keymap)
+}
+
+pub fn load_keymap(path: impl AsRef<Path>) -> Result<CuboardKeymap, KeymapError> {
+    parse_keymap(&fs::read_to_string(path)?)
+}
+
+// a key costs one turn per first symbol (two for shifted keys) plus the second symbol;
+// counterclockwise first symbols are slightly harder to turn
+pub fn key_cost(main: CubeMove, is_shifted: bool) -> f32 {
+    const COUNTERCLOCKWISE_PENALTY: f32 = 0.1;
+    let turns = if is_shifted { 3.0 } else { 2.0 };
+    let penalty = if main.is_clockwise() {
+        0.0
+    } else {
+        COUNTERCLOCKWISE_PENALTY
+    };
+    turns + penalty
+}
+
+// expected number of turns per character of the corpus, characters not in the keymap are
+// ignored
+pub fn expected_cost(keymap: &CuboardKeymap, corpus: &str) -> f32 {
+    let mut costs = HashMap::new();
+    for mv in CubeMove::iter() {
+        for shifted in [false, true] {
+            for key in keymap[shifted as usize][mv as u8 as usize] {
+                costs.insert(key, key_cost(mv, shifted));
+            }
+        }
+    }
+
+    let mut total = 0.0;
+    let mut count = 0;
+    for c in corpus.chars() {
+        if let Some(cost) = costs.get(c.encode_utf8(&mut [0; 4]) as &str) {
+            total += cost;
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    total / count as f32
+}
+
+// a keymap looked up in reverse, from the typed text to the moves typing it
+pub struct Keymap {
+    keymap: CuboardKeymap,
+}
+
+impl Keymap {
+    pub fn new(keymap: CuboardKeymap) -> Self {
+        Keymap { keymap }
+    }
+
+    // the moves of every key typing `key`: the main move (twice if shifted) and then a clockwise
+    // adjacent move; unshifted keys come first
+    pub fn sequences(&self, key: &str) -> Vec<Vec<CubeMove>> {
+        let mut res = Vec::new();
+        for shifted in [false, true] {
+            for main in CubeMove::iter() {
+                let keys = &self.keymap[shifted as usize][main as u8 as usize];
+                for (num, _) in keys.iter().enumerate().filter(|(_, &k)| k == key) {
+                    let Some(adj) = CubeMove::iter()
+                        .filter(|mv| mv.is_clockwise())
+                        .find(|&adj| CuboardKey::num_of(main, adj) == Some(num))
+                    else {
+                        continue;
+                    };
+                    let mut moves = vec![main];
+                    if shifted {
+                        moves.push(main);
+                    }
+                    moves.push(adj);
+                    res.push(moves);
+                }
+            }
+        }
+        res
+    }
+
+    // the cheapest moves typing the key
+    pub fn key_moves(&self, key: &str) -> Option<Vec<CubeMove>> {
+        self.sequences(key).into_iter().next()
+    }
+
+    // the moves typing the text character by character, or the first character without a key
+    pub fn text_moves(&self, text: &str) -> Result<Vec<CubeMove>, char> {
+        let mut moves = Vec::new();
+        for c in text.chars() {
+            let key = self.key_moves(c.encode_utf8(&mut [0; 4])).ok_or(c)?;
+            moves.extend(key);
+        }
+        Ok(moves)
+    }
+}
+
+// assign the characters of the default keymap to keys, so that more frequent characters in the
+// corpus take cheaper keys; since the cost only depends on the key, sorting both sides gives the
+// optimal assignment
+pub fn optimize(corpus: &str) -> CuboardKeymap {
+    let mut frequencies = HashMap::new();
+    for c in corpus.chars() {
+        *frequencies.entry(c).or_insert(0usize) += 1;
+    }
+
+    let mut chars = Vec::new();
+    for mv in CubeMove::iter() {
+        for shifted in [0, 1] {
+            chars.extend(DEFAULT_KEYMAP[shifted][mv as u8 as usize]);
+        }
+    }
+    // stable sort keeps the default order for characters of the same frequency
+    chars.sort_by_key(|key| {
+        let c = key.chars().next().unwrap();
+        std::cmp::Reverse(frequencies.get(&c).copied().unwrap_or(0))
+    });
+
+    let mut slots = Vec::new();
+    for shifted in [false, true] {
+        for mv in CubeMove::iter() {
+            for num in 0..4 {
+                slots.push((mv, shifted, num));
+            }
+        }
+    }
+    slots.sort_by(|a, b| key_cost(a.0, a.1).total_cmp(&key_cost(b.0, b.1)));
+
+    let mut keymap = [[[""; 4]; 12]; 2];
+    for ((mv, shifted, num), key) in slots.into_iter().zip(chars) {
+        keymap[shifted as usize][mv as u8 as usize][num] = key;
+    }
+    keymap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_and_parse_roundtrip() {
+        let text = format_keymap(&DEFAULT_KEYMAP);
+        assert_eq!(parse_keymap(&text).unwrap(), DEFAULT_KEYMAP);
+    }
+
+    #[test]
+    fn keys_are_looked_up_by_moves() {
+        use CubeMove::*;
+        let keymap = Keymap::new(DEFAULT_KEYMAP);
+        assert_eq!(keymap.key_moves("a"), Some(vec![R, F]));
+        assert_eq!(keymap.key_moves("A"), Some(vec![R, R, F]));
+        assert_eq!(keymap.key_moves(" "), Some(vec![Rp, F]));
+        assert_eq!(keymap.key_moves("é"), None);
+        assert_eq!(keymap.text_moves("aA"), Ok(vec![R, F, R, R, F]));
+        assert_eq!(keymap.text_moves("aé"), Err('é'));
+
+        // a key typed by two moves
+        let mut twice = DEFAULT_KEYMAP;
+        twice[0][U as u8 as usize][0] = "a";
+        assert_eq!(Keymap::new(twice).sequences("a"), [vec![U, L], vec![R, F]]);
+    }
+
+    #[test]
+    fn optimized_keymap_is_cheaper() {
+        let corpus = "the quick brown fox jumps over the lazy dog\n".repeat(10);
+        let keymap = optimize(&corpus);
+        assert!(expected_cost(&keymap, &corpus) < expected_cost(&DEFAULT_KEYMAP, &corpus));
+
+        let mut keys = keymap.concat().concat();
+        let mut default_keys = DEFAULT_KEYMAP.concat().concat();
+        keys.sort();
+        default_keys.sort();
+        assert_eq!(keys, default_keys);
+    }
+}
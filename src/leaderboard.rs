@@ -0,0 +1,157 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LeaderboardError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid result: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid result at line {line}: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+// a session of the trainer, kept as a line of json in the results files shared between users
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct SessionResult {
+    pub name: String,
+    // what is typed, such as `words`, `keys:aeiou` or the name of the text file
+    pub text: String,
+    // seconds since the unix epoch
+    pub date: u64,
+    pub seconds: u64,
+    // the typed graphemes
+    pub keys: usize,
+    pub wpm: f32,
+    // percentage of the correct graphemes
+    pub accuracy: f32,
+}
+
+pub fn append(path: impl AsRef<Path>, result: &SessionResult) -> Result<(), LeaderboardError> {
+    let mut line = serde_json::to_string(result)?;
+    line.push('\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+// the results of a file, skipping the blank lines
+pub fn parse(text: &str) -> Result<Vec<SessionResult>, LeaderboardError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|source| LeaderboardError::Parse {
+                line: i + 1,
+                source,
+            })
+        })
+        .collect()
+}
+
+// the best result of each user for each text, grouped by the texts and fastest first; the same
+// session found in several files is counted once
+pub fn rank(results: impl IntoIterator<Item = SessionResult>) -> Vec<SessionResult> {
+    let mut best: Vec<SessionResult> = Vec::new();
+    for result in results {
+        let same = best
+            .iter_mut()
+            .find(|other| other.name == result.name && other.text == result.text);
+        match same {
+            Some(other) if other.wpm < result.wpm => *other = result,
+            Some(_) => {}
+            None => best.push(result),
+        }
+    }
+    best.sort_by(|a, b| a.text.cmp(&b.text).then(b.wpm.total_cmp(&a.wpm)));
+    best
+}
+
+// the ranked table of the results of the files
+pub fn merge(paths: &[String]) -> Result<Vec<SessionResult>, LeaderboardError> {
+    let mut results = Vec::new();
+    for path in paths {
+        results.extend(parse(&fs::read_to_string(path)?)?);
+    }
+    Ok(rank(results))
+}
+
+pub fn print(ranked: &[SessionResult]) {
+    let mut text = None;
+    let mut place = 0;
+    for result in ranked {
+        if text != Some(&result.text) {
+            if text.is_some() {
+                println!();
+            }
+            println!("{}", result.text);
+            text = Some(&result.text);
+            place = 0;
+        }
+        place += 1;
+        println!(
+            "{:>3}. {:<16} {:>5.1} wpm  {:>3.0}%  {:>5} keys  {:>3}:{:02}",
+            place,
+            result.name,
+            result.wpm,
+            result.accuracy,
+            result.keys,
+            result.seconds / 60,
+            result.seconds % 60
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_are_ranked_by_text_and_speed() {
+        let result = |name: &str, text: &str, wpm| SessionResult {
+            name: name.to_string(),
+            text: text.to_string(),
+            wpm,
+            ..SessionResult::default()
+        };
+        let file = [
+            result("alice", "words", 20.0),
+            result("bob", "words", 25.0),
+            result("alice", "words", 30.0),
+            result("alice", "pangrams", 10.0),
+        ]
+        .iter()
+        .map(|result| serde_json::to_string(result).unwrap() + "\n\n")
+        .collect::<String>();
+        let results = parse(&file).unwrap();
+        assert_eq!(results.len(), 4);
+
+        let ranked = rank(results.into_iter().chain([result("bob", "words", 25.0)]));
+        let table = ranked
+            .iter()
+            .map(|result| (result.text.as_str(), result.name.as_str(), result.wpm))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            table,
+            [
+                ("pangrams", "alice", 10.0),
+                ("words", "alice", 30.0),
+                ("words", "bob", 25.0)
+            ]
+        );
+
+        assert!(matches!(
+            parse("{}\nnot json\n"),
+            Err(LeaderboardError::Parse { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,3 @@
+// the cube model and the solver, which don't need a cube, shared with the benchmarks
+pub mod cube;
+pub mod solver;
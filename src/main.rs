@@ -1,35 +1,454 @@
-use std::error::Error;
+use std::{error::Error, fs, path::PathBuf, time::Duration};
 
-use train::{cuboard_input_printer, cuboard_input_trainer};
+use config::{flag_secs, flag_value, Config};
+use cuboard::{ComposeTable, InputMode, DEFAULT_KEYMAP};
+use cuboard_core::{cube, solver};
+use devices::Devices;
+use idle::{IdleMonitor, IdlePolicy};
+use keyboard::VirtualKeyboard;
+
+use profile::Profile;
+use shutdown::Shutdown;
+use sink::{AnnounceSink, ClipboardSink, FileSink, KeyboardSink, OutputSink, WebSocketSink};
+use sound::{Sound, SoundEvent};
+use steno::{StenoDictionary, StenoError};
+use textgen::{TextMode, TrainingText};
+use train::{
+    cuboard_input_chording, cuboard_input_printer, cuboard_input_trainer, cuboard_input_viewer,
+};
 
 mod algorithm;
+mod algs;
+mod attach;
+mod bench;
+mod blind;
 mod bluetooth;
+mod bus;
+mod calibrate;
+mod capture;
+mod cheatsheet;
+mod config;
 mod console;
-mod cube;
+mod cstimer;
 mod cuboard;
+mod daemon;
+mod devices;
+mod drill;
+mod engine;
+mod hooks;
+mod i18n;
+mod idle;
+mod keyboard;
+mod keymap;
+mod leaderboard;
+mod metrics;
+mod near;
+mod net;
+mod orientation;
+mod overlay;
+mod pattern;
+mod profile;
+mod race;
+mod scramble;
+mod shutdown;
+mod signal;
+mod simon;
+mod sink;
+mod snake;
+mod sound;
+mod stats;
+mod steno;
+mod switch;
+mod terminal;
+mod textgen;
+mod theme;
+mod verify;
 mod view;
 mod train;
+mod transcript;
+mod websocket;
+mod webview;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let command = std::env::args().nth(1);
-    let text_filename = std::env::args().nth(2);
+    let (flags, args): (Vec<_>, Vec<_>) = std::env::args()
+        .skip(1)
+        .partition(|arg| arg.starts_with("--"));
+    let mut args = args.into_iter();
+    let command = args.next();
+    let text_filename = args.next();
+
+    let config_path = match flag_value(&flags, "config") {
+        Some(path) => PathBuf::from(path),
+        None => config::config_path()?,
+    };
+    let mut config = Config::load(&config_path)?;
+    config.apply_flags(&flags)?;
+    // the keymap of the remembered cube, unless given
+    if config.input.keymap.is_none() {
+        let devices = Devices::remembered()?;
+        config.input.keymap = devices
+            .preferred(&config.device)
+            .and_then(|device| device.keymap.clone());
+    }
+    config::init(config);
+
+    let disconnect = flags.iter().any(|flag| flag == "--disconnect");
+    let shutdown = Shutdown::listen(disconnect);
+
+    let mut idle = IdleMonitor::default();
+    if let Some(policy) = flag_value(&flags, "idle") {
+        idle.policy = policy.parse::<IdlePolicy>()?;
+    }
+    if let Some(timeout) = flag_secs(&flags, "idle-timeout", 60.0)? {
+        idle.timeout = timeout;
+    }
+
+    let keymap = match &config::get().input.keymap {
+        Some(path) => keymap::load_keymap(path)?,
+        None => DEFAULT_KEYMAP,
+    };
+    match (config::get().input.mode, &config::get().input.dictionary) {
+        (InputMode::Steno | InputMode::T9, None) => return Err(StenoError::NoDictionary.into()),
+        (InputMode::Steno, Some(path)) => steno::init(StenoDictionary::load(path)?),
+        _ => {}
+    }
+
+    let transcript = flag_value(&flags, "transcript").map(str::to_string);
+    let sound = if flags.iter().any(|flag| flag == "--sound") {
+        Some(Sound::new(SoundEvent::ALL)?)
+    } else if let Some(events) = flag_value(&flags, "sound") {
+        let events = events
+            .split(',')
+            .map(str::parse::<SoundEvent>)
+            .collect::<Result<Vec<_>, _>>()?;
+        Some(Sound::new(events)?)
+    } else {
+        None
+    };
 
     match command {
         Some(command) if command == "console" => {
-            console::run().await?;
+            let script = flag_value(&flags, "script").map(str::to_string);
+            let capture = flag_value(&flags, "capture").map(str::to_string);
+            console::run(script, capture, shutdown).await?;
+        }
+        Some(command) if command == "decode" => {
+            let Some(filename) = text_filename else {
+                println!("missing capture file");
+                return Ok(());
+            };
+            capture::decode(filename)?;
         }
-        Some(command) if command == "cube" => {
-            view::window::run().await?;
+        #[cfg(feature = "view")]
+        Some(command) if command == "cube" => match flag_value(&flags, "replay") {
+            Some(path) => view::window::replay(path, shutdown)?,
+            None => {
+                let record = flag_value(&flags, "record").map(str::to_string);
+                let f2l = flags.iter().any(|flag| flag == "--f2l").then(|| {
+                    if flags.iter().any(|flag| flag == "--suggest") {
+                        view::window::F2lHint::Insertion
+                    } else {
+                        view::window::F2lHint::Pair
+                    }
+                });
+                view::window::run(shutdown, record, None, f2l, None).await?;
+            }
+        },
+        #[cfg(not(feature = "view"))]
+        Some(command) if command == "cube" => return Err(view::ViewError::Unsupported.into()),
+        Some(command) if command == "train" => {
+            let mode = flag_value(&flags, "mode")
+                .map(str::parse::<TextMode>)
+                .transpose()?;
+            let url = flag_value(&flags, "url").map(str::to_string);
+            let text = match (text_filename, url, mode) {
+                (Some(filename), _, _) if filename == "-" => Some(TrainingText::Stdin),
+                (Some(filename), _, _) => Some(TrainingText::File(filename)),
+                (None, Some(url), _) => Some(TrainingText::Url(url)),
+                (None, None, Some(mode)) => Some(TrainingText::Generated(mode)),
+                (None, None, None) => None,
+            };
+            match text {
+                Some(text) => {
+                    let net = flags.iter().any(|flag| flag == "--net");
+                    cuboard_input_trainer(text, keymap, transcript, sound, net, shutdown, idle)
+                        .await?;
+                }
+                None => {
+                    cuboard_input_printer(
+                        keymap,
+                        compose_table(&flags)?,
+                        output_sinks(&flags).await?,
+                        transcript,
+                        sound,
+                        shutdown,
+                        idle,
+                    )
+                    .await?;
+                }
+            }
+        }
+        Some(command) if command == "all" => {
+            cuboard_input_viewer(
+                keymap,
+                compose_table(&flags)?,
+                output_sinks(&flags).await?,
+                transcript,
+                sound,
+                shutdown,
+                idle,
+            )
+            .await?;
+        }
+        Some(command) if command == "algs" => {
+            let algs = algs::select(&flags, shutdown.clone()).await?;
+            let drill = flags.iter().any(|flag| flag == "--drill");
+            let execute = flags.iter().any(|flag| flag == "--execute");
+            if execute && !algs.is_empty() {
+                drill::run(algs, shutdown, idle).await?;
+            } else if drill && !algs.is_empty() {
+                let lines = algs.iter().map(|alg| alg.notation.to_string()).collect();
+                let text = TrainingText::Drill(lines);
+                let net = flags.iter().any(|flag| flag == "--net");
+                cuboard_input_trainer(text, keymap, transcript, sound, net, shutdown, idle).await?;
+            } else {
+                algs::print(&algs);
+            }
         }
-        Some(command) if command == "train" => match text_filename {
-            Some(filename) => {
-                cuboard_input_trainer(filename).await?;
+        Some(command) if command == "drill" => {
+            let Some(keys) = flag_value(&flags, "keys") else {
+                println!("missing --keys=CHARS");
+                return Ok(());
+            };
+            let mut chars = Vec::new();
+            for c in keys.chars() {
+                if !chars.contains(&c) {
+                    chars.push(c);
+                }
+            }
+            let text = TrainingText::Keys(chars);
+            let net = flags.iter().any(|flag| flag == "--net");
+            cuboard_input_trainer(text, keymap, transcript, sound, net, shutdown, idle).await?;
+        }
+        Some(command) if command == "scramble" => {
+            let turns = match flag_value(&flags, "scramble") {
+                Some(text) => cube::parse_turns(text)?,
+                None => {
+                    let length =
+                        flag_value(&flags, "length").map_or(Ok(20), str::parse::<usize>)?;
+                    solver::random_scramble(length, &mut rand::thread_rng())
+                }
+            };
+            let view = flags.iter().any(|flag| flag == "--view");
+            let timed = flags.iter().any(|flag| flag == "--time");
+            scramble::run(turns, view, timed, shutdown, idle).await?;
+        }
+        Some(command) if command == "solve" => {
+            let Some(text) = text_filename else {
+                println!("missing scramble");
+                return Ok(());
+            };
+            let scramble = cube::parse_turns(&text)?;
+            let depth = flag_value(&flags, "depth").map_or(Ok(6), str::parse::<usize>)?;
+            solver::print_solution(&config::get().solver, &scramble, depth)?;
+        }
+        Some(command) if command == "pattern" => {
+            let Some(name) = text_filename else {
+                println!("missing pattern name");
+                return Ok(());
+            };
+            pattern::run(name.parse()?, shutdown).await?;
+        }
+        Some(command) if command == "reset" => {
+            let facelets = flag_value(&flags, "facelets").map(str::to_string);
+            if facelets.is_none() && !flags.iter().any(|flag| flag == "--as-is") {
+                println!("reset the cube to the state it is in by `reset --as-is`");
+                return Ok(());
+            }
+            pattern::reset_as_is(facelets, shutdown).await?;
+        }
+        Some(command) if command == "daemon" => {
+            let path = match flag_value(&flags, "socket") {
+                Some(path) => PathBuf::from(path),
+                None => daemon::socket_path(),
+            };
+            let dbus = flags.iter().any(|flag| flag == "--dbus");
+            let metrics = match flag_value(&flags, "metrics") {
+                Some(address) => Some(address.to_string()),
+                None if flags.iter().any(|flag| flag == "--metrics") => {
+                    Some(metrics::DEFAULT_ADDRESS.to_string())
+                }
+                None => None,
+            };
+            let web = match flag_value(&flags, "web") {
+                Some(address) => Some(address.to_string()),
+                None if flags.iter().any(|flag| flag == "--web") => {
+                    Some(webview::DEFAULT_ADDRESS.to_string())
+                }
+                None => None,
+            };
+            daemon::run(path, keymap, dbus, metrics, web, shutdown).await?;
+        }
+        Some(command) if command == "attach" => {
+            let path = match flag_value(&flags, "socket") {
+                Some(path) => PathBuf::from(path),
+                None => daemon::socket_path(),
+            };
+            attach::run(path, shutdown).await?;
+        }
+        Some(command) if command == "simon" => {
+            let view = flags.iter().any(|flag| flag == "--view");
+            simon::run(view, shutdown, idle).await?;
+        }
+        Some(command) if command == "blind" => {
+            let train = flags.iter().any(|flag| flag == "--train");
+            blind::run(train, shutdown, idle).await?;
+        }
+        Some(command) if command == "snake" => {
+            snake::run(shutdown, idle).await?;
+        }
+        Some(command) if command == "overlay" => {
+            let address = flag_value(&flags, "address").unwrap_or(overlay::DEFAULT_ADDRESS);
+            overlay::run(address.to_string(), keymap, shutdown, idle).await?;
+        }
+        Some(command) if command == "race" => match flag_value(&flags, "join") {
+            Some(address) => {
+                let name = match flag_value(&flags, "name") {
+                    Some(name) => name.to_string(),
+                    None => std::env::var("USER").unwrap_or_else(|_| "player".to_string()),
+                };
+                race::join(address, name, keymap, shutdown, idle).await?;
             }
             None => {
-                cuboard_input_printer().await?;
+                let text = match text_filename {
+                    Some(filename) => TrainingText::File(filename),
+                    None => {
+                        let mode = flag_value(&flags, "mode")
+                            .map_or(Ok(TextMode::default()), str::parse)?;
+                        TrainingText::Generated(mode)
+                    }
+                };
+                let players = flag_value(&flags, "players").map_or(Ok(2), str::parse::<usize>)?;
+                let address = flag_value(&flags, "address").unwrap_or(race::DEFAULT_ADDRESS);
+                race::host(address, text, players, shutdown).await?;
+            }
+        },
+        Some(command) if command == "switch" => {
+            profile::run(vec![Profile::Switch], shutdown, idle).await?;
+        }
+        Some(command) if command == "control" => {
+            let profiles = flag_value(&flags, "profiles")
+                .unwrap_or("media,mouse,switch")
+                .split(',')
+                .map(str::parse::<Profile>)
+                .collect::<Result<Vec<_>, _>>()?;
+            profile::run(profiles, shutdown, idle).await?;
+        }
+        Some(command) if command == "bench" && flags.iter().any(|flag| flag == "--engine") => {
+            let rounds = flag_value(&flags, "rounds").map_or(Ok(100), str::parse::<u32>)?;
+            print!("{}", bench::run_engine(rounds));
+        }
+        Some(command) if command == "bench" => {
+            let duration = flag_secs(&flags, "duration", 1.0)?.unwrap_or(Duration::from_secs(30));
+            bench::run(keymap, shutdown, duration).await?;
+        }
+        Some(command) if command == "calibrate" => {
+            calibrate::run(config_path, shutdown).await?;
+        }
+        Some(command) if command == "chord" => {
+            cuboard_input_chording(keymap, shutdown, idle).await?;
+        }
+        Some(command) if command == "devices" => match text_filename.as_deref() {
+            Some("add") => {
+                let keymap = flag_value(&flags, "keymap").map(PathBuf::from);
+                devices::add(keymap, shutdown).await?;
+            }
+            Some("remove") => {
+                let Some(address) = args.next() else {
+                    println!("missing address");
+                    return Ok(());
+                };
+                devices::remove(&address)?;
+            }
+            _ => devices::list()?,
+        },
+        Some(command) if command == "leaderboard" => match text_filename.as_deref() {
+            Some("merge") => {
+                let paths = args.collect::<Vec<_>>();
+                if paths.is_empty() {
+                    println!("missing results files");
+                    return Ok(());
+                }
+                leaderboard::print(&leaderboard::merge(&paths)?);
+            }
+            _ => println!("missing subcommand, such as `merge`"),
+        },
+        Some(command) if command == "streak" => stats::print_streak()?,
+        Some(command) if command == "solves" => match text_filename.as_deref() {
+            Some("import") => {
+                let Some(path) = args.next() else {
+                    println!("missing csTimer file");
+                    return Ok(());
+                };
+                let added = cstimer::import(path)?;
+                println!("{} solves imported", added);
             }
+            Some("export") => {
+                let Some(path) = args.next() else {
+                    println!("missing csTimer file");
+                    return Ok(());
+                };
+                let exported = cstimer::export(path)?;
+                println!("{} solves exported", exported);
+            }
+            _ => stats::list_solves()?,
         },
+        Some(command) if command == "keys" => {
+            let Some(text) = text_filename else {
+                println!("missing text");
+                return Ok(());
+            };
+            let keymap = keymap::Keymap::new(keymap);
+            let mut seen = Vec::new();
+            for c in text.chars() {
+                if seen.contains(&c) {
+                    continue;
+                }
+                seen.push(c);
+                let sequences = keymap
+                    .sequences(c.encode_utf8(&mut [0; 4]))
+                    .iter()
+                    .map(|moves| cube::format_moves(moves))
+                    .collect::<Vec<_>>();
+                if sequences.is_empty() {
+                    println!("{:?}: no key", c);
+                } else {
+                    println!("{:?}: {}", c, sequences.join(" or "));
+                }
+            }
+            if let Ok(moves) = keymap.text_moves(&text) {
+                println!("{} moves: {}", moves.len(), cube::format_moves(&moves));
+            }
+        }
+        Some(command) if command == "optimize" => {
+            let Some(filename) = text_filename else {
+                println!("missing corpus file");
+                return Ok(());
+            };
+            let corpus = fs::read_to_string(filename)?;
+            let optimized = keymap::optimize(&corpus);
+            println!(
+                "expected turns per character: {:.3} -> {:.3}",
+                keymap::expected_cost(&keymap, &corpus),
+                keymap::expected_cost(&optimized, &corpus),
+            );
+            let text = keymap::format_keymap(&optimized);
+            match flag_value(&flags, "output") {
+                Some(path) => fs::write(path, text)?,
+                None => print!("{}", text),
+            }
+        }
         _ => {
             println!("unknown command");
         }
@@ -37,3 +456,88 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+fn compose_table(flags: &[String]) -> Result<Option<ComposeTable>, Box<dyn Error>> {
+    let table = match flag_value(flags, "compose") {
+        Some("dead") => Some(ComposeTable::dead_keys()),
+        Some("kana") => Some(ComposeTable::kana()),
+        Some(path) => Some(ComposeTable::parse(&fs::read_to_string(path)?)),
+        None => match &config::get().input.dictionary {
+            Some(path) if config::get().input.mode == InputMode::T9 => {
+                Some(ComposeTable::t9(&fs::read_to_string(path)?))
+            }
+            _ => None,
+        },
+    };
+    Ok(table)
+}
+
+// where the typed text goes besides the terminal
+async fn output_sinks(flags: &[String]) -> Result<Vec<Box<dyn OutputSink>>, Box<dyn Error>> {
+    let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+    if flags.iter().any(|flag| flag == "--clipboard") {
+        sinks.push(Box::new(ClipboardSink::new()?));
+    }
+    if let Some(path) = flag_value(flags, "output-file") {
+        sinks.push(Box::new(FileSink::open(path)?));
+    }
+    if flags.iter().any(|flag| flag == "--output-keyboard") {
+        sinks.push(Box::new(KeyboardSink::new(VirtualKeyboard::new()?)));
+    }
+    let websocket = match flag_value(flags, "output-websocket") {
+        Some(address) => Some(address),
+        None if flags.iter().any(|flag| flag == "--output-websocket") => {
+            Some(websocket::DEFAULT_ADDRESS)
+        }
+        None => None,
+    };
+    if let Some(address) = websocket {
+        sinks.push(Box::new(WebSocketSink::bind(address).await?));
+    }
+    if let Some(path) = flag_value(flags, "announce") {
+        sinks.push(Box::new(AnnounceSink::open(Some(path))?));
+    } else if flags.iter().any(|flag| flag == "--announce") {
+        sinks.push(Box::new(AnnounceSink::open(None)?));
+    }
+    Ok(sinks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    // every source file should be compiled, or it rots silently as the code around changes
+    fn assert_declared(dir: &Path, roots: &[&str]) {
+        let roots = roots
+            .iter()
+            .map(|root| fs::read_to_string(dir.join(root)).unwrap())
+            .collect::<String>();
+        let declared = |name: &str| {
+            roots.lines().any(|line| {
+                let line = line
+                    .trim_start_matches("pub ")
+                    .trim_start_matches("pub(crate) ");
+                line == format!("mod {};", name)
+            })
+        };
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            if path.is_dir() {
+                assert!(declared(name), "{} is not a module", path.display());
+                assert_declared(&path, &["mod.rs"]);
+            } else if path.extension().is_some_and(|ext| ext == "rs")
+                && !["main", "lib", "mod"].contains(&name)
+            {
+                assert!(declared(name), "{} is not a module", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn all_source_files_are_modules() {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        // the modules of the library are declared in its root instead
+        assert_declared(&src, &["main.rs", "lib.rs"]);
+    }
+}
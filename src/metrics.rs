@@ -0,0 +1,283 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    bluetooth::gancubev2::{NotificationError, NotificationTiming, ResponseMessage},
+    daemon::DaemonEvent,
+};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:9898";
+
+// counts of the observed values up to each bound, in seconds
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, name: &str, out: &mut String) {
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+// counters of the daemon, in the text format of prometheus
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metrics {
+    messages: BTreeMap<&'static str, u64>,
+    decode_errors: BTreeMap<&'static str, u64>,
+    connections: u64,
+    keys: u64,
+    gestures: BTreeMap<&'static str, u64>,
+    // from receiving a notification until its events are sent
+    latency: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        const LATENCY_BOUNDS: [f64; 7] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1];
+        Metrics {
+            messages: BTreeMap::new(),
+            decode_errors: BTreeMap::new(),
+            connections: 0,
+            keys: 0,
+            gestures: BTreeMap::new(),
+            latency: Histogram::new(&LATENCY_BOUNDS),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn connect(&mut self) {
+        self.connections += 1;
+    }
+
+    pub fn observe_message(&mut self, msg: &ResponseMessage) {
+        let kind = match msg {
+            ResponseMessage::Moves { .. } => "moves",
+            ResponseMessage::State { .. } => "state",
+            ResponseMessage::Battery { .. } => "battery",
+            ResponseMessage::Gyroscope { .. } => "gyroscope",
+            ResponseMessage::Hardware(_) => "hardware",
+            ResponseMessage::Disconnect => "disconnect",
+        };
+        *self.messages.entry(kind).or_default() += 1;
+    }
+
+    pub fn observe_error(&mut self, err: &NotificationError) {
+        let kind = match err {
+            NotificationError::UnknownCharacteristic(_) => "unknown-characteristic",
+            NotificationError::DecodeFail(_) => "decode",
+        };
+        *self.decode_errors.entry(kind).or_default() += 1;
+    }
+
+    pub fn observe_event(&mut self, event: &DaemonEvent) {
+        match event {
+            DaemonEvent::Input { accept, .. } => self.keys += accept.chars().count() as u64,
+            DaemonEvent::Finish { text } => {
+                self.keys += text.chars().count() as u64;
+                *self.gestures.entry("turning-around").or_default() += 1;
+            }
+            DaemonEvent::Cancel => *self.gestures.entry("shaking").or_default() += 1,
+            _ => {}
+        }
+    }
+
+    pub fn observe_latency(&mut self, timing: NotificationTiming) {
+        let latency = timing.received.elapsed();
+        self.latency.observe(latency.as_secs_f64());
+    }
+
+    pub fn render(&self) -> String {
+        fn counters(
+            out: &mut String,
+            name: &str,
+            help: &str,
+            label: &str,
+            values: &BTreeMap<&str, u64>,
+        ) {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (value, count) in values {
+                let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value, count);
+            }
+        }
+
+        let mut out = String::new();
+        counters(
+            &mut out,
+            "cuboard_messages_total",
+            "Messages decoded from the cube.",
+            "type",
+            &self.messages,
+        );
+        counters(
+            &mut out,
+            "cuboard_decode_errors_total",
+            "Notifications which cannot be decoded.",
+            "kind",
+            &self.decode_errors,
+        );
+        counters(
+            &mut out,
+            "cuboard_gestures_total",
+            "Gestures recognized.",
+            "gesture",
+            &self.gestures,
+        );
+        let _ = writeln!(
+            out,
+            "# HELP cuboard_connections_total Connections to the cube."
+        );
+        let _ = writeln!(out, "# TYPE cuboard_connections_total counter");
+        let _ = writeln!(out, "cuboard_connections_total {}", self.connections);
+        let _ = writeln!(out, "# HELP cuboard_keys_total Characters accepted.");
+        let _ = writeln!(out, "# TYPE cuboard_keys_total counter");
+        let _ = writeln!(out, "cuboard_keys_total {}", self.keys);
+        let name = "cuboard_notification_latency_seconds";
+        let _ = writeln!(
+            out,
+            "# HELP {} From receiving a notification to sending its events.",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        self.latency.render(name, &mut out);
+        out
+    }
+}
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// answer a request of http, only `GET /metrics` is found
+async fn serve_request(stream: TcpStream, metrics: Arc<Mutex<Metrics>>) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let request = async {
+        let request_line = lines.next_line().await?.unwrap_or_default();
+        // skip the headers
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                break;
+            }
+        }
+        std::io::Result::Ok(request_line)
+    };
+    let request_line = tokio::time::timeout(REQUEST_TIMEOUT, request).await??;
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = metrics
+                .lock()
+                .map_or(String::new(), |metrics| metrics.render());
+            ("200 OK", body)
+        }
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await
+}
+
+pub async fn serve(listener: TcpListener, metrics: Arc<Mutex<Metrics>>) -> std::io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let request = serve_request(stream, Arc::clone(&metrics));
+        tokio::spawn(async move {
+            if let Err(err) = request.await {
+                println!("metrics error: {}", err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn metrics_are_served_over_http() {
+        let mut metrics = Metrics::default();
+        metrics.connect();
+        metrics.observe_message(&ResponseMessage::Disconnect);
+        metrics.observe_event(&DaemonEvent::Finish {
+            text: "hi\n".to_string(),
+        });
+        metrics.latency.observe(0.002);
+        let metrics = Arc::new(Mutex::new(metrics));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, metrics));
+
+        let get = |path: &'static str| async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        for line in [
+            "cuboard_messages_total{type=\"disconnect\"} 1",
+            "cuboard_gestures_total{gesture=\"turning-around\"} 1",
+            "cuboard_connections_total 1",
+            "cuboard_keys_total 3",
+            "cuboard_notification_latency_seconds_bucket{le=\"0.001\"} 0",
+            "cuboard_notification_latency_seconds_bucket{le=\"0.005\"} 1",
+            "cuboard_notification_latency_seconds_count 1",
+        ] {
+            assert!(response.lines().any(|l| l == line), "missing {}", line);
+        }
+        assert!(get("/").await.starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    bluetooth::gancubev2::ResponseMessage,
+    cube::{format_turns, FaceTurn},
+    i18n::{tr, Message},
+    net::CubeNet,
+    solver::NearSolved,
+};
+
+// watch the tracked cube getting within a few turns of solved, for fiddling with the cube idly,
+// or for not solving it by accident while typing
+pub struct NearSolvedWatch {
+    cube: CubeNet,
+    table: NearSolved,
+    // the turns finishing the cube, if it is near solved but not solved
+    finish: Option<Vec<FaceTurn>>,
+    alerted_at: Option<Instant>,
+}
+
+impl NearSolvedWatch {
+    const FLASH: Duration = Duration::from_millis(500);
+
+    pub fn new(depth: usize) -> Self {
+        NearSolvedWatch {
+            cube: CubeNet::new(),
+            table: NearSolved::new(depth),
+            finish: None,
+            alerted_at: None,
+        }
+    }
+
+    // whether the cube just got near solved, coming from farther away or from the solved state
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> bool {
+        if !self.cube.handle_message(msg) {
+            return false;
+        }
+        let finish = self
+            .cube
+            .state()
+            .and_then(|state| self.table.finish(state))
+            .filter(|turns| !turns.is_empty());
+        let alert = finish.is_some() && self.finish.is_none();
+        self.finish = finish;
+        if alert {
+            self.alerted_at = Some(Instant::now());
+        }
+        alert
+    }
+
+    // the turns finishing the cube, and whether they are flashed since the cube just got near
+    pub fn status(&self) -> Option<(String, bool)> {
+        let finish = self.finish.as_ref()?;
+        let text = tr(Message::NearSolved, &[&finish.len(), &format_turns(finish)]);
+        let flashed = self
+            .alerted_at
+            .is_some_and(|time| time.elapsed() < Self::FLASH);
+        Some((text, flashed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::{parse_turns, CubeState};
+
+    fn state_message(count: u8, turns: &str) -> ResponseMessage {
+        let moves = parse_turns(turns)
+            .unwrap()
+            .iter()
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        ResponseMessage::State {
+            count,
+            state: Some(CubeState::from_moves(&moves)),
+        }
+    }
+
+    #[test]
+    fn alerts_once_when_getting_near() {
+        let mut watch = NearSolvedWatch::new(2);
+        assert!(!watch.handle_message(state_message(0, "R U F")));
+        assert_eq!(watch.status(), None);
+        assert!(watch.handle_message(state_message(1, "R U")));
+        assert!(watch.status().unwrap().0.ends_with(": U' R'"));
+        assert!(!watch.handle_message(state_message(2, "R")));
+        assert!(!watch.handle_message(state_message(3, "")));
+        assert_eq!(watch.status(), None);
+        assert!(watch.handle_message(state_message(4, "R")));
+        assert!(watch.status().unwrap().1);
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Instant;
+
+use crate::{
+    bluetooth::{gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::CubeState,
+    terminal::{text_width, Cell},
+    theme,
+};
+
+pub const NET_ROWS: usize = 9;
+pub const NET_COLUMNS: usize = 12;
+
+// the top left facelet of U, R, F, D, L, B on the net
+const FACE_ORIGINS: [(usize, usize); 6] = [(0, 3), (3, 6), (3, 3), (6, 3), (3, 0), (3, 9)];
+
+// the row and the column of the facelet on the net, with L, F, R, B in the middle row
+pub fn net_cell(facelet: usize) -> (usize, usize) {
+    let (row, column) = FACE_ORIGINS[facelet / 9];
+    (row + facelet % 9 / 3, column + facelet % 3)
+}
+
+// the faces shown by the facelets, unfolded into a net
+fn net_grid(state: &CubeState) -> [[Option<usize>; NET_COLUMNS]; NET_ROWS] {
+    let mut grid = [[None; NET_COLUMNS]; NET_ROWS];
+    for (i, facelet) in state.facelets().chars().enumerate() {
+        let (row, column) = net_cell(i);
+        grid[row][column] = "URFDLB".find(facelet);
+    }
+    grid
+}
+
+// the cube state tracked from the moves, drawn as a net in a few rows of the terminal
+#[derive(Default)]
+pub struct CubeNet {
+    state: Option<CubeState>,
+    sequencer: MoveSequencer,
+}
+
+impl CubeNet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // whether the tracked state is changed
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> bool {
+        let sequenced = self.sequencer.handle_message(msg, Instant::now());
+        if let ResponseMessage::State {
+            count: _,
+            state: Some(mut state),
+        } = msg
+        {
+            state.reset_centers();
+            self.state = Some(state);
+            return true;
+        }
+
+        let Some(state) = &mut self.state else {
+            return false;
+        };
+        for &(mv, _) in &sequenced.moves {
+            state.apply(mv);
+        }
+        !sequenced.moves.is_empty()
+    }
+
+    pub fn state(&self) -> Option<&CubeState> {
+        self.state.as_ref()
+    }
+
+    // two rows of facelets in a row of half blocks, or the letters of the faces without colors
+    pub fn rows(&self) -> Vec<Vec<Cell>> {
+        let Some(state) = &self.state else {
+            return Vec::new();
+        };
+        let grid = net_grid(state);
+        let colors = &theme::get().net;
+        if colors.iter().all(|(fg, _)| fg.is_empty()) {
+            return grid
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|face| {
+                            let letter = face.map_or(" ", |face| &"URFDLB"[face..face + 1]);
+                            (letter.to_string(), String::new())
+                        })
+                        .collect()
+                })
+                .collect();
+        }
+        grid.chunks(2)
+            .map(|rows| {
+                (0..NET_COLUMNS)
+                    .map(|column| {
+                        let top = rows[0][column];
+                        let bottom = rows.get(1).and_then(|row| row[column]);
+                        match (top, bottom) {
+                            (Some(top), Some(bottom)) => (
+                                "▀".to_string(),
+                                format!("{}{}", colors[top].0, colors[bottom].1),
+                            ),
+                            (Some(top), None) => ("▀".to_string(), colors[top].0.clone()),
+                            (None, Some(bottom)) => ("▄".to_string(), colors[bottom].0.clone()),
+                            (None, None) => (" ".to_string(), String::new()),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// put the net at the right of the rows if it fits in the width, otherwise below them
+pub fn beside(mut rows: Vec<Vec<Cell>>, net: Vec<Vec<Cell>>, width: usize) -> Vec<Vec<Cell>> {
+    const GAP: usize = 2;
+
+    let row_width = |row: &[Cell]| row.iter().map(|(g, _)| text_width(g)).sum::<usize>();
+    let left = rows.iter().map(|row| row_width(row)).max().unwrap_or(0);
+    // the last column is left empty, so that the cursor never wraps by itself
+    if left + GAP + NET_COLUMNS >= width {
+        rows.extend(net);
+        return rows;
+    }
+    rows.resize(rows.len().max(net.len()), Vec::new());
+    for (row, net_row) in rows.iter_mut().zip(net) {
+        let padding = " ".repeat(left + GAP - row_width(row));
+        row.push((padding, String::new()));
+        row.extend(net_row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cube::CubeMove, terminal::cells};
+
+    #[test]
+    fn net_is_unfolded_around_the_front() {
+        let grid = net_grid(&CubeState::default());
+        assert_eq!(grid[0][3], Some(0));
+        assert_eq!(grid[4][4], Some(2));
+        assert_eq!(grid[4][10], Some(5));
+        assert_eq!(grid[8][5], Some(3));
+        assert_eq!(grid[0][0], None);
+
+        // the front column of U shows F after R
+        let grid = net_grid(&CubeState::of_move(CubeMove::R));
+        assert_eq!(grid[0][5], Some(2));
+        assert_eq!(grid[4][6], Some(1));
+    }
+
+    #[test]
+    fn net_is_put_beside_the_rows() {
+        let net = vec![cells("ab", ""), cells("cd", ""), cells("ef", "")];
+        let rows = beside(vec![cells("xyz", ""), cells("x", "")], net.clone(), 80);
+        let text = rows
+            .iter()
+            .map(|row| row.iter().map(|(g, _)| g.as_str()).collect::<String>())
+            .collect::<Vec<_>>();
+        assert_eq!(text, ["xyz  ab", "x    cd", "     ef"]);
+
+        let rows = beside(vec![cells("xyz", "")], net, 10);
+        assert_eq!(rows.len(), 4);
+    }
+}
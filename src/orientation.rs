@@ -0,0 +1,176 @@
+use std::{collections::VecDeque, str::FromStr};
+
+use nalgebra::{Quaternion, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+
+// smooth the noisy orientations reported by the gyroscope
+pub trait OrientationFilter {
+    fn put(&mut self, orientation: UnitQuaternion<f32>);
+    fn get(&self) -> UnitQuaternion<f32>;
+}
+
+// q and -q are the same orientation, take the one closer to the reference
+fn align(q: UnitQuaternion<f32>, reference: &UnitQuaternion<f32>) -> Quaternion<f32> {
+    let q = q.into_inner();
+    if q.dot(reference.quaternion()) < 0.0 {
+        -q
+    } else {
+        q
+    }
+}
+
+// the last orientation as it is
+#[derive(Default)]
+pub struct Unfiltered(UnitQuaternion<f32>);
+
+impl OrientationFilter for Unfiltered {
+    fn put(&mut self, orientation: UnitQuaternion<f32>) {
+        self.0 = orientation;
+    }
+
+    fn get(&self) -> UnitQuaternion<f32> {
+        self.0
+    }
+}
+
+// the normalized mean of the last orientations, which is close to the true mean when they are
+// close to each other
+pub struct MovingAverage {
+    orientations: VecDeque<UnitQuaternion<f32>>,
+    size: usize,
+}
+
+impl MovingAverage {
+    pub fn new(size: usize) -> Self {
+        MovingAverage {
+            orientations: VecDeque::with_capacity(size),
+            size: size.max(1),
+        }
+    }
+}
+
+impl OrientationFilter for MovingAverage {
+    fn put(&mut self, orientation: UnitQuaternion<f32>) {
+        if self.orientations.len() == self.size {
+            self.orientations.pop_front();
+        }
+        self.orientations.push_back(orientation);
+    }
+
+    fn get(&self) -> UnitQuaternion<f32> {
+        let Some(last) = self.orientations.back() else {
+            return UnitQuaternion::identity();
+        };
+        let sum = self
+            .orientations
+            .iter()
+            .map(|&q| align(q, last))
+            .fold(Quaternion::default(), |acc, q| acc + q);
+        UnitQuaternion::new_normalize(sum)
+    }
+}
+
+// move toward each new orientation by a fraction of the way, along the shortest arc
+pub struct SlerpSmoother {
+    orientation: Option<UnitQuaternion<f32>>,
+    factor: f32,
+}
+
+impl SlerpSmoother {
+    pub fn new(factor: f32) -> Self {
+        SlerpSmoother {
+            orientation: None,
+            factor: factor.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl OrientationFilter for SlerpSmoother {
+    fn put(&mut self, orientation: UnitQuaternion<f32>) {
+        let Some(current) = self.orientation else {
+            self.orientation = Some(orientation);
+            return;
+        };
+        let target = UnitQuaternion::new_unchecked(align(orientation, &current));
+        let next = current
+            .try_slerp(&target, self.factor, 1.0e-6)
+            .unwrap_or(target);
+        self.orientation = Some(next);
+    }
+
+    fn get(&self) -> UnitQuaternion<f32> {
+        self.orientation.unwrap_or_else(UnitQuaternion::identity)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilterStrategy {
+    #[default]
+    None,
+    Average,
+    Slerp,
+}
+
+impl FromStr for FilterStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(FilterStrategy::None),
+            "average" => Ok(FilterStrategy::Average),
+            "slerp" => Ok(FilterStrategy::Slerp),
+            _ => Err(format!("unknown filter: {}", s)),
+        }
+    }
+}
+
+impl FilterStrategy {
+    const AVERAGE_SIZE: usize = 5;
+    const SLERP_FACTOR: f32 = 0.3;
+
+    pub fn filter(&self) -> Box<dyn OrientationFilter + Send> {
+        match self {
+            FilterStrategy::None => Box::<Unfiltered>::default(),
+            FilterStrategy::Average => Box::new(MovingAverage::new(Self::AVERAGE_SIZE)),
+            FilterStrategy::Slerp => Box::new(SlerpSmoother::new(Self::SLERP_FACTOR)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn filters_ignore_the_sign_of_quaternions() {
+        let q = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.5);
+        let neg = UnitQuaternion::new_unchecked(-q.into_inner());
+        for strategy in [
+            FilterStrategy::None,
+            FilterStrategy::Average,
+            FilterStrategy::Slerp,
+        ] {
+            let mut filter = strategy.filter();
+            for i in 0..10 {
+                filter.put(if i % 2 == 0 { q } else { neg });
+            }
+            assert!(filter.get().angle_to(&q) < 1.0e-3, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn slerp_smoother_follows_gradually() {
+        let mut filter = SlerpSmoother::new(0.5);
+        filter.put(UnitQuaternion::identity());
+        let q = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 1.0);
+        filter.put(q);
+        assert!((filter.get().angle() - 0.5).abs() < 1.0e-4);
+        for _ in 0..20 {
+            filter.put(q);
+        }
+        assert!(filter.get().angle_to(&q) < 1.0e-4);
+    }
+}
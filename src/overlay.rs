@@ -0,0 +1,324 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::{
+    io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    time::interval,
+};
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::{CubeMove, CubeState},
+    cuboard::{CuboardInput, CuboardKeymap},
+    engine::{CuboardEngine, EngineEvent},
+    idle::IdleMonitor,
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    train::handle_until_disconnected,
+    websocket,
+};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:9901";
+
+const PAGE: &str = include_str!("overlay.html");
+const LAST_MOVES: usize = 12;
+// the typing speed is measured over the recent keys
+const WPM_WINDOW: Duration = Duration::from_secs(30);
+// the wpm decays while not typing
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// what the overlay shows, sent to the page as json whenever it changes
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct OverlayStatus {
+    // the last moves, oldest first
+    pub moves: Vec<String>,
+    // unknown before the state is reported by the cube
+    pub solved: Option<bool>,
+    // the seconds and the moves since the cube was last solved, or of the last solve if it is
+    // solved again
+    pub seconds: Option<f32>,
+    pub solve_moves: usize,
+    pub wpm: f32,
+    // the unfinished line, including the text not accepted yet
+    pub text: String,
+}
+
+// track the solves and the typing for the overlay
+pub struct Overlay {
+    engine: CuboardEngine,
+    state: Option<CubeState>,
+    sequencer: MoveSequencer,
+    moves: VecDeque<CubeMove>,
+    solve_start: Option<Instant>,
+    solve_moves: usize,
+    last_solve: Option<Duration>,
+    keys: VecDeque<Instant>,
+}
+
+impl Overlay {
+    pub fn new(engine: CuboardEngine) -> Self {
+        Overlay {
+            engine,
+            state: None,
+            sequencer: MoveSequencer::default(),
+            moves: VecDeque::new(),
+            solve_start: None,
+            solve_moves: 0,
+            last_solve: None,
+            keys: VecDeque::new(),
+        }
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage, now: Instant) {
+        if let ResponseMessage::State {
+            count: _,
+            state: Some(mut state),
+        } = msg
+        {
+            state.reset_centers();
+            self.state = Some(state);
+        }
+        for (mv, _) in self.sequencer.handle_message(msg, now).moves {
+            self.turn(mv, now);
+        }
+
+        for event in self.engine.handle_message(msg) {
+            if let EngineEvent::TextCommitted(text) = event {
+                self.keys.extend(text.chars().map(|_| now));
+            }
+        }
+        while let Some(&key) = self.keys.front() {
+            if now.duration_since(key) < WPM_WINDOW {
+                break;
+            }
+            self.keys.pop_front();
+        }
+    }
+
+    fn turn(&mut self, mv: CubeMove, now: Instant) {
+        if self.moves.len() == LAST_MOVES {
+            self.moves.pop_front();
+        }
+        self.moves.push_back(mv);
+
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        // a solve is counted from the first move after the cube is solved
+        if self.solve_start.is_none() {
+            self.solve_start = Some(now);
+            self.solve_moves = 0;
+        }
+        state.apply(mv);
+        self.solve_moves += 1;
+        if state.is_solved() {
+            self.last_solve = self.solve_start.take().map(|start| now - start);
+        }
+    }
+
+    pub fn status(&self, now: Instant) -> OverlayStatus {
+        let seconds = match self.solve_start {
+            Some(start) => Some(now - start),
+            None => self.last_solve,
+        };
+        let keys = self
+            .keys
+            .iter()
+            .filter(|&&key| now.duration_since(key) < WPM_WINDOW);
+        // a word is 5 characters by convention
+        let wpm = match keys.clone().min() {
+            Some(&first) => {
+                let minutes = now.duration_since(first).max(UPDATE_INTERVAL).as_secs_f32() / 60.0;
+                keys.count() as f32 / 5.0 / minutes
+            }
+            None => 0.0,
+        };
+        OverlayStatus {
+            moves: self.moves.iter().map(|mv| mv.to_string()).collect(),
+            solved: self.state.map(|state| state.is_solved()),
+            seconds: seconds.map(|seconds| seconds.as_secs_f32()),
+            solve_moves: self.solve_moves,
+            wpm,
+            text: format!("{}{}", self.engine.line(), self.engine.buffered_text()),
+        }
+    }
+}
+
+// serve the page, and the status by websocket at `/feed`
+async fn serve_client(
+    stream: TcpStream,
+    mut statuses: watch::Receiver<OverlayStatus>,
+) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let key = match websocket::read_request(&mut lines).await? {
+        (path, Some(key)) if path == "/feed" => key,
+        (path, _) if path == "/" => {
+            let response = format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/html; charset=utf-8\r\n\
+                 Content-Length: {}\r\n\
+                 Connection: close\r\n\r\n{}",
+                PAGE.len(),
+                PAGE,
+            );
+            writer.write_all(response.as_bytes()).await?;
+            return writer.shutdown().await;
+        }
+        _ => {
+            writer
+                .write_all(b"HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n")
+                .await?;
+            return writer.shutdown().await;
+        }
+    };
+    websocket::accept(&mut writer, &key).await?;
+
+    let mut frames = websocket::client_frames(lines.into_inner());
+    loop {
+        let status = serde_json::to_string(&*statuses.borrow_and_update())?;
+        websocket::send_text(&mut writer, &status).await?;
+        // answer the client until the status changes
+        loop {
+            tokio::select! {
+                changed = statuses.changed() => {
+                    if changed.is_err() {
+                        return writer.shutdown().await;
+                    }
+                    break;
+                }
+                frame = frames.next() => {
+                    let Some(frame) = frame else {
+                        return Ok(());
+                    };
+                    if !websocket::answer(&mut writer, frame?).await? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn serve(listener: TcpListener, statuses: watch::Receiver<OverlayStatus>) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let client = serve_client(stream, statuses.clone());
+        tokio::spawn(async move {
+            let _ = client.await;
+        });
+    }
+}
+
+// show the solves and the typing on a page, to be added as a browser source of OBS
+pub async fn run(
+    address: String,
+    keymap: CuboardKeymap,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(&address).await?;
+
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("connected! add http://{} as a browser source", address);
+
+    let engine = CuboardEngine::new(CuboardInput::new(keymap));
+    let overlay = Arc::new(Mutex::new(Overlay::new(engine)));
+    let (statuses, receiver) = watch::channel(OverlayStatus::default());
+    let server = tokio::spawn(serve(listener, receiver));
+
+    let statuses = Arc::new(statuses);
+    let update = {
+        let overlay = Arc::clone(&overlay);
+        let statuses = Arc::clone(&statuses);
+        move || {
+            if let Ok(overlay) = overlay.lock() {
+                let status = overlay.status(Instant::now());
+                statuses.send_if_modified(|last| {
+                    let modified = *last != status;
+                    *last = status;
+                    modified
+                });
+            }
+        }
+    };
+    let ticker = {
+        let update = update.clone();
+        tokio::spawn(async move {
+            let mut ticks = interval(UPDATE_INTERVAL);
+            loop {
+                ticks.tick().await;
+                update();
+            }
+        })
+    };
+    let input_handler: Box<dyn FnMut(ResponseMessage) + Send> = Box::new(move |msg| {
+        if let Ok(mut overlay) = overlay.lock() {
+            overlay.handle_message(msg, Instant::now());
+        }
+        update();
+    });
+    let res = handle_until_disconnected(
+        &gancube,
+        input_handler,
+        shutdown,
+        idle,
+        SignalMonitor::default(),
+        None,
+    )
+    .await;
+
+    ticker.abort();
+    server.abort();
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bluetooth::mock::MoveWindow, cuboard::DEFAULT_KEYMAP};
+
+    #[test]
+    fn solves_and_moves_are_tracked() {
+        use CubeMove::*;
+
+        let start = Instant::now();
+        let mut overlay = Overlay::new(CuboardEngine::new(CuboardInput::new(DEFAULT_KEYMAP)));
+        let mut window = MoveWindow::default();
+        let mut turn = |overlay: &mut Overlay, mv: CubeMove, secs: u64| {
+            overlay.handle_message(window.turn(mv), start + Duration::from_secs(secs));
+        };
+
+        assert_eq!(overlay.status(start), OverlayStatus::default());
+        let state = ResponseMessage::State {
+            count: 0,
+            state: Some(CubeState::default()),
+        };
+        overlay.handle_message(state, start);
+        assert_eq!(overlay.status(start).solved, Some(true));
+
+        turn(&mut overlay, R, 1);
+        turn(&mut overlay, U, 2);
+        let status = overlay.status(start + Duration::from_secs(4));
+        assert_eq!(status.moves, ["R", "U"]);
+        assert_eq!(status.solved, Some(false));
+        assert_eq!(status.seconds, Some(3.0));
+        assert_eq!(status.solve_moves, 2);
+
+        turn(&mut overlay, Up, 6);
+        turn(&mut overlay, Rp, 7);
+        let status = overlay.status(start + Duration::from_secs(10));
+        assert_eq!(status.moves, ["R", "U", "U'", "R'"]);
+        assert_eq!(status.solved, Some(true));
+        assert_eq!(status.seconds, Some(6.0));
+        assert_eq!(status.solve_moves, 4);
+    }
+}
@@ -0,0 +1,217 @@
+use std::{error::Error, io, process, str::FromStr, time::Duration};
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter};
+use tokio::{
+    io::{stdin, AsyncBufReadExt, BufReader},
+    sync::mpsc,
+    time::timeout,
+};
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::ResponseMessage},
+    cube::{format_turns, parse_turns, CubeDiff, CubeState, FaceTurn},
+    shutdown::Shutdown,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PatternError {
+    #[error("unknown pattern: {0}, try one of {1}")]
+    UnknownPattern(String, String),
+    #[error("no cube state received after the reset")]
+    NoState,
+    #[error("the cube reports another state after the reset: {0}")]
+    Mismatch(CubeDiff),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Display, EnumIter)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Pattern {
+    Checkerboard,
+    CubeInCube,
+    Superflip,
+}
+
+impl FromStr for Pattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pattern::iter()
+            .find(|pattern| pattern.to_string() == s)
+            .ok_or_else(|| {
+                let names = Pattern::iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                PatternError::UnknownPattern(s.to_string(), names.join(", "))
+            })
+    }
+}
+
+impl Pattern {
+    // the turns building the pattern from the solved cube
+    pub fn notation(self) -> &'static str {
+        match self {
+            Pattern::Checkerboard => "U2 D2 F2 B2 L2 R2",
+            Pattern::CubeInCube => "F L F U' R U F2 L2 U' L' B D' B' L2 U",
+            Pattern::Superflip => "U R2 F B R B2 R U2 L B2 R U' D' R2 F R' L B2 U2 F2",
+        }
+    }
+
+    pub fn turns(self) -> Vec<FaceTurn> {
+        parse_turns(self.notation()).unwrap()
+    }
+
+    pub fn state(self) -> CubeState {
+        let moves = self
+            .turns()
+            .iter()
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        CubeState::from_moves(&moves)
+    }
+}
+
+const STATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// read a line from stdin, or exit if shutdown is requested before that, since the pending read
+// can't be cancelled and would keep the runtime from exiting
+async fn read_line(shutdown: &Shutdown) -> io::Result<String> {
+    let mut line = String::new();
+    let mut reader = BufReader::new(stdin());
+    tokio::select! {
+        biased;
+        _ = shutdown.wait() => {
+            shutdown.run_hooks();
+            process::exit(130);
+        }
+        res = reader.read_line(&mut line) => res?,
+    };
+    Ok(line)
+}
+
+// set the state tracked by the cube to the pattern, after it is built on the cube
+pub async fn run(pattern: Pattern, shutdown: Shutdown) -> Result<(), Box<dyn Error>> {
+    println!(
+        "build {} from the solved cube, held white up and green front:",
+        pattern
+    );
+    println!("  {}", format_turns(&pattern.turns()));
+    println!("then press enter to connect and reset the state of the cube");
+    read_line(&shutdown).await?;
+
+    if reset_to(pattern.state(), shutdown).await? {
+        println!("the cube is now tracked as {}", pattern);
+    }
+    Ok(())
+}
+
+// set the state tracked by the cube, and read it back to check the reset; tell whether it is
+// done before shutdown
+pub async fn reset_to(state: CubeState, shutdown: Shutdown) -> Result<bool, Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    // only moves are needed
+    gancube.message_filter().set_gyroscope(false);
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let handle = gancube
+        .register_handler(Box::new(move |msg| {
+            if let ResponseMessage::State {
+                count: _,
+                state: Some(state),
+            } = msg
+            {
+                let _ = sender.send(state);
+            }
+        }))
+        .await?;
+
+    gancube.subscribe_response().await?;
+    gancube.reset_cube_state(state).await?;
+    gancube.request_cube_state().await?;
+
+    // read the state back to check the reset
+    let reported = tokio::select! {
+        reported = timeout(STATE_TIMEOUT, receiver.recv()) => reported.ok().flatten(),
+        _ = shutdown.wait() => None,
+    };
+
+    shutdown.close(&gancube).await?;
+    handle.abort();
+
+    match reported {
+        _ if shutdown.is_requested() => Ok(false),
+        None => Err(PatternError::NoState.into()),
+        Some(mut reported) => {
+            reported.reset_centers();
+            let diff = state.diff(&reported);
+            if !diff.is_empty() {
+                return Err(PatternError::Mismatch(diff).into());
+            }
+            Ok(true)
+        }
+    }
+}
+
+// reset the state tracked by the cube to the state it is physically in, which is solved or given
+// by its facelets
+pub async fn reset_as_is(
+    facelets: Option<String>,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn Error>> {
+    let facelets = match facelets {
+        Some(facelets) => facelets,
+        None => {
+            println!("hold the cube white up and green front, and enter its facelets in the order");
+            println!("of faces U, R, F, D, L, B, such as UUUUUUUUURRRRRRRRRFFFFFFFFF...,");
+            println!("or just press enter if it is solved");
+            read_line(&shutdown).await?.trim().to_string()
+        }
+    };
+    let state = if facelets.is_empty() {
+        CubeState::default()
+    } else {
+        CubeState::from_facelets(&facelets)?
+    };
+
+    if reset_to(state, shutdown).await? {
+        if state.is_solved() {
+            println!("the cube is now tracked as solved");
+        } else {
+            println!("the cube is now tracked as {}", state.facelets());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::{Corner, Edge};
+
+    #[test]
+    fn patterns_are_valid() {
+        for pattern in Pattern::iter() {
+            let state = pattern.state();
+            assert!(state.is_valid(), "{}", pattern);
+            assert!(!state.is_solved(), "{}", pattern);
+            assert_eq!(pattern.to_string().parse::<Pattern>().unwrap(), pattern);
+        }
+        assert!(Pattern::Checkerboard
+            .state()
+            .then(&Pattern::Checkerboard.state())
+            .is_solved());
+    }
+
+    #[test]
+    fn superflip_flips_every_edge_in_place() {
+        let state = Pattern::Superflip.state();
+        assert!(state
+            .corners
+            .iter()
+            .enumerate()
+            .all(|(i, &Corner(pos, ori))| pos.repr() as usize == i && ori.repr() == 0));
+        assert!(state
+            .edges
+            .iter()
+            .enumerate()
+            .all(|(i, &Edge(pos, ori))| pos.repr() as usize == i && ori.repr() == 1));
+    }
+}
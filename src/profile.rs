@@ -0,0 +1,428 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use nalgebra::{UnitQuaternion, Vector3};
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    config::{self, MouseConfig},
+    cube::CubeMove,
+    cuboard::{GyroGesture, GyroGestureRecognizer, BUFFER_SIZE},
+    idle::IdleMonitor,
+    keyboard::{Action, Key, KeyAction, VirtualKeyboard},
+    orientation::{FilterStrategy, OrientationFilter},
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    switch::{SwitchError, SwitchMap},
+    train::handle_until_disconnected,
+};
+
+// turn the cube into key actions instead of text
+pub trait ActionProfile {
+    fn name(&self) -> &'static str;
+    fn input(&mut self, mv: CubeMove) -> Option<Action>;
+    // analog controls by the orientation of the cube
+    fn orient(&mut self, _orientation: UnitQuaternion<f32>, _now: Instant) -> Option<Action> {
+        None
+    }
+    // called when the profile is switched to
+    fn reset(&mut self) {}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Profile {
+    Switch,
+    Media,
+    Mouse,
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "switch" => Ok(Profile::Switch),
+            "media" => Ok(Profile::Media),
+            "mouse" => Ok(Profile::Mouse),
+            _ => Err(format!("unknown profile: {}", s)),
+        }
+    }
+}
+
+impl Profile {
+    pub fn build(self) -> Result<Box<dyn ActionProfile + Send>, SwitchError> {
+        Ok(match self {
+            Profile::Switch => Box::new(SwitchMap::new(&config::get().switch)?),
+            Profile::Media => Box::new(MediaProfile::default()),
+            Profile::Mouse => {
+                let config = config::get();
+                Box::new(MouseProfile::new(&config.mouse, config.gesture.filter))
+            }
+        })
+    }
+}
+
+// the media keys by faces, and the volume and the scrubbing by tilting the cube: raise the F or B
+// face to turn the volume up or down, and raise the R or L face to fast forward or rewind
+#[derive(Clone, Debug, Default)]
+pub struct MediaProfile {
+    reference: Option<UnitQuaternion<f32>>,
+    last: Option<Instant>,
+    // steps pending for the scrubbing and the volume, none within the dead zone
+    scrub: Option<f32>,
+    volume: Option<f32>,
+}
+
+impl MediaProfile {
+    const PLAY_PAUSE: KeyAction = KeyAction {
+        name: "play/pause",
+        keys: &[Key::PlayPause],
+    };
+    const NEXT_SONG: KeyAction = KeyAction {
+        name: "next song",
+        keys: &[Key::NextSong],
+    };
+    const PREVIOUS_SONG: KeyAction = KeyAction {
+        name: "previous song",
+        keys: &[Key::PreviousSong],
+    };
+    const MUTE: KeyAction = KeyAction {
+        name: "mute",
+        keys: &[Key::Mute],
+    };
+    const VOLUME_UP: KeyAction = KeyAction {
+        name: "volume up",
+        keys: &[Key::VolumeUp],
+    };
+    const VOLUME_DOWN: KeyAction = KeyAction {
+        name: "volume down",
+        keys: &[Key::VolumeDown],
+    };
+    const FAST_FORWARD: KeyAction = KeyAction {
+        name: "fast forward",
+        keys: &[Key::FastForward],
+    };
+    const REWIND: KeyAction = KeyAction {
+        name: "rewind",
+        keys: &[Key::Rewind],
+    };
+
+    // the tilt, as the sine of the angle, from the dead zone to the full speed
+    const DEAD_ZONE: f32 = 0.25;
+    const FULL_TILT: f32 = 0.7;
+    // steps per second at the full tilt
+    const MAX_RATE: f32 = 10.0;
+
+    // the first step is taken at once when the tilt leaves the dead zone, then by the rate
+    fn step(pending: &mut Option<f32>, tilt: f32, dt: f32) -> bool {
+        let speed = (tilt.abs() - Self::DEAD_ZONE) / (Self::FULL_TILT - Self::DEAD_ZONE);
+        if speed <= 0.0 {
+            *pending = None;
+            return false;
+        }
+        let steps = match *pending {
+            Some(steps) => steps + speed.min(1.0) * Self::MAX_RATE * dt,
+            None => 1.0,
+        };
+        *pending = Some(steps % 1.0);
+        steps >= 1.0
+    }
+}
+
+impl ActionProfile for MediaProfile {
+    fn name(&self) -> &'static str {
+        "media"
+    }
+
+    fn input(&mut self, mv: CubeMove) -> Option<Action> {
+        let action = match mv.abs() {
+            CubeMove::U => Self::PLAY_PAUSE,
+            CubeMove::R => Self::NEXT_SONG,
+            CubeMove::L => Self::PREVIOUS_SONG,
+            CubeMove::D => Self::MUTE,
+            _ => return None,
+        };
+        Some(Action::Keys(action))
+    }
+
+    fn orient(&mut self, orientation: UnitQuaternion<f32>, now: Instant) -> Option<Action> {
+        let reference = *self.reference.get_or_insert(orientation);
+        let dt = self
+            .last
+            .replace(now)
+            .map_or(Duration::ZERO, |last| now.duration_since(last))
+            .as_secs_f32();
+        // the up direction of the reference orientation in the frame of the cube
+        let up = (orientation.conjugate() * reference) * Vector3::y();
+        let action = if Self::step(&mut self.volume, up.z, dt) {
+            if up.z > 0.0 {
+                Self::VOLUME_UP
+            } else {
+                Self::VOLUME_DOWN
+            }
+        } else if Self::step(&mut self.scrub, up.x, dt) {
+            if up.x > 0.0 {
+                Self::FAST_FORWARD
+            } else {
+                Self::REWIND
+            }
+        } else {
+            return None;
+        };
+        Some(Action::Keys(action))
+    }
+
+    // the current orientation becomes the reference orientation
+    fn reset(&mut self) {
+        *self = MediaProfile::default();
+    }
+}
+
+// an air mouse: turn the cube like a laser pointer to move the pointer, turn R, L and F to click
+// the left, right and middle buttons, and turn U to scroll
+pub struct MouseProfile {
+    filter: Box<dyn OrientationFilter + Send>,
+    last: Option<(UnitQuaternion<f32>, Instant)>,
+    // the fractions of pixels not moved yet
+    remainder: (f32, f32),
+    sensitivity: f32,
+    dead_zone: f32,
+}
+
+impl MouseProfile {
+    const LEFT_CLICK: KeyAction = KeyAction {
+        name: "left click",
+        keys: &[Key::LeftButton],
+    };
+    const RIGHT_CLICK: KeyAction = KeyAction {
+        name: "right click",
+        keys: &[Key::RightButton],
+    };
+    const MIDDLE_CLICK: KeyAction = KeyAction {
+        name: "middle click",
+        keys: &[Key::MiddleButton],
+    };
+
+    pub fn new(config: &MouseConfig, filter: FilterStrategy) -> Self {
+        MouseProfile {
+            filter: filter.filter(),
+            last: None,
+            remainder: (0.0, 0.0),
+            sensitivity: config.sensitivity,
+            dead_zone: config.dead_zone,
+        }
+    }
+}
+
+impl ActionProfile for MouseProfile {
+    fn name(&self) -> &'static str {
+        "mouse"
+    }
+
+    fn input(&mut self, mv: CubeMove) -> Option<Action> {
+        let action = match mv {
+            CubeMove::U => Action::Scroll(1),
+            CubeMove::Up => Action::Scroll(-1),
+            _ => match mv.abs() {
+                CubeMove::R => Action::Keys(Self::LEFT_CLICK),
+                CubeMove::L => Action::Keys(Self::RIGHT_CLICK),
+                CubeMove::F => Action::Keys(Self::MIDDLE_CLICK),
+                _ => return None,
+            },
+        };
+        Some(action)
+    }
+
+    fn orient(&mut self, orientation: UnitQuaternion<f32>, now: Instant) -> Option<Action> {
+        self.filter.put(orientation);
+        let orientation = self.filter.get();
+        let (last, since) = self.last.replace((orientation, now))?;
+        let dt = now.duration_since(since).as_secs_f32();
+        let rotation = (orientation * last.conjugate()).scaled_axis();
+
+        // ignore the tremor slower than the dead zone
+        if rotation.norm().to_degrees() <= self.dead_zone * dt {
+            return None;
+        }
+        // turning the F face left or up moves the pointer left or up
+        let dx = -rotation.y.to_degrees() * self.sensitivity + self.remainder.0;
+        let dy = rotation.x.to_degrees() * self.sensitivity + self.remainder.1;
+        let (x, y) = (dx.trunc(), dy.trunc());
+        self.remainder = (dx - x, dy - y);
+        (x != 0.0 || y != 0.0).then_some(Action::Pointer(x as i32, y as i32))
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+        self.remainder = (0.0, 0.0);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProfileEvent {
+    Switched(&'static str),
+    Action(Action),
+}
+
+// run the active profile, and switch to the next profile by turning the cube around
+pub struct ProfileRunner {
+    profiles: Vec<Box<dyn ActionProfile + Send>>,
+    active: usize,
+    sequencer: MoveSequencer,
+    recognizer: GyroGestureRecognizer<BUFFER_SIZE>,
+}
+
+impl ProfileRunner {
+    pub fn new(profiles: Vec<Box<dyn ActionProfile + Send>>) -> Self {
+        ProfileRunner {
+            profiles,
+            active: 0,
+            sequencer: MoveSequencer::default(),
+            recognizer: GyroGestureRecognizer::new(),
+        }
+    }
+
+    pub fn active(&self) -> &'static str {
+        self.profiles[self.active].name()
+    }
+
+    pub fn switch_profile(&mut self) -> &'static str {
+        self.active = (self.active + 1) % self.profiles.len();
+        self.profiles[self.active].reset();
+        self.active()
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> Vec<ProfileEvent> {
+        if self.recognizer.handle_message(msg) == Some(GyroGesture::TurningAround) {
+            return vec![ProfileEvent::Switched(self.switch_profile())];
+        }
+
+        let now = Instant::now();
+        let sequenced = self.sequencer.handle_message(msg, now);
+        let profile = &mut self.profiles[self.active];
+        let mut events = sequenced
+            .moves
+            .iter()
+            .filter_map(|&(mv, _)| profile.input(mv))
+            .map(ProfileEvent::Action)
+            .collect::<Vec<_>>();
+        if let Some((frame, _)) = msg.orientation_frames() {
+            let action = profile.orient(frame.orientation, now);
+            events.extend(action.map(ProfileEvent::Action));
+        }
+        events
+    }
+}
+
+// send the actions of the profiles by a virtual keyboard, until the cube disconnects
+pub async fn run(
+    profiles: Vec<Profile>,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    if profiles.is_empty() {
+        println!("no profile");
+        return Ok(());
+    }
+    let profiles = profiles
+        .into_iter()
+        .map(Profile::build)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut keyboard = VirtualKeyboard::new()?;
+
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("connected! turn the cube around to switch the profile");
+
+    let mut runner = ProfileRunner::new(profiles);
+    print!("profile {}", runner.active());
+    let _ = stdout().flush();
+    let handler: Box<dyn FnMut(ResponseMessage) + Send> = Box::new(move |msg| {
+        for event in runner.handle_message(msg) {
+            let status = match event {
+                ProfileEvent::Switched(name) => format!("profile {}", name),
+                ProfileEvent::Action(action) => {
+                    let res = keyboard.perform(action);
+                    let name = action.name().unwrap_or("pointer");
+                    match res {
+                        Err(err) => format!("cannot send {}: {}", name, err),
+                        Ok(()) if action.name().is_none() => continue,
+                        Ok(()) => format!("profile {}: {}", runner.active(), name),
+                    }
+                }
+            };
+            print!("\r\x1b[2K{}", status);
+            let _ = stdout().flush();
+        }
+        if matches!(msg, ResponseMessage::Disconnect) {
+            println!();
+        }
+    });
+    handle_until_disconnected(
+        &gancube,
+        handler,
+        shutdown,
+        idle,
+        SignalMonitor::default(),
+        None,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn media_steps_by_tilting() {
+        let mut media = MediaProfile::default();
+        assert_eq!(
+            media.input(CubeMove::Lp),
+            Some(Action::Keys(MediaProfile::PREVIOUS_SONG))
+        );
+        assert_eq!(media.input(CubeMove::F), None);
+
+        let start = Instant::now();
+        let level = UnitQuaternion::identity();
+        assert_eq!(media.orient(level, start), None);
+
+        // raise the R face fully, which steps at once and then by the rate
+        let tilted = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.8);
+        let actions = (1..=100)
+            .filter_map(|i| media.orient(tilted, start + Duration::from_millis(i * 10)))
+            .collect::<Vec<_>>();
+        assert!((9..=11).contains(&actions.len()));
+        assert!(actions
+            .iter()
+            .all(|&action| action == Action::Keys(MediaProfile::FAST_FORWARD)));
+
+        let second = start + Duration::from_secs(2);
+        assert_eq!(media.orient(level, second), None);
+        assert_eq!(media.scrub, None);
+    }
+
+    #[test]
+    fn mouse_moves_by_turning() {
+        let config = MouseConfig::default();
+        let mut mouse = MouseProfile::new(&config, FilterStrategy::None);
+        assert_eq!(mouse.input(CubeMove::Up), Some(Action::Scroll(-1)));
+
+        let start = Instant::now();
+        let step = Duration::from_millis(10);
+        let turned = |degrees: f32| {
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), degrees.to_radians())
+        };
+        assert_eq!(mouse.orient(turned(0.0), start), None);
+        // turning left by a degree moves the pointer left
+        let Some(Action::Pointer(dx, dy)) = mouse.orient(turned(1.0), start + step) else {
+            panic!("the pointer is not moved");
+        };
+        assert_eq!((dx, dy), (-config.sensitivity.round() as i32, 0));
+        // slow drifts are ignored
+        let drift = config.dead_zone * step.as_secs_f32() / 2.0;
+        assert_eq!(mouse.orient(turned(1.0 + drift), start + step * 2), None);
+    }
+}
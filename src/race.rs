@@ -0,0 +1,682 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc},
+    task::JoinSet,
+    time::{interval, timeout},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    bluetooth::connect_cube,
+    cuboard::{CuboardInput, CuboardInputEvent, CuboardKeymap},
+    idle::IdleMonitor,
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    textgen::{ErrorHeatmap, TrainingText},
+    train::handle_until_disconnected,
+    verify::VerifyAlert,
+};
+
+pub const DEFAULT_ADDRESS: &str = "0.0.0.0:7878";
+const EVENT_CAPACITY: usize = 64;
+// time for the players to receive the result before the server quits
+const END_TIMEOUT: Duration = Duration::from_secs(1);
+// how often the alert of the verifier is checked
+const ALERT_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+const BAR_WIDTH: usize = 30;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RaceError {
+    #[error("no text to race on")]
+    NoText,
+    #[error("the race has already started")]
+    Started,
+    #[error("the name {0} is taken")]
+    NameTaken(String),
+    #[error("join the race first")]
+    NotJoined,
+    #[error("already joined the race")]
+    Joined,
+    #[error("rejected by the server: {0}")]
+    Rejected(String),
+    #[error("the server closed the connection")]
+    Closed,
+}
+
+// a request is a line of json, such as `{"command":"join","name":"alice"}`
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum RaceRequest {
+    Join { name: String },
+    // the number of correctly typed graphemes, and of the wrong ones
+    Progress { typed: usize, misses: usize },
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Player {
+    #[serde(skip)]
+    id: usize,
+    pub name: String,
+    pub typed: usize,
+    pub misses: usize,
+    // the time to finish the text
+    pub seconds: Option<f32>,
+    pub left: bool,
+}
+
+// every line sent to the players is an event
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum RaceEvent {
+    // the players joined so far, until enough players join
+    Waiting { players: Vec<String>, needed: usize },
+    Start { text: String },
+    Progress { players: Vec<Player> },
+    // the final standings, when every player finishes or leaves
+    End { players: Vec<Player> },
+    Error { message: String },
+}
+
+struct Race {
+    text: String,
+    length: usize,
+    needed: usize,
+    players: Vec<Player>,
+    next_id: usize,
+    start: Option<Instant>,
+}
+
+impl Race {
+    fn new(text: String, needed: usize) -> Self {
+        Race {
+            length: text.graphemes(true).count(),
+            text,
+            needed: needed.max(1),
+            players: Vec::new(),
+            next_id: 0,
+            start: None,
+        }
+    }
+
+    // the players finished in order, followed by the others by their progress
+    fn standings(&self) -> Vec<Player> {
+        let mut players = self.players.clone();
+        players.sort_by(|a, b| match (a.seconds, b.seconds) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => b.typed.cmp(&a.typed),
+        });
+        players
+    }
+
+    fn progress(&self) -> Vec<RaceEvent> {
+        let players = self.standings();
+        let over = self.players.iter().all(|p| p.left || p.seconds.is_some());
+        if over {
+            vec![RaceEvent::End { players }]
+        } else {
+            vec![RaceEvent::Progress { players }]
+        }
+    }
+
+    fn waiting(&self) -> RaceEvent {
+        RaceEvent::Waiting {
+            players: self.players.iter().map(|p| p.name.clone()).collect(),
+            needed: self.needed,
+        }
+    }
+
+    // the id of the player, and the events to broadcast
+    fn join(&mut self, name: String) -> Result<(usize, Vec<RaceEvent>), RaceError> {
+        if self.start.is_some() {
+            return Err(RaceError::Started);
+        }
+        if self.players.iter().any(|p| p.name == name) {
+            return Err(RaceError::NameTaken(name));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.players.push(Player {
+            id,
+            name,
+            typed: 0,
+            misses: 0,
+            seconds: None,
+            left: false,
+        });
+        if self.players.len() < self.needed {
+            return Ok((id, vec![self.waiting()]));
+        }
+        self.start = Some(Instant::now());
+        let text = self.text.clone();
+        let mut events = vec![RaceEvent::Start { text }];
+        events.extend(self.progress());
+        Ok((id, events))
+    }
+
+    fn update(&mut self, id: usize, typed: usize, misses: usize) -> Vec<RaceEvent> {
+        let Some(start) = self.start else {
+            return Vec::new();
+        };
+        let Some(player) = self.players.iter_mut().find(|p| p.id == id) else {
+            return Vec::new();
+        };
+        if player.seconds.is_some() {
+            return Vec::new();
+        }
+        player.typed = typed.min(self.length);
+        player.misses = misses;
+        if player.typed == self.length {
+            player.seconds = Some(start.elapsed().as_secs_f32());
+        }
+        self.progress()
+    }
+
+    fn leave(&mut self, id: usize) -> Vec<RaceEvent> {
+        if self.start.is_none() {
+            // free the place and the name before the race starts
+            self.players.retain(|p| p.id != id);
+            return vec![self.waiting()];
+        }
+        if let Some(player) = self.players.iter_mut().find(|p| p.id == id) {
+            player.left = true;
+        }
+        self.progress()
+    }
+}
+
+async fn send_event<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    event: &RaceEvent,
+) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+fn broadcast_all(events: &broadcast::Sender<RaceEvent>, race_events: Vec<RaceEvent>) {
+    for event in race_events {
+        let _ = events.send(event);
+    }
+}
+
+fn handle_request(
+    request: RaceRequest,
+    id: &mut Option<usize>,
+    race: &Mutex<Race>,
+    events: &broadcast::Sender<RaceEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let mut race = race.lock().map_err(|err| err.to_string())?;
+    match (request, *id) {
+        (RaceRequest::Join { .. }, Some(_)) => Err(RaceError::Joined.into()),
+        (RaceRequest::Join { name }, None) => {
+            let (player, race_events) = race.join(name)?;
+            *id = Some(player);
+            broadcast_all(events, race_events);
+            Ok(())
+        }
+        (RaceRequest::Progress { .. }, None) => Err(RaceError::NotJoined.into()),
+        (RaceRequest::Progress { typed, misses }, Some(player)) => {
+            broadcast_all(events, race.update(player, typed, misses));
+            Ok(())
+        }
+    }
+}
+
+// relay the requests of a player to the race, and the events of the race to the player
+async fn serve_player(
+    stream: TcpStream,
+    race: Arc<Mutex<Race>>,
+    events: broadcast::Sender<RaceEvent>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut receiver = events.subscribe();
+    let mut id = None;
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+                let res = match serde_json::from_str::<RaceRequest>(&line) {
+                    Ok(request) => handle_request(request, &mut id, &race, &events),
+                    Err(err) => Err(err.into()),
+                };
+                if let Err(message) = res.map_err(|err| err.to_string()) {
+                    send_event(&mut writer, &RaceEvent::Error { message }).await?;
+                }
+            }
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    send_event(&mut writer, &event).await?;
+                    if matches!(event, RaceEvent::End { .. }) {
+                        return Ok(());
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+
+    if let (Some(id), Ok(mut race)) = (id, race.lock()) {
+        broadcast_all(&events, race.leave(id));
+    }
+    Ok(())
+}
+
+// accept players until the race ends, and return the final standings
+async fn serve(listener: TcpListener, text: String, needed: usize) -> std::io::Result<Vec<Player>> {
+    let race = Arc::new(Mutex::new(Race::new(text, needed)));
+    let (events, mut receiver) = broadcast::channel(EVENT_CAPACITY);
+    let mut players = JoinSet::new();
+    let standings = loop {
+        tokio::select! {
+            res = listener.accept() => {
+                let (stream, _) = res?;
+                players.spawn(serve_player(stream, Arc::clone(&race), events.clone()));
+            }
+            event = receiver.recv() => match event {
+                Ok(RaceEvent::Waiting { players, needed }) => {
+                    println!("{}/{} joined: {}", players.len(), needed, players.join(", "));
+                }
+                Ok(RaceEvent::Start { text }) => println!("start racing on: {}", text),
+                Ok(RaceEvent::End { players }) => break players,
+                _ => {}
+            },
+        }
+    };
+
+    let _ = timeout(END_TIMEOUT, async {
+        while players.join_next().await.is_some() {}
+    })
+    .await;
+    Ok(standings)
+}
+
+fn format_standings(players: &[Player], length: usize) -> Vec<String> {
+    players
+        .iter()
+        .map(|player| {
+            let ratio = player.typed as f32 / length.max(1) as f32;
+            let filled = (ratio * BAR_WIDTH as f32).round() as usize;
+            let status = match player.seconds {
+                _ if player.left => "left".to_string(),
+                Some(seconds) => format!("{:.1}s", seconds),
+                None => String::new(),
+            };
+            format!(
+                "{:<12} [{}{}] {:>3}% {:>3} miss {}",
+                player.name,
+                "#".repeat(filled),
+                ".".repeat(BAR_WIDTH - filled),
+                (ratio * 100.0).round(),
+                player.misses,
+                status,
+            )
+        })
+        .collect()
+}
+
+// host a race on the text, which starts when enough players join
+pub async fn host(
+    address: &str,
+    text: TrainingText,
+    needed: usize,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn Error>> {
+    let text = text
+        .lines(ErrorHeatmap::default())?
+        .find(|line| !line.trim().is_empty())
+        .ok_or(RaceError::NoText)?;
+    let length = text.graphemes(true).count();
+
+    let listener = TcpListener::bind(address).await?;
+    println!(
+        "waiting for {} players on {}",
+        needed,
+        listener.local_addr()?
+    );
+
+    let standings = tokio::select! {
+        res = serve(listener, text, needed) => res?,
+        _ = shutdown.wait() => return Ok(()),
+    };
+    println!("the race is over!");
+    for line in format_standings(&standings, length) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// the progress of typing the text, where only the expected grapheme moves on
+struct RaceProgress {
+    text: Vec<String>,
+    typed: usize,
+    misses: usize,
+}
+
+impl RaceProgress {
+    fn new(text: &str) -> Self {
+        RaceProgress {
+            text: text.graphemes(true).map(str::to_string).collect(),
+            typed: 0,
+            misses: 0,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.typed == self.text.len()
+    }
+
+    fn accept(&mut self, text: &str) {
+        for grapheme in text.graphemes(true) {
+            if self.is_finished() {
+                return;
+            }
+            if self.text[self.typed] == grapheme {
+                self.typed += 1;
+            } else {
+                self.misses += 1;
+            }
+        }
+    }
+
+    // tell whether the buffered text types exactly the rest of the text
+    fn completes(&self, buffer: &str) -> bool {
+        !self.is_finished()
+            && buffer
+                .graphemes(true)
+                .eq(self.text[self.typed..].iter().map(String::as_str))
+    }
+
+    fn render(&self) -> String {
+        let typed = self.text[..self.typed].concat();
+        let cursor = self.text.get(self.typed).map_or("", String::as_str);
+        let rest = self.text.get(self.typed + 1..).unwrap_or_default().concat();
+        format!("{}\x1b[7m{}\x1b[m\x1b[2m{}\x1b[m", typed, cursor, rest)
+    }
+}
+
+// the text being typed above the progress of every player
+struct RaceView {
+    progress: RaceProgress,
+    players: Vec<Player>,
+    rows: usize,
+    // shown between the progress and the standings
+    alert: VerifyAlert,
+    shown: Option<String>,
+}
+
+impl RaceView {
+    fn is_alert_changed(&self) -> bool {
+        self.alert.status(Instant::now()) != self.shown
+    }
+
+    fn render(&mut self) {
+        let length = self.progress.text.len();
+        self.shown = self.alert.status(Instant::now());
+        let alert = self.shown.clone().unwrap_or_default();
+        let mut lines = vec![self.progress.render(), alert];
+        lines.extend(format_standings(&self.players, length));
+        let mut terminal = stdout();
+        if self.rows > 0 {
+            let _ = write!(terminal, "\x1b[{}A", self.rows);
+        }
+        for line in lines.iter() {
+            let _ = write!(terminal, "\r\x1b[2K{}\n", line);
+        }
+        let _ = terminal.flush();
+        self.rows = lines.len();
+    }
+}
+
+fn request_line(request: &RaceRequest) -> String {
+    let mut line = serde_json::to_string(request).unwrap();
+    line.push('\n');
+    line
+}
+
+// join the race on the server, and type the text on the cube
+pub async fn join(
+    address: &str,
+    name: String,
+    keymap: CuboardKeymap,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+
+    println!("join the race on {}...", address);
+    let stream = TcpStream::connect(address).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    writer
+        .write_all(request_line(&RaceRequest::Join { name }).as_bytes())
+        .await?;
+
+    let mut next_event = async || -> Result<RaceEvent, Box<dyn Error>> {
+        let line = lines.next_line().await?.ok_or(RaceError::Closed)?;
+        match serde_json::from_str(&line)? {
+            RaceEvent::Error { message } => Err(RaceError::Rejected(message).into()),
+            event => Ok(event),
+        }
+    };
+    let text = loop {
+        let event = tokio::select! {
+            event = next_event() => event?,
+            _ = shutdown.wait() => {
+                shutdown.close(&gancube).await?;
+                return Ok(());
+            }
+        };
+        match event {
+            RaceEvent::Waiting { players, needed } => {
+                println!(
+                    "{}/{} joined: {}",
+                    players.len(),
+                    needed,
+                    players.join(", ")
+                );
+            }
+            RaceEvent::Start { text } => break text,
+            _ => {}
+        }
+    };
+    println!("go!");
+    println!();
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut input = CuboardInput::new(keymap);
+    let mut progress = RaceProgress::new(&text);
+    let input_handler = Box::new(move |msg| {
+        if progress.is_finished() {
+            return;
+        }
+        match input.handle_message(msg) {
+            Some(CuboardInputEvent::Input { accept, skip: _ }) => progress.accept(&accept),
+            Some(CuboardInputEvent::Finish(accept)) => progress.accept(&accept),
+            Some(CuboardInputEvent::Cancel) => input.cancel(),
+            _ => return,
+        }
+        // the last key is accepted without waiting for the next one
+        if progress.completes(&input.buffered_text()) {
+            let accept = input.finish();
+            progress.accept(&accept);
+        }
+        let _ = sender.send((progress.typed, progress.misses));
+    });
+
+    let mut view = RaceView {
+        progress: RaceProgress::new(&text),
+        players: Vec::new(),
+        rows: 0,
+        alert: VerifyAlert::default(),
+        shown: None,
+    };
+    let typing = handle_until_disconnected(
+        &gancube,
+        input_handler,
+        shutdown.clone(),
+        idle,
+        SignalMonitor::default(),
+        Some(view.alert.clone()),
+    );
+    tokio::pin!(typing);
+    let mut alert_check = interval(ALERT_CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            res = &mut typing => return res,
+            _ = alert_check.tick() => {
+                if view.is_alert_changed() {
+                    view.render();
+                }
+            }
+            Some((typed, misses)) = receiver.recv() => {
+                view.progress.typed = typed;
+                view.progress.misses = misses;
+                view.render();
+                let request = RaceRequest::Progress { typed, misses };
+                writer.write_all(request_line(&request).as_bytes()).await?;
+            }
+            event = next_event() => match event? {
+                RaceEvent::Progress { players } => {
+                    view.players = players;
+                    view.render();
+                }
+                RaceEvent::End { players } => {
+                    view.players = players;
+                    view.render();
+                    println!("the race is over!");
+                    break;
+                }
+                _ => {}
+            },
+        }
+    }
+
+    shutdown.request();
+    typing.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn race_starts_with_enough_players() {
+        let mut race = Race::new("ab cd".to_string(), 2);
+        let (alice, events) = race.join("alice".to_string()).unwrap();
+        assert!(matches!(events[..], [RaceEvent::Waiting { needed: 2, .. }]));
+        assert!(matches!(
+            race.join("alice".to_string()),
+            Err(RaceError::NameTaken(_))
+        ));
+        let (bob, events) = race.join("bob".to_string()).unwrap();
+        assert!(matches!(events[0], RaceEvent::Start { .. }));
+        assert!(matches!(
+            race.join("carol".to_string()),
+            Err(RaceError::Started)
+        ));
+
+        assert!(matches!(
+            race.update(bob, 3, 1)[..],
+            [RaceEvent::Progress { .. }]
+        ));
+        assert!(matches!(
+            race.update(alice, 9, 0)[..],
+            [RaceEvent::Progress { .. }]
+        ));
+        let events = race.leave(bob);
+        let [RaceEvent::End { players }] = &events[..] else {
+            panic!("the race should end: {:?}", events);
+        };
+        assert_eq!(players[0].name, "alice");
+        assert_eq!(players[0].typed, 5);
+        assert!(players[0].seconds.is_some());
+        assert!(players[1].left);
+    }
+
+    #[test]
+    fn only_expected_graphemes_move_on() {
+        let mut progress = RaceProgress::new("hello");
+        progress.accept("hex");
+        assert_eq!((progress.typed, progress.misses), (2, 1));
+        assert!(!progress.completes("l"));
+        assert!(progress.completes("llo"));
+        progress.accept("llo!");
+        assert!(progress.is_finished());
+        assert_eq!(progress.misses, 1);
+    }
+
+    #[tokio::test]
+    async fn players_race_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(listener, "go".to_string(), 2));
+
+        let mut clients = Vec::new();
+        for name in ["alice", "bob"] {
+            let stream = TcpStream::connect(address).await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let join = request_line(&RaceRequest::Join {
+                name: name.to_string(),
+            });
+            writer.write_all(join.as_bytes()).await.unwrap();
+            let mut lines = BufReader::new(reader).lines();
+            // wait for the join to be handled, so that the order of the players is fixed
+            let first = lines.next_line().await.unwrap().unwrap();
+            assert!(first.starts_with(r#"{"event":"#), "{}", first);
+            clients.push((lines, writer));
+        }
+
+        let (lines, writer) = &mut clients[0];
+        let finish = request_line(&RaceRequest::Progress {
+            typed: 2,
+            misses: 0,
+        });
+        writer.write_all(finish.as_bytes()).await.unwrap();
+        // let alice finish first
+        loop {
+            let line = lines.next_line().await.unwrap().unwrap();
+            if let RaceEvent::Progress { players } = serde_json::from_str(&line).unwrap() {
+                if players[0].seconds.is_some() {
+                    break;
+                }
+            }
+        }
+        let (_, writer) = &mut clients[1];
+        let finish = request_line(&RaceRequest::Progress {
+            typed: 2,
+            misses: 3,
+        });
+        writer.write_all(finish.as_bytes()).await.unwrap();
+
+        let standings = server.await.unwrap().unwrap();
+        let names = standings
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["alice", "bob"]);
+        assert!(standings.iter().all(|p| p.seconds.is_some()));
+        assert_eq!(standings[1].misses, 3);
+
+        let (lines, _) = &mut clients[0];
+        let mut last = None;
+        while let Ok(Some(line)) = lines.next_line().await {
+            last = Some(serde_json::from_str::<RaceEvent>(&line).unwrap());
+        }
+        assert!(matches!(last, Some(RaceEvent::End { .. })));
+    }
+}
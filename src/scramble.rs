@@ -0,0 +1,438 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::{format_turns, merge_turns, CubeMove, CubeState, FaceTurn},
+    idle::IdleMonitor,
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    solver::{solve, BuiltinSolver, Solver},
+    stats::{self, SolveRecord},
+    theme,
+    train::handle_until_disconnected,
+    verify::VerifyAlert,
+    view::{self, GuideHandler},
+};
+
+// the longest correction searched by the solver, longer detours are undone turn by turn
+const MAX_CORRECTION: usize = 3;
+
+// follow the turns of the cube along a scramble, relative to the state at the start
+pub struct ScrambleGuide {
+    turns: Vec<FaceTurn>,
+    // the states after each turn of the scramble
+    states: Vec<CubeState>,
+    state: CubeState,
+    // the number of turns of the scramble done
+    progress: usize,
+    // the turns since the cube left the scramble
+    detour: Vec<CubeMove>,
+    // the turns leading back to the scramble, and the number of turns of the scramble done then
+    correction: Vec<FaceTurn>,
+    resume: usize,
+    // the last turn made the way longer
+    mistake: bool,
+    solver: Box<dyn Solver + Send>,
+}
+
+impl ScrambleGuide {
+    pub fn new(turns: Vec<FaceTurn>) -> Self {
+        let mut state = CubeState::default();
+        let mut states = vec![state];
+        for turn in &turns {
+            for mv in turn.moves() {
+                state.apply(mv);
+            }
+            states.push(state);
+        }
+        ScrambleGuide {
+            turns,
+            states,
+            state: CubeState::default(),
+            progress: 0,
+            detour: Vec::new(),
+            correction: Vec::new(),
+            resume: 0,
+            mistake: false,
+            solver: Box::new(BuiltinSolver),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress == self.turns.len() && self.correction.is_empty()
+    }
+
+    pub fn is_mistake(&self) -> bool {
+        self.mistake
+    }
+
+    // relative to the state at the start
+    pub fn state(&self) -> CubeState {
+        self.state
+    }
+
+    // the number of turns to perform, including the correction
+    fn remaining(&self) -> usize {
+        self.correction.len() + self.turns.len() - self.resume
+    }
+
+    pub fn next_turn(&self) -> Option<FaceTurn> {
+        self.correction
+            .first()
+            .or_else(|| self.turns.get(self.resume))
+            .copied()
+    }
+
+    pub fn input(&mut self, mv: CubeMove) {
+        let remaining = self.remaining();
+        self.state.apply(mv);
+
+        if let Some(i) = self.states.iter().rposition(|s| s == &self.state) {
+            self.progress = i;
+            self.resume = i;
+            self.detour.clear();
+            self.correction.clear();
+            self.mistake = false;
+            return;
+        }
+
+        // the shortest way back to a state of the scramble, such as the rest of a half turn
+        self.detour.push(mv);
+        let undo = self
+            .detour
+            .iter()
+            .rev()
+            .map(|mv| mv.rev())
+            .collect::<Vec<_>>();
+        self.correction = merge_turns(&undo);
+        self.resume = self.progress;
+        for j in self.progress..self.states.len() {
+            let max_depth = MAX_CORRECTION.min(self.remaining() + j - self.turns.len());
+            // the search is short enough to fall back to if the solver fails
+            let path = self
+                .solver
+                .solve(&self.state, &self.states[j], max_depth)
+                .unwrap_or_else(|_| solve(&self.state, &self.states[j], max_depth));
+            if let Some(path) = path {
+                if path.len() + self.turns.len() - j < self.remaining() {
+                    self.correction = path;
+                    self.resume = j;
+                }
+            }
+        }
+        self.mistake |= self.remaining() > remaining;
+    }
+
+    // the turns done, the correction, and the rest of the scramble
+    pub fn split(&self) -> (&[FaceTurn], &[FaceTurn], &[FaceTurn]) {
+        (
+            &self.turns[..self.progress],
+            &self.correction,
+            &self.turns[self.resume..],
+        )
+    }
+}
+
+// time the solve after the scramble, from the first turn until it is solved
+pub struct SolveTimer {
+    state: CubeState,
+    start: Option<Instant>,
+    moves: usize,
+}
+
+impl SolveTimer {
+    pub fn new(state: CubeState) -> Self {
+        SolveTimer {
+            state,
+            start: None,
+            moves: 0,
+        }
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.start.is_some()
+    }
+
+    // the time of the solve if it is solved by the move
+    pub fn input(&mut self, mv: CubeMove, now: Instant) -> Option<Duration> {
+        let start = *self.start.get_or_insert(now);
+        self.state.apply(mv);
+        self.moves += 1;
+        self.state.is_solved().then(|| now - start)
+    }
+}
+
+// show the scramble with the turns done struck through
+pub struct ScrambleAssistant<F: Write> {
+    terminal: F,
+    guide: ScrambleGuide,
+    sequencer: MoveSequencer,
+    shutdown: Shutdown,
+    // time the solve after the scramble is finished
+    timed: bool,
+    timer: Option<SolveTimer>,
+    // shown after the status, and redrawn whenever it changes
+    alert: VerifyAlert,
+    shown: Option<String>,
+}
+
+impl<F: Write> ScrambleAssistant<F> {
+    pub fn new(terminal: F, turns: Vec<FaceTurn>, shutdown: Shutdown) -> Self {
+        ScrambleAssistant {
+            terminal,
+            guide: ScrambleGuide::new(turns),
+            sequencer: MoveSequencer::default(),
+            shutdown,
+            timed: false,
+            timer: None,
+            alert: VerifyAlert::default(),
+            shown: None,
+        }
+    }
+
+    pub fn with_timer(mut self) -> Self {
+        self.timed = true;
+        self
+    }
+
+    pub fn next_turn(&self) -> Option<FaceTurn> {
+        self.guide.next_turn()
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) {
+        let uninit = self.sequencer.count().is_none();
+        let sequenced = self.sequencer.handle_message(msg, Instant::now());
+        match msg {
+            ResponseMessage::State { .. } if uninit => self.render(),
+            ResponseMessage::Disconnect => {
+                let _ = writeln!(self.terminal);
+            }
+            _ => {}
+        }
+        if !sequenced.moves.is_empty() {
+            self.turn(&sequenced.moves);
+        } else if !uninit && self.alert.status(Instant::now()) != self.shown {
+            self.render();
+        }
+    }
+
+    fn turn(&mut self, moves: &[(CubeMove, Duration)]) {
+        for &(mv, _) in moves {
+            let Some(timer) = &mut self.timer else {
+                self.guide.input(mv);
+                if self.timed && self.guide.is_finished() {
+                    self.timer = Some(SolveTimer::new(self.guide.state()));
+                }
+                continue;
+            };
+            if let Some(time) = timer.input(mv, Instant::now()) {
+                let moves = timer.moves;
+                self.finish_solve(time, moves);
+                return;
+            }
+        }
+        self.render();
+        if !self.timed && self.guide.is_finished() {
+            let _ = writeln!(self.terminal);
+            self.shutdown.request();
+        }
+    }
+
+    fn finish_solve(&mut self, time: Duration, moves: usize) {
+        let solve = SolveRecord {
+            millis: time.as_millis() as u64,
+            scramble: format_turns(&self.guide.turns),
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |date| date.as_secs()),
+            ..SolveRecord::default()
+        };
+        let _ = write!(
+            self.terminal,
+            "\r\x1b[2Ksolved in {:.2}s, {} moves",
+            time.as_secs_f32(),
+            moves
+        );
+        match stats::record_solve(solve) {
+            Ok(true) => {
+                let _ = writeln!(self.terminal, ", a new best!");
+            }
+            Ok(false) => {
+                let _ = writeln!(self.terminal);
+            }
+            Err(err) => {
+                let _ = writeln!(self.terminal, ", but it cannot be recorded: {}", err);
+            }
+        }
+        self.shutdown.request();
+    }
+
+    fn render(&mut self) {
+        let join = |turns: &[FaceTurn]| {
+            turns
+                .iter()
+                .map(|turn| format!("{} ", turn))
+                .collect::<String>()
+        };
+        let (done, correction, rest) = self.guide.split();
+        let correction = if correction.is_empty() {
+            String::new()
+        } else if self.guide.is_mistake() {
+            format!("{}{}\x1b[m", theme::get().error, join(correction))
+        } else {
+            format!("\x1b[1m{}\x1b[m", join(correction))
+        };
+        let status = match &self.timer {
+            Some(timer) if timer.is_started() => "solving...",
+            Some(_) => "scrambled! the timer starts at the first turn",
+            None if self.guide.is_finished() => "scrambled!",
+            None if self.guide.is_mistake() => "wrong turn, follow the correction",
+            None => "",
+        };
+        self.shown = self.alert.status(Instant::now());
+        let alert = match &self.shown {
+            Some(alert) => format!(" {}{}\x1b[m", theme::get().error, alert),
+            None => String::new(),
+        };
+        let _ = write!(
+            self.terminal,
+            "\r\x1b[2K\x1b[9;2m{}\x1b[m{}{} {}{}",
+            join(done),
+            correction,
+            join(rest),
+            status,
+            alert,
+        );
+        let _ = self.terminal.flush();
+    }
+}
+
+// guide the scramble in the terminal, and in the 3D view if asked, then time the solve if asked
+pub async fn run(
+    turns: Vec<FaceTurn>,
+    view: bool,
+    timed: bool,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    let mut assistant = ScrambleAssistant::new(stdout(), turns, shutdown.clone());
+    let alert = assistant.alert.clone();
+    if timed {
+        assistant = assistant.with_timer();
+    }
+    if view {
+        let guide: GuideHandler = Box::new(move |msg| {
+            assistant.handle_message(msg);
+            assistant.next_turn()
+        });
+        return view::guide(shutdown, guide).await;
+    }
+
+    let gancube = connect_cube(shutdown.clone()).await?;
+    // only moves are needed
+    gancube.message_filter().set_gyroscope(false);
+    println!("connected! hold the cube white up and green front, and follow the scramble");
+    println!();
+
+    let handler: Box<dyn FnMut(ResponseMessage) + Send> =
+        Box::new(move |msg| assistant.handle_message(msg));
+    handle_until_disconnected(
+        &gancube,
+        handler,
+        shutdown,
+        idle,
+        SignalMonitor::default(),
+        Some(alert),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cube::parse_turns, solver::SolverError};
+
+    #[test]
+    fn half_turns_are_done_either_way() {
+        use CubeMove::*;
+
+        let mut guide = ScrambleGuide::new(parse_turns("R U2 F").unwrap());
+        for mv in [R, Up] {
+            guide.input(mv);
+        }
+        assert!(!guide.is_mistake());
+        assert_eq!(guide.next_turn(), Some("U'".parse().unwrap()));
+        for mv in [Up, F] {
+            guide.input(mv);
+        }
+        assert!(guide.is_finished());
+    }
+
+    #[test]
+    fn wrong_turns_are_corrected() {
+        use CubeMove::*;
+
+        let mut guide = ScrambleGuide::new(parse_turns("R U2 F D").unwrap());
+        for mv in [R, L] {
+            guide.input(mv);
+        }
+        assert!(guide.is_mistake());
+        assert_eq!(guide.next_turn(), Some("L'".parse().unwrap()));
+
+        // turning the wrong face further is not undone turn by turn
+        for mv in [L, L] {
+            guide.input(mv);
+        }
+        assert_eq!(guide.split().1, &parse_turns("L").unwrap()[..]);
+
+        guide.input(L);
+        assert!(!guide.is_mistake());
+        assert_eq!(guide.next_turn(), Some("U2".parse().unwrap()));
+    }
+
+    struct UnreachableSolver;
+
+    impl Solver for UnreachableSolver {
+        fn solve(
+            &self,
+            _from: &CubeState,
+            _to: &CubeState,
+            _max_depth: usize,
+        ) -> Result<Option<Vec<FaceTurn>>, SolverError> {
+            Err(SolverError::Request {
+                url: "http://localhost".to_string(),
+                message: "connection refused".to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn corrections_fall_back_to_the_search() {
+        use CubeMove::*;
+
+        let mut guide = ScrambleGuide::new(parse_turns("R U2 F D").unwrap());
+        guide.solver = Box::new(UnreachableSolver);
+        for mv in [R, L, L, L] {
+            guide.input(mv);
+        }
+        assert_eq!(guide.split().1, &parse_turns("L").unwrap()[..]);
+    }
+
+    #[test]
+    fn solves_are_timed_from_the_first_turn() {
+        use CubeMove::*;
+
+        let start = Instant::now();
+        let mut timer = SolveTimer::new(CubeState::from_moves(&[R, U]));
+        assert!(!timer.is_started());
+        assert_eq!(timer.input(Up, start), None);
+        assert!(timer.is_started());
+        let end = start + Duration::from_secs(3);
+        assert_eq!(timer.input(Rp, end), Some(Duration::from_secs(3)));
+        assert_eq!(timer.moves, 2);
+    }
+}
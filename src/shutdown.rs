@@ -0,0 +1,107 @@
+use std::{
+    io::{stdout, Write},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+use crate::bluetooth::{
+    gancubev2::{Error, GanCubeV2},
+    transport::Transport,
+};
+
+type ShutdownHook = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct ShutdownState {
+    requested: AtomicBool,
+    notify: Notify,
+    hooks: Mutex<Vec<ShutdownHook>>,
+}
+
+// shutdown signal shared by all commands, triggered by Ctrl-C or by the command itself
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    state: Arc<ShutdownState>,
+    disconnect: bool,
+}
+
+impl Shutdown {
+    pub fn listen(disconnect: bool) -> Self {
+        let shutdown = Shutdown {
+            state: Arc::default(),
+            disconnect,
+        };
+        let signal = shutdown.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                signal.request();
+            }
+            // exit at once on the second Ctrl-C, in case closing gets stuck
+            if tokio::signal::ctrl_c().await.is_ok() {
+                signal.run_hooks();
+                restore_terminal();
+                process::exit(130);
+            }
+        });
+        shutdown
+    }
+
+    pub fn request(&self) {
+        self.state.requested.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.state.requested.load(Ordering::SeqCst)
+    }
+
+    pub async fn wait(&self) {
+        let notified = self.state.notify.notified();
+        if self.is_requested() {
+            return;
+        }
+        notified.await;
+    }
+
+    // hooks are executed in reverse order of registration when closing
+    pub fn on_shutdown(&self, hook: impl FnOnce() + Send + 'static) {
+        if let Ok(mut hooks) = self.state.hooks.lock() {
+            hooks.push(Box::new(hook));
+        }
+    }
+
+    pub fn run_hooks(&self) {
+        let hooks = match self.state.hooks.lock() {
+            Ok(mut hooks) => hooks.drain(..).collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        for hook in hooks.into_iter().rev() {
+            hook();
+        }
+    }
+
+    pub async fn close<P: Transport>(&self, gancube: &GanCubeV2<P>) -> Result<(), Error> {
+        self.run_hooks();
+
+        if !gancube.device.is_connected().await? {
+            return Ok(());
+        }
+        gancube.unsubscribe_response().await?;
+        if self.disconnect {
+            gancube.disconnect().await?;
+        }
+        Ok(())
+    }
+}
+
+// reset styles and leave the current line
+pub fn restore_terminal() {
+    let mut terminal = stdout();
+    let _ = writeln!(terminal, "\x1b[m");
+    let _ = terminal.flush();
+}
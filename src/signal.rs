@@ -0,0 +1,96 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    bluetooth::{
+        gancubev2::{Error, GanCubeV2},
+        transport::Transport,
+    },
+    i18n::{tr, Message},
+};
+
+// below this the link may drop move notifications, which corrupts the typed text
+pub const WEAK_RSSI: i16 = -80;
+
+pub fn is_weak(rssi: i16) -> bool {
+    rssi < WEAK_RSSI
+}
+
+// the signal strength shown in the terminal, or a warning if it is weak
+pub fn describe(rssi: Option<i16>) -> String {
+    match rssi {
+        Some(rssi) if is_weak(rssi) => tr(Message::WeakSignal, &[&rssi]),
+        Some(rssi) => format!("rssi {}dBm", rssi),
+        None => "rssi unknown".to_string(),
+    }
+}
+
+// the last signal strength of the connected cube
+#[derive(Clone, Default)]
+pub struct SignalMonitor {
+    rssi: Arc<Mutex<Option<i16>>>,
+}
+
+impl SignalMonitor {
+    pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+    // the bluetooth stack may stop reporting while connected, so keep the last value
+    pub fn update(&self, rssi: Option<i16>) {
+        if let (Some(rssi), Ok(mut last)) = (rssi, self.rssi.lock()) {
+            *last = Some(rssi);
+        }
+    }
+
+    pub fn rssi(&self) -> Option<i16> {
+        self.rssi.lock().ok().and_then(|rssi| *rssi)
+    }
+
+    // shown in the status line
+    pub fn status(&self) -> Option<String> {
+        self.rssi().map(|rssi| describe(Some(rssi)))
+    }
+
+    // shown in the status line only when the link is weak
+    pub fn warning(&self) -> Option<String> {
+        self.rssi()
+            .filter(|&rssi| is_weak(rssi))
+            .map(|rssi| describe(Some(rssi)))
+    }
+
+    pub async fn check<P: Transport>(&self, gancube: &GanCubeV2<P>) -> Result<(), Error> {
+        self.update(gancube.rssi().await?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth::mock::MockTransport;
+
+    #[tokio::test]
+    async fn weak_signal_is_warned() {
+        let mock = MockTransport::default();
+        let gancube = mock.builder().connect().await.unwrap();
+        let signal = SignalMonitor::default();
+
+        signal.check(&gancube).await.unwrap();
+        assert_eq!(signal.status(), None);
+
+        mock.set_rssi(Some(-60));
+        signal.check(&gancube).await.unwrap();
+        assert_eq!(signal.status().as_deref(), Some("rssi -60dBm"));
+        assert_eq!(signal.warning(), None);
+
+        mock.set_rssi(Some(-90));
+        signal.check(&gancube).await.unwrap();
+        mock.set_rssi(None);
+        signal.check(&gancube).await.unwrap();
+        assert_eq!(
+            signal.warning().as_deref(),
+            Some("weak signal -90dBm, moves may be lost")
+        );
+    }
+}
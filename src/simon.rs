@@ -0,0 +1,316 @@
+use std::{
+    error::Error,
+    io::{stdout, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use strum::IntoEnumIterator;
+use tokio::time::interval;
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::{format_moves, merge_turns, CubeMove, FaceTurn},
+    idle::IdleMonitor,
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    stats, theme,
+    train::handle_until_disconnected,
+    view::{self, GuideHandler},
+};
+
+const START_LENGTH: usize = 3;
+// each move is shown for the most of the interval, so that repeated moves are told apart
+const SHOW_INTERVAL: Duration = Duration::from_millis(800);
+const SHOW_DURATION: Duration = Duration::from_millis(600);
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Phase {
+    // showing the sequence since the time
+    Show(Instant),
+    // the number of moves repeated so far
+    Repeat(usize),
+    Over,
+}
+
+// a growing sequence of moves to repeat, which gets one move longer every round
+pub struct SimonGame<R: Rng> {
+    sequence: Vec<CubeMove>,
+    phase: Phase,
+    score: usize,
+    rng: R,
+}
+
+impl<R: Rng> SimonGame<R> {
+    pub fn new(rng: R, now: Instant) -> Self {
+        let mut game = SimonGame {
+            sequence: Vec::new(),
+            phase: Phase::Show(now),
+            score: 0,
+            rng,
+        };
+        while game.sequence.len() < START_LENGTH {
+            game.extend();
+        }
+        game
+    }
+
+    // a random move, which doesn't undo the last one
+    fn extend(&mut self) {
+        let moves = CubeMove::iter().collect::<Vec<_>>();
+        loop {
+            let mv = moves[self.rng.gen_range(0..moves.len())];
+            if self.sequence.last() != Some(&mv.rev()) {
+                self.sequence.push(mv);
+                return;
+            }
+        }
+    }
+
+    // the length of the longest sequence repeated
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.phase == Phase::Over
+    }
+
+    pub fn tick(&mut self, now: Instant) {
+        if let Phase::Show(since) = self.phase {
+            if now.duration_since(since) >= SHOW_INTERVAL * self.sequence.len() as u32 {
+                self.phase = Phase::Repeat(0);
+            }
+        }
+    }
+
+    // the index of the move shown now, if it is shown
+    fn shown_index(&self, now: Instant) -> Option<usize> {
+        let Phase::Show(since) = self.phase else {
+            return None;
+        };
+        let elapsed = now.duration_since(since);
+        let index = (elapsed.as_secs_f32() / SHOW_INTERVAL.as_secs_f32()) as usize;
+        let offset = elapsed.saturating_sub(SHOW_INTERVAL * index as u32);
+        (index < self.sequence.len() && offset < SHOW_DURATION).then_some(index)
+    }
+
+    pub fn shown(&self, now: Instant) -> Option<CubeMove> {
+        self.shown_index(now).map(|index| self.sequence[index])
+    }
+
+    // moves turned while the sequence is shown are ignored
+    pub fn input(&mut self, mv: CubeMove, now: Instant) {
+        let Phase::Repeat(progress) = self.phase else {
+            return;
+        };
+        if self.sequence[progress] != mv {
+            self.phase = Phase::Over;
+            return;
+        }
+        if progress + 1 < self.sequence.len() {
+            self.phase = Phase::Repeat(progress + 1);
+            return;
+        }
+        self.score = self.sequence.len();
+        self.extend();
+        self.phase = Phase::Show(now);
+    }
+}
+
+// play the game by the messages of the cube, and show it in the terminal
+struct SimonPlayer<F: Write> {
+    terminal: F,
+    game: Option<SimonGame<StdRng>>,
+    sequencer: MoveSequencer,
+    shown: String,
+    shutdown: Shutdown,
+}
+
+impl<F: Write> SimonPlayer<F> {
+    fn new(terminal: F, shutdown: Shutdown) -> Self {
+        SimonPlayer {
+            terminal,
+            game: None,
+            sequencer: MoveSequencer::default(),
+            shown: String::new(),
+            shutdown,
+        }
+    }
+
+    fn score(&self) -> usize {
+        self.game.as_ref().map_or(0, SimonGame::score)
+    }
+
+    fn handle_message(&mut self, msg: ResponseMessage) {
+        let now = Instant::now();
+        let sequenced = self.sequencer.handle_message(msg, now);
+        if let Some(game) = &mut self.game {
+            for (mv, _) in sequenced.moves {
+                game.input(mv, now);
+            }
+        }
+        match msg {
+            // start the game once the moves can be counted
+            ResponseMessage::State { .. } if self.game.is_none() => {
+                self.game = Some(SimonGame::new(StdRng::from_entropy(), now));
+            }
+            ResponseMessage::Disconnect => {
+                let _ = writeln!(self.terminal);
+                return;
+            }
+            _ => {}
+        }
+        self.tick(now);
+    }
+
+    fn tick(&mut self, now: Instant) {
+        let Some(game) = &mut self.game else {
+            return;
+        };
+        game.tick(now);
+        let line = match game.phase {
+            Phase::Show(since) => {
+                let elapsed = now.duration_since(since).as_secs_f32();
+                let revealed = (elapsed / SHOW_INTERVAL.as_secs_f32()) as usize;
+                let shown = game.shown_index(now);
+                let moves = game
+                    .sequence
+                    .iter()
+                    .enumerate()
+                    .map(|(i, mv)| match shown {
+                        Some(index) if index == i => format!("\x1b[1m{}\x1b[m", mv),
+                        _ if i < revealed => mv.to_string(),
+                        _ => "_".to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                format!("watch:  {}", moves.join(" "))
+            }
+            Phase::Repeat(progress) => {
+                let moves = (0..game.sequence.len())
+                    .map(|i| if i < progress { "*" } else { "_" })
+                    .collect::<Vec<_>>();
+                format!("repeat: {}", moves.join(" "))
+            }
+            Phase::Over => format!(
+                "{}wrong move!\x1b[m the sequence was {}, score {}",
+                theme::get().error,
+                format_moves(&game.sequence),
+                game.score(),
+            ),
+        };
+        if line == self.shown {
+            return;
+        }
+        let _ = write!(self.terminal, "\r\x1b[2K{}", line);
+        let _ = self.terminal.flush();
+        self.shown = line;
+        if game.is_over() {
+            let _ = writeln!(self.terminal);
+            self.shutdown.request();
+        }
+    }
+
+    // the move shown on the 3D view
+    fn hint(&self) -> Option<FaceTurn> {
+        let mv = self.game.as_ref()?.shown(Instant::now())?;
+        merge_turns(&[mv]).first().copied()
+    }
+}
+
+// show the growing sequences in the terminal, and in the 3D view if asked, until a wrong move
+pub async fn run(view: bool, shutdown: Shutdown, idle: IdleMonitor) -> Result<(), Box<dyn Error>> {
+    let player = Arc::new(Mutex::new(SimonPlayer::new(stdout(), shutdown.clone())));
+    let player_handler = Arc::clone(&player);
+
+    if view {
+        let guide: GuideHandler = Box::new(move |msg| {
+            let mut player = player_handler.lock().ok()?;
+            player.handle_message(msg);
+            player.hint()
+        });
+        view::guide(shutdown, guide).await?;
+    } else {
+        let gancube = connect_cube(shutdown.clone()).await?;
+        // only moves are needed
+        gancube.message_filter().set_gyroscope(false);
+        println!("connected! watch the moves, then repeat them on the cube");
+        println!();
+
+        let handler: Box<dyn FnMut(ResponseMessage) + Send> = Box::new(move |msg| {
+            if let Ok(mut player) = player_handler.lock() {
+                player.handle_message(msg);
+            }
+        });
+        let player_ticker = Arc::clone(&player);
+        let ticking = async move {
+            let mut ticker = interval(TICK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if let Ok(mut player) = player_ticker.lock() {
+                    player.tick(Instant::now());
+                }
+            }
+        };
+        let signal = SignalMonitor::default();
+        let handling = handle_until_disconnected(&gancube, handler, shutdown, idle, signal, None);
+        tokio::select! {
+            res = handling => res?,
+            _ = ticking => {}
+        }
+    }
+
+    let score = player.lock().map_err(|err| err.to_string())?.score();
+    if score > 0 {
+        match stats::update_high_score("simon", score)? {
+            (true, _) => println!("new high score: {}", score),
+            (false, high_score) => println!("high score: {}", high_score),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_grow_after_repeated() {
+        let start = Instant::now();
+        let mut game = SimonGame::new(StdRng::seed_from_u64(0), start);
+        assert_eq!(game.sequence.len(), START_LENGTH);
+        assert_eq!(game.shown(start), Some(game.sequence[0]));
+        assert_eq!(game.shown(start + SHOW_DURATION), None);
+        assert_eq!(game.shown(start + SHOW_INTERVAL), Some(game.sequence[1]));
+
+        // moves are ignored while shown
+        game.input(game.sequence[0].rev(), start);
+        let end = start + SHOW_INTERVAL * START_LENGTH as u32;
+        game.tick(end);
+        assert_eq!(game.phase, Phase::Repeat(0));
+
+        for mv in game.sequence.clone() {
+            game.input(mv, end);
+        }
+        assert_eq!(game.score(), START_LENGTH);
+        assert_eq!(game.sequence.len(), START_LENGTH + 1);
+        assert_eq!(game.phase, Phase::Show(end));
+        assert!(game
+            .sequence
+            .windows(2)
+            .all(|pair| pair[1] != pair[0].rev()));
+    }
+
+    #[test]
+    fn wrong_moves_end_the_game() {
+        let start = Instant::now();
+        let mut game = SimonGame::new(StdRng::seed_from_u64(1), start);
+        game.tick(start + SHOW_INTERVAL * START_LENGTH as u32);
+        game.input(game.sequence[0], start);
+        game.input(game.sequence[1].rev(), start);
+        assert!(game.is_over());
+        assert_eq!(game.score(), 0);
+    }
+}
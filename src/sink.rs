@@ -0,0 +1,237 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use arboard::Clipboard;
+use tokio::{net::TcpListener, sync::broadcast};
+
+use crate::{
+    cuboard::GyroGesture,
+    engine::EngineEvent,
+    i18n::{tr, Message},
+    keyboard::VirtualKeyboard,
+    websocket,
+};
+
+// where the accepted text goes, besides the terminal showing it
+pub trait OutputSink: Send {
+    fn name(&self) -> &'static str;
+
+    // text accepted by the input, which may contain newlines
+    fn accept(&mut self, text: &str) -> io::Result<()>;
+
+    // the line is finished by a newline or by turning the cube around
+    fn finish_line(&mut self, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    // every event of the engine, for the sinks telling how the text is typed
+    fn event(&mut self, _event: &EngineEvent) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// copy every finished line into the system clipboard
+pub struct ClipboardSink {
+    // the clipboard should be kept alive to serve its contents on some platforms
+    clipboard: Clipboard,
+}
+
+impl ClipboardSink {
+    pub fn new() -> Result<Self, arboard::Error> {
+        Ok(ClipboardSink {
+            clipboard: Clipboard::new()?,
+        })
+    }
+}
+
+impl OutputSink for ClipboardSink {
+    fn name(&self) -> &'static str {
+        "clipboard"
+    }
+
+    fn accept(&mut self, _text: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish_line(&mut self, line: &str) -> io::Result<()> {
+        self.clipboard.set_text(line).map_err(io::Error::other)
+    }
+}
+
+// append the text to a file
+pub struct FileSink {
+    file: File,
+}
+
+impl FileSink {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(FileSink { file })
+    }
+}
+
+impl OutputSink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn accept(&mut self, text: &str) -> io::Result<()> {
+        self.file.write_all(text.as_bytes())?;
+        self.file.flush()
+    }
+}
+
+// type the text into the focused application by a virtual keyboard
+pub struct KeyboardSink {
+    keyboard: VirtualKeyboard,
+}
+
+impl KeyboardSink {
+    pub fn new(keyboard: VirtualKeyboard) -> Self {
+        KeyboardSink { keyboard }
+    }
+}
+
+impl OutputSink for KeyboardSink {
+    fn name(&self) -> &'static str {
+        "keyboard"
+    }
+
+    fn accept(&mut self, text: &str) -> io::Result<()> {
+        self.keyboard.type_text(text)
+    }
+}
+
+// send the text to the clients of a websocket server
+pub struct WebSocketSink {
+    texts: broadcast::Sender<String>,
+}
+
+impl WebSocketSink {
+    pub async fn bind(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        println!("send the text on ws://{}", listener.local_addr()?);
+        let (texts, _) = broadcast::channel(64);
+        let server = websocket::serve(listener, texts.clone());
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                println!("websocket error: {}", err);
+            }
+        });
+        Ok(WebSocketSink { texts })
+    }
+}
+
+impl OutputSink for WebSocketSink {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn accept(&mut self, text: &str) -> io::Result<()> {
+        // no client is listening
+        let _ = self.texts.send(text.to_string());
+        Ok(())
+    }
+}
+
+// announce the typing in short sentences, one per line, for screen readers listening on the
+// stream instead of reading the terminal
+pub struct AnnounceSink {
+    stream: Box<dyn Write + Send>,
+}
+
+impl AnnounceSink {
+    pub fn new(stream: Box<dyn Write + Send>) -> Self {
+        AnnounceSink { stream }
+    }
+
+    // a file, such as a pipe read by the screen reader, or stderr without the path
+    pub fn open(path: Option<&str>) -> io::Result<Self> {
+        let stream: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stderr()),
+        };
+        Ok(Self::new(stream))
+    }
+
+    fn say(&mut self, sentence: &str) -> io::Result<()> {
+        writeln!(self.stream, "{}", sentence)?;
+        self.stream.flush()
+    }
+}
+
+impl OutputSink for AnnounceSink {
+    fn name(&self) -> &'static str {
+        "announce"
+    }
+
+    fn accept(&mut self, text: &str) -> io::Result<()> {
+        let text = text.replace('\n', "");
+        let text = match text.as_str() {
+            "" => return Ok(()),
+            " " => tr(Message::Space, &[]),
+            "\t" => tr(Message::Tab, &[]),
+            _ => text,
+        };
+        self.say(&tr(Message::Typed, &[&text]))
+    }
+
+    fn finish_line(&mut self, _line: &str) -> io::Result<()> {
+        self.say(&tr(Message::LineCommitted, &[]))
+    }
+
+    fn event(&mut self, event: &EngineEvent) -> io::Result<()> {
+        match event {
+            EngineEvent::Started => self.say(&tr(Message::Ready, &[])),
+            EngineEvent::GestureFired(GyroGesture::Shaking) => {
+                self.say(&tr(Message::Cancelled, &[]))
+            }
+            EngineEvent::Paused => self.say(&tr(Message::Paused, &[])),
+            EngineEvent::Resumed => self.say(&tr(Message::Resumed, &[])),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn file_sink_appends_text() {
+        let path = std::env::temp_dir().join(format!("cuboard-sink-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut sink = FileSink::open(&path).unwrap();
+        sink.accept("hello\n").unwrap();
+        sink.finish_line("hello").unwrap();
+        let mut sink = FileSink::open(&path).unwrap();
+        sink.accept("world").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hello\nworld");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn announcements_are_short_sentences() {
+        let path =
+            std::env::temp_dir().join(format!("cuboard-announce-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut sink = AnnounceSink::open(path.to_str()).unwrap();
+        sink.event(&EngineEvent::Started).unwrap();
+        sink.accept("hello").unwrap();
+        sink.accept(" ").unwrap();
+        sink.accept("\n").unwrap();
+        sink.finish_line("hello ").unwrap();
+        sink.event(&EngineEvent::GestureFired(GyroGesture::Shaking))
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "ready\ntyped: hello\ntyped: space\nline committed\ncancelled\n"
+        );
+        let _ = fs::remove_file(&path);
+    }
+}
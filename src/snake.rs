@@ -0,0 +1,351 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{stdout, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::time::interval;
+
+use crate::{
+    bluetooth::{connect_cube, gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::CubeMove,
+    idle::IdleMonitor,
+    shutdown::Shutdown,
+    signal::SignalMonitor,
+    stats,
+    terminal::terminal_size,
+    theme,
+    train::handle_until_disconnected,
+};
+
+const MAX_WIDTH: usize = 40;
+const MAX_HEIGHT: usize = 16;
+const STEP_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SnakeCommand {
+    Steer(Direction),
+    Pause,
+    Restart,
+}
+
+impl SnakeCommand {
+    // the faces U, D, L, R steer in either direction, F pauses and B restarts
+    pub fn of_move(mv: CubeMove) -> Self {
+        match mv.abs() {
+            CubeMove::U => SnakeCommand::Steer(Direction::Up),
+            CubeMove::D => SnakeCommand::Steer(Direction::Down),
+            CubeMove::L => SnakeCommand::Steer(Direction::Left),
+            CubeMove::R => SnakeCommand::Steer(Direction::Right),
+            CubeMove::F => SnakeCommand::Pause,
+            _ => SnakeCommand::Restart,
+        }
+    }
+}
+
+pub struct SnakeGame<R: Rng> {
+    width: usize,
+    height: usize,
+    // the head is at the front
+    snake: VecDeque<(i32, i32)>,
+    direction: Direction,
+    // the direction steered since the last step
+    steered: Direction,
+    food: (i32, i32),
+    paused: bool,
+    over: bool,
+    rng: R,
+}
+
+impl<R: Rng> SnakeGame<R> {
+    pub fn new(width: usize, height: usize, rng: R) -> Self {
+        let mut game = SnakeGame {
+            width,
+            height,
+            snake: VecDeque::new(),
+            direction: Direction::Right,
+            steered: Direction::Right,
+            food: (0, 0),
+            paused: false,
+            over: false,
+            rng,
+        };
+        game.restart();
+        game
+    }
+
+    pub fn restart(&mut self) {
+        let (x, y) = (self.width as i32 / 2, self.height as i32 / 2);
+        self.snake = (0..3).map(|i| (x - i, y)).collect();
+        self.direction = Direction::Right;
+        self.steered = Direction::Right;
+        self.paused = false;
+        self.over = false;
+        self.place_food();
+    }
+
+    fn place_food(&mut self) {
+        if self.snake.len() >= self.width * self.height {
+            return;
+        }
+        loop {
+            let x = self.rng.gen_range(0..self.width as i32);
+            let y = self.rng.gen_range(0..self.height as i32);
+            if !self.snake.contains(&(x, y)) {
+                self.food = (x, y);
+                return;
+            }
+        }
+    }
+
+    pub fn score(&self) -> usize {
+        self.snake.len() - 3
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    pub fn command(&mut self, command: SnakeCommand) {
+        match command {
+            // the snake can't turn back into itself
+            SnakeCommand::Steer(direction) if direction != self.direction.opposite() => {
+                self.steered = direction;
+            }
+            SnakeCommand::Steer(_) => {}
+            SnakeCommand::Pause if !self.over => self.paused = !self.paused,
+            SnakeCommand::Pause => {}
+            SnakeCommand::Restart if self.over => self.restart(),
+            SnakeCommand::Restart => {}
+        }
+    }
+
+    pub fn step(&mut self) {
+        if self.paused || self.over {
+            return;
+        }
+        self.direction = self.steered;
+        let (dx, dy) = self.direction.offset();
+        let (x, y) = self.snake[0];
+        let head = (x + dx, y + dy);
+        let inside =
+            (0..self.width as i32).contains(&head.0) && (0..self.height as i32).contains(&head.1);
+        // the tail moves away unless the snake eats
+        let grows = head == self.food;
+        let body = self.snake.len() - usize::from(!grows);
+        if !inside || self.snake.range(..body).any(|&p| p == head) {
+            self.over = true;
+            return;
+        }
+        self.snake.push_front(head);
+        if grows {
+            self.place_food();
+        } else {
+            self.snake.pop_back();
+        }
+    }
+
+    pub fn render(&self) -> Vec<String> {
+        let border = format!("+{}+", "-".repeat(self.width));
+        let mut lines = vec![border.clone()];
+        for y in 0..self.height as i32 {
+            let row = (0..self.width as i32)
+                .map(|x| match (x, y) {
+                    p if p == self.snake[0] => '@',
+                    p if self.snake.contains(&p) => 'o',
+                    p if p == self.food => '*',
+                    _ => ' ',
+                })
+                .collect::<String>();
+            lines.push(format!("|{}|", row));
+        }
+        lines.push(border);
+        lines
+    }
+}
+
+// play the game by the moves of the cube, and redraw it in the terminal
+struct SnakePlayer<F: Write> {
+    terminal: F,
+    game: SnakeGame<StdRng>,
+    sequencer: MoveSequencer,
+    high_score: Option<usize>,
+    rows: usize,
+}
+
+impl<F: Write> SnakePlayer<F> {
+    fn new(terminal: F, width: usize, height: usize) -> Self {
+        SnakePlayer {
+            terminal,
+            game: SnakeGame::new(width, height, StdRng::from_entropy()),
+            sequencer: MoveSequencer::default(),
+            high_score: None,
+            rows: 0,
+        }
+    }
+
+    fn handle_message(&mut self, msg: ResponseMessage) {
+        let sequenced = self.sequencer.handle_message(msg, Instant::now());
+        if sequenced.moves.is_empty() {
+            return;
+        }
+        for (mv, _) in sequenced.moves {
+            let command = SnakeCommand::of_move(mv);
+            if command == SnakeCommand::Restart && self.game.is_over() {
+                self.high_score = None;
+            }
+            self.game.command(command);
+        }
+        self.render();
+    }
+
+    fn step(&mut self) {
+        if self.sequencer.count().is_none() {
+            return;
+        }
+        let was_over = self.game.is_over();
+        self.game.step();
+        if self.game.is_over() && !was_over {
+            let score = self.game.score();
+            self.high_score = stats::update_high_score("snake", score)
+                .ok()
+                .map(|(_, high_score)| high_score);
+        }
+        self.render();
+    }
+
+    fn render(&mut self) {
+        let mut lines = self.game.render();
+        let status = if self.game.is_over() {
+            let high_score = self
+                .high_score
+                .map_or(String::new(), |score| format!(", high score {}", score));
+            format!(
+                "{}game over!\x1b[m score {}{}, turn B to play again",
+                theme::get().error,
+                self.game.score(),
+                high_score,
+            )
+        } else if self.game.paused {
+            format!("score {}, paused, turn F to resume", self.game.score())
+        } else {
+            format!("score {}", self.game.score())
+        };
+        lines.push(status);
+        if self.rows > 0 {
+            let _ = write!(self.terminal, "\x1b[{}A", self.rows);
+        }
+        for line in lines.iter() {
+            let _ = write!(self.terminal, "\r\x1b[2K{}\n", line);
+        }
+        let _ = self.terminal.flush();
+        self.rows = lines.len();
+    }
+}
+
+// steer a snake by turning the faces, until the cube disconnects
+pub async fn run(shutdown: Shutdown, idle: IdleMonitor) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    // only moves are needed
+    gancube.message_filter().set_gyroscope(false);
+    println!("connected! turn U, D, L, R to steer, F to pause");
+    println!();
+
+    // leave room for the borders and the status line
+    let (columns, rows) = terminal_size().unwrap_or((MAX_WIDTH + 2, MAX_HEIGHT + 3));
+    let width = columns.saturating_sub(2).clamp(10, MAX_WIDTH);
+    let height = rows.saturating_sub(3).clamp(5, MAX_HEIGHT);
+    let player = Arc::new(Mutex::new(SnakePlayer::new(stdout(), width, height)));
+    let player_handler = Arc::clone(&player);
+    let handler: Box<dyn FnMut(ResponseMessage) + Send> = Box::new(move |msg| {
+        if let Ok(mut player) = player_handler.lock() {
+            player.handle_message(msg);
+        }
+    });
+    let stepping = async move {
+        let mut ticker = interval(STEP_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Ok(mut player) = player.lock() {
+                player.step();
+            }
+        }
+    };
+    let signal = SignalMonitor::default();
+    let handling = handle_until_disconnected(&gancube, handler, shutdown, idle, signal, None);
+    tokio::select! {
+        res = handling => res,
+        _ = stepping => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_grows_by_eating() {
+        let mut game = SnakeGame::new(10, 5, StdRng::seed_from_u64(0));
+        assert_eq!(game.snake[0], (5, 2));
+        game.food = (7, 2);
+        game.step();
+        game.step();
+        assert_eq!(game.score(), 1);
+        assert_ne!(game.food, (7, 2));
+
+        // turning back is ignored
+        game.command(SnakeCommand::of_move(CubeMove::Lp));
+        game.command(SnakeCommand::of_move(CubeMove::U));
+        game.step();
+        assert_eq!(game.snake[0], (7, 1));
+    }
+
+    #[test]
+    fn walls_end_the_game() {
+        let mut game = SnakeGame::new(10, 5, StdRng::seed_from_u64(0));
+        game.food = (0, 0);
+        game.command(SnakeCommand::Steer(Direction::Down));
+        game.command(SnakeCommand::Pause);
+        game.step();
+        assert_eq!(game.snake[0], (5, 2));
+        game.command(SnakeCommand::Pause);
+        for _ in 0..3 {
+            game.step();
+        }
+        assert!(game.is_over());
+        game.command(SnakeCommand::of_move(CubeMove::B));
+        assert!(!game.is_over());
+        assert_eq!(game.snake.len(), 3);
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt, io,
+    process::Command,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::cube::{format_turns, parse_turns, Corner, CubeMove, CubeState, Edge, FaceTurn};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SolverError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("the solver at {url} failed: {message}")]
+    Request { url: String, message: String },
+    #[error("the solver at {url} answered: {answer}")]
+    Answer { url: String, answer: String },
+}
+
+// the 18 face turns, in the order of the faces
+fn face_turns() -> impl Iterator<Item = FaceTurn> {
+    CubeMove::iter()
+        .filter(|mv| mv.is_clockwise())
+        .flat_map(|face| (1..4).filter_map(move |amount| FaceTurn::new(face, amount)))
+}
+
+// sequences turning a face twice in a row, or turning opposite faces in both orders, are never
+// the shortest ones
+fn is_redundant(last: Option<FaceTurn>, turn: FaceTurn) -> bool {
+    last.is_some_and(|last| {
+        last.face() == turn.face()
+            || (last.face().commute(turn.face()) && last.face().repr() > turn.face().repr())
+    })
+}
+
+// the pieces of a state as bytes, `position * 3 + twist` for the corners and `position * 2 +
+// flip` for the edges, so that the inner loop of the search turns them by looking up tables
+// instead of composing states
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct CubieCoords {
+    corners: [u8; 8],
+    edges: [u8; 12],
+}
+
+impl CubieCoords {
+    pub fn of(state: &CubeState) -> Self {
+        CubieCoords {
+            corners: state
+                .corners
+                .map(|Corner(pos, ori)| pos as u8 * 3 + ori.repr()),
+            edges: state.edges.map(|Edge(pos, ori)| pos as u8 * 2 + ori.repr()),
+        }
+    }
+
+    pub fn then(&self, turn: &TurnTable) -> Self {
+        CubieCoords {
+            corners: std::array::from_fn(|i| {
+                turn.corners[i][self.corners[turn.corner_from[i]] as usize]
+            }),
+            edges: std::array::from_fn(|i| turn.edges[i][self.edges[turn.edge_from[i]] as usize]),
+        }
+    }
+}
+
+// where each position takes its piece from by the turn, and the byte of the piece after it is
+// twisted or flipped there
+pub struct TurnTable {
+    corner_from: [usize; 8],
+    corners: [[u8; 24]; 8],
+    edge_from: [usize; 12],
+    edges: [[u8; 24]; 12],
+}
+
+impl TurnTable {
+    pub fn of(turn: FaceTurn) -> Self {
+        let state = CubeState::from_moves(&turn.moves());
+        let add = |ori: u8, n: u8| -> [u8; 24] {
+            std::array::from_fn(|c| c as u8 / n * n + (c as u8 % n + ori) % n)
+        };
+        TurnTable {
+            corner_from: state.corners.map(|Corner(pos, _)| pos as usize),
+            corners: state.corners.map(|Corner(_, ori)| add(ori.repr(), 3)),
+            edge_from: state.edges.map(|Edge(pos, _)| pos as usize),
+            edges: state.edges.map(|Edge(_, ori)| add(ori.repr(), 2)),
+        }
+    }
+}
+
+// the 18 face turns with their tables
+pub fn turn_tables() -> Vec<(FaceTurn, TurnTable)> {
+    face_turns()
+        .map(|turn| (turn, TurnTable::of(turn)))
+        .collect()
+}
+
+fn search_from(
+    state: &CubieCoords,
+    goal: &CubieCoords,
+    tables: &[(FaceTurn, TurnTable)],
+    depth: usize,
+    path: &mut Vec<FaceTurn>,
+) -> bool {
+    if depth == 0 {
+        return state == goal;
+    }
+    for (turn, table) in tables {
+        if is_redundant(path.last().copied(), *turn) {
+            continue;
+        }
+        path.push(*turn);
+        if search_from(&state.then(table), goal, tables, depth - 1, path) {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
+
+// the shortest face turns leading from the state to the goal, searched by iterative deepening up
+// to the given number of turns
+pub fn solve(from: &CubeState, to: &CubeState, max_depth: usize) -> Option<Vec<FaceTurn>> {
+    let (from, to) = (CubieCoords::of(from), CubieCoords::of(to));
+    let tables = turn_tables();
+    let mut path = Vec::new();
+    (0..=max_depth)
+        .find_map(|depth| search_from(&from, &to, &tables, depth, &mut path).then(|| path.clone()))
+}
+
+// the first turn towards solved of every state within a few turns of it, found by a breadth first
+// search from the solved state; about 3500 states are within 3 turns, and 620000 within 5
+pub struct NearSolved {
+    solved: CubieCoords,
+    finishes: HashMap<CubieCoords, FaceTurn>,
+}
+
+impl NearSolved {
+    pub fn new(depth: usize) -> Self {
+        let tables = turn_tables();
+        let solved = CubieCoords::of(&CubeState::default());
+        let mut finishes = HashMap::new();
+        let mut frontier = vec![solved];
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for coords in &frontier {
+                for (turn, table) in &tables {
+                    let reached = coords.then(table);
+                    if reached == solved {
+                        continue;
+                    }
+                    if let Entry::Vacant(entry) = finishes.entry(reached) {
+                        entry.insert(turn.rev());
+                        next.push(reached);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        NearSolved { solved, finishes }
+    }
+
+    // the shortest turns solving the state, if it is within the depth of the table
+    pub fn finish(&self, state: &CubeState) -> Option<Vec<FaceTurn>> {
+        let mut coords = CubieCoords::of(state);
+        let mut turns = Vec::new();
+        while coords != self.solved {
+            let turn = *self.finishes.get(&coords)?;
+            coords = coords.then(&TurnTable::of(turn));
+            turns.push(turn);
+        }
+        Some(turns)
+    }
+}
+
+pub trait Solver {
+    // the turns leading from the state to the goal, if found within the given number of turns;
+    // the number only bounds a search, a server answers its full solution anyway
+    fn solve(
+        &self,
+        from: &CubeState,
+        to: &CubeState,
+        max_depth: usize,
+    ) -> Result<Option<Vec<FaceTurn>>, SolverError>;
+}
+
+// the search by iterative deepening, which finds the shortest turns but only for a few of them
+pub struct BuiltinSolver;
+
+impl Solver for BuiltinSolver {
+    fn solve(
+        &self,
+        from: &CubeState,
+        to: &CubeState,
+        max_depth: usize,
+    ) -> Result<Option<Vec<FaceTurn>>, SolverError> {
+        Ok(solve(from, to, max_depth))
+    }
+}
+
+// a server of the kociemba algorithm, answering `GET URL/FACELETS` by the turns solving the
+// facelets, such as `R U2 F'`; it only solves to the solved state, so it is asked for the goal
+// seen from the state
+pub struct HttpSolver {
+    pub url: String,
+}
+
+impl HttpSolver {
+    // the state solved by the turns leading from the state to the goal
+    fn relative(from: &CubeState, to: &CubeState) -> CubeState {
+        to.inverse().then(from)
+    }
+}
+
+impl Solver for HttpSolver {
+    fn solve(
+        &self,
+        from: &CubeState,
+        to: &CubeState,
+        _max_depth: usize,
+    ) -> Result<Option<Vec<FaceTurn>>, SolverError> {
+        let state = Self::relative(from, to);
+        if state.is_solved() {
+            return Ok(Some(Vec::new()));
+        }
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), state.facelets());
+        let output = Command::new("curl")
+            .args(["-fsS", "--max-time", "10", &url])
+            .output()?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(SolverError::Request { url, message });
+        }
+        let answer = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let turns = parse_turns(&answer).map_err(|_| SolverError::Answer {
+            url: self.url.clone(),
+            answer: answer.clone(),
+        })?;
+        Ok(Some(turns))
+    }
+}
+
+// the solver used by the `solve` command; the scramble guide keeps the builtin solver, since its
+// corrections are too short and too frequent to wait for a server
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SolverBackend {
+    #[default]
+    Builtin,
+    // the url of a kociemba server
+    Http(String),
+}
+
+impl SolverBackend {
+    pub fn solver(&self) -> Box<dyn Solver + Send> {
+        match self {
+            SolverBackend::Builtin => Box::new(BuiltinSolver),
+            SolverBackend::Http(url) => Box::new(HttpSolver { url: url.clone() }),
+        }
+    }
+}
+
+impl FromStr for SolverBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "builtin" => Ok(SolverBackend::Builtin),
+            _ if s.starts_with("http://") || s.starts_with("https://") => {
+                Ok(SolverBackend::Http(s.to_string()))
+            }
+            _ => Err(format!("unknown solver: {}, try builtin or a url", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for SolverBackend {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for SolverBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolverBackend::Builtin => write!(f, "builtin"),
+            SolverBackend::Http(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+impl From<SolverBackend> for String {
+    fn from(backend: SolverBackend) -> Self {
+        backend.to_string()
+    }
+}
+
+// the turns solving the scramble, by the configured solver
+pub fn print_solution(
+    backend: &SolverBackend,
+    scramble: &[FaceTurn],
+    max_depth: usize,
+) -> Result<(), SolverError> {
+    let moves = scramble
+        .iter()
+        .flat_map(|turn| turn.moves())
+        .collect::<Vec<_>>();
+    let state = CubeState::from_moves(&moves);
+    match backend
+        .solver()
+        .solve(&state, &CubeState::default(), max_depth)?
+    {
+        Some(turns) => println!("{} turns: {}", turns.len(), format_turns(&turns)),
+        None => println!("no solution within {} turns", max_depth),
+    }
+    Ok(())
+}
+
+// random face turns without redundant pairs, such as `U U'` or `D U D`
+pub fn random_scramble<R: rand::Rng>(length: usize, rng: &mut R) -> Vec<FaceTurn> {
+    let turns = face_turns().collect::<Vec<_>>();
+    let mut res: Vec<FaceTurn> = Vec::with_capacity(length);
+    while res.len() < length {
+        let turn = turns[rng.gen_range(0..turns.len())];
+        let last = res.last().copied();
+        let second_last = res.len().checked_sub(2).map(|i| res[i]);
+        let same_axis_thrice = last.is_some_and(|last| last.face().commute(turn.face()))
+            && second_last.is_some_and(|other| other.face().commute(turn.face()));
+        if last.is_some_and(|last| last.face() == turn.face()) || same_axis_thrice {
+            continue;
+        }
+        res.push(turn);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use std::collections::{BTreeSet, HashSet};
+
+    use super::*;
+    use crate::cube::{merge_turns, parse_turns};
+
+    // the numbers of the states first reached by 0, 1, 2 and 3 face turns
+    const PERFT: [usize; 4] = [1, 18, 243, 3240];
+
+    #[test]
+    fn turns_reach_the_known_numbers_of_states() {
+        let turns = face_turns().collect::<Vec<_>>();
+        let tables = turn_tables();
+
+        // by composing states, and by turning the coordinates
+        let mut seen = BTreeSet::from([CubeState::default()]);
+        let mut frontier = vec![CubeState::default()];
+        let mut seen_coords = HashSet::from([CubieCoords::of(&CubeState::default())]);
+        let mut frontier_coords = vec![CubieCoords::of(&CubeState::default())];
+        for &count in &PERFT[1..] {
+            let mut next = Vec::new();
+            for state in &frontier {
+                for turn in &turns {
+                    let mut state = *state;
+                    for mv in turn.moves() {
+                        state.apply(mv);
+                    }
+                    if seen.insert(state) {
+                        next.push(state);
+                    }
+                }
+            }
+            frontier = next;
+            assert_eq!(frontier.len(), count);
+
+            let mut next = Vec::new();
+            for coords in &frontier_coords {
+                for (_, table) in &tables {
+                    let coords = coords.then(table);
+                    if seen_coords.insert(coords) {
+                        next.push(coords);
+                    }
+                }
+            }
+            frontier_coords = next;
+            assert_eq!(frontier_coords.len(), count);
+        }
+
+        // both agree on the same sequences
+        let mut rng = StdRng::seed_from_u64(1);
+        let scramble = random_scramble(30, &mut rng);
+        let mut coords = CubieCoords::of(&CubeState::default());
+        for turn in &scramble {
+            coords = coords.then(&TurnTable::of(*turn));
+        }
+        let moves = scramble
+            .iter()
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        assert_eq!(coords, CubieCoords::of(&CubeState::from_moves(&moves)));
+    }
+
+    #[test]
+    fn solutions_are_the_shortest() {
+        let turns = parse_turns("R U2 F'").unwrap();
+        let moves = turns
+            .iter()
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        let state = CubeState::from_moves(&moves);
+        let solution = solve(&state, &CubeState::default(), 4).unwrap();
+        assert_eq!(
+            solution,
+            turns
+                .iter()
+                .rev()
+                .map(|turn| turn.rev())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(solve(&state, &CubeState::default(), 2), None);
+    }
+
+    #[test]
+    fn near_states_are_finished_by_the_shortest_turns() {
+        let turns = parse_turns("R U2 F'").unwrap();
+        let moves = turns
+            .iter()
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        let state = CubeState::from_moves(&moves);
+        let near = NearSolved::new(3);
+        assert_eq!(
+            near.finish(&state),
+            Some(turns.iter().rev().map(|turn| turn.rev()).collect())
+        );
+        assert_eq!(near.finish(&CubeState::default()), Some(Vec::new()));
+        assert_eq!(NearSolved::new(2).finish(&state), None);
+    }
+
+    #[test]
+    fn servers_are_asked_for_the_goal_seen_from_the_state() {
+        assert_eq!("builtin".parse(), Ok(SolverBackend::Builtin));
+        let url = "http://localhost:8080/";
+        assert_eq!(url.parse(), Ok(SolverBackend::Http(url.to_string())));
+        assert!("kociemba".parse::<SolverBackend>().is_err());
+
+        // solving the relative state to solved leads from the state to the goal
+        let from = CubeState::from_moves(&[CubeMove::R, CubeMove::F]);
+        let to = CubeState::from_moves(&[CubeMove::R, CubeMove::F, CubeMove::U, CubeMove::Lp]);
+        let relative = HttpSolver::relative(&from, &to);
+        let turns = BuiltinSolver
+            .solve(&relative, &CubeState::default(), 3)
+            .unwrap();
+        assert_eq!(turns, Some(parse_turns("U L'").unwrap()));
+    }
+
+    #[test]
+    fn scrambles_have_no_redundant_turns() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let scramble = random_scramble(25, &mut rng);
+        assert_eq!(scramble.len(), 25);
+        let moves = scramble
+            .iter()
+            .flat_map(|turn| turn.moves())
+            .collect::<Vec<_>>();
+        assert_eq!(merge_turns(&moves), scramble);
+    }
+}
@@ -0,0 +1,122 @@
+use std::{collections::HashSet, str::FromStr, sync::mpsc, thread};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SoundEvent {
+    // a key is typed
+    Key,
+    // a line is finished
+    Finish,
+    // the input is cancelled
+    Cancel,
+    // a key is mistyped in the trainer
+    Error,
+    // the cube gets within a few turns of solved
+    Near,
+}
+
+impl SoundEvent {
+    pub const ALL: [SoundEvent; 5] = [
+        SoundEvent::Key,
+        SoundEvent::Finish,
+        SoundEvent::Cancel,
+        SoundEvent::Error,
+        SoundEvent::Near,
+    ];
+}
+
+impl FromStr for SoundEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "key" => Ok(SoundEvent::Key),
+            "finish" => Ok(SoundEvent::Finish),
+            "cancel" => Ok(SoundEvent::Cancel),
+            "error" => Ok(SoundEvent::Error),
+            "near" => Ok(SoundEvent::Near),
+            _ => Err(format!("unknown sound event: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SoundError {
+    #[cfg_attr(feature = "sound", allow(dead_code))]
+    #[error("built without sound support, enable the feature `sound`")]
+    Unsupported,
+    #[error("no audio output: {0}")]
+    NoOutput(String),
+}
+
+// plays sounds in a dedicated thread, since the audio output cannot be sent between threads
+pub struct Sound {
+    events: HashSet<SoundEvent>,
+    sender: mpsc::Sender<SoundEvent>,
+}
+
+impl Sound {
+    pub fn new(events: impl IntoIterator<Item = SoundEvent>) -> Result<Self, SoundError> {
+        let (sender, receiver) = mpsc::channel();
+        let (ready_sender, ready_receiver) = mpsc::channel();
+        thread::spawn(move || play_sounds(receiver, ready_sender));
+        ready_receiver
+            .recv()
+            .map_err(|_| SoundError::NoOutput("audio thread exited".to_string()))??;
+        Ok(Sound {
+            events: events.into_iter().collect(),
+            sender,
+        })
+    }
+
+    pub fn play(&self, event: SoundEvent) {
+        if self.events.contains(&event) {
+            let _ = self.sender.send(event);
+        }
+    }
+}
+
+#[cfg(feature = "sound")]
+fn play_sounds(receiver: mpsc::Receiver<SoundEvent>, ready: mpsc::Sender<Result<(), SoundError>>) {
+    use std::time::Duration;
+
+    use rodio::{source::SineWave, OutputStream, Sink, Source};
+
+    let (_stream, handle) = match OutputStream::try_default() {
+        Ok(output) => output,
+        Err(err) => {
+            let _ = ready.send(Err(SoundError::NoOutput(err.to_string())));
+            return;
+        }
+    };
+    let _ = ready.send(Ok(()));
+
+    // (frequency, milliseconds) of each tone
+    fn tones(event: SoundEvent) -> &'static [(f32, u64)] {
+        match event {
+            SoundEvent::Key => &[(1760.0, 15)],
+            SoundEvent::Finish => &[(660.0, 100), (990.0, 150)],
+            SoundEvent::Cancel => &[(220.0, 80), (165.0, 120)],
+            SoundEvent::Error => &[(110.0, 200)],
+            SoundEvent::Near => &[(880.0, 60), (1320.0, 60), (1760.0, 60)],
+        }
+    }
+
+    const VOLUME: f32 = 0.2;
+    while let Ok(event) = receiver.recv() {
+        let Ok(sink) = Sink::try_new(&handle) else {
+            continue;
+        };
+        for &(frequency, millis) in tones(event) {
+            let tone = SineWave::new(frequency)
+                .take_duration(Duration::from_millis(millis))
+                .amplify(VOLUME);
+            sink.append(tone);
+        }
+        sink.detach();
+    }
+}
+
+#[cfg(not(feature = "sound"))]
+fn play_sounds(_receiver: mpsc::Receiver<SoundEvent>, ready: mpsc::Sender<Result<(), SoundError>>) {
+    let _ = ready.send(Err(SoundError::Unsupported));
+}
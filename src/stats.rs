@@ -0,0 +1,362 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, GoalConfig};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatsError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid stats: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("cannot find the data directory, set $HOME or $XDG_DATA_HOME")]
+    NoDataDir,
+}
+
+// the records kept between sessions
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Stats {
+    // the best score of each game
+    pub high_scores: BTreeMap<String, usize>,
+    // the timed solves, oldest first
+    pub solves: Vec<SolveRecord>,
+    // the practice in the trainer by the local day since the unix epoch
+    pub practice: BTreeMap<u64, DailyPractice>,
+    // where the sessions of the trainer stopped, by the path or the url of the text
+    pub checkpoints: BTreeMap<String, Checkpoint>,
+}
+
+// the lines of a text already typed, and the stats of typing them
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Checkpoint {
+    pub line: usize,
+    pub correct: usize,
+    pub typed: usize,
+    pub seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct DailyPractice {
+    pub seconds: u64,
+    pub keys: usize,
+    pub best_wpm: f32,
+}
+
+impl DailyPractice {
+    // the goals of zero are always reached
+    pub fn reaches(&self, goal: &GoalConfig) -> bool {
+        self.seconds >= goal.minutes * 60 && self.keys >= goal.keys && self.best_wpm >= goal.wpm
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Penalty {
+    #[default]
+    None,
+    PlusTwo,
+    Dnf,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SolveRecord {
+    // the time before the penalty
+    pub millis: u64,
+    pub penalty: Penalty,
+    pub scramble: String,
+    pub comment: String,
+    // seconds since the unix epoch
+    pub date: u64,
+}
+
+impl SolveRecord {
+    // the time with the penalty, or `None` for DNF
+    pub fn result(&self) -> Option<u64> {
+        match self.penalty {
+            Penalty::None => Some(self.millis),
+            Penalty::PlusTwo => Some(self.millis + 2000),
+            Penalty::Dnf => None,
+        }
+    }
+}
+
+// `$XDG_DATA_HOME/cuboard/stats.json`, or `~/.local/share/cuboard/stats.json`
+pub fn stats_path() -> Result<PathBuf, StatsError> {
+    let dir = match (env::var_os("XDG_DATA_HOME"), env::var_os("HOME")) {
+        (Some(dir), _) if !dir.is_empty() => PathBuf::from(dir),
+        (_, Some(home)) if !home.is_empty() => PathBuf::from(home).join(".local").join("share"),
+        _ => return Err(StatsError::NoDataDir),
+    };
+    Ok(dir.join("cuboard").join("stats.json"))
+}
+
+impl Stats {
+    // a missing stats file has no records
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StatsError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Stats::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StatsError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    // tell whether the score beats the high score of the game
+    pub fn record_score(&mut self, game: &str, score: usize) -> bool {
+        let high_score = self.high_scores.entry(game.to_string()).or_default();
+        if score <= *high_score {
+            return false;
+        }
+        *high_score = score;
+        true
+    }
+
+    // add the solves not recorded yet, and tell how many are added
+    pub fn merge_solves(&mut self, solves: impl IntoIterator<Item = SolveRecord>) -> usize {
+        let len = self.solves.len();
+        for solve in solves {
+            if !self.solves.contains(&solve) {
+                self.solves.push(solve);
+            }
+        }
+        self.solves.sort_by_key(|solve| solve.date);
+        self.solves.len() - len
+    }
+
+    pub fn record_practice(&mut self, day: u64, seconds: u64, keys: usize, wpm: f32) {
+        let practice = self.practice.entry(day).or_default();
+        practice.seconds += seconds;
+        practice.keys += keys;
+        practice.best_wpm = practice.best_wpm.max(wpm);
+    }
+
+    // the days in a row reaching the goals until today, or until yesterday if today is not
+    // finished yet, and the longest of such streaks
+    pub fn streak(&self, today: u64, goal: &GoalConfig) -> (usize, usize) {
+        let reached = |day| self.practice.get(&day).is_some_and(|p| p.reaches(goal));
+        let last = if reached(today) {
+            today
+        } else {
+            today.saturating_sub(1)
+        };
+        let current = (0..=last).rev().take_while(|&day| reached(day)).count();
+        let mut longest = 0;
+        let mut run = 0;
+        let mut prev = None;
+        for (&day, practice) in &self.practice {
+            if !practice.reaches(goal) {
+                run = 0;
+            } else if prev.is_some_and(|prev| prev + 1 == day) && run > 0 {
+                run += 1;
+            } else {
+                run = 1;
+            }
+            prev = Some(day);
+            longest = longest.max(run);
+        }
+        (current, longest)
+    }
+
+    // today's practice against the goals, and the streak
+    pub fn goal_summary(&self, today: u64, goal: &GoalConfig) -> String {
+        let practice = self.practice.get(&today).copied().unwrap_or_default();
+        let mut summary = format!("today: {} min", practice.seconds / 60);
+        if goal.minutes > 0 {
+            summary += &format!("/{}", goal.minutes);
+        }
+        summary += &format!("  {} keys", practice.keys);
+        if goal.keys > 0 {
+            summary += &format!("/{}", goal.keys);
+        }
+        summary += &format!("  best {:.0} wpm", practice.best_wpm);
+        if goal.wpm > 0.0 {
+            summary += &format!("/{:.0}", goal.wpm);
+        }
+        if practice.reaches(goal) {
+            summary += "  goal reached!";
+        }
+        let (current, longest) = self.streak(today, goal);
+        summary + &format!("  streak: {} days (longest {})", current, longest)
+    }
+}
+
+// the days since the unix epoch in the local time zone
+pub fn local_day(secs: u64) -> u64 {
+    let time = secs as libc::time_t;
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    let local = unsafe { libc::localtime_r(&time, &mut tm) };
+    let offset = if local.is_null() { 0 } else { tm.tm_gmtoff };
+    (secs as i64 + offset) as u64 / 86400
+}
+
+fn today() -> u64 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    local_day(secs)
+}
+
+// record the score in the stats file, and tell whether it is a new high score with the high score
+pub fn update_high_score(game: &str, score: usize) -> Result<(bool, usize), StatsError> {
+    let path = stats_path()?;
+    let mut stats = Stats::load(&path)?;
+    let beaten = stats.record_score(game, score);
+    if beaten {
+        stats.save(&path)?;
+    }
+    Ok((beaten, stats.high_scores[game]))
+}
+
+// record the solve in the stats file, and tell whether it is the best result
+pub fn record_solve(solve: SolveRecord) -> Result<bool, StatsError> {
+    let path = stats_path()?;
+    let mut stats = Stats::load(&path)?;
+    let best = match solve.result() {
+        Some(result) => stats
+            .solves
+            .iter()
+            .filter_map(SolveRecord::result)
+            .all(|best| result < best),
+        None => false,
+    };
+    stats.merge_solves([solve]);
+    stats.save(&path)?;
+    Ok(best)
+}
+
+// record a session of the trainer in the stats file, and tell the summary of the goals
+pub fn record_practice(seconds: u64, keys: usize, wpm: f32) -> Result<String, StatsError> {
+    let path = stats_path()?;
+    let mut stats = Stats::load(&path)?;
+    let today = today();
+    stats.record_practice(today, seconds, keys, wpm);
+    stats.save(&path)?;
+    Ok(stats.goal_summary(today, &config::get().goal))
+}
+
+pub fn load_checkpoint(text: &str) -> Result<Option<Checkpoint>, StatsError> {
+    let stats = Stats::load(stats_path()?)?;
+    Ok(stats.checkpoints.get(text).copied())
+}
+
+// keep where the session stopped, or forget it if the text is finished
+pub fn save_checkpoint(text: &str, checkpoint: Option<Checkpoint>) -> Result<(), StatsError> {
+    let path = stats_path()?;
+    let mut stats = Stats::load(&path)?;
+    match checkpoint {
+        Some(checkpoint) => stats.checkpoints.insert(text.to_string(), checkpoint),
+        None => stats.checkpoints.remove(text),
+    };
+    stats.save(&path)
+}
+
+pub fn print_streak() -> Result<(), StatsError> {
+    let stats = Stats::load(stats_path()?)?;
+    let goal = &config::get().goal;
+    let today = today();
+    // the last week, oldest first
+    for day in today.saturating_sub(6)..=today {
+        let practice = stats.practice.get(&day).copied().unwrap_or_default();
+        let mark = if practice.reaches(goal) { "*" } else { " " };
+        println!(
+            "{} {:>3} days ago  {:>3} min  {:>5} keys  {:>3.0} wpm",
+            mark,
+            today - day,
+            practice.seconds / 60,
+            practice.keys,
+            practice.best_wpm
+        );
+    }
+    println!("{}", stats.goal_summary(today, goal));
+    Ok(())
+}
+
+pub fn list_solves() -> Result<(), StatsError> {
+    let stats = Stats::load(stats_path()?)?;
+    if stats.solves.is_empty() {
+        println!("no solve recorded, time one by `cuboard scramble --time`");
+    }
+    for solve in &stats.solves {
+        let result = match solve.result() {
+            Some(millis) => format!("{:.2}s", millis as f32 / 1000.0),
+            None => "DNF".to_string(),
+        };
+        let penalty = if solve.penalty == Penalty::PlusTwo {
+            "+"
+        } else {
+            ""
+        };
+        println!("{}{}  {}", result, penalty, solve.scramble);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_better_scores_are_recorded() {
+        let mut stats = Stats::default();
+        assert!(stats.record_score("simon", 5));
+        assert!(!stats.record_score("simon", 5));
+        assert!(!stats.record_score("simon", 3));
+        assert!(stats.record_score("simon", 6));
+        assert_eq!(stats.high_scores["simon"], 6);
+
+        let solve = |date, millis| SolveRecord {
+            millis,
+            date,
+            ..SolveRecord::default()
+        };
+        assert_eq!(stats.merge_solves([solve(2, 9000), solve(1, 8000)]), 2);
+        assert_eq!(stats.merge_solves([solve(2, 9000), solve(3, 7000)]), 1);
+        let dates = stats
+            .solves
+            .iter()
+            .map(|solve| solve.date)
+            .collect::<Vec<_>>();
+        assert_eq!(dates, [1, 2, 3]);
+
+        let goal = GoalConfig {
+            minutes: 10,
+            keys: 0,
+            wpm: 0.0,
+        };
+        stats.record_practice(10, 300, 100, 12.0);
+        stats.record_practice(10, 300, 200, 15.0);
+        stats.record_practice(11, 900, 300, 10.0);
+        stats.record_practice(13, 600, 300, 10.0);
+        stats.record_practice(14, 60, 30, 20.0);
+        assert_eq!(stats.practice[&10].keys, 300);
+        assert_eq!(stats.practice[&10].best_wpm, 15.0);
+        // today is not finished yet
+        assert_eq!(stats.streak(14, &goal), (1, 2));
+        assert_eq!(stats.streak(15, &goal), (0, 2));
+
+        let text = serde_json::to_string(&stats).unwrap();
+        assert_eq!(serde_json::from_str::<Stats>(&text).unwrap(), stats);
+        assert_eq!(
+            serde_json::from_str::<Stats>("{}").unwrap(),
+            Stats::default()
+        );
+    }
+}
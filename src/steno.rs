@@ -0,0 +1,159 @@
+use std::{collections::HashMap, fs, path::Path, sync::OnceLock, time::Duration};
+
+use crate::{
+    cube::{format_moves, parse_turns, CubeMove},
+    cuboard::TurnDecoder,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StenoError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("line {line}: {reason}")]
+    Parse { line: usize, reason: String },
+    #[error("the input mode needs a dictionary, set --dictionary=PATH")]
+    NoDictionary,
+}
+
+// the words of the chords, where the order of the moves in a chord doesn't matter
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StenoDictionary {
+    words: HashMap<Vec<CubeMove>, String>,
+}
+
+fn normalize(chord: &[CubeMove]) -> Vec<CubeMove> {
+    let mut chord = chord.to_vec();
+    chord.sort();
+    chord
+}
+
+impl StenoDictionary {
+    // the dictionary file has one chord per line, such as `R U F': the`, where half turns are
+    // chords of two same moves; lines starting with `#` are comments
+    pub fn parse(text: &str) -> Result<Self, StenoError> {
+        let mut words = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |reason: &str| StenoError::Parse {
+                line: i + 1,
+                reason: reason.to_string(),
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((chord, word)) = line.split_once(':') else {
+                return Err(error("expect `CHORD: WORD`"));
+            };
+            let chord = parse_turns(chord).map_err(|err| error(&err))?;
+            let chord = chord
+                .iter()
+                .flat_map(|turn| turn.moves())
+                .collect::<Vec<_>>();
+            let word = word.trim();
+            if chord.is_empty() || word.is_empty() {
+                return Err(error("empty chord or word"));
+            }
+            if words.insert(normalize(&chord), word.to_string()).is_some() {
+                return Err(error("duplicated chord"));
+            }
+        }
+        Ok(StenoDictionary { words })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StenoError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn word(&self, chord: &[CubeMove]) -> Option<&str> {
+        self.words.get(&normalize(chord)).map(String::as_str)
+    }
+}
+
+static DICTIONARY: OnceLock<StenoDictionary> = OnceLock::new();
+
+// the dictionary is loaded once at startup, like the config
+pub fn init(dictionary: StenoDictionary) {
+    let _ = DICTIONARY.set(dictionary);
+}
+
+pub fn get() -> Option<&'static StenoDictionary> {
+    DICTIONARY.get()
+}
+
+// moves turned in quick succession form a chord, which types its word followed by a space
+pub struct StenoDecoder {
+    dictionary: &'static StenoDictionary,
+    chord: Vec<CubeMove>,
+}
+
+impl StenoDecoder {
+    pub const CHORD_GAP: Duration = Duration::from_millis(250);
+
+    pub fn new(dictionary: &'static StenoDictionary) -> Self {
+        StenoDecoder {
+            dictionary,
+            chord: Vec::new(),
+        }
+    }
+}
+
+impl TurnDecoder for StenoDecoder {
+    fn input(&mut self, mv: CubeMove, pause: Duration) -> String {
+        let mut res = String::new();
+        if !self.chord.is_empty() && pause >= Self::CHORD_GAP {
+            res = self.pending();
+            self.cancel();
+        }
+        self.chord.push(mv);
+        res
+    }
+
+    // unknown chords are dropped
+    fn pending(&self) -> String {
+        self.dictionary
+            .word(&self.chord)
+            .map_or(String::new(), |word| format!("{} ", word))
+    }
+
+    fn symbols(&self) -> String {
+        format_moves(&self.chord)
+    }
+
+    fn cancel(&mut self) {
+        self.chord.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chords_ignore_the_order_of_moves() {
+        use CubeMove::*;
+
+        let dictionary = StenoDictionary::parse("# common words\nR U: the\nF' D2: and\n").unwrap();
+        let dictionary = Box::leak(Box::new(dictionary));
+        assert_eq!(dictionary.word(&[D, Fp, D]), Some("and"));
+        assert!(matches!(
+            StenoDictionary::parse("R U: the\nU R: of\n"),
+            Err(StenoError::Parse { line: 2, .. })
+        ));
+
+        let mut decoder = StenoDecoder::new(dictionary);
+        let short = Duration::from_millis(50);
+        let mut accepted = String::new();
+        for (mv, pause) in [
+            (U, short),
+            (R, short),
+            (D, StenoDecoder::CHORD_GAP),
+            (Fp, short),
+        ] {
+            accepted += &decoder.input(mv, pause);
+        }
+        decoder.input(D, short);
+        assert_eq!(accepted, "the ");
+        assert_eq!(decoder.pending(), "and ");
+    }
+}
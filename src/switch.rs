@@ -0,0 +1,134 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::{
+    config::SwitchConfig,
+    cube::{parse_turns, CubeMove},
+    keyboard::{Action, Key, KeyAction},
+    profile::ActionProfile,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SwitchError {
+    #[error("invalid moves of the switch {name}: {reason}")]
+    Moves { name: String, reason: String },
+    #[error("the move {0} is bound to more than one switch")]
+    Conflict(CubeMove),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SwitchEvent {
+    Next,
+    Previous,
+    Select,
+}
+
+impl SwitchEvent {
+    pub const ALL: [SwitchEvent; 3] = [
+        SwitchEvent::Next,
+        SwitchEvent::Previous,
+        SwitchEvent::Select,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SwitchEvent::Next => "next",
+            SwitchEvent::Previous => "previous",
+            SwitchEvent::Select => "select",
+        }
+    }
+
+    // Tab, Shift+Tab and Enter step through the controls of most applications, and can be bound
+    // by switch scanning software
+    pub fn action(self) -> KeyAction {
+        let keys: &'static [Key] = match self {
+            SwitchEvent::Next => &[Key::Tab],
+            SwitchEvent::Previous => &[Key::LeftShift, Key::Tab],
+            SwitchEvent::Select => &[Key::Enter],
+        };
+        KeyAction {
+            name: self.name(),
+            keys,
+        }
+    }
+}
+
+impl FromStr for SwitchEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SwitchEvent::ALL
+            .into_iter()
+            .find(|event| event.name() == s)
+            .ok_or_else(|| format!("unknown switch event: {}", s))
+    }
+}
+
+// the switch event of each bound move
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SwitchMap {
+    bindings: HashMap<CubeMove, SwitchEvent>,
+}
+
+impl SwitchMap {
+    pub fn new(config: &SwitchConfig) -> Result<Self, SwitchError> {
+        let mut bindings = HashMap::new();
+        for event in SwitchEvent::ALL {
+            let moves = match event {
+                SwitchEvent::Next => &config.next,
+                SwitchEvent::Previous => &config.previous,
+                SwitchEvent::Select => &config.select,
+            };
+            let turns = parse_turns(moves).map_err(|reason| SwitchError::Moves {
+                name: event.name().to_string(),
+                reason,
+            })?;
+            for mv in turns.iter().flat_map(|turn| turn.moves()) {
+                match bindings.insert(mv, event) {
+                    Some(bound) if bound != event => return Err(SwitchError::Conflict(mv)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(SwitchMap { bindings })
+    }
+
+    pub fn event(&self, mv: CubeMove) -> Option<SwitchEvent> {
+        self.bindings.get(&mv).copied()
+    }
+}
+
+impl ActionProfile for SwitchMap {
+    fn name(&self) -> &'static str {
+        "switch"
+    }
+
+    fn input(&mut self, mv: CubeMove) -> Option<Action> {
+        self.event(mv).map(|event| Action::Keys(event.action()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_are_bound_to_switches() {
+        let mut map = SwitchMap::new(&SwitchConfig::default()).unwrap();
+        assert_eq!(
+            map.input(CubeMove::Rp),
+            Some(Action::Keys(SwitchEvent::Next.action()))
+        );
+        assert_eq!(map.input(CubeMove::F), None);
+        assert_eq!(map.event(CubeMove::Up), Some(SwitchEvent::Select));
+
+        let config = SwitchConfig {
+            previous: "R2".to_string(),
+            ..SwitchConfig::default()
+        };
+        assert!(matches!(
+            SwitchMap::new(&config),
+            Err(SwitchError::Conflict(CubeMove::R))
+        ));
+        assert_eq!("select".parse(), Ok(SwitchEvent::Select));
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    collections::VecDeque,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    task::JoinHandle,
+    time::{interval, MissedTickBehavior},
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    config,
+    cuboard::GyroGesture,
+    engine::EngineEvent,
+    i18n::{tr, Message},
+};
+
+// (columns, rows) of the terminal attached to stdout
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut size = libc::winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    let res = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if res != 0 || size.ws_col == 0 || size.ws_row == 0 {
+        return None;
+    }
+    Some((size.ws_col as usize, size.ws_row as usize))
+}
+
+// a grapheme with its ANSI style
+pub type StyledGrapheme<'a> = (&'a str, &'static str);
+
+pub fn styled<'a>(text: &'a str, style: &'static str) -> impl Iterator<Item = StyledGrapheme<'a>> {
+    text.graphemes(true).map(move |g| (g, style))
+}
+
+// number of columns taken by the text
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(UnicodeWidthStr::width).sum()
+}
+
+// a grapheme with its whole ANSI style, owned by a frame
+pub type Cell = (String, String);
+
+pub fn cells(text: &str, style: &str) -> Vec<Cell> {
+    text.graphemes(true)
+        .map(|g| (g.to_string(), style.to_string()))
+        .collect()
+}
+
+// the rows at the bottom of the terminal, composed in memory and written at once; only the cells
+// changed since the last frame are written, so that nothing flickers at the rate of the gyroscope
+#[derive(Default)]
+pub struct Screen {
+    // the cursor is left at the end of the last row
+    frame: Vec<Vec<Cell>>,
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Screen { frame: Vec::new() }
+    }
+
+    // the next frame starts at the row of the cursor, such as after the terminal is cleared
+    pub fn reset(&mut self) {
+        self.frame.clear();
+    }
+
+    fn write_cells(buf: &mut Vec<u8>, cells: &[Cell]) {
+        let mut current_style = None;
+        for (g, style) in cells {
+            if current_style != Some(style) {
+                let _ = write!(buf, "\x1b[m{}", style);
+                current_style = Some(style);
+            }
+            buf.extend_from_slice(g.as_bytes());
+        }
+        let _ = write!(buf, "\x1b[m\x1b[K");
+    }
+
+    // the rows above scroll away from the frame, and are not redrawn later
+    pub fn draw<F: Write>(&mut self, terminal: &mut F, above: &[Vec<Cell>], rows: Vec<Vec<Cell>>) {
+        if above.is_empty() && rows == self.frame {
+            return;
+        }
+        let mut buf = Vec::new();
+        let _ = write!(buf, "\r");
+        if self.frame.len() > 1 {
+            let _ = write!(buf, "\x1b[{}A", self.frame.len() - 1);
+        }
+        if !above.is_empty() {
+            let _ = write!(buf, "\x1b[J");
+            for row in above {
+                Self::write_cells(&mut buf, row);
+                let _ = write!(buf, "\r\n");
+            }
+            self.frame.clear();
+        }
+
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                let _ = write!(buf, "\r\n");
+            }
+            let prev = self.frame.get(i).map_or(&[][..], Vec::as_slice);
+            if i < self.frame.len() && prev == row.as_slice() {
+                continue;
+            }
+            let same = prev.iter().zip(row).take_while(|(a, b)| a == b).count();
+            let column = row[..same].iter().map(|(g, _)| g.width()).sum::<usize>();
+            if column > 0 {
+                let _ = write!(buf, "\x1b[{}C", column);
+            }
+            Self::write_cells(&mut buf, &row[same..]);
+        }
+        if rows.len() < self.frame.len() {
+            if rows.is_empty() {
+                let _ = write!(buf, "\r\x1b[J");
+            } else {
+                let _ = write!(buf, "\r\n\x1b[J\x1b[A");
+            }
+        }
+        let width = rows
+            .last()
+            .map_or(0, |row| row.iter().map(|(g, _)| g.width()).sum());
+        let _ = write!(buf, "\r");
+        if width > 0 {
+            let _ = write!(buf, "\x1b[{}C", width);
+        }
+
+        let _ = terminal.write_all(&buf);
+        let _ = terminal.flush();
+        self.frame = rows;
+    }
+}
+
+// what is drawn on the terminal by the frames, rather than on every message
+pub trait Repaint {
+    fn repaint(&mut self);
+}
+
+// whether the frames are drawn by `repaint_at_frame_rate`
+pub fn is_frame_limited() -> bool {
+    config::get().input.frame_rate > 0.0
+}
+
+// repaint at the configured frame rate, so that a burst of messages doesn't flood the terminal
+pub fn repaint_at_frame_rate<T: Repaint + Send + 'static>(
+    target: &Arc<Mutex<T>>,
+) -> Option<JoinHandle<()>> {
+    if !is_frame_limited() {
+        return None;
+    }
+    let target = Arc::clone(target);
+    let period = Duration::from_secs_f32(1.0 / config::get().input.frame_rate);
+    Some(tokio::spawn(async move {
+        let mut ticks = interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticks.tick().await;
+            if let Ok(mut target) = target.lock() {
+                target.repaint();
+            }
+        }
+    }))
+}
+
+// whether the output is appended lines, for dumb terminals, logs and screen readers
+pub fn is_plain() -> bool {
+    config::get().input.plain || std::env::var("TERM").is_ok_and(|term| term == "dumb")
+}
+
+// write the events of the engine as appended lines, without any cursor addressing; the text is
+// written once a line is finished, and the changes of the state are written as notes
+#[derive(Default)]
+pub struct PlainRenderer {
+    status: Option<String>,
+}
+
+impl PlainRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `line` is the unfinished line after the events
+    pub fn render<F: Write>(
+        &mut self,
+        terminal: &mut F,
+        events: &[EngineEvent],
+        line: &str,
+        status: Option<String>,
+    ) {
+        for event in events {
+            let _ = match event {
+                EngineEvent::Started => writeln!(terminal, "[{}]", tr(Message::Started, &[])),
+                EngineEvent::LineCompleted(line) => writeln!(terminal, "{}", line),
+                EngineEvent::GestureFired(GyroGesture::TurningAround) => {
+                    writeln!(terminal, "{}", line)
+                }
+                EngineEvent::GestureFired(GyroGesture::Shaking) => {
+                    writeln!(terminal, "[{}]", tr(Message::Cancelled, &[]))
+                }
+                EngineEvent::Paused => writeln!(terminal, "[{}]", tr(Message::Paused, &[])),
+                EngineEvent::Resumed => writeln!(terminal, "[{}]", tr(Message::Resumed, &[])),
+                EngineEvent::TextCommitted(_) | EngineEvent::BufferChanged { .. } => Ok(()),
+            };
+        }
+        if status != self.status {
+            if let Some(status) = &status {
+                let _ = writeln!(terminal, "[{}]", status);
+            }
+            self.status = status;
+        }
+        let _ = terminal.flush();
+    }
+}
+
+// render the text being typed above the prompt line, wrapping long lines at the terminal width
+// and redrawing from the scrollback when the terminal is resized
+pub struct TextRenderer {
+    size: (usize, usize),
+    screen: Screen,
+    scrollback: VecDeque<String>,
+}
+
+impl TextRenderer {
+    const DEFAULT_SIZE: (usize, usize) = (80, 24);
+    const SCROLLBACK_SIZE: usize = 1000;
+
+    pub fn new() -> Self {
+        TextRenderer {
+            size: terminal_size().unwrap_or(Self::DEFAULT_SIZE),
+            screen: Screen::new(),
+            scrollback: VecDeque::new(),
+        }
+    }
+
+    // leave the last column empty, so that the cursor never wraps by itself
+    fn wrap<'a, 'b>(
+        graphemes: &'b [StyledGrapheme<'a>],
+        width: usize,
+    ) -> Vec<&'b [StyledGrapheme<'a>]> {
+        // wide graphemes take two columns
+        let width = width.saturating_sub(1).max(2);
+        let mut rows = Vec::new();
+        let mut start = 0;
+        let mut row_width = 0;
+        for (i, (g, _)) in graphemes.iter().enumerate() {
+            let g_width = g.width();
+            if row_width + g_width > width && i > start {
+                rows.push(&graphemes[start..i]);
+                start = i;
+                row_width = 0;
+            }
+            row_width += g_width;
+        }
+        rows.push(&graphemes[start..]);
+        rows
+    }
+
+    fn write_row<F: Write>(terminal: &mut F, row: &[StyledGrapheme]) {
+        let mut current_style = "";
+        for &(g, style) in row {
+            if style != current_style {
+                let _ = write!(terminal, "\x1b[m{}", style);
+                current_style = style;
+            }
+            let _ = write!(terminal, "{}", g);
+        }
+        let _ = write!(terminal, "\x1b[m\x1b[K\r\n");
+    }
+
+    // the panel is drawn between the finished lines and the line being typed, and the cursor is
+    // left at the end of the prompt line
+    pub fn render<F: Write>(
+        &mut self,
+        terminal: &mut F,
+        finished: &[String],
+        panel: Vec<Vec<Cell>>,
+        line: &[StyledGrapheme],
+        prompt: Vec<Cell>,
+    ) {
+        let size = terminal_size().unwrap_or(self.size);
+        if size != self.size {
+            // the terminal reflows wrapped rows by itself, so redraw everything
+            self.size = size;
+            self.screen.reset();
+            let (width, height) = size;
+            let _ = write!(terminal, "\x1b[2J\x1b[H");
+            let rows = self
+                .scrollback
+                .iter()
+                .map(|line| styled(line, "").collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            let rows = rows
+                .iter()
+                .flat_map(|line| Self::wrap(line, width))
+                .collect::<Vec<_>>();
+            let visible = height.saturating_sub(panel.len() + Self::wrap(line, width).len() + 1);
+            for row in &rows[rows.len().saturating_sub(visible)..] {
+                Self::write_row(terminal, row);
+            }
+        }
+
+        let width = self.size.0;
+        let to_cells = |row: &[StyledGrapheme]| {
+            row.iter()
+                .map(|&(g, style)| (g.to_string(), style.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let mut above = Vec::new();
+        for text in finished {
+            let graphemes = styled(text, "").collect::<Vec<_>>();
+            above.extend(Self::wrap(&graphemes, width).into_iter().map(to_cells));
+            self.scrollback.push_back(text.clone());
+        }
+        while self.scrollback.len() > Self::SCROLLBACK_SIZE {
+            self.scrollback.pop_front();
+        }
+
+        let mut rows = panel;
+        rows.extend(Self::wrap(line, width).into_iter().map(to_cells));
+        rows.push(prompt);
+        self.screen.draw(terminal, &above, rows);
+    }
+}
+
+impl Default for TextRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_output_is_appended_lines() {
+        let mut renderer = PlainRenderer::new();
+        let mut terminal = Vec::new();
+        let events = [
+            EngineEvent::Started,
+            EngineEvent::TextCommitted("abc\nde".to_string()),
+            EngineEvent::LineCompleted("abc".to_string()),
+        ];
+        renderer.render(&mut terminal, &events, "de", None);
+        renderer.render(
+            &mut terminal,
+            &[EngineEvent::Paused],
+            "de",
+            Some("x".to_string()),
+        );
+        renderer.render(&mut terminal, &[], "de", Some("x".to_string()));
+        let events = [
+            EngineEvent::Resumed,
+            EngineEvent::GestureFired(GyroGesture::TurningAround),
+        ];
+        renderer.render(&mut terminal, &events, "def", None);
+        let output = String::from_utf8(terminal).unwrap();
+        assert!(!output.contains('\x1b'));
+        assert_eq!(output, "[started]\nabc\n[paused]\n[x]\n[resumed]\ndef\n");
+    }
+
+    #[test]
+    fn only_changed_cells_are_written() {
+        let mut screen = Screen::new();
+        let mut terminal = Vec::new();
+        screen.draw(&mut terminal, &[], vec![cells("abc", ""), cells("> R", "")]);
+        assert_eq!(
+            String::from_utf8(terminal).unwrap(),
+            "\r\x1b[mabc\x1b[m\x1b[K\r\n\x1b[m> R\x1b[m\x1b[K\r\x1b[3C"
+        );
+
+        // the cursor goes up to the first row, skips it, and writes from the changed cell
+        let mut terminal = Vec::new();
+        screen.draw(&mut terminal, &[], vec![cells("abc", ""), cells("> U", "")]);
+        assert_eq!(
+            String::from_utf8(terminal).unwrap(),
+            "\r\x1b[1A\r\n\x1b[2C\x1b[mU\x1b[m\x1b[K\r\x1b[3C"
+        );
+
+        // the rows above redraw the frame below them
+        let mut terminal = Vec::new();
+        screen.draw(&mut terminal, &[cells("abc", "")], vec![cells("> ", "")]);
+        assert_eq!(
+            String::from_utf8(terminal).unwrap(),
+            "\r\x1b[1A\x1b[J\x1b[mabc\x1b[m\x1b[K\r\n\x1b[m> \x1b[m\x1b[K\r\x1b[2C"
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    process::Command,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng, SeedableRng};
+
+#[rustfmt::skip]
+const COMMON_WORDS: &[&str] = &[
+    "the", "be", "to", "of", "and", "a", "in", "that", "have", "i",
+    "it", "for", "not", "on", "with", "he", "as", "you", "do", "at",
+    "this", "but", "his", "by", "from", "they", "we", "say", "her", "she",
+    "or", "an", "will", "my", "one", "all", "would", "there", "their", "what",
+    "so", "up", "out", "if", "about", "who", "get", "which", "go", "me",
+    "when", "make", "can", "like", "time", "no", "just", "him", "know", "take",
+    "people", "into", "year", "your", "good", "some", "could", "them", "see", "other",
+    "than", "then", "now", "look", "only", "come", "its", "over", "think", "also",
+    "back", "after", "use", "two", "how", "our", "work", "first", "well", "way",
+    "even", "new", "want", "because", "any", "these", "give", "day", "most", "us",
+];
+
+const PANGRAMS: &[&str] = &[
+    "the quick brown fox jumps over the lazy dog",
+    "pack my box with five dozen liquor jugs",
+    "how vexingly quick daft zebras jump",
+    "sphinx of black quartz, judge my vow",
+    "the five boxing wizards jump quickly",
+    "jackdaws love my big sphinx of quartz",
+    "waltz, bad nymph, for quick jigs vex",
+    "glib jocks quiz nymph to vex dwarf",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TextMode {
+    // random stream of common words
+    #[default]
+    Words,
+    // sentences using every letter
+    Pangrams,
+    // common words weighted by the error rates of their letters
+    Weakness,
+}
+
+impl FromStr for TextMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "words" => Ok(TextMode::Words),
+            "pangrams" => Ok(TextMode::Pangrams),
+            "weakness" => Ok(TextMode::Weakness),
+            _ => Err(format!("unknown text mode: {}", s)),
+        }
+    }
+}
+
+pub enum TrainingText {
+    File(String),
+    Generated(TextMode),
+    // lines repeated in turn, such as algorithms to drill
+    Drill(Vec<String>),
+    // text made only of these characters, to memorize their keys
+    Keys(Vec<char>),
+    // text piped into the trainer
+    Stdin,
+    // text fetched by `curl`, such as a page or a feed
+    Url(String),
+}
+
+impl TrainingText {
+    // what is typed, for the results of the sessions
+    pub fn name(&self) -> String {
+        match self {
+            TrainingText::File(filename) => Path::new(filename)
+                .file_name()
+                .map_or(filename.clone(), |name| name.to_string_lossy().to_string()),
+            TrainingText::Generated(TextMode::Words) => "words".to_string(),
+            TrainingText::Generated(TextMode::Pangrams) => "pangrams".to_string(),
+            TrainingText::Generated(TextMode::Weakness) => "weakness".to_string(),
+            TrainingText::Drill(_) => "drill".to_string(),
+            TrainingText::Keys(chars) => format!("keys:{}", chars.iter().collect::<String>()),
+            TrainingText::Stdin => "stdin".to_string(),
+            TrainingText::Url(url) => url.clone(),
+        }
+    }
+
+    // the texts read again the same, whose sessions can be resumed
+    pub fn resumable(&self) -> Option<String> {
+        match self {
+            TrainingText::File(filename) => Some(
+                std::fs::canonicalize(filename)
+                    .map_or(filename.clone(), |path| path.to_string_lossy().to_string()),
+            ),
+            TrainingText::Url(url) => Some(url.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn lines(
+        self,
+        heatmap: ErrorHeatmap,
+    ) -> io::Result<Box<dyn Iterator<Item = String> + Send>> {
+        match self {
+            TrainingText::File(filename) => Ok(Box::new(
+                BufReader::new(File::open(filename)?)
+                    .lines()
+                    .map_while(|l| l.ok()),
+            )),
+            TrainingText::Generated(mode) => Ok(Box::new(TextGenerator::new(mode, heatmap))),
+            TrainingText::Drill(lines) => Ok(Box::new(lines.into_iter().cycle())),
+            TrainingText::Keys(chars) => Ok(Box::new(KeyDrill::new(chars))),
+            TrainingText::Stdin => {
+                let mut text = String::new();
+                io::stdin().read_to_string(&mut text)?;
+                Ok(Box::new(text_lines(&text).into_iter()))
+            }
+            TrainingText::Url(url) => Ok(Box::new(text_lines(&fetch(&url)?).into_iter())),
+        }
+    }
+}
+
+fn fetch(url: &str) -> io::Result<String> {
+    let output = Command::new("curl").args(["-fsSL", url]).output()?;
+    if !output.status.success() {
+        let err = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "cannot fetch {}: {}",
+            url,
+            err.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// the text of html or xml, without the tags, the scripts and the styles
+fn plain_text(markup: &str) -> String {
+    const ENTITIES: [(&str, &str); 7] = [
+        ("&lt;", "<"),
+        ("&gt;", ">"),
+        ("&quot;", "\""),
+        ("&#39;", "'"),
+        ("&apos;", "'"),
+        ("&nbsp;", " "),
+        ("&amp;", "&"),
+    ];
+
+    let lower = markup.to_ascii_lowercase();
+    let mut text = String::new();
+    let mut i = 0;
+    while let Some(start) = lower[i..].find('<').map(|start| i + start) {
+        text += &markup[i..start];
+        let tag = &lower[start + 1..];
+        if tag.starts_with("![cdata[") {
+            let data = start + "<![CDATA[".len();
+            let end = lower[data..]
+                .find("]]>")
+                .map_or(lower.len(), |end| data + end);
+            text += &markup[data..end];
+            i = (end + 3).min(lower.len());
+            continue;
+        }
+        let end = if tag.starts_with("!--") {
+            tag.find("-->").map(|end| end + 3)
+        } else if let Some(name) = ["script", "style"].iter().find(|&&n| tag.starts_with(n)) {
+            tag.find(&format!("</{}", name))
+                .and_then(|end| tag[end..].find('>').map(|i| end + i + 1))
+        } else {
+            tag.find('>').map(|end| end + 1)
+        };
+        // the tags breaking the lines
+        let name = tag.trim_start_matches('/');
+        if ["p", "br", "div", "li", "h", "tr", "title", "item"]
+            .iter()
+            .any(|&n| name.starts_with(n))
+        {
+            text += "\n";
+        }
+        i = end.map_or(lower.len(), |end| start + 1 + end);
+    }
+    text += &markup[i..];
+    for (entity, c) in ENTITIES {
+        text = text.replace(entity, c);
+    }
+    text
+}
+
+// the lines of the text to type, extracted from markup, without the blank lines and wrapped at
+// the spaces
+fn text_lines(text: &str) -> Vec<String> {
+    const LINE_WIDTH: usize = 60;
+
+    let looks_like_markup = text.trim_start().starts_with('<');
+    let text = if looks_like_markup {
+        plain_text(text)
+    } else {
+        text.to_string()
+    };
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let line = line.replace('\t', "    ");
+        let line = if looks_like_markup {
+            line.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            line.trim_end().to_string()
+        };
+        // the indents of the short lines are kept, such as of code
+        if line.chars().count() <= LINE_WIDTH {
+            if !line.trim().is_empty() {
+                lines.push(line);
+            }
+            continue;
+        }
+        let mut wrapped = String::new();
+        for word in line.split_whitespace() {
+            if !wrapped.is_empty()
+                && wrapped.chars().count() + 1 + word.chars().count() > LINE_WIDTH
+            {
+                lines.push(std::mem::take(&mut wrapped));
+            } else if !wrapped.is_empty() {
+                wrapped.push(' ');
+            }
+            wrapped += word;
+        }
+        if !wrapped.is_empty() {
+            lines.push(wrapped);
+        }
+    }
+    lines
+}
+
+// numbers of correct and wrong inputs per expected character, shared between the trainer and
+// the generator
+#[derive(Clone, Default)]
+pub struct ErrorHeatmap {
+    counts: Arc<Mutex<HashMap<char, (usize, usize)>>>,
+}
+
+impl ErrorHeatmap {
+    pub fn record(&self, expected: char, correct: bool) {
+        if let Ok(mut counts) = self.counts.lock() {
+            let count = counts.entry(expected).or_default();
+            if correct {
+                count.0 += 1;
+            } else {
+                count.1 += 1;
+            }
+        }
+    }
+
+    // smoothed error rate, unseen characters count as half wrong
+    pub fn error_rate(&self, c: char) -> f32 {
+        let (hits, misses) = self
+            .counts
+            .lock()
+            .map_or((0, 0), |counts| counts.get(&c).copied().unwrap_or_default());
+        (misses as f32 + 1.0) / ((hits + misses) as f32 + 2.0)
+    }
+}
+
+pub struct TextGenerator {
+    mode: TextMode,
+    heatmap: ErrorHeatmap,
+    rng: StdRng,
+}
+
+impl TextGenerator {
+    const LINE_WIDTH: usize = 40;
+
+    pub fn new(mode: TextMode, heatmap: ErrorHeatmap) -> Self {
+        TextGenerator {
+            mode,
+            heatmap,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    fn fill_line(&mut self, mut next_word: impl FnMut(&mut StdRng) -> &'static str) -> String {
+        let mut line = next_word(&mut self.rng).to_string();
+        loop {
+            let word = next_word(&mut self.rng);
+            if line.len() + 1 + word.len() > Self::LINE_WIDTH {
+                return line;
+            }
+            line += " ";
+            line += word;
+        }
+    }
+}
+
+impl Iterator for TextGenerator {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let line = match self.mode {
+            TextMode::Words => {
+                self.fill_line(|rng| COMMON_WORDS[rng.gen_range(0..COMMON_WORDS.len())])
+            }
+            TextMode::Pangrams => PANGRAMS[self.rng.gen_range(0..PANGRAMS.len())].to_string(),
+            TextMode::Weakness => {
+                let weights = COMMON_WORDS.iter().map(|word| {
+                    word.chars()
+                        .map(|c| self.heatmap.error_rate(c))
+                        .sum::<f32>()
+                        / word.len() as f32
+                });
+                let dist = WeightedIndex::new(weights).unwrap();
+                self.fill_line(|rng| COMMON_WORDS[dist.sample(rng)])
+            }
+        };
+        Some(line)
+    }
+}
+
+// common words made only of the chosen characters, mixed with random groups of them
+pub struct KeyDrill {
+    chars: Vec<char>,
+    words: Vec<&'static str>,
+    rng: StdRng,
+}
+
+impl KeyDrill {
+    const LINE_WIDTH: usize = 40;
+
+    pub fn new(chars: Vec<char>) -> Self {
+        let words = COMMON_WORDS
+            .iter()
+            .copied()
+            .filter(|word| word.chars().all(|c| chars.contains(&c)))
+            .collect();
+        KeyDrill {
+            chars,
+            words,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    fn next_word(&mut self) -> String {
+        if !self.words.is_empty() && self.rng.gen_bool(0.5) {
+            return self.words[self.rng.gen_range(0..self.words.len())].to_string();
+        }
+        let len = self.rng.gen_range(1..=4);
+        (0..len)
+            .map(|_| self.chars[self.rng.gen_range(0..self.chars.len())])
+            .collect()
+    }
+}
+
+impl Iterator for KeyDrill {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.chars.is_empty() {
+            return None;
+        }
+        // the words are separated by spaces only if spaces are drilled too
+        let separator = if self.chars.contains(&' ') { " " } else { "" };
+        let mut line = self.next_word();
+        loop {
+            let word = self.next_word();
+            if line.chars().count() + separator.len() + word.chars().count() > Self::LINE_WIDTH {
+                return Some(line);
+            }
+            line += separator;
+            line += &word;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_are_extracted_into_lines() {
+        let page = "<html><head><title>Cubes &amp; keys</title><style>p { color: red; }</style>
+            </head><body><!-- <p>hidden</p> --><p>Type <b>this</b>,<br>then that.</p>
+            <script>if (a < b) {}</script><p><![CDATA[x < y]]></p></body></html>";
+        assert_eq!(
+            text_lines(page),
+            ["Cubes & keys", "Type this,", "then that.", "x < y"]
+        );
+
+        let code = format!(
+            "fn main() {{\n\n    println!();\n}}\n{}",
+            "word ".repeat(20)
+        );
+        let lines = text_lines(&code);
+        assert_eq!(lines[..3], ["fn main() {", "    println!();", "}"]);
+        assert_eq!(lines[3], "word ".repeat(12).trim_end());
+        assert_eq!(lines[4], "word ".repeat(8).trim_end());
+    }
+
+    #[test]
+    fn key_drill_uses_only_the_chosen_characters() {
+        let lines = KeyDrill::new("aeiou ".chars().collect())
+            .take(10)
+            .collect::<Vec<_>>();
+        assert!(lines
+            .iter()
+            .all(|line| line.chars().all(|c| "aeiou ".contains(c))));
+        assert!(lines
+            .iter()
+            .all(|line| line.chars().count() <= KeyDrill::LINE_WIDTH));
+        assert_eq!(KeyDrill::new(Vec::new()).next(), None);
+    }
+
+    fn generator(mode: TextMode, heatmap: ErrorHeatmap) -> TextGenerator {
+        TextGenerator {
+            mode,
+            heatmap,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    #[test]
+    fn generated_text_follows_the_mode() {
+        let lines = generator(TextMode::Words, ErrorHeatmap::default())
+            .take(20)
+            .collect::<Vec<_>>();
+        for line in &lines {
+            assert!(line.len() <= TextGenerator::LINE_WIDTH);
+            assert!(line.split(' ').all(|word| COMMON_WORDS.contains(&word)));
+        }
+
+        let lines = generator(TextMode::Pangrams, ErrorHeatmap::default()).take(20);
+        for line in lines {
+            assert!(PANGRAMS.contains(&line.as_str()));
+        }
+
+        // the words with the often mistyped letter are drilled mostly
+        let heatmap = ErrorHeatmap::default();
+        for c in 'a'..='z' {
+            for _ in 0..1000 {
+                heatmap.record(c, c != 'y');
+            }
+        }
+        let words = generator(TextMode::Weakness, heatmap)
+            .take(20)
+            .flat_map(|line| line.split(' ').map(str::to_string).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let drilled = words.iter().filter(|word| word.contains('y')).count();
+        assert!(drilled * 10 >= words.len() * 8, "{:?}", words);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{env, fmt, str::FromStr, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, ThemeConfig};
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+// a color of the 16 ANSI colors, or a true color
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Color {
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    // `red`, `bright-red` or `#dc322f`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("unknown color: {}", s);
+        if let Some(hex) = s.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(error());
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| error());
+            return Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+        }
+        let (name, bright) = match s.strip_prefix("bright-") {
+            Some(name) => (name, 8),
+            None => (s, 0),
+        };
+        let index = COLOR_NAMES
+            .iter()
+            .position(|&n| n == name)
+            .ok_or_else(error)?;
+        Ok(Color::Ansi(index as u8 + bright))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Color::Ansi(n) if n >= 8 => write!(f, "bright-{}", COLOR_NAMES[(n % 8) as usize]),
+            Color::Ansi(n) => write!(f, "{}", COLOR_NAMES[n as usize]),
+            Color::Rgb(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> Self {
+        color.to_string()
+    }
+}
+
+impl Color {
+    // the xterm colors for the ANSI colors
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        const ANSI: [(u8, u8, u8); 16] = [
+            (0, 0, 0),
+            (205, 0, 0),
+            (0, 205, 0),
+            (205, 205, 0),
+            (0, 0, 238),
+            (205, 0, 205),
+            (0, 205, 205),
+            (229, 229, 229),
+            (127, 127, 127),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (92, 92, 255),
+            (255, 0, 255),
+            (0, 255, 255),
+            (255, 255, 255),
+        ];
+        match *self {
+            Color::Ansi(n) => ANSI[n as usize % 16],
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    // SGR parameters of the color, true colors fall back to the 256 colors if not supported
+    fn sgr(&self, background: bool, true_color: bool) -> String {
+        match *self {
+            Color::Ansi(n) => {
+                let base = match (background, n >= 8) {
+                    (false, false) => 30,
+                    (true, false) => 40,
+                    (false, true) => 90,
+                    (true, true) => 100,
+                };
+                (base + n % 8).to_string()
+            }
+            Color::Rgb(r, g, b) => {
+                let layer = if background { 48 } else { 38 };
+                if true_color {
+                    format!("{};2;{};{};{}", layer, r, g, b)
+                } else {
+                    // the 6x6x6 color cube
+                    let level = |c: u8| (c as u16 * 5 + 127) / 255;
+                    let index = 16 + 36 * level(r) + 6 * level(g) + level(b);
+                    format!("{};5;{}", layer, index)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Palette {
+    #[default]
+    Default,
+    Solarized,
+    Gruvbox,
+    NoColor,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Palette::Default),
+            "solarized" => Ok(Palette::Solarized),
+            "gruvbox" => Ok(Palette::Gruvbox),
+            "no-color" => Ok(Palette::NoColor),
+            _ => Err(format!("unknown palette: {}", s)),
+        }
+    }
+}
+
+struct Colors {
+    // U, R, F, D, L, B
+    faces: [Color; 6],
+    face_text: Color,
+    prompt: Color,
+    status: Color,
+    error: Color,
+    preedit: Color,
+}
+
+impl Palette {
+    fn colors(&self) -> Option<Colors> {
+        fn rgb(hex: &str) -> Color {
+            hex.parse().unwrap()
+        }
+
+        match self {
+            Palette::Default => Some(Colors {
+                faces: [7, 1, 2, 3, 5, 4].map(Color::Ansi),
+                face_text: Color::Ansi(0),
+                prompt: Color::Ansi(8),
+                status: Color::Ansi(3),
+                error: Color::Ansi(1),
+                preedit: Color::Ansi(6),
+            }),
+            Palette::Solarized => Some(Colors {
+                faces: [
+                    "#eee8d5", "#dc322f", "#859900", "#b58900", "#d33682", "#268bd2",
+                ]
+                .map(rgb),
+                face_text: rgb("#002b36"),
+                prompt: rgb("#073642"),
+                status: rgb("#b58900"),
+                error: rgb("#dc322f"),
+                preedit: rgb("#2aa198"),
+            }),
+            Palette::Gruvbox => Some(Colors {
+                faces: [
+                    "#ebdbb2", "#cc241d", "#98971a", "#d79921", "#b16286", "#458588",
+                ]
+                .map(rgb),
+                face_text: rgb("#282828"),
+                prompt: rgb("#3c3836"),
+                status: rgb("#fabd2f"),
+                error: rgb("#cc241d"),
+                preedit: rgb("#689d6a"),
+            }),
+            Palette::NoColor => None,
+        }
+    }
+}
+
+// escape sequences of the terminal UI
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    // U, R, F, D, L, B on the cheatsheet
+    pub faces: [String; 6],
+    // U, R, F, D, L, B as the foreground and the background of the cube net
+    pub net: [(String, String); 6],
+    // the prompt line
+    pub prompt: String,
+    // the status on the prompt line
+    pub status: String,
+    // mistyped text of the trainer
+    pub error: String,
+    // underlined text being composed
+    pub preedit: String,
+}
+
+impl Theme {
+    // mistyped text is underlined without colors, so that it is still visible
+    const NO_COLOR_ERROR: &'static str = "\x1b[4m";
+
+    pub fn new(config: &ThemeConfig, true_color: bool) -> Self {
+        let Some(mut colors) = config.palette.colors() else {
+            return Theme {
+                faces: Default::default(),
+                net: Default::default(),
+                prompt: String::new(),
+                status: String::new(),
+                error: Self::NO_COLOR_ERROR.to_string(),
+                preedit: "\x1b[4m".to_string(),
+            };
+        };
+        colors.prompt = config.prompt.unwrap_or(colors.prompt);
+        colors.status = config.status.unwrap_or(colors.status);
+        colors.error = config.error.unwrap_or(colors.error);
+
+        let escape = |params: &[String]| format!("\x1b[{}m", params.join(";"));
+        let fg = |color: Color| color.sgr(false, true_color);
+        let bg = |color: Color| color.sgr(true, true_color);
+        Theme {
+            faces: colors
+                .faces
+                .map(|face| escape(&[fg(colors.face_text), bg(face)])),
+            net: colors
+                .faces
+                .map(|face| (escape(&[fg(face)]), escape(&[bg(face)]))),
+            prompt: escape(&[bg(colors.prompt)]),
+            status: escape(&[bg(colors.prompt), fg(colors.status)]),
+            error: escape(&[bg(colors.error)]),
+            preedit: escape(&["4".to_string(), fg(colors.preedit)]),
+        }
+    }
+
+    // honor `NO_COLOR` (https://no-color.org) and `COLORTERM`
+    pub fn from_env(config: &ThemeConfig) -> Self {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        if no_color {
+            let config = ThemeConfig {
+                palette: Palette::NoColor,
+                ..config.clone()
+            };
+            return Theme::new(&config, false);
+        }
+        let true_color = env::var("COLORTERM").is_ok_and(|v| v == "truecolor" || v == "24bit");
+        Theme::new(config, true_color)
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+pub fn get() -> &'static Theme {
+    THEME.get_or_init(|| Theme::from_env(&config::get().theme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_parsed_and_formatted() {
+        for name in ["red", "bright-black", "#dc322f"] {
+            assert_eq!(name.parse::<Color>().unwrap().to_string(), name);
+        }
+        assert!("#12345".parse::<Color>().is_err());
+        assert!("purple".parse::<Color>().is_err());
+
+        let color = Color::Rgb(255, 0, 0);
+        assert_eq!(color.sgr(true, true), "48;2;255;0;0");
+        assert_eq!(color.sgr(false, false), "38;5;196");
+        assert_eq!(Color::Ansi(9).sgr(false, false), "91");
+    }
+
+    #[test]
+    fn no_color_palette_has_no_colors() {
+        let config = ThemeConfig {
+            palette: Palette::NoColor,
+            error: Some(Color::Ansi(1)),
+            ..ThemeConfig::default()
+        };
+        let theme = Theme::new(&config, true);
+        assert_eq!(theme.prompt, "");
+        assert_eq!(theme.error, Theme::NO_COLOR_ERROR);
+        assert!(theme.faces.iter().all(String::is_empty));
+    }
+}
@@ -1,371 +1,1454 @@
-use crate::cuboard::{CuboardInputEvent, CuboardKeymap};
-use btleplug::api::{Central, Manager, ScanFilter};
-use btleplug::platform;
+use crate::cuboard::{
+    ComposeTable, CuboardComposer, CuboardKeymap, CuboardModifier, GestureFlash, GyroGesture,
+};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
-use std::fs::File;
-use std::io::{stdout, BufRead, BufReader, Write};
+use std::io::{stdout, Stdout, Write};
 use std::iter::repeat;
-use std::ops::Range;
-use tokio::time::{sleep, Duration};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::time::{interval, Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::bluetooth::gancubev2::{GanCubeV2Builder, ResponseMessage};
+use crate::bluetooth::connect::Connector;
+use crate::bluetooth::gancubev2::{GanCubeV2, ResponseMessage};
+use crate::bluetooth::scan::Scanner;
+use crate::bluetooth::session::GanCubeV2Session;
+use crate::bluetooth::transport::Transport;
+use crate::bluetooth::{connect_cube, open_adapter};
+use crate::cheatsheet::{make_cheatsheet, CheatsheetWidget};
+use crate::config;
+use crate::cube::format_moves;
+use crate::engine::{CuboardEngine, EngineEvent};
 
-use crate::cuboard::{CuboardInput, DEFAULT_KEYMAP};
+use crate::cuboard::CuboardInput;
+use crate::i18n::{tr, Message};
+use crate::idle::IdleMonitor;
+use crate::keymap::Keymap;
+use crate::leaderboard::{self, SessionResult};
+use crate::near::NearSolvedWatch;
+use crate::net::{beside, CubeNet};
+use crate::shutdown::{restore_terminal, Shutdown};
+use crate::signal::SignalMonitor;
+use crate::sink::OutputSink;
+use crate::sound::{Sound, SoundEvent};
+use crate::stats::{self, Checkpoint};
+use crate::terminal::{
+    cells, is_frame_limited, is_plain, repaint_at_frame_rate, styled, terminal_size, text_width,
+    Cell, PlainRenderer, Repaint, Screen, TextRenderer,
+};
+use crate::textgen::{ErrorHeatmap, TrainingText};
+use crate::theme;
+use crate::transcript::TranscriptRecorder;
+use crate::verify::{StateVerifier, VerifyAlert, VerifyEvent};
+use crate::view::{self, InputHandler};
 
-pub async fn cuboard_input_printer() -> Result<(), Box<dyn Error>> {
-    // get the first bluetooth adapter
-    let manager = platform::Manager::new().await.unwrap();
-    let adapters = manager.adapters().await?;
-    let adapter = adapters.into_iter().next().unwrap();
-
-    // start scanning for devices
-    adapter.start_scan(ScanFilter::default()).await?;
-    print!("scan devices");
+pub async fn cuboard_input_printer(
+    keymap: CuboardKeymap,
+    compose_table: Option<ComposeTable>,
+    sinks: Vec<Box<dyn OutputSink>>,
+    transcript: Option<String>,
+    sound: Option<Sound>,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("{}", tr(Message::Connected, &[]));
+    println!();
 
-    let builder = 'a: loop {
-        print!(".");
-        let _ = stdout().flush();
+    println!("{}", make_cheatsheet(&keymap));
+    println!();
 
-        let found = GanCubeV2Builder::find_gancube_device(&adapter).await?;
-        if let Some(builder) = found.into_iter().next() {
-            break 'a builder;
+    let recorder = TranscriptRecorder::new();
+    let signal = SignalMonitor::default();
+    let alert = VerifyAlert::default();
+    let printer = input_printer(
+        keymap,
+        compose_table,
+        sinks,
+        transcript.as_ref().map(|_| recorder.clone()),
+        sound,
+        idle.clone(),
+    )
+    .with_signal(signal.clone())
+    .with_alert(alert.clone());
+    let printer = Arc::new(Mutex::new(printer));
+    let repaint = repaint_at_frame_rate(&printer);
+    let input_handler: Box<dyn FnMut(ResponseMessage) + Send> = Box::new(move |msg| {
+        if let Ok(mut printer) = printer.lock() {
+            printer.handle_message(msg);
         }
+    });
+    let res =
+        handle_until_disconnected(&gancube, input_handler, shutdown, idle, signal, Some(alert))
+            .await;
+    if let Some(repaint) = repaint {
+        repaint.abort();
+    }
+    if let Some(path) = transcript {
+        recorder.save(path)?;
+    }
+    res
+}
 
-        sleep(Duration::from_secs(1)).await;
-    };
+// type with the cube shown by the 3D view, which shows the typed line too
+pub async fn cuboard_input_viewer(
+    keymap: CuboardKeymap,
+    compose_table: Option<ComposeTable>,
+    sinks: Vec<Box<dyn OutputSink>>,
+    transcript: Option<String>,
+    sound: Option<Sound>,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    println!("{}", make_cheatsheet(&keymap));
     println!();
 
-    adapter.stop_scan().await?;
+    let recorder = TranscriptRecorder::new();
+    let mut printer = input_printer(
+        keymap,
+        compose_table,
+        sinks,
+        transcript.as_ref().map(|_| recorder.clone()),
+        sound,
+        idle,
+    );
+    let input: InputHandler = Box::new(move |msg| {
+        printer.handle_message(msg);
+        // the window is drawn at its own frame rate
+        printer.repaint();
+        let engine = &printer.engine;
+        [engine.line(), engine.preedit(), engine.buffered_text()].concat()
+    });
+    shutdown.on_shutdown(restore_terminal);
+    let res = view::input(shutdown, input).await;
+    if let Some(path) = transcript {
+        recorder.save(path)?;
+    }
+    res
+}
 
-    println!("connect to GANCube...");
-    let gancube = builder.connect().await?;
-    println!("connected! have fun~");
-    println!();
+fn input_printer(
+    keymap: CuboardKeymap,
+    compose_table: Option<ComposeTable>,
+    sinks: Vec<Box<dyn OutputSink>>,
+    transcript: Option<TranscriptRecorder>,
+    sound: Option<Sound>,
+    idle: IdleMonitor,
+) -> CuboardInputPrinter<Stdout> {
+    let mut engine = CuboardEngine::new(CuboardInput::new(keymap));
+    if let Some(table) = compose_table {
+        engine = engine.with_composer(CuboardComposer::new(table));
+    }
+    if let Some(recorder) = transcript {
+        engine = engine.with_transcript(recorder);
+    }
+    let mut printer = CuboardInputPrinter::new(stdout(), engine, idle);
+    for sink in sinks {
+        printer = printer.with_sink(sink);
+    }
+    if let Some(sound) = sound {
+        printer = printer.with_sound(sound);
+    }
+    let depth = config::get().input.near_solved;
+    if depth > 0 {
+        printer = printer.with_near_solved(NearSolvedWatch::new(depth));
+    }
+    printer
+}
 
-    let input = CuboardInput::new(DEFAULT_KEYMAP);
-    println!("{}", make_cheatsheet(&DEFAULT_KEYMAP));
+pub async fn cuboard_input_trainer(
+    text: TrainingText,
+    keymap: CuboardKeymap,
+    transcript: Option<String>,
+    sound: Option<Sound>,
+    net: bool,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("{}", tr(Message::Connected, &[]));
     println!();
 
-    let mut printer = CuboardInputPrinter::new(stdout(), input);
-    let input_handler: Box<dyn FnMut(ResponseMessage) + Send> =
-        Box::new(move |msg| printer.handle_message(msg));
-    let handle = gancube.register_handler(input_handler).await?;
-
-    gancube.subscribe_response().await?;
-    gancube.request_cube_state().await?;
-
-    handle.await?;
+    let input = CuboardInput::new(keymap);
+    println!("{}", make_cheatsheet(&keymap));
+    println!();
 
-    Ok(())
+    let mut engine = CuboardEngine::new(input);
+    let recorder = TranscriptRecorder::new();
+    if transcript.is_some() {
+        engine = engine.with_transcript(recorder.clone());
+    }
+    let heatmap = ErrorHeatmap::default();
+    // the drill of chosen keys is for learning their moves
+    let hints = match text {
+        TrainingText::Keys(_) => HintLevel::Always,
+        _ => config::get().trainer.hints,
+    };
+    let text_name = text.name();
+    let resumable = text.resumable();
+    // seek to the given line, counted from 1, or to where the last session stopped
+    let trainer_config = &config::get().trainer;
+    let checkpoint = match (&resumable, trainer_config.start_line) {
+        (_, Some(line)) => Some(Checkpoint {
+            line: line.saturating_sub(1),
+            ..Checkpoint::default()
+        }),
+        (Some(text), None) if trainer_config.resume => stats::load_checkpoint(text)?,
+        _ => None,
+    };
+    let mut text = text.lines(heatmap.clone())?;
+    let checkpoint = checkpoint.unwrap_or_default();
+    for _ in 0..checkpoint.line {
+        text.next();
+    }
+    if config::get().trainer.code {
+        // tabs are typed as spaces
+        text = Box::new(text.map(|line| line.replace('\t', "    ")));
+    }
+    let mut trainer = CuboardInputTrainer::new(
+        stdout(),
+        engine,
+        text,
+        config::get().trainer.margin,
+        heatmap,
+        idle.clone(),
+    );
+    if transcript.is_some() {
+        trainer.transcript = Some(recorder.clone());
+    }
+    trainer.sound.sound = sound;
+    trainer.hints = hints;
+    trainer.correction = config::get().trainer.correction;
+    trainer.line = checkpoint.line;
+    trainer.stats.correct = checkpoint.correct;
+    trainer.stats.typed = checkpoint.typed;
+    trainer.stats.earlier = Duration::from_secs(checkpoint.seconds);
+    if config::get().trainer.code {
+        trainer.code = true;
+        trainer.stats.symbols = Some((0, 0));
+    }
+    if net {
+        trainer.net = Some(CubeNet::new());
+    }
+    let depth = config::get().input.near_solved;
+    if depth > 0 {
+        trainer.near_solved = Some(NearSolvedWatch::new(depth));
+    }
+    let signal = SignalMonitor::default();
+    trainer.signal = signal.clone();
+    let alert = VerifyAlert::default();
+    trainer.alert = alert.clone();
+    let trainer = Arc::new(Mutex::new(trainer));
+    let repaint = repaint_at_frame_rate(&trainer);
+    let session = Arc::clone(&trainer);
+    let input_handler: Box<dyn FnMut(ResponseMessage) + Send> = Box::new(move |msg| {
+        if let Ok(mut trainer) = trainer.lock() {
+            trainer.handle_message(msg);
+        }
+    });
+    let res =
+        handle_until_disconnected(&gancube, input_handler, shutdown, idle, signal, Some(alert))
+            .await;
+    if let Some(repaint) = repaint {
+        repaint.abort();
+    }
+    if let Some(path) = transcript {
+        recorder.save(path)?;
+    }
+    let (result, stopped) = match session.lock() {
+        Ok(trainer) => (
+            trainer.stats.session(&trainer.current),
+            trainer.checkpoint(),
+        ),
+        Err(_) => (None, None),
+    };
+    if let (Some(text), Some(_)) = (&resumable, &result) {
+        stats::save_checkpoint(text, stopped)?;
+    }
+    if let Some(mut result) = result {
+        // the banner of the daily goals at the end of the session, without the resumed sessions
+        let seconds = result.seconds.saturating_sub(checkpoint.seconds);
+        let keys = result.keys.saturating_sub(checkpoint.typed);
+        match stats::record_practice(seconds, keys, result.wpm) {
+            Ok(summary) => println!("{}", summary),
+            Err(err) => println!("{}", tr(Message::Error, &[&err])),
+        }
+        if let Some(path) = &config::get().trainer.results {
+            result.name = config::get().trainer.name.clone().unwrap_or_else(|| {
+                std::env::var("USER").unwrap_or_else(|_| "anonymous".to_string())
+            });
+            result.text = text_name;
+            leaderboard::append(path, &result)?;
+        }
+    }
+    res
 }
 
-pub async fn cuboard_input_trainer(text_filename: String) -> Result<(), Box<dyn Error>> {
-    // get the first bluetooth adapter
-    let manager = platform::Manager::new().await.unwrap();
-    let adapters = manager.adapters().await?;
-    let adapter = adapters.into_iter().next().unwrap();
-
-    // start scanning for devices
-    adapter.start_scan(ScanFilter::default()).await?;
-    print!("scan devices");
-
-    let builder = 'a: loop {
-        print!(".");
-        let _ = stdout().flush();
+pub async fn cuboard_input_chording(
+    keymap: CuboardKeymap,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+) -> Result<(), Box<dyn Error>> {
+    // scan for the GANCubes with the configured bluetooth adapter
+    let adapter = open_adapter().await?;
+    let builders = Scanner::new(shutdown.clone()).scan(&adapter, 2).await?;
 
-        let found = GanCubeV2Builder::find_gancube_device(&adapter).await?;
-        if let Some(builder) = found.into_iter().next() {
-            break 'a builder;
-        }
-
-        sleep(Duration::from_secs(1)).await;
+    println!("{}", tr(Message::ConnectingCubes, &[]));
+    let connector = Connector::new(shutdown.clone());
+    let session = GanCubeV2Session::connect(&builders, &connector).await?;
+    let [typing, modifier] = &session.cubes[..] else {
+        unreachable!();
     };
+    println!("{}", tr(Message::Connected, &[]));
+    println!("{}", tr(Message::ChordingHint, &[]));
     println!();
 
-    adapter.stop_scan().await?;
+    let layer = Arc::new(AtomicUsize::new(0));
+    let layer_modifier = Arc::clone(&layer);
+    let mut modifier_input = CuboardModifier::new();
+    let modifier_handle = modifier
+        .register_handler(Box::new(move |msg| {
+            if let Some(layer) = modifier_input.handle_message(msg) {
+                layer_modifier.store(layer, Ordering::Relaxed);
+            }
+        }))
+        .await?;
+    modifier.subscribe_response().await?;
 
-    println!("connect to GANCube...");
-    let gancube = builder.connect().await?;
-    println!("connected! have fun~");
+    // all tilted orientations shift the keymap
+    let shifted_keymap = [keymap[1], keymap[0]];
+    let input = CuboardInput::new(keymap).with_layers(vec![shifted_keymap; 5]);
+    println!("{}", make_cheatsheet(&keymap));
     println!();
 
-    let input = CuboardInput::new(DEFAULT_KEYMAP);
-    println!("{}", make_cheatsheet(&DEFAULT_KEYMAP));
-    println!();
+    let signal = SignalMonitor::default();
+    let alert = VerifyAlert::default();
+    let printer = CuboardInputPrinter::new(stdout(), CuboardEngine::new(input), idle.clone())
+        .with_signal(signal.clone())
+        .with_alert(alert.clone());
+    let printer = Arc::new(Mutex::new(printer));
+    let repaint = repaint_at_frame_rate(&printer);
+    let input_handler: Box<dyn FnMut(ResponseMessage) + Send> = Box::new(move |msg| {
+        let Ok(mut printer) = printer.lock() else {
+            return;
+        };
+        printer
+            .engine
+            .input
+            .set_layer(layer.load(Ordering::Relaxed));
+        printer.handle_message(msg);
+    });
+    let res = handle_until_disconnected(
+        typing,
+        input_handler,
+        shutdown.clone(),
+        idle,
+        signal,
+        Some(alert),
+    )
+    .await;
+    if let Some(repaint) = repaint {
+        repaint.abort();
+    }
+    res?;
+
+    shutdown.close(modifier).await?;
+    modifier_handle.abort();
+
+    Ok(())
+}
+
+pub(crate) const BATTERY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// a burst of lost notifications requests the cube state once
+const RESYNC_INTERVAL: Duration = Duration::from_secs(1);
 
-    let text = BufReader::new(File::open(text_filename)?)
-        .lines()
-        .map_while(|l| l.ok());
-    let mut trainer = CuboardInputTrainer::new(stdout(), input, text, 3);
-    let input_handler: Box<dyn FnMut(ResponseMessage) + Send> =
-        Box::new(move |msg| trainer.handle_message(msg));
-    let handle = gancube.register_handler(input_handler).await?;
+// request the cube state again whenever a response is lost, so that the move count of the
+// input can be resynchronized, and verify the state of the cube periodically if configured; the
+// alerts of the verifier go to the status line of the renderer if any, or are printed
+pub(crate) async fn handle_until_disconnected<P: Transport>(
+    gancube: &GanCubeV2<P>,
+    mut input_handler: Box<dyn FnMut(ResponseMessage) + Send>,
+    shutdown: Shutdown,
+    idle: IdleMonitor,
+    signal: SignalMonitor,
+    alert: Option<VerifyAlert>,
+) -> Result<(), Box<dyn Error>> {
+    let verify_interval = config::get()
+        .device
+        .verify_interval
+        .filter(|&secs| secs > 0.0)
+        .map(Duration::from_secs_f32);
+    let mut verifier = verify_interval.map(|_| StateVerifier::new());
+    let (verify_sender, mut verify_receiver) = mpsc::unbounded_channel();
+
+    let idle_handler = idle.clone();
+    let input_handler = Box::new(move |msg| {
+        if matches!(msg, ResponseMessage::Moves { .. }) {
+            idle_handler.touch();
+        }
+        if let Some(verifier) = &mut verifier {
+            let desynced = verifier.is_desynced();
+            if let Some(event) = verifier.handle_message(msg) {
+                let _ = verify_sender.send(event);
+            }
+            // gestures resolve the desync instead of going to the input
+            if desynced && matches!(msg, ResponseMessage::Gyroscope { .. }) {
+                return;
+            }
+        }
+        input_handler(msg);
+    });
+    let (error_sender, mut error_receiver) = mpsc::unbounded_channel();
+    let error_handler = Box::new(move |err| {
+        let _ = error_sender.send(err);
+    });
+    let mut handle = gancube
+        .register_handler_with_errors(input_handler, error_handler)
+        .await?;
+    shutdown.on_shutdown(restore_terminal);
 
     gancube.subscribe_response().await?;
     gancube.request_cube_state().await?;
 
-    handle.await?;
+    let mut idle_check = interval(IdleMonitor::CHECK_INTERVAL);
+    let mut battery_check = interval(BATTERY_CHECK_INTERVAL);
+    let mut signal_check = interval(SignalMonitor::CHECK_INTERVAL);
+    let mut verify_check = interval(verify_interval.unwrap_or(BATTERY_CHECK_INTERVAL));
+    // the gyroscope is needed by the gestures resolving the desync
+    let mut gyroscope = None;
+    let mut resynced_at: Option<Instant> = None;
+    loop {
+        tokio::select! {
+            res = &mut handle => break res?,
+            Some(err) = error_receiver.recv() => {
+                let recent = resynced_at.is_some_and(|time| time.elapsed() < RESYNC_INTERVAL);
+                if err.is_lost_response() && !recent {
+                    resynced_at = Some(Instant::now());
+                    gancube.request_cube_state().await?;
+                }
+            }
+            _ = idle_check.tick() => {
+                idle.check(gancube, &shutdown).await?;
+            }
+            // don't keep an idle cube alive, which is decided by the idle policy
+            _ = battery_check.tick(), if idle.idle_time() < BATTERY_CHECK_INTERVAL => {
+                gancube.request_battery_state().await?;
+            }
+            _ = signal_check.tick() => {
+                signal.check(gancube).await?;
+            }
+            _ = verify_check.tick(), if verify_interval.is_some() => {
+                gancube.request_cube_state().await?;
+            }
+            Some(event) = verify_receiver.recv() => {
+                let filter = gancube.message_filter();
+                let resolved = !matches!(event, VerifyEvent::Desync(_));
+                match &event {
+                    VerifyEvent::Desync(_) => {
+                        gyroscope.get_or_insert(filter.is_gyroscope_enabled());
+                        filter.set_gyroscope(true);
+                    }
+                    VerifyEvent::TrustCube => {}
+                    VerifyEvent::ResetCube(state) => {
+                        gancube.reset_cube_state(*state).await?;
+                        gancube.request_cube_state().await?;
+                    }
+                }
+                if let Some(enabled) = gyroscope.filter(|_| resolved) {
+                    filter.set_gyroscope(enabled);
+                    gyroscope = None;
+                }
+                match &alert {
+                    Some(alert) => alert.put(&event, Instant::now().into_std()),
+                    None => {
+                        let style = if resolved { "" } else { &theme::get().error };
+                        print!("\r\n\x1b[2K{}{}\x1b[m\r\n", style, event.describe());
+                        let _ = stdout().flush();
+                    }
+                }
+            }
+            _ = shutdown.wait() => break,
+        }
+    }
+
+    shutdown.close(gancube).await?;
+    handle.abort();
 
     Ok(())
 }
 
-fn make_cheatsheet(keymap: &CuboardKeymap) -> String {
-    const STYLED_TEMPLATE: &str = "
-     \x1b[30;44m  {B.3}  \x1b[m     
-     \x1b[30;44m{B.2}   {B.0}\x1b[m     
-     \x1b[30;44m  {B.1}  \x1b[m     
-     \x1b[30;47m  {U.1}  \x1b[m     
-     \x1b[30;47m{U.0}   {U.2}\x1b[m     
-     \x1b[30;47m  {U.3}  \x1b[m     
-\x1b[30;45m  {L.3}  \x1b[42m  {F.0}  \x1b[41m  {R.2}  \x1b[m
-\x1b[30;45m{L.2}   {L.0}\x1b[42m{F.3}   {F.1}\x1b[41m{R.1}   {R.3}\x1b[m
-\x1b[30;45m  {L.1}  \x1b[42m  {F.2}  \x1b[41m  {R.0}  \x1b[m
-     \x1b[30;43m  {D.2}  \x1b[m     
-     \x1b[30;43m{D.1}   {D.3}\x1b[m     
-     \x1b[30;43m  {D.0}  \x1b[m     
-";
-    const STYLED_TEMPLATE_BAR: &str = "CHEAT SHEET:
-     double     |      single     |     single      |     double
-    clockwise   |     clockwise   |counter-clockwise|counter-clockwise
-----------------|-----------------|-----------------|-----------------
-";
-    use crate::cube::CubeMove::*;
-    let mut a = STYLED_TEMPLATE.to_string();
-    let mut b = STYLED_TEMPLATE.to_string();
-    let mut c = STYLED_TEMPLATE.to_string();
-    let mut d = STYLED_TEMPLATE.to_string();
-
-    for side in [U, D, F, B, L, R] {
-        for i in 0..4 {
-            fn f(s: &str) -> String {
-                s.replace('\n', "↵").replace(' ', "⌴")
-            }
-            let name = format!("{{{}.{}}}", &side.to_string(), i);
-            a = a.replace(&name, &f(keymap[1][side as u8 as usize][i]));
-            b = b.replace(&name, &f(keymap[0][side as u8 as usize][i]));
-            c = c.replace(&name, &f(keymap[0][side.rev() as u8 as usize][i]));
-            d = d.replace(&name, &f(keymap[1][side.rev() as u8 as usize][i]));
-        }
-    }
-
-    let a = a.trim_matches('\n').split('\n');
-    let b = b.trim_matches('\n').split('\n');
-    let c = c.trim_matches('\n').split('\n');
-    let d = d.trim_matches('\n').split('\n');
-    STYLED_TEMPLATE_BAR.to_string()
-        + &a.zip(b)
-            .zip(c)
-            .zip(d)
-            .map(|(((a, b), c), d)| [a, b, c, d].join(" | "))
-            .collect::<Vec<_>>()
-            .join("\n")
-}
-
-struct CuboardInputPrinter<F: Write> {
+pub struct CuboardInputPrinter<F: Write> {
     terminal: F,
-    accepted_text: String,
-    input: CuboardInput,
+    pub engine: CuboardEngine,
+    sinks: Vec<Box<dyn OutputSink>>,
+    // the last failure of the sinks, shown in the status line
+    sink_error: Option<String>,
+    sound: SoundFeedback,
+    renderer: TextRenderer,
+    // replace the renderer if the output is plain
+    plain: Option<PlainRenderer>,
+    cheatsheet: CheatsheetWidget,
+    // nothing was pending before the last events, so that shaking the cube cancels nothing
+    nothing_pending: bool,
+    flash: GestureFlash,
+    idle: IdleMonitor,
+    signal: SignalMonitor,
+    alert: VerifyAlert,
+    // the lines finished since the last frame
+    finished: Vec<String>,
+    // nothing is drawn after the cube is disconnected
+    closed: bool,
+    near_solved: Option<NearSolvedWatch>,
 }
 
 impl<F: Write> CuboardInputPrinter<F> {
-    fn new(terminal: F, input: CuboardInput) -> Self {
+    pub fn new(terminal: F, engine: CuboardEngine, idle: IdleMonitor) -> Self {
         CuboardInputPrinter {
             terminal,
-            accepted_text: String::new(),
-            input,
+            engine,
+            sinks: Vec::new(),
+            sink_error: None,
+            sound: SoundFeedback::default(),
+            renderer: TextRenderer::new(),
+            plain: is_plain().then(PlainRenderer::new),
+            cheatsheet: CheatsheetWidget::new(),
+            nothing_pending: true,
+            flash: GestureFlash::default(),
+            idle,
+            signal: SignalMonitor::default(),
+            alert: VerifyAlert::default(),
+            finished: Vec::new(),
+            closed: false,
+            near_solved: None,
         }
     }
 
-    fn handle_message(&mut self, msg: ResponseMessage) {
+    pub fn with_signal(mut self, signal: SignalMonitor) -> Self {
+        self.signal = signal;
+        self
+    }
+
+    pub fn with_alert(mut self, alert: VerifyAlert) -> Self {
+        self.alert = alert;
+        self
+    }
+
+    pub fn with_sink(mut self, sink: Box<dyn OutputSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn with_sound(mut self, sound: Sound) -> Self {
+        self.sound.sound = Some(sound);
+        self
+    }
+
+    pub fn with_near_solved(mut self, watch: NearSolvedWatch) -> Self {
+        self.near_solved = Some(watch);
+        self
+    }
+
+    fn each_sink(&mut self, mut f: impl FnMut(&mut dyn OutputSink) -> std::io::Result<()>) {
+        for sink in &mut self.sinks {
+            match f(sink.as_mut()) {
+                Ok(()) => {}
+                Err(err) => self.sink_error = Some(format!("{} error: {}", sink.name(), err)),
+            }
+        }
+    }
+
+    // candidates with the selected one highlighted
+    fn candidates_status(&self) -> Option<Vec<Cell>> {
+        let composer = self.engine.composer()?;
+        let candidates = composer.candidates();
+        if candidates.len() <= 1 {
+            return None;
+        }
+        let style = &theme::get().status;
+        let selected = format!("{}\x1b[7m", style);
+        let mut status = Vec::new();
+        for (i, candidate) in candidates.iter().enumerate() {
+            if i > 0 {
+                status.extend(cells(" ", style));
+            }
+            let style = if i == composer.selected() {
+                &selected
+            } else {
+                style
+            };
+            status.extend(cells(&format!("{}:{}", i + 1, candidate), style));
+        }
+        Some(status)
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) {
         if matches!(msg, ResponseMessage::Disconnect) {
+            self.repaint();
+            self.closed = true;
             let _ = writeln!(self.terminal);
             return;
         }
-
-        match self.input.handle_message(msg) {
-            Some(CuboardInputEvent::Uninit) => {
-                return;
-            }
-            Some(CuboardInputEvent::Init) => {
-                let _ = write!(self.terminal, "\r\x1b[7m \x1b[m\n\x1b[100m\x1b[2K\x1b[m");
-                let _ = self.terminal.flush();
-                return;
-            }
-            None => {}
-            Some(CuboardInputEvent::Cancel) => {
-                self.input.cancel();
-            }
-            Some(CuboardInputEvent::Finish(accept))
-            | Some(CuboardInputEvent::Input { accept, skip: _ }) => {
-                self.accepted_text += &accept;
+        if let Some(watch) = &mut self.near_solved {
+            if watch.handle_message(msg) {
+                self.sound.play(SoundEvent::Near);
             }
         }
 
-        let buffered_text = self.input.buffered_text();
-        let _ = write!(
-            self.terminal,
-            "\x1b[A\r\x1b[2K{}\x1b[4m{}\x1b[m\x1b[K\x1b[0;7m \x1b[m\n",
-            self.accepted_text, buffered_text
-        );
+        let events = self.engine.handle_message(msg);
+        self.handle_events(events);
+    }
 
-        if buffered_text.contains('\n') {
-            self.accepted_text += &self.input.finish();
+    // the frame is drawn by `repaint` if the frames are limited
+    pub fn handle_events(&mut self, events: Vec<EngineEvent>) {
+        if !self.engine.is_started() {
+            return;
         }
 
-        if let Some(i) = self.accepted_text.rfind('\n') {
-            self.accepted_text.drain(0..=i);
+        let mut gesture = None;
+        let mut finished = Vec::new();
+        for event in &events {
+            self.each_sink(|sink| sink.event(event));
+            match event {
+                EngineEvent::TextCommitted(text) => self.each_sink(|sink| sink.accept(text)),
+                EngineEvent::LineCompleted(line) => {
+                    self.each_sink(|sink| sink.finish_line(line));
+                    finished.push(line.clone());
+                }
+                EngineEvent::GestureFired(fired) => {
+                    // the line is finished by turning the cube around, but stays on the screen
+                    if *fired == GyroGesture::TurningAround {
+                        let line = self.engine.line().to_string();
+                        self.each_sink(|sink| sink.finish_line(&line));
+                    }
+                    if *fired == GyroGesture::Shaking && self.nothing_pending {
+                        self.cheatsheet.toggle();
+                    }
+                    gesture = Some(*fired);
+                }
+                EngineEvent::Started
+                | EngineEvent::BufferChanged { .. }
+                | EngineEvent::Paused
+                | EngineEvent::Resumed => {}
+            }
+        }
+        self.flash.put(gesture, Instant::now().into_std());
+        self.sound.update(
+            SoundFeedback::gesture(gesture),
+            !finished.is_empty(),
+            self.engine.key_count(),
+            false,
+        );
+        self.nothing_pending = self.engine.input.buffer.moves().is_empty()
+            && self.engine.preedit().is_empty()
+            && self.engine.buffered_text().is_empty();
+        if let Some(plain) = &mut self.plain {
+            let status = self
+                .alert
+                .status(Instant::now().into_std())
+                .or_else(|| self.sink_error.clone());
+            plain.render(&mut self.terminal, &events, self.engine.line(), status);
+            return;
         }
+        self.finished.extend(finished);
+        if !is_frame_limited() {
+            self.repaint();
+        }
+    }
+}
+
+impl<F: Write> Repaint for CuboardInputPrinter<F> {
+    fn repaint(&mut self) {
+        if !self.engine.is_started() || self.closed || self.plain.is_some() {
+            return;
+        }
+
+        let line = styled(self.engine.line(), "")
+            .chain(styled(self.engine.preedit(), &theme::get().preedit))
+            .chain(styled(self.engine.buffered_text(), "\x1b[4m"))
+            .chain([(" ", "\x1b[7m")])
+            .collect::<Vec<_>>();
 
-        show_input_prompt(&mut self.terminal, &self.input, Self::INPUT_PROMPT_WIDTH);
+        let status = self
+            .idle
+            .warning()
+            .or_else(|| self.alert.status(Instant::now().into_std()))
+            .or_else(|| self.signal.warning())
+            .or_else(|| self.sink_error.clone())
+            .or_else(|| grip_status(&self.engine.input))
+            .map(|status| cells(&status, &theme::get().status))
+            .or_else(|| near_solved_status(&self.near_solved))
+            .or_else(|| self.candidates_status());
+        let prompt = prompt_row(
+            &self.engine.input,
+            config::get().input.prompt_width,
+            status,
+            self.flash.get(Instant::now().into_std()),
+        );
+        let width = terminal_size().map_or(80, |(width, _)| width);
+        let panel = self.cheatsheet.rows(&self.engine.input, width);
+        let finished = std::mem::take(&mut self.finished);
+        self.renderer
+            .render(&mut self.terminal, &finished, panel, &line, prompt);
     }
+}
 
-    const INPUT_PROMPT_WIDTH: usize = 12;
+// the moves are ignored until the cube is picked up or held steady
+fn grip_status(input: &CuboardInput) -> Option<String> {
+    let grip = input.grip();
+    if grip.is_paused() {
+        return Some(tr(Message::PausedHint, &[]));
+    }
+    if !grip.is_regripping() {
+        return None;
+    }
+    match grip.facing() {
+        Some(face) => Some(tr(Message::RegrippingFacing, &[&&"URFDLB"[face..face + 1]])),
+        None => Some(tr(Message::Regripping, &[])),
+    }
 }
 
-fn show_input_prompt<F: Write>(terminal: &mut F, input: &CuboardInput, width: usize) {
+// the turns finishing the cube, in inverse video for a moment after the cube got near solved
+fn near_solved_status(watch: &Option<NearSolvedWatch>) -> Option<Vec<Cell>> {
+    let (status, flashed) = watch.as_ref()?.status()?;
+    let style = if flashed {
+        "\x1b[7m"
+    } else {
+        &theme::get().status
+    };
+    Some(cells(&status, style))
+}
+
+// the prompt line showing the moves of the input
+fn prompt_row(
+    input: &CuboardInput,
+    width: usize,
+    status: Option<Vec<Cell>>,
+    flash: Option<GyroGesture>,
+) -> Vec<Cell> {
+    // acknowledge the gesture by flashing the whole line in inverse video; erasing the line
+    // doesn't fill it with inverse video, so fill it by spaces except the last column
+    if let Some(gesture) = flash {
+        let width = terminal_size().map_or(80, |(width, _)| width);
+        let text = format!(
+            " {}{}",
+            gesture.symbol(),
+            " ".repeat(width.saturating_sub(3))
+        );
+        return cells(&text, "\x1b[7m");
+    }
+
     let complete_part = input.complete_part();
     let remain_part = input.remain_part();
 
-    let complete_range = 0..complete_part.len();
-    let remain_range = complete_part.len()..complete_part.len() + remain_part.len();
-    let total = complete_part + &remain_part;
-    let mut visible_range = total.len().saturating_sub(width)..total.len();
-    if visible_range.start > 0 {
-        // remain space for overflow symbol
-        visible_range.start += 1;
-    }
-    let visible_range = visible_range;
+    let complete = complete_part.graphemes(true).collect::<Vec<_>>();
+    let total = [complete.clone(), remain_part.graphemes(true).collect()].concat();
 
-    fn clamp(range1: &Range<usize>, range2: &Range<usize>) -> Range<usize> {
-        range1.start.clamp(range2.start, range2.end)..range1.end.clamp(range2.start, range2.end)
+    // show the tail which fits the width, and remain space for overflow symbol
+    let mut start = 0;
+    if text_width(&complete_part) + text_width(&remain_part) > width {
+        let mut visible_width = 1;
+        start = total.len();
+        while start > 0 && visible_width + total[start - 1].width() <= width {
+            start -= 1;
+            visible_width += total[start].width();
+        }
     }
-    let complete_range = clamp(&complete_range, &visible_range);
-    let remain_range = clamp(&remain_range, &visible_range);
-    let overflow = if visible_range.start > 0 { "…" } else { "" };
+    let split = complete.len().max(start);
+    let overflow = if start > 0 { "…" } else { "" };
 
-    let _ = write!(
-        terminal,
-        "\r\x1b[100m\x1b[2K{}\x1b[4m{}\x1b[2m{}\x1b[m",
-        overflow, &total[complete_range], &total[remain_range],
-    );
-    let _ = terminal.flush();
+    let prompt = &theme::get().prompt;
+    let mut row = cells(overflow, prompt);
+    row.extend(cells(
+        &total[start..split].concat(),
+        &format!("{}\x1b[4m", prompt),
+    ));
+    row.extend(cells(
+        &total[split..].concat(),
+        &format!("{}\x1b[4m\x1b[2m", prompt),
+    ));
+    if let Some(status) = status {
+        row.extend(cells("  ", &theme::get().status));
+        row.extend(status);
+    }
+    row
 }
 
 struct CuboardInputTrainer<F: Write, T: Iterator<Item = String>> {
     terminal: F,
-    accepted_text: String,
-    input: CuboardInput,
+    engine: CuboardEngine,
     textgen: T,
     lines: Box<[String]>,
+    heatmap: ErrorHeatmap,
+    // record the errors, besides the engine recording the input
+    transcript: Option<TranscriptRecorder>,
+    sound: SoundFeedback,
+    stats: TypingStats,
+    flash: GestureFlash,
+    idle: IdleMonitor,
+    signal: SignalMonitor,
+    alert: VerifyAlert,
+    screen: Screen,
+    // the rows finished since the last frame, the line being typed and whether each grapheme
+    // of it is correct
+    above: Vec<Vec<Cell>>,
+    input: String,
+    current: Vec<bool>,
+    // nothing is drawn after the cube is disconnected
+    closed: bool,
+    // write the lines to type and the typed lines as appended lines
+    plain: bool,
+    // shown beside the lines to type
+    net: Option<CubeNet>,
+    // shown under the prompt, so that the cube is not solved by accident while typing
+    near_solved: Option<NearSolvedWatch>,
+    // when to show the moves of the next character to type
+    hints: HintLevel,
+    // what to do with a mistyped grapheme, the number of graphemes of the line already checked,
+    // and when the mistake being held was typed
+    correction: Correction,
+    checked: usize,
+    mistyped_at: Option<Instant>,
+    // type source code, where shaking the cube at the start of a line repeats the indentation
+    // of the previous line
+    code: bool,
+    indent: String,
+    // nothing was pending before the last events, so that shaking the cube cancels nothing
+    nothing_pending: bool,
+    // the index of the line being typed in the text
+    line: usize,
 }
 
 impl<F: Write, T: Iterator<Item = String>> CuboardInputTrainer<F, T> {
-    fn new(terminal: F, input: CuboardInput, mut textgen: T, margin: usize) -> Self {
+    fn new(
+        terminal: F,
+        engine: CuboardEngine,
+        mut textgen: T,
+        margin: usize,
+        heatmap: ErrorHeatmap,
+        idle: IdleMonitor,
+    ) -> Self {
         let lines = (0..margin)
             .map(|_| textgen.next().unwrap_or_default())
             .collect();
         CuboardInputTrainer {
             terminal,
-            accepted_text: String::new(),
-            input,
+            engine,
             textgen,
             lines,
+            heatmap,
+            transcript: None,
+            sound: SoundFeedback::default(),
+            stats: TypingStats::default(),
+            flash: GestureFlash::default(),
+            idle,
+            signal: SignalMonitor::default(),
+            alert: VerifyAlert::default(),
+            screen: Screen::new(),
+            above: Vec::new(),
+            input: String::new(),
+            current: Vec::new(),
+            closed: false,
+            plain: is_plain(),
+            net: None,
+            near_solved: None,
+            hints: HintLevel::Off,
+            correction: Correction::Off,
+            checked: 0,
+            mistyped_at: None,
+            code: false,
+            indent: String::new(),
+            nothing_pending: true,
+            line: 0,
         }
     }
 
+    // show the lines to type when the input is started
+    fn start(&mut self) {
+        self.stats.start = Some(Instant::now());
+        if self.plain {
+            let _ = writeln!(self.terminal, "{}", self.lines[0]);
+            return;
+        }
+        self.draw("", &[], vec![Vec::new()]);
+    }
+
     fn handle_message(&mut self, msg: ResponseMessage) {
         if matches!(msg, ResponseMessage::Disconnect) {
+            self.repaint();
+            self.closed = true;
             let _ = writeln!(self.terminal);
             return;
         }
+        if let ResponseMessage::Battery {
+            charging,
+            percentage,
+        } = msg
+        {
+            self.stats.battery = Some((percentage, charging));
+        }
+        if let Some(net) = &mut self.net {
+            net.handle_message(msg);
+        }
+        if let Some(watch) = &mut self.near_solved {
+            if watch.handle_message(msg) {
+                self.sound.play(SoundEvent::Near);
+            }
+        }
 
-        match self.input.handle_message(msg) {
-            Some(CuboardInputEvent::Uninit) => {
-                return;
+        let events = self.engine.handle_message(msg);
+        if !self.engine.is_started() {
+            return;
+        }
+        let mut gesture = None;
+        let mut completed = Vec::new();
+        for event in events {
+            match event {
+                EngineEvent::Started => {
+                    self.start();
+                    return;
+                }
+                EngineEvent::LineCompleted(line) => completed.push(line),
+                EngineEvent::GestureFired(fired) => gesture = Some(fired),
+                // the time the cube is set down is not counted
+                EngineEvent::Paused => self.stats.pause(),
+                EngineEvent::Resumed => self.stats.resume(),
+                EngineEvent::TextCommitted(_) | EngineEvent::BufferChanged { .. } => {}
             }
-            Some(CuboardInputEvent::Init) => {
-                let cursor = self.lines[0].chars().next().unwrap_or(' ');
-                let _ = write!(self.terminal, "\x1b[2m{}\x1b[m", self.lines[0]);
-                let _ = write!(self.terminal, "\r\x1b[7m{}\x1b[m\n", cursor);
-                for line in self.lines.iter().skip(1) {
-                    let _ = writeln!(self.terminal, "\x1b[2m{}\x1b[m", line);
+        }
+        self.flash.put(gesture, Instant::now().into_std());
+        let nothing_pending = std::mem::replace(
+            &mut self.nothing_pending,
+            self.engine.input.buffer.moves().is_empty()
+                && self.engine.preedit().is_empty()
+                && self.engine.buffered_text().is_empty(),
+        );
+        let indented = self.code
+            && gesture == Some(GyroGesture::Shaking)
+            && nothing_pending
+            && completed.is_empty()
+            && self.engine.line().is_empty();
+        if indented {
+            let indent = self.indent.clone();
+            self.engine.type_text(&indent);
+        }
+        // the shaking for the indentation erases no mistake
+        self.hold_mistake(gesture.filter(|_| !indented), completed.len());
+
+        let mut text = completed
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        text += self.engine.line();
+        text += self.engine.buffered_text();
+        let inputs = text.split('\n').collect::<Vec<_>>();
+        let (&last_line, finished_lines) = inputs.split_last().unwrap();
+        let finished = finished_lines.len();
+
+        // record errors of finished lines
+        for (input, expect) in finished_lines.iter().zip(self.lines.iter()) {
+            for (a, b) in input.graphemes(true).zip(expect.graphemes(true)) {
+                self.stats.count(b, a == b);
+                for c in b.chars() {
+                    self.heatmap.record(c, a == b);
+                }
+                match &self.transcript {
+                    Some(transcript) if a != b => transcript.error(b, a),
+                    _ => {}
                 }
-                let _ = write!(self.terminal, "\r\x1b[100m\x1b[2K \x1b[m\r");
-                let _ = self.terminal.flush();
-                return;
             }
-            None => {}
-            Some(CuboardInputEvent::Cancel) => {
-                self.input.cancel();
+        }
+        let current = last_line
+            .graphemes(true)
+            .zip(self.lines[finished].graphemes(true))
+            .map(|(a, b)| a == b)
+            .collect::<Vec<_>>();
+        let mistyped = current.last() == Some(&false);
+        self.sound.update(
+            SoundFeedback::gesture(gesture),
+            finished > 0,
+            self.engine.key_count(),
+            mistyped,
+        );
+
+        if let Some(line) = finished_lines.last() {
+            let indent = line.len() - line.trim_start().len();
+            self.indent = line[..indent].to_string();
+        }
+
+        // the finished lines scroll away
+        self.above.extend(
+            finished_lines
+                .iter()
+                .zip(self.lines.iter())
+                .map(|(input, expect)| typed_row(input, expect, false)),
+        );
+        self.line += finished;
+        for _ in 0..finished {
+            let new_line = self.textgen.next().unwrap_or_default();
+            self.lines.rotate_left(1);
+            *self.lines.last_mut().unwrap() = new_line;
+        }
+        if self.plain {
+            // the typed lines under the lines to type, with the stats and the next line to type
+            for input in finished_lines {
+                let _ = writeln!(self.terminal, "> {}", input);
             }
-            Some(CuboardInputEvent::Finish(accept))
-            | Some(CuboardInputEvent::Input { accept, skip: _ }) => {
-                self.accepted_text += &accept;
+            if finished > 0 {
+                let status = self.stats.status(&[]);
+                let _ = writeln!(self.terminal, "[{}]", status);
+                let _ = writeln!(self.terminal, "{}", self.lines[0]);
             }
+            return;
+        }
+        self.input = last_line.to_string();
+        self.current = current;
+        if !is_frame_limited() {
+            self.repaint();
         }
+    }
 
-        let _ = write!(self.terminal, "\x1b[{}A", self.lines.len());
-        for line in self.lines.iter() {
-            let _ = writeln!(self.terminal, "\r\x1b[2m\x1b[2K{}\x1b[m", line);
-        }
-
-        let buffered_text = self.input.buffered_text();
-        let text = self.accepted_text.clone() + &buffered_text;
-        let decorated_texts = text
-            .split('\n')
-            .zip(self.lines.iter().chain(repeat(&String::new())))
-            .map(|(input, expect)| {
-                input
-                    .chars()
-                    .zip(expect.chars().chain(repeat(' ')))
-                    .map(|(a, b)| {
-                        if a == b {
-                            format!("{}", a)
-                        } else {
-                            format!("\x1b[41m{}\x1b[m", a)
-                        }
-                    })
-                    .collect::<String>()
-            })
-            .collect::<Vec<_>>();
+    // the start of the line being typed, or nothing if the text is finished
+    fn checkpoint(&self) -> Option<Checkpoint> {
+        if self.lines.iter().all(String::is_empty) {
+            return None;
+        }
+        Some(Checkpoint {
+            line: self.line,
+            correct: self.stats.correct,
+            typed: self.stats.typed,
+            seconds: self.stats.elapsed().as_secs(),
+        })
+    }
 
-        let _ = write!(self.terminal, "\x1b[{}A", self.lines.len());
-        for decorated_text in decorated_texts[..decorated_texts.len() - 1].iter() {
-            let _ = write!(self.terminal, "\r{}\n", decorated_text);
-        }
-        let last_decorated_text = decorated_texts.last().unwrap();
-        let char_on_cursor = self.lines[decorated_texts.len() - 1]
-            .chars()
-            .nth(text.split('\n').last().unwrap().len())
-            .unwrap_or(' ');
-        let _ = write!(
-            self.terminal,
-            "\r{}\x1b[7m{}\x1b[m\n",
-            last_decorated_text, char_on_cursor
+    // keep a mistake at the end of the line until it is erased by shaking the cube, or skipped
+    // after a while, so that nothing can be typed after it
+    fn hold_mistake(&mut self, gesture: Option<GyroGesture>, finished: usize) {
+        if finished > 0 {
+            self.checked = 0;
+            self.mistyped_at = None;
+        }
+        let skip_after = match self.correction {
+            Correction::Off => return,
+            Correction::Block => None,
+            Correction::Skip => Some(config::get().trainer.skip_after),
+        };
+        let expected = self.lines.get(finished).map_or("", String::as_str);
+        let line = self.engine.line();
+        let Some((index, start, end)) = first_mistake(line, expected, self.checked) else {
+            self.checked = line.graphemes(true).count();
+            self.mistyped_at = None;
+            return;
+        };
+        let mistyped_at = *self.mistyped_at.get_or_insert_with(Instant::now);
+
+        if gesture == Some(GyroGesture::Shaking) {
+            // the erased mistake is not in the finished line, so it is counted now
+            let mistake = line[start..end].to_string();
+            let expect = expected.graphemes(true).nth(index).unwrap_or_default();
+            self.stats.count(expect, false);
+            for c in expect.chars() {
+                self.heatmap.record(c, false);
+            }
+            if let Some(transcript) = &self.transcript {
+                transcript.error(expect, &mistake);
+            }
+            self.engine.truncate_line(start);
+            self.mistyped_at = None;
+            return;
+        }
+        match skip_after {
+            // the skipped mistake is left in the line, and counted when the line is finished
+            Some(secs) if mistyped_at.elapsed().as_secs_f32() >= secs => {
+                self.checked = index + 1;
+                self.mistyped_at = None;
+            }
+            _ => self.engine.truncate_line(end),
+        }
+    }
+
+    // the moves of the next character to type, such as `a: RF`
+    fn key_hint(&self) -> Option<String> {
+        let next = self.lines[0].graphemes(true).nth(self.current.len())?;
+        let c = next.chars().next()?;
+        if !self.hints.shows(self.heatmap.error_rate(c)) {
+            return None;
+        }
+        let moves = Keymap::new(self.engine.input.keymap).key_moves(next)?;
+        let next = next.replace(' ', "⌴");
+        Some(format!("{}: {}", next, format_moves(&moves)))
+    }
+
+    // the lines to type, the first of which shows the input on it, and the rows under them
+    fn draw(&mut self, input: &str, above: &[Vec<Cell>], bottom: Vec<Vec<Cell>>) {
+        let mut rows = vec![typed_row(input, &self.lines[0], true)];
+        rows.extend(self.lines.iter().skip(1).map(|line| cells(line, "\x1b[2m")));
+        if let Some(net) = &self.net {
+            let width = terminal_size().map_or(80, |(width, _)| width);
+            rows = beside(rows, net.rows(), width);
+        }
+        rows.extend(bottom);
+        self.screen.draw(&mut self.terminal, above, rows);
+    }
+}
+
+impl<F: Write, T: Iterator<Item = String>> Repaint for CuboardInputTrainer<F, T> {
+    fn repaint(&mut self) {
+        if !self.engine.is_started() || self.closed || self.plain {
+            return;
+        }
+
+        let mut status = self.stats.status(&self.current);
+        if let Some(signal) = self.signal.status() {
+            status = format!("{}  {}", status, signal);
+        }
+        if let Some(warning) = self.idle.warning() {
+            status = format!("{}  {}", status, warning);
+        }
+        if let Some(alert) = self.alert.status(Instant::now().into_std()) {
+            status = format!("{}  {}", status, alert);
+        }
+        if let Some(grip) = grip_status(&self.engine.input) {
+            status = format!("{}  {}", status, grip);
+        }
+        if self.mistyped_at.is_some() {
+            status = format!("{}  {}", status, tr(Message::MistakeHint, &[]));
+        }
+        let prompt = prompt_row(
+            &self.engine.input,
+            config::get().input.prompt_width,
+            Some(cells(&status, &theme::get().status)),
+            self.flash.get(Instant::now().into_std()),
         );
-        let _ = write!(
-            self.terminal,
-            "\x1b[{}B\r",
-            self.lines.len() - decorated_texts.len()
+        let mut bottom = vec![prompt];
+        // the hint is under the prompt, where the eyes are while typing
+        if let Some(hint) = self.key_hint() {
+            bottom.push(cells(&hint, &theme::get().status));
+        }
+        bottom.extend(near_solved_status(&self.near_solved));
+        let above = std::mem::take(&mut self.above);
+        let input = self.input.clone();
+        self.draw(&input, &above, bottom);
+    }
+}
+
+// the typed text over the expected line, with the mistyped graphemes colored, followed by the
+// rest of the expected line dimmed, and by the cursor if asked
+fn typed_row(input: &str, expect: &str, cursor: bool) -> Vec<Cell> {
+    let error_color = &theme::get().error;
+    let mut row = Vec::new();
+    let expected = expect.graphemes(true).collect::<Vec<_>>();
+    let mut count = 0;
+    for (a, &b) in input
+        .graphemes(true)
+        .zip(expected.iter().chain(repeat(&" ")))
+    {
+        // keep the following graphemes aligned with the expected text
+        let padding = " ".repeat(b.width().saturating_sub(a.width()));
+        let style = if a == b { "" } else { error_color.as_str() };
+        row.push((format!("{}{}", a, padding), style.to_string()));
+        count += 1;
+    }
+    let mut rest = expected.iter().skip(count);
+    if cursor {
+        let on_cursor = rest.next().unwrap_or(&" ");
+        row.push((on_cursor.to_string(), "\x1b[7m".to_string()));
+    }
+    row.extend(rest.map(|g| (g.to_string(), "\x1b[2m".to_string())));
+    row
+}
+
+// the index and the byte range of the first mistyped grapheme of the line after the checked
+// graphemes
+fn first_mistake(line: &str, expected: &str, checked: usize) -> Option<(usize, usize, usize)> {
+    let expected = expected.graphemes(true).map(Some).chain(repeat(None));
+    line.grapheme_indices(true)
+        .zip(expected)
+        .enumerate()
+        .skip(checked)
+        .find(|(_, ((_, a), b))| Some(*a) != *b)
+        .map(|(index, ((start, a), _))| (index, start, start + a.len()))
+}
+
+// what the trainer does with a mistyped grapheme
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Correction {
+    // leave it in the line
+    #[default]
+    Off,
+    // block the input after it until it is erased by shaking the cube
+    Block,
+    // block the input after it for a while, then leave it in the line
+    Skip,
+}
+
+impl FromStr for Correction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Correction::Off),
+            "block" => Ok(Correction::Block),
+            "skip" => Ok(Correction::Skip),
+            _ => Err(format!("unknown correction: {}", s)),
+        }
+    }
+}
+
+// when the trainer shows the moves of the next character under the prompt
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HintLevel {
+    Off,
+    // only for the characters still often mistyped, so that the hints fade out with practice
+    #[default]
+    Fading,
+    Always,
+}
+
+impl HintLevel {
+    // the smoothed error rate under which the hints fade out, about ten correct inputs in a row
+    const FADING_RATE: f32 = 0.1;
+
+    fn shows(self, error_rate: f32) -> bool {
+        match self {
+            HintLevel::Off => false,
+            HintLevel::Fading => error_rate > Self::FADING_RATE,
+            HintLevel::Always => true,
+        }
+    }
+}
+
+impl FromStr for HintLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(HintLevel::Off),
+            "fading" => Ok(HintLevel::Fading),
+            "always" => Ok(HintLevel::Always),
+            _ => Err(format!("unknown hint level: {}", s)),
+        }
+    }
+}
+
+// play sounds for the changes of the input
+#[derive(Default)]
+struct SoundFeedback {
+    sound: Option<Sound>,
+    key_count: usize,
+}
+
+impl SoundFeedback {
+    fn gesture(gesture: Option<GyroGesture>) -> Option<SoundEvent> {
+        match gesture {
+            Some(GyroGesture::TurningAround) => Some(SoundEvent::Finish),
+            Some(GyroGesture::Shaking) => Some(SoundEvent::Cancel),
+            None => None,
+        }
+    }
+
+    fn play(&self, event: SoundEvent) {
+        if let Some(sound) = &self.sound {
+            sound.play(event);
+        }
+    }
+
+    fn update(
+        &mut self,
+        gesture: Option<SoundEvent>,
+        line_finished: bool,
+        key_count: usize,
+        mistyped: bool,
+    ) {
+        let typed = key_count > self.key_count;
+        self.key_count = key_count;
+        let Some(sound) = &self.sound else {
+            return;
+        };
+
+        let event = match gesture {
+            Some(event) => event,
+            None if line_finished => SoundEvent::Finish,
+            None if typed && mistyped => SoundEvent::Error,
+            None if typed => SoundEvent::Key,
+            None => return,
+        };
+        sound.play(event);
+    }
+}
+
+// typing performance of the trainer since the session started
+#[derive(Default)]
+struct TypingStats {
+    start: Option<Instant>,
+    correct: usize,
+    typed: usize,
+    battery: Option<(u32, bool)>,
+    // the correct and the typed brackets and symbols of the finished lines, if counted
+    symbols: Option<(usize, usize)>,
+    // the time of the resumed sessions, and when the cube is set down
+    earlier: Duration,
+    paused_at: Option<Instant>,
+}
+
+impl TypingStats {
+    fn count(&mut self, expected: &str, correct: bool) {
+        self.typed += 1;
+        if correct {
+            self.correct += 1;
+        }
+        let is_symbol = expected.chars().all(|c| c.is_ascii_punctuation());
+        if let Some((symbols_correct, symbols_typed)) = &mut self.symbols {
+            if is_symbol {
+                *symbols_typed += 1;
+                *symbols_correct += correct as usize;
+            }
+        }
+    }
+
+    fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(Instant::now);
+    }
+
+    fn resume(&mut self) {
+        if let (Some(start), Some(paused_at)) = (&mut self.start, self.paused_at.take()) {
+            *start += paused_at.elapsed();
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        let Some(start) = self.start else {
+            return self.earlier;
+        };
+        let now = self.paused_at.unwrap_or_else(Instant::now);
+        self.earlier + now.duration_since(start)
+    }
+
+    // the speed of the correct graphemes
+    fn wpm(correct: usize, elapsed: Duration) -> f32 {
+        // a word is 5 characters by convention
+        let minutes = elapsed.as_secs_f32() / 60.0;
+        if minutes > 0.0 {
+            correct as f32 / 5.0 / minutes
+        } else {
+            0.0
+        }
+    }
+
+    fn accuracy(correct: usize, typed: usize) -> f32 {
+        if typed > 0 {
+            correct as f32 / typed as f32 * 100.0
+        } else {
+            100.0
+        }
+    }
+
+    // the result of the session without the name of the user and of the text, if it is started
+    fn session(&self, current: &[bool]) -> Option<SessionResult> {
+        self.start?;
+        let elapsed = self.elapsed();
+        let correct = self.correct + current.iter().filter(|&&c| c).count();
+        let typed = self.typed + current.len();
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Some(SessionResult {
+            date,
+            seconds: elapsed.as_secs(),
+            keys: typed,
+            wpm: Self::wpm(correct, elapsed),
+            accuracy: Self::accuracy(correct, typed),
+            ..SessionResult::default()
+        })
+    }
+
+    // include the unfinished line, given whether each grapheme is correct
+    fn status(&self, current: &[bool]) -> String {
+        let elapsed = self.elapsed();
+        let correct = self.correct + current.iter().filter(|&&c| c).count();
+        let typed = self.typed + current.len();
+        let wpm = Self::wpm(correct, elapsed);
+        let accuracy = Self::accuracy(correct, typed);
+        let secs = elapsed.as_secs();
+        let mut status = format!(
+            "{:.0} wpm  {:.0}%  {:02}:{:02}",
+            wpm,
+            accuracy,
+            secs / 60,
+            secs % 60
         );
+        if let Some((correct, typed)) = self.symbols {
+            status += &format!("  sym {:.0}%", Self::accuracy(correct, typed));
+        }
+        if let Some((percentage, charging)) = self.battery {
+            let charging = if charging { "+" } else { "" };
+            status += &format!("  bat {}%{}", percentage, charging);
+        }
+        status
+    }
+}
 
-        for _ in 0..decorated_texts.len() - 1 {
-            let new_line = self.textgen.next().unwrap_or_default();
-            let _ = write!(
-                self.terminal,
-                "\r\x1b[m\x1b[2K\r\x1b[2m{}\x1b[m\n",
-                new_line
-            );
-            self.lines.rotate_left(1);
-            *self.lines.last_mut().unwrap() = new_line;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_fade_out_with_practice() {
+        let heatmap = ErrorHeatmap::default();
+        assert!(HintLevel::Fading.shows(heatmap.error_rate('a')));
+        for _ in 0..9 {
+            heatmap.record('a', true);
         }
+        assert!(!HintLevel::Fading.shows(heatmap.error_rate('a')));
+        assert!(HintLevel::Always.shows(heatmap.error_rate('a')));
+        heatmap.record('a', false);
+        assert!(HintLevel::Fading.shows(heatmap.error_rate('a')));
+        assert!(!HintLevel::Off.shows(heatmap.error_rate('b')));
+    }
 
-        if let Some(i) = self.accepted_text.rfind('\n') {
-            self.accepted_text.drain(0..=i);
+    #[test]
+    fn symbols_are_scored_apart() {
+        let mut stats = TypingStats {
+            symbols: Some((0, 0)),
+            ..TypingStats::default()
+        };
+        for (expected, correct) in [("f", true), ("(", true), (")", false), ("{", true)] {
+            stats.count(expected, correct);
         }
+        assert_eq!((stats.correct, stats.typed), (3, 4));
+        assert_eq!(stats.symbols, Some((2, 3)));
+        assert!(stats.status(&[]).contains("sym 67%"));
+    }
 
-        show_input_prompt(&mut self.terminal, &self.input, Self::INPUT_PROMPT_WIDTH);
+    #[test]
+    fn paused_time_is_not_counted() {
+        let start = Instant::now() - Duration::from_secs(10);
+        let mut stats = TypingStats {
+            start: Some(start),
+            earlier: Duration::from_secs(60),
+            paused_at: Some(start + Duration::from_secs(4)),
+            ..TypingStats::default()
+        };
+        assert_eq!(stats.elapsed(), Duration::from_secs(64));
+        stats.resume();
+        assert!(stats.paused_at.is_none());
+        assert!(stats.elapsed() < Duration::from_secs(65));
+        assert!(stats.status(&[]).contains("01:04"));
     }
 
-    const INPUT_PROMPT_WIDTH: usize = 12;
+    #[test]
+    fn mistakes_are_found_after_the_checked_graphemes() {
+        assert_eq!(first_mistake("the", "the fox", 0), None);
+        assert_eq!(first_mistake("thx fo", "the fox", 0), Some((2, 2, 3)));
+        assert_eq!(first_mistake("thx fo", "the fox", 3), None);
+        assert_eq!(first_mistake("thx fi", "the fox", 3), Some((5, 5, 6)));
+        // typed past the end of the line
+        assert_eq!(first_mistake("héy", "hé", 0), Some((2, 3, 4)));
+    }
 }
@@ -0,0 +1,215 @@
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct KeyEntry {
+    pub time_ms: u64,
+    // the buffered text after the key is typed
+    pub buffered: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Gesture {
+    Finish,
+    Cancel,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct GestureEntry {
+    pub time_ms: u64,
+    pub gesture: Gesture,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ErrorEntry {
+    pub time_ms: u64,
+    pub expected: String,
+    pub typed: String,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Transcript {
+    // unix time in seconds
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub text: String,
+    pub keys: Vec<KeyEntry>,
+    pub gestures: Vec<GestureEntry>,
+    pub errors: Vec<ErrorEntry>,
+}
+
+impl Transcript {
+    pub fn dump(&self) -> String {
+        fn secs(time_ms: u64) -> f32 {
+            time_ms as f32 / 1000.0
+        }
+
+        let mut res = String::new();
+        let _ = writeln!(
+            res,
+            "# session started at {}, lasted {:.1}s",
+            self.started_at,
+            secs(self.duration_ms)
+        );
+        res += &self.text;
+        res += "\n# keys\n";
+        for key in &self.keys {
+            let _ = writeln!(res, "{:>9.3} {:?}", secs(key.time_ms), key.buffered);
+        }
+        res += "# gestures\n";
+        for gesture in &self.gestures {
+            let _ = writeln!(res, "{:>9.3} {:?}", secs(gesture.time_ms), gesture.gesture);
+        }
+        res += "# errors\n";
+        for error in &self.errors {
+            let _ = writeln!(
+                res,
+                "{:>9.3} {:?} -> {:?}",
+                secs(error.time_ms),
+                error.expected,
+                error.typed
+            );
+        }
+        res
+    }
+}
+
+// records a session, shared between the input handler and the command, which saves it at the end
+#[derive(Clone)]
+pub struct TranscriptRecorder {
+    start: Instant,
+    transcript: Arc<Mutex<Transcript>>,
+}
+
+impl TranscriptRecorder {
+    pub fn new() -> Self {
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        TranscriptRecorder {
+            start: Instant::now(),
+            transcript: Arc::new(Mutex::new(Transcript {
+                started_at,
+                ..Transcript::default()
+            })),
+        }
+    }
+
+    fn time_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Transcript, u64)) {
+        let time_ms = self.time_ms();
+        if let Ok(mut transcript) = self.transcript.lock() {
+            f(&mut transcript, time_ms);
+        }
+    }
+
+    pub fn accept(&self, text: &str) {
+        self.update(|transcript, _| transcript.text += text);
+    }
+
+    // record only changes of the buffered text
+    pub fn buffer(&self, buffered: &str) {
+        self.update(|transcript, time_ms| {
+            let last = transcript.keys.last().map_or("", |key| &key.buffered);
+            if last != buffered {
+                transcript.keys.push(KeyEntry {
+                    time_ms,
+                    buffered: buffered.to_string(),
+                });
+            }
+        });
+    }
+
+    pub fn gesture(&self, gesture: Gesture) {
+        self.update(|transcript, time_ms| {
+            transcript.gestures.push(GestureEntry { time_ms, gesture });
+        });
+    }
+
+    pub fn error(&self, expected: &str, typed: &str) {
+        self.update(|transcript, time_ms| {
+            transcript.errors.push(ErrorEntry {
+                time_ms,
+                expected: expected.to_string(),
+                typed: typed.to_string(),
+            });
+        });
+    }
+
+    // write the transcript as json and as plain text, next to each other
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut transcript = self
+            .transcript
+            .lock()
+            .map_or_else(|err| err.into_inner().clone(), |t| t.clone());
+        transcript.duration_ms = self.time_ms();
+
+        let path = path.as_ref();
+        fs::write(
+            path.with_extension("json"),
+            serde_json::to_string_pretty(&transcript)?,
+        )?;
+        fs::write(path.with_extension("txt"), transcript.dump())?;
+        Ok(())
+    }
+}
+
+impl Default for TranscriptRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_is_saved_as_json_and_plain_text() {
+        let path = std::env::temp_dir().join(format!("cuboard-transcript-{}", std::process::id()));
+        let recorder = TranscriptRecorder::new();
+        recorder.buffer("h");
+        recorder.buffer("h");
+        recorder.buffer("hi");
+        recorder.error("i", "o");
+        recorder.accept("hi");
+        recorder.gesture(Gesture::Finish);
+        recorder.save(&path).unwrap();
+
+        let json = fs::read_to_string(path.with_extension("json")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["text"], "hi");
+        let keys = json["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1]["buffered"], "hi");
+        assert_eq!(json["gestures"][0]["gesture"], "finish");
+        assert_eq!(json["errors"][0]["expected"], "i");
+        assert_eq!(json["errors"][0]["typed"], "o");
+
+        let text = fs::read_to_string(path.with_extension("txt")).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with("# session started at "));
+        assert_eq!(lines[1], "hi");
+        assert_eq!(lines[2], "# keys");
+        assert!(lines[3].ends_with(" \"h\"") && lines[4].ends_with(" \"hi\""));
+        assert_eq!(lines[5], "# gestures");
+        assert!(lines[6].ends_with(" Finish"));
+        assert_eq!(lines[7], "# errors");
+        assert!(lines[8].ends_with(" \"i\" -> \"o\""));
+        assert_eq!(lines.len(), 9);
+
+        let _ = fs::remove_file(path.with_extension("json"));
+        let _ = fs::remove_file(path.with_extension("txt"));
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bluetooth::{gancubev2::ResponseMessage, sequencer::MoveSequencer},
+    cube::{CubeDiff, CubeState},
+    cuboard::{GyroGesture, GyroGestureRecognizer, BUFFER_SIZE},
+    i18n::{tr, Message},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyEvent {
+    // the pieces differing, as `position: tracked/reported`
+    Desync(CubeDiff),
+    // the state reported by the cube is taken as the tracked state
+    TrustCube,
+    // the state of the cube should be reset to the tracked state
+    ResetCube(CubeState),
+}
+
+impl VerifyEvent {
+    // the alert shown for the event
+    pub fn describe(&self) -> String {
+        match self {
+            VerifyEvent::Desync(diff) => tr(Message::StateDesync, &[diff]),
+            VerifyEvent::TrustCube => tr(Message::StateTrusted, &[]),
+            VerifyEvent::ResetCube(_) => tr(Message::StateReset, &[]),
+        }
+    }
+}
+
+// the last alert of the verifier, shown in the status line of the renderer rather than printed
+// over its frame; a desync is shown until resolved, and the resolution for a moment
+#[derive(Clone, Default)]
+pub struct VerifyAlert {
+    // the alert, and when it was resolved
+    alert: Arc<Mutex<Option<Alert>>>,
+}
+
+type Alert = (String, Option<Instant>);
+
+impl VerifyAlert {
+    const RESOLVED_TIME: Duration = Duration::from_secs(3);
+
+    pub fn put(&self, event: &VerifyEvent, now: Instant) {
+        let resolved = match event {
+            VerifyEvent::Desync(_) => None,
+            _ => Some(now),
+        };
+        if let Ok(mut alert) = self.alert.lock() {
+            *alert = Some((event.describe(), resolved));
+        }
+    }
+
+    // shown in the status line
+    pub fn status(&self, now: Instant) -> Option<String> {
+        let alert = self.alert.lock().ok()?;
+        match alert.as_ref()? {
+            (_, Some(resolved)) if now.duration_since(*resolved) >= Self::RESOLVED_TIME => None,
+            (alert, _) => Some(alert.clone()),
+        }
+    }
+}
+
+// compare the state tracked from the moves with the state reported by the cube, which drift
+// apart when notifications are lost or the cube resets itself; while they differ, turning the
+// cube around trusts the cube, and shaking it resets the cube to the tracked state
+pub struct StateVerifier {
+    state: Option<CubeState>,
+    sequencer: MoveSequencer,
+    // the state of the cube while it differs, following the moves since reported
+    reported: Option<CubeState>,
+    recognizer: GyroGestureRecognizer<BUFFER_SIZE>,
+}
+
+impl StateVerifier {
+    pub fn new() -> Self {
+        StateVerifier {
+            state: None,
+            sequencer: MoveSequencer::default(),
+            reported: None,
+            recognizer: GyroGestureRecognizer::new(),
+        }
+    }
+
+    pub fn is_desynced(&self) -> bool {
+        self.reported.is_some()
+    }
+
+    pub fn handle_message(&mut self, msg: ResponseMessage) -> Option<VerifyEvent> {
+        // the reported state only synchronizes the count when the states are compared
+        if !matches!(msg, ResponseMessage::State { .. }) {
+            for (mv, _) in self.sequencer.handle_message(msg, Instant::now()).moves {
+                for state in [&mut self.state, &mut self.reported].into_iter().flatten() {
+                    state.apply(mv);
+                }
+            }
+        }
+
+        match msg {
+            ResponseMessage::State {
+                count,
+                state: Some(mut reported),
+            } => {
+                reported.reset_centers();
+                let tracked = match (self.state, self.sequencer.count()) {
+                    // moves may be on the way
+                    (Some(_), Some(prev_count)) if prev_count != count => return None,
+                    (Some(tracked), Some(_)) => tracked,
+                    _ => {
+                        self.state = Some(reported);
+                        self.sequencer.reset(count);
+                        return None;
+                    }
+                };
+                let diff = tracked.diff(&reported);
+                if diff.is_empty() {
+                    self.reported = None;
+                    return None;
+                }
+                // alert once until it is resolved or changed
+                let alerted = self.reported == Some(reported);
+                self.reported = Some(reported);
+                (!alerted).then_some(VerifyEvent::Desync(diff))
+            }
+            ResponseMessage::Gyroscope { .. } if self.is_desynced() => {
+                match self.recognizer.handle_message(msg)? {
+                    GyroGesture::TurningAround => {
+                        self.state = self.reported.take();
+                        Some(VerifyEvent::TrustCube)
+                    }
+                    GyroGesture::Shaking => {
+                        // the count may be reset with the state, so take the next reported one
+                        self.reported = None;
+                        self.sequencer = MoveSequencer::default();
+                        Some(VerifyEvent::ResetCube(self.state?))
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bluetooth::mock::MoveWindow, cube::CubeMove};
+
+    #[test]
+    fn desync_is_alerted_once() {
+        let state = |count, state| ResponseMessage::State {
+            count,
+            state: Some(state),
+        };
+        let mut verifier = StateVerifier::new();
+        assert_eq!(
+            verifier.handle_message(state(0, CubeState::default())),
+            None
+        );
+
+        // the move R is applied to the tracked state, but the cube doesn't know it
+        let msg = MoveWindow::default().turn(CubeMove::R);
+        assert_eq!(verifier.handle_message(msg), None);
+        assert_eq!(
+            verifier.handle_message(state(0, CubeState::default())),
+            None
+        );
+        assert!(!verifier.is_desynced());
+
+        let Some(VerifyEvent::Desync(diff)) =
+            verifier.handle_message(state(1, CubeState::default()))
+        else {
+            panic!("desync is not alerted");
+        };
+        assert_eq!(diff.counts(), (8, 0));
+        assert!(diff.to_string().contains("UR: FR/UR"));
+        assert!(verifier.is_desynced());
+        assert_eq!(
+            verifier.handle_message(state(1, CubeState::default())),
+            None
+        );
+
+        // resolved by the cube itself
+        let r = CubeState::of_move(CubeMove::R);
+        assert_eq!(verifier.handle_message(state(1, r)), None);
+        assert!(!verifier.is_desynced());
+    }
+
+    #[test]
+    fn resolved_alerts_are_shown_for_a_moment() {
+        let now = Instant::now();
+        let alert = VerifyAlert::default();
+        assert_eq!(alert.status(now), None);
+        let diff = CubeState::default().diff(&CubeState::of_move(CubeMove::R));
+        alert.put(&VerifyEvent::Desync(diff), now);
+        let later = now + Duration::from_secs(10);
+        assert!(alert.status(later).unwrap().contains("differs"));
+
+        alert.put(&VerifyEvent::TrustCube, now);
+        assert_eq!(
+            alert.status(now).as_deref(),
+            Some("the cube state is trusted")
+        );
+        assert_eq!(alert.status(later), None);
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    f32::consts::PI,
+    time::{Duration, Instant},
+};
+
+// an animation of a value, such as the face turned or the gesture recognized, started again
+// whenever it happens
+pub struct Timeline<T> {
+    duration: Duration,
+    started: Option<(T, Instant)>,
+}
+
+impl<T: Copy> Timeline<T> {
+    pub const fn new(duration: Duration) -> Self {
+        Timeline {
+            duration,
+            started: None,
+        }
+    }
+
+    pub fn start(&mut self, value: T, now: Instant) {
+        self.started = Some((value, now));
+    }
+
+    // the value with the progress from 0 to 1, until the animation is over
+    pub fn get(&self, now: Instant) -> Option<(T, f32)> {
+        let (value, start) = self.started?;
+        let elapsed = now.saturating_duration_since(start);
+        (elapsed < self.duration)
+            .then(|| (value, elapsed.as_secs_f32() / self.duration.as_secs_f32()))
+    }
+}
+
+// fast at first and slow at the end
+pub fn ease_out(t: f32) -> f32 {
+    1.0 - (1.0 - t).powi(3)
+}
+
+// rises to 1 in the middle and falls back to 0
+pub fn pulse(t: f32) -> f32 {
+    (PI * t).sin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timelines_end_after_the_duration() {
+        let start = Instant::now();
+        let mut timeline = Timeline::new(Duration::from_millis(200));
+        assert_eq!(timeline.get(start), None);
+
+        timeline.start('R', start);
+        assert_eq!(timeline.get(start), Some(('R', 0.0)));
+        assert_eq!(
+            timeline.get(start + Duration::from_millis(50)),
+            Some(('R', 0.25))
+        );
+        assert_eq!(timeline.get(start + Duration::from_millis(200)), None);
+
+        assert_eq!(ease_out(0.0), 0.0);
+        assert_eq!(ease_out(1.0), 1.0);
+        assert!(pulse(0.5) > pulse(0.25));
+    }
+}
@@ -1,2 +1,51 @@
+use std::error::Error;
+
+use crate::{bluetooth::gancubev2::ResponseMessage, cube::FaceTurn, shutdown::Shutdown};
+
+#[cfg(feature = "view")]
+pub mod animation;
+// the options of the view are parsed even without it
+pub mod options;
+#[cfg(feature = "view")]
+pub mod recording;
+#[cfg(feature = "view")]
+pub mod snapshot;
+#[cfg(feature = "view")]
 pub mod virtualcuboard;
-pub mod window;
\ No newline at end of file
+#[cfg(feature = "view")]
+pub mod window;
+
+#[cfg_attr(feature = "view", allow(dead_code))]
+#[derive(Debug, thiserror::Error)]
+pub enum ViewError {
+    #[error("built without the 3D view, enable the feature `view`")]
+    Unsupported,
+}
+
+// the face turned next by the guide, such as the scramble assistant
+pub type GuideHandler = Box<dyn FnMut(ResponseMessage) -> Option<FaceTurn> + Send>;
+
+// the text typed so far, such as the line of the input printer
+pub type InputHandler = Box<dyn FnMut(ResponseMessage) -> String + Send>;
+
+// show the cube with the face to turn given by the guide
+#[cfg(feature = "view")]
+pub async fn guide(shutdown: Shutdown, guide: GuideHandler) -> Result<(), Box<dyn Error>> {
+    window::run(shutdown, None, Some(guide), None, None).await
+}
+
+#[cfg(not(feature = "view"))]
+pub async fn guide(_shutdown: Shutdown, _guide: GuideHandler) -> Result<(), Box<dyn Error>> {
+    Err(ViewError::Unsupported.into())
+}
+
+// show the cube with the text typed by it
+#[cfg(feature = "view")]
+pub async fn input(shutdown: Shutdown, input: InputHandler) -> Result<(), Box<dyn Error>> {
+    window::run(shutdown, None, None, None, Some(input)).await
+}
+
+#[cfg(not(feature = "view"))]
+pub async fn input(_shutdown: Shutdown, _input: InputHandler) -> Result<(), Box<dyn Error>> {
+    Err(ViewError::Unsupported.into())
+}
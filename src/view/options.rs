@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::theme::Color;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CameraMode {
+    // look at the cube from a fixed point, which can be rotated by dragging
+    #[default]
+    Fixed,
+    // keep the same face of the cube toward the camera
+    OrbitFollow,
+    // move by arrow keys and look around by dragging
+    FreeFly,
+}
+
+// switched by the keys of the view
+#[cfg_attr(not(feature = "view"), allow(dead_code))]
+impl CameraMode {
+    pub fn next(&self) -> Self {
+        match self {
+            CameraMode::Fixed => CameraMode::OrbitFollow,
+            CameraMode::OrbitFollow => CameraMode::FreeFly,
+            CameraMode::FreeFly => CameraMode::Fixed,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraMode::Fixed => "fixed",
+            CameraMode::OrbitFollow => "orbit-follow",
+            CameraMode::FreeFly => "free-fly",
+        }
+    }
+}
+
+impl FromStr for CameraMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(CameraMode::Fixed),
+            "orbit-follow" => Ok(CameraMode::OrbitFollow),
+            "free-fly" => Ok(CameraMode::FreeFly),
+            _ => Err(format!("unknown camera mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FacePalette {
+    // the colors of gancube
+    #[default]
+    Gan,
+    // distinguishable without red and green, from the Okabe-Ito palette
+    Deuteranopia,
+    // fully saturated colors far apart in brightness
+    HighContrast,
+    // the colors given by the config
+    Custom,
+}
+
+impl FromStr for FacePalette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gan" => Ok(FacePalette::Gan),
+            "deuteranopia" => Ok(FacePalette::Deuteranopia),
+            "high-contrast" => Ok(FacePalette::HighContrast),
+            "custom" => Ok(FacePalette::Custom),
+            _ => Err(format!("unknown face palette: {}", s)),
+        }
+    }
+}
+
+impl FacePalette {
+    // colors of U, R, F, D, L, B, the custom colors are used only by the custom palette
+    pub fn colors(&self, custom: &[Color; 6]) -> [Color; 6] {
+        let rgb = |hex: &str| hex.parse::<Color>().unwrap();
+        match self {
+            FacePalette::Gan => [
+                "#ffffff", "#ff0000", "#00ff00", "#ffff00", "#ff00ff", "#0000ff",
+            ],
+            FacePalette::Deuteranopia => [
+                "#ffffff", "#d55e00", "#56b4e9", "#f0e442", "#cc79a7", "#0072b2",
+            ],
+            FacePalette::HighContrast => [
+                "#ffffff", "#ff0000", "#00c000", "#ffd700", "#ff8000", "#0030ff",
+            ],
+            FacePalette::Custom => return *custom,
+        }
+        .map(rgb)
+    }
+}
@@ -0,0 +1,201 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use kiss3d::event::Key;
+use serde::{Deserialize, Serialize};
+
+use crate::bluetooth::gancubev2::ResponseMessage;
+
+// a gyroscope message with the time since the recording started
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GyroFrame {
+    pub time_ms: u64,
+    pub q1: (f32, f32, f32, f32),
+    pub q1p: (f32, f32, f32),
+    pub q2: (f32, f32, f32, f32),
+    pub q2p: (f32, f32, f32),
+}
+
+impl GyroFrame {
+    pub fn message(&self) -> ResponseMessage {
+        ResponseMessage::Gyroscope {
+            q1: self.q1,
+            q1p: self.q1p,
+            q2: self.q2,
+            q2p: self.q2p,
+        }
+    }
+}
+
+pub struct GyroRecorder {
+    start: Instant,
+    frames: Vec<GyroFrame>,
+}
+
+impl GyroRecorder {
+    pub fn new() -> Self {
+        GyroRecorder {
+            start: Instant::now(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn put(&mut self, msg: ResponseMessage) {
+        let ResponseMessage::Gyroscope { q1, q1p, q2, q2p } = msg else {
+            return;
+        };
+        self.frames.push(GyroFrame {
+            time_ms: self.start.elapsed().as_millis() as u64,
+            q1,
+            q1p,
+            q2,
+            q2p,
+        });
+    }
+
+    // one json object per line
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        for frame in &self.frames {
+            writeln!(file, "{}", serde_json::to_string(frame)?)?;
+        }
+        file.flush()
+    }
+}
+
+impl Default for GyroRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn load_recording(path: impl AsRef<Path>) -> io::Result<Vec<GyroFrame>> {
+    let mut frames = Vec::new();
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            frames.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(frames)
+}
+
+// replay the recorded frames in the pace of the recording
+pub struct Playback {
+    frames: Vec<GyroFrame>,
+    // index of the next frame to play
+    index: usize,
+    position: Duration,
+    speed: f32,
+    paused: bool,
+}
+
+impl Playback {
+    const SEEK_STEP: Duration = Duration::from_secs(1);
+    const MIN_SPEED: f32 = 1.0 / 8.0;
+    const MAX_SPEED: f32 = 8.0;
+
+    pub fn new(frames: Vec<GyroFrame>) -> Self {
+        Playback {
+            frames,
+            index: 0,
+            position: Duration::ZERO,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.frames
+            .last()
+            .map_or(Duration::ZERO, |frame| Duration::from_millis(frame.time_ms))
+    }
+
+    // the messages of the frames passed during the elapsed time
+    pub fn advance(&mut self, elapsed: Duration) -> Vec<ResponseMessage> {
+        if !self.paused {
+            self.position = (self.position + elapsed.mul_f32(self.speed)).min(self.duration());
+        }
+        let mut res = Vec::new();
+        while let Some(frame) = self.frames.get(self.index) {
+            if Duration::from_millis(frame.time_ms) > self.position {
+                break;
+            }
+            res.push(frame.message());
+            self.index += 1;
+        }
+        res
+    }
+
+    // jump to the position without playing the frames in between
+    pub fn seek(&mut self, position: Duration) {
+        self.position = position.min(self.duration());
+        self.index = self
+            .frames
+            .partition_point(|frame| Duration::from_millis(frame.time_ms) < self.position);
+    }
+
+    // space to pause, `[` and `]` to change the speed, `,` and `.` to scrub, and home to restart
+    pub fn handle_key(&mut self, key: Key) {
+        match key {
+            Key::Space => self.paused = !self.paused,
+            Key::LBracket => self.speed = (self.speed / 2.0).max(Self::MIN_SPEED),
+            Key::RBracket => self.speed = (self.speed * 2.0).min(Self::MAX_SPEED),
+            Key::Comma => self.seek(self.position.saturating_sub(Self::SEEK_STEP)),
+            Key::Period => self.seek(self.position + Self::SEEK_STEP),
+            Key::Home => self.seek(Duration::ZERO),
+            _ => {}
+        }
+    }
+
+    pub fn status(&self) -> String {
+        let paused = if self.paused { "  paused" } else { "" };
+        format!(
+            "{:.1}s / {:.1}s  x{}{}",
+            self.position.as_secs_f32(),
+            self.duration().as_secs_f32(),
+            self.speed,
+            paused
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(times: &[u64]) -> Vec<GyroFrame> {
+        times
+            .iter()
+            .map(|&time_ms| GyroFrame {
+                time_ms,
+                q1: (1.0, 0.0, 0.0, 0.0),
+                q1p: (0.0, 0.0, 0.0),
+                q2: (1.0, 0.0, 0.0, 0.0),
+                q2p: (0.0, 0.0, 0.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn playback_follows_speed_and_seeking() {
+        let mut playback = Playback::new(frames(&[0, 100, 200, 300, 400]));
+        assert_eq!(playback.advance(Duration::from_millis(150)).len(), 2);
+
+        playback.handle_key(Key::RBracket);
+        assert_eq!(playback.advance(Duration::from_millis(100)).len(), 2);
+
+        playback.handle_key(Key::Space);
+        assert_eq!(playback.advance(Duration::from_secs(1)).len(), 0);
+
+        playback.seek(Duration::from_millis(150));
+        playback.handle_key(Key::Space);
+        assert_eq!(playback.advance(Duration::ZERO).len(), 0);
+        assert_eq!(playback.advance(Duration::from_secs(10)).len(), 3);
+        assert_eq!(playback.status(), "0.4s / 0.4s  x2");
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::{self, FilterType},
+    Delay, DynamicImage, Frame, ImageError, RgbImage,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("image error: {0}")]
+    Image(#[from] ImageError),
+}
+
+// a new file in the working directory named by the time, such as `cuboard-1700000000.png`
+pub fn snapshot_path(extension: &str) -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    PathBuf::from(format!("cuboard-{}.{}", secs, extension))
+}
+
+pub fn save_png(image: &RgbImage) -> Result<PathBuf, SnapshotError> {
+    let path = snapshot_path("png");
+    image.save(&path)?;
+    Ok(path)
+}
+
+// the frames of the window at a lower rate and in half size, which keeps the gif small enough to
+// attach to a bug report
+pub struct GifRecorder {
+    frames: Vec<RgbImage>,
+    last: Option<Instant>,
+}
+
+impl GifRecorder {
+    const INTERVAL: Duration = Duration::from_millis(100);
+    // stop after 15 seconds
+    const MAX_FRAMES: usize = 150;
+
+    pub fn new() -> Self {
+        GifRecorder {
+            frames: Vec::new(),
+            last: None,
+        }
+    }
+
+    // take a frame by `snap` if it is time to, and tell if the recording is full
+    pub fn put(&mut self, now: Instant, snap: impl FnOnce() -> RgbImage) -> bool {
+        if self
+            .last
+            .is_none_or(|last| now.duration_since(last) >= Self::INTERVAL)
+        {
+            let frame = snap();
+            let (width, height) = ((frame.width() / 2).max(1), (frame.height() / 2).max(1));
+            self.frames.push(imageops::resize(
+                &frame,
+                width,
+                height,
+                FilterType::Triangle,
+            ));
+            self.last = Some(now);
+        }
+        self.frames.len() >= Self::MAX_FRAMES
+    }
+
+    pub fn encode<W: Write>(self, writer: W) -> Result<(), SnapshotError> {
+        const SPEED: i32 = 10;
+        let delay = Delay::from_numer_denom_ms(Self::INTERVAL.as_millis() as u32, 1);
+        let mut encoder = GifEncoder::new_with_speed(writer, SPEED);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let frames = self.frames.into_iter().map(|frame| {
+            let frame = DynamicImage::ImageRgb8(frame).into_rgba8();
+            Frame::from_parts(frame, 0, 0, delay)
+        });
+        encoder.encode_frames(frames)?;
+        Ok(())
+    }
+
+    pub fn save(self) -> Result<PathBuf, SnapshotError> {
+        let path = snapshot_path("gif");
+        self.encode(BufWriter::new(File::create(&path)?))?;
+        Ok(path)
+    }
+}
+
+impl Default for GifRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_taken_at_the_interval() {
+        let start = Instant::now();
+        let mut recorder = GifRecorder::new();
+        let snap = || RgbImage::new(8, 6);
+        for ms in [0, 50, 100, 120, 250] {
+            assert!(!recorder.put(start + Duration::from_millis(ms), snap));
+        }
+        assert_eq!(recorder.frames.len(), 3);
+        assert_eq!(recorder.frames[0].dimensions(), (4, 3));
+
+        let mut gif = Vec::new();
+        recorder.encode(&mut gif).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+    }
+}
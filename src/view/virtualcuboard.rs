@@ -4,42 +4,83 @@ use std::{
     cell::RefCell,
     f32::consts::PI,
     ops::{Mul, Neg},
+    path::PathBuf,
     rc::Rc,
+    time::Instant,
 };
 
 use kiss3d::{
-    camera::ArcBall,
+    camera::{ArcBall, Camera, FirstPerson},
+    event::{Action, Key, WindowEvent},
     light::Light,
-    nalgebra::{Point3, Quaternion, UnitQuaternion, Vector3},
+    nalgebra::{Point2, Point3, Quaternion, Translation2, Unit, UnitQuaternion, Vector2, Vector3},
     resource::Mesh,
-    scene::SceneNode,
+    scene::{PlanarSceneNode, SceneNode},
     window::Window,
 };
 use palette::{rgb::Rgb, Hsv, IntoColor};
 
+use super::{
+    options::CameraMode,
+    snapshot::{save_png, GifRecorder, SnapshotError},
+};
+use crate::{
+    config,
+    i18n::{tr, Message},
+    net::{net_cell, NET_COLUMNS, NET_ROWS},
+    theme::Color,
+};
+
 type Array3D<T, const I: usize, const J: usize, const K: usize> = [[[T; K]; J]; I];
 type VirtualCuboardMeshes =
     Array3D<Rc<RefCell<Mesh>>, /*face*/ 6, /*row*/ 3, /*column*/ 3>;
 type VirtualCuboardNodes = Array3D<SceneNode, /*face*/ 6, /*row*/ 3, /*column*/ 3>;
+// the facelets in the order of `CubeState::facelets`
+type VirtualNetNodes = [PlanarSceneNode; 54];
 
+// faces B, L, U, F, R, D in the world frame of `CubeOrientationFrame`
 fn uvw_to_xyz(f: usize, u: f32, v: f32, w: f32) -> Point3<f32> {
     match f {
-        0 => Point3::new(w, u, v),
-        1 => Point3::new(-v, -u, -w),
-        2 => Point3::new(v, w, u),
-        3 => Point3::new(-w, -v, -u),
-        4 => Point3::new(u, v, w),
-        5 => Point3::new(-u, -w, -v),
+        0 => Point3::new(v, u, -w),
+        1 => Point3::new(-w, -u, v),
+        2 => Point3::new(u, w, -v),
+        3 => Point3::new(-u, -v, w),
+        4 => Point3::new(w, v, -u),
+        5 => Point3::new(-v, -w, u),
         _ => panic!(),
     }
 }
 
+// the node of the facelet in the order of faces U, R, F, D, L, B, as given by
+// `CubeState::facelets`, of the cube held white up and green front
+pub fn facelet_node(facelet: usize) -> (usize, usize, usize) {
+    // U, R, F, D, L, B of the nodes
+    const FACE_INDICES: [usize; 6] = [2, 4, 3, 5, 1, 0];
+    let row = (facelet % 9 / 3) as f32 - 1.0;
+    let col = (facelet % 3) as f32 - 1.0;
+    let position = match facelet / 9 {
+        0 => Point3::new(col, 1.0, row),
+        1 => Point3::new(1.0, -row, -col),
+        2 => Point3::new(col, -row, 1.0),
+        3 => Point3::new(col, -1.0, -row),
+        4 => Point3::new(-1.0, -row, col),
+        5 => Point3::new(-col, -row, -1.0),
+        _ => panic!(),
+    };
+    let f = FACE_INDICES[facelet / 9];
+    let (r, c) = (0..9)
+        .map(|i| (i / 3, i % 3))
+        .find(|&(r, c)| uvw_to_xyz(f, r as f32 - 1.0, c as f32 - 1.0, 1.0) == position)
+        .unwrap();
+    (f, r, c)
+}
+
 const CENTERS: [Vector3<f32>; 6] = [
-    Vector3::new(1.0, 0.0, 0.0),
     Vector3::new(0.0, 0.0, -1.0),
-    Vector3::new(0.0, 1.0, 0.0),
     Vector3::new(-1.0, 0.0, 0.0),
+    Vector3::new(0.0, 1.0, 0.0),
     Vector3::new(0.0, 0.0, 1.0),
+    Vector3::new(1.0, 0.0, 0.0),
     Vector3::new(0.0, -1.0, 0.0),
 ];
 
@@ -98,80 +139,282 @@ pub fn set_face_visible(nodes: &mut VirtualCuboardNodes, visible: [bool; 6]) {
     }
 }
 
+// turn the stickers of a face about its axis by the angle, counterclockwise as seen from outside,
+// and put the others back
+pub fn set_face_turned(nodes: &mut VirtualCuboardNodes, turned: Option<(usize, f32)>) {
+    for (f, face) in nodes.iter_mut().enumerate() {
+        let rotation = match turned {
+            Some((turned, angle)) if turned == f => {
+                let axis = uvw_to_xyz(f, 0.0, 0.0, 1.0).coords;
+                UnitQuaternion::from_axis_angle(&Unit::new_normalize(axis), angle)
+            }
+            _ => UnitQuaternion::identity(),
+        };
+        for node in face.iter_mut().flatten() {
+            node.set_local_rotation(rotation);
+        }
+    }
+}
+
+// the channels of the color, dimmed by the value
+fn channels(color: Color, value: f32) -> (f32, f32, f32) {
+    let (red, green, blue) = color.rgb();
+    let channel = |c: u8| c as f32 / 255.0 * value;
+    (channel(red), channel(green), channel(blue))
+}
+
+// show only the facelets, colored by the faces U, R, F, D, L, B they show
+pub fn set_facelets_visible(
+    nodes: &mut VirtualCuboardNodes,
+    facelets: &[(usize, usize)],
+    colors: [Color; 6],
+) {
+    set_face_visible(nodes, [false; 6]);
+    for &(facelet, face) in facelets {
+        let (f, r, c) = facelet_node(facelet);
+        let (red, green, blue) = channels(colors[face], 1.0);
+        let node = &mut nodes[f][r][c];
+        node.set_color(red, green, blue);
+        node.set_visible(true);
+    }
+}
+
+// the facelets unfolded into a net in the middle of the window, in pixels
+pub fn add_net(window: &mut Window) -> VirtualNetNodes {
+    const SIZE: f32 = 30.0;
+    const STEP: f32 = 33.0;
+    core::array::from_fn(|facelet| {
+        let (row, column) = net_cell(facelet);
+        let x = (column as f32 - (NET_COLUMNS - 1) as f32 / 2.0) * STEP;
+        let y = ((NET_ROWS - 1) as f32 / 2.0 - row as f32) * STEP;
+        let mut node = window.add_rectangle(SIZE, SIZE);
+        node.set_local_translation(Translation2::new(x, y));
+        node.set_visible(false);
+        node
+    })
+}
+
+// color the net by the facelets of a state, such as `UUUUUUUUURRRRRRRRR...`
+pub fn set_net_colors(nodes: &mut VirtualNetNodes, facelets: &str, colors: [Color; 6]) {
+    for (node, facelet) in nodes.iter_mut().zip(facelets.chars()) {
+        let face = "URFDLB".find(facelet).unwrap_or_default();
+        let (red, green, blue) = channels(colors[face], 1.0);
+        node.set_color(red, green, blue);
+    }
+}
+
+// draw the facelets as outlines, so that the stickers under them show through
+pub fn set_facelets_ghost(nodes: &mut VirtualCuboardNodes, ghost: bool) {
+    const LINES_WIDTH: f32 = 4.0;
+    for node in nodes.iter_mut().flatten().flatten() {
+        node.set_surface_rendering_activation(!ghost);
+        node.set_lines_width(if ghost { LINES_WIDTH } else { 0.0 });
+    }
+}
+
 pub struct VirtualCuboard {
     pub window: Window,
     pub node: SceneNode,
     pub components: VirtualCuboardNodes,
     pub components_raise: VirtualCuboardNodes,
     pub camera: ArcBall,
+    pub fly_camera: FirstPerson,
+    pub camera_mode: CameraMode,
+    // the net shown instead of the cube
+    pub net: VirtualNetNodes,
+    pub flat: bool,
+    // label the stickers by the pieces they belong to
+    pub labels: bool,
+    // the window being recorded into a gif
+    gif: Option<GifRecorder>,
+    // keys released since the last frame
+    pub released_keys: Vec<Key>,
 }
 
 impl VirtualCuboard {
-    const INIT_EYE: Vector3<f32> = Vector3::new(-1.0, 1.0, -1.0);
+    const INIT_EYE: Vector3<f32> = Vector3::new(-1.0, 1.0, 1.0);
+    const CAMERA_KEY: Key = Key::C;
+    const FLAT_KEY: Key = Key::N;
+    const LABELS_KEY: Key = Key::P;
+    const SNAPSHOT_KEY: Key = Key::S;
+    const GIF_KEY: Key = Key::G;
+    const RADIUS: f32 = 0.2;
+    const GAP: f32 = 0.02;
+    const RAISE: f32 = 0.1;
 
     pub fn new() -> Self {
-        const RADIUS: f32 = 0.2;
-        const GAP: f32 = 0.02;
-        const RAISE: f32 = 0.1;
         let mut window = Window::new("cube");
         let mut node = window.add_group();
 
-        let meshes = make_meshes(RADIUS, GAP, 0.0);
+        let meshes = make_meshes(Self::RADIUS, Self::GAP, 0.0);
+        let view = &config::get().view;
+        let colors = view.palette.colors(&view.custom);
         let mut components = add_meshes(&meshes, &mut node);
-        set_colors_gan(&mut components, 1.0);
+        set_colors_gan(&mut components, colors, 1.0);
 
-        let meshes_raise = make_meshes(RADIUS, GAP, RAISE);
+        let meshes_raise = make_meshes(Self::RADIUS, Self::GAP, Self::RAISE);
         let mut components_raise = add_meshes(&meshes_raise, &mut node);
-        set_colors_gan(&mut components_raise, 0.7);
+        set_colors_gan(&mut components_raise, colors, 0.7);
         set_face_visible(&mut components_raise, [false; 6]);
+        let net = add_net(&mut window);
 
-        let eye = Point3::new(Self::INIT_EYE.x, Self::INIT_EYE.y, Self::INIT_EYE.z);
+        let eye = Point3::from(Self::INIT_EYE);
         let camera = ArcBall::new(eye, Point3::default());
+        let fly_camera = FirstPerson::new(eye, Point3::default());
         VirtualCuboard {
             window,
             node,
             components,
             components_raise,
             camera,
+            fly_camera,
+            camera_mode: view.camera,
+            net,
+            flat: false,
+            labels: false,
+            gif: None,
+            released_keys: Vec::new(),
         }
     }
 
     pub fn render_loop<F: FnMut(&mut Self)>(&mut self, mut f: F) {
         self.window.set_light(Light::StickToCamera);
         self.camera.rebind_drag_button(None);
+        self.set_camera_mode(self.camera_mode);
+
+        loop {
+            self.released_keys.clear();
+            for event in self.window.events().iter() {
+                if let WindowEvent::Key(key, Action::Release, _) = event.value {
+                    self.released_keys.push(key);
+                }
+            }
+            if self.released_keys.contains(&Self::CAMERA_KEY) {
+                self.set_camera_mode(self.camera_mode.next());
+            }
+            if self.released_keys.contains(&Self::FLAT_KEY) {
+                self.set_flat(!self.flat);
+            }
+            if self.released_keys.contains(&Self::LABELS_KEY) {
+                self.labels = !self.labels;
+            }
 
-        while self.window.render_with_camera(&mut self.camera) {
+            let camera: &mut dyn Camera = match self.camera_mode {
+                CameraMode::Fixed | CameraMode::OrbitFollow => &mut self.camera,
+                CameraMode::FreeFly => &mut self.fly_camera,
+            };
+            if !self.window.render_with_camera(camera) {
+                break;
+            }
+            self.capture();
             f(self)
         }
     }
 
+    // save the frame just rendered as a png, or start and stop recording it into a gif
+    fn capture(&mut self) {
+        fn report(res: Result<PathBuf, SnapshotError>) {
+            match res {
+                Ok(path) => println!("{}", tr(Message::RecordedTo, &[&path.display()])),
+                Err(err) => println!("{}", tr(Message::Error, &[&err])),
+            }
+        }
+
+        if self.released_keys.contains(&Self::SNAPSHOT_KEY) {
+            report(save_png(&self.window.snap_image()));
+        }
+        if self.released_keys.contains(&Self::GIF_KEY) {
+            match self.gif.take() {
+                Some(gif) => report(gif.save()),
+                None => self.gif = Some(GifRecorder::new()),
+            }
+        }
+        if let Some(gif) = &mut self.gif {
+            if gif.put(Instant::now(), || self.window.snap_image()) {
+                report(self.gif.take().unwrap().save());
+            }
+        }
+    }
+
+    // start the camera from the initial eye, and show the mode in the title
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        self.camera_mode = mode;
+        let eye = Point3::from(Self::INIT_EYE);
+        self.camera.look_at(eye, Point3::default());
+        self.fly_camera.look_at(eye, Point3::default());
+        let title = format!(
+            "cube (camera: {}, press C to switch, N for the net, P for the pieces, S or G to capture)",
+            mode.name()
+        );
+        self.window.set_title(&title);
+    }
+
+    // show the net instead of the cube, which is colored by `set_net_colors`
+    pub fn set_flat(&mut self, flat: bool) {
+        self.flat = flat;
+        self.node.set_visible(!flat);
+        for node in &mut self.net {
+            node.set_visible(flat);
+        }
+    }
+
+    // the facelets facing the camera, with their centers in the pixels of the window taken by
+    // `draw_text`, whose y axis points down
+    pub fn facing_facelets(&self) -> Vec<(usize, Point2<f32>)> {
+        let camera: &dyn Camera = match self.camera_mode {
+            CameraMode::Fixed | CameraMode::OrbitFollow => &self.camera,
+            CameraMode::FreeFly => &self.fly_camera,
+        };
+        let eye = camera.eye();
+        let rotation = self.node.data().local_rotation();
+        let size = Vector2::new(self.window.width() as f32, self.window.height() as f32);
+        let step = (Self::RADIUS * 2.0 + Self::GAP) / 3.0;
+        let width = (Self::RADIUS * 2.0 - Self::GAP * 2.0) / 3.0;
+        (0..54)
+            .filter_map(|facelet| {
+                let (f, r, c) = facelet_node(facelet);
+                let u = -Self::RADIUS + r as f32 * step + width / 2.0;
+                let v = -Self::RADIUS + c as f32 * step + width / 2.0;
+                let center = rotation * uvw_to_xyz(f, u, v, Self::RADIUS);
+                let normal = rotation * uvw_to_xyz(f, 0.0, 0.0, 1.0).coords;
+                if normal.dot(&(eye - center)) <= 0.0 {
+                    return None;
+                }
+                let position = camera.project(&center, &size);
+                Some((facelet, Point2::new(position.x, size.y - position.y)))
+            })
+            .collect()
+    }
+
     pub fn set_orientation(&mut self, orientation: UnitQuaternion<f32>) {
         self.node.set_local_rotation(orientation);
+        if self.camera_mode == CameraMode::OrbitFollow {
+            // keep the distance, which can be changed by scrolling
+            let eye = orientation * Self::INIT_EYE.normalize() * self.camera.dist();
+            self.camera.look_at(Point3::from(eye), Point3::default());
+        }
     }
 }
 
-// set colors by gancube
-pub fn set_colors_gan(nodes: &mut VirtualCuboardNodes, value: f32) {
-    let colors: [Rgb; 6] = [
-        Hsv::new(240.0, 1.0, value).into_color(),
-        Hsv::new(300.0, 1.0, value).into_color(),
-        Hsv::new(000.0, 0.0, value).into_color(),
-        Hsv::new(120.0, 1.0, value).into_color(),
-        Hsv::new(000.0, 1.0, value).into_color(),
-        Hsv::new(060.0, 1.0, value).into_color(),
-    ];
-    for f in 0..6 {
+// set colors of faces U, R, F, D, L, B, dimmed by the value
+#[allow(clippy::needless_range_loop)]
+pub fn set_colors_gan(nodes: &mut VirtualCuboardNodes, colors: [Color; 6], value: f32) {
+    // U, R, F, D, L, B of the nodes
+    const FACE_INDICES: [usize; 6] = [2, 4, 3, 5, 1, 0];
+    for (i, color) in colors.into_iter().enumerate() {
+        let f = FACE_INDICES[i];
+        let (red, green, blue) = channels(color, value);
         for r in 0..3 {
             for c in 0..3 {
                 let node = &mut nodes[f][r][c];
-                let color = colors[f];
-                node.set_color(color.red, color.green, color.blue);
+                node.set_color(red, green, blue);
             }
         }
     }
 }
 
 // set colors by hue colormap
+#[allow(clippy::needless_range_loop)]
 pub fn set_colors_hue(nodes: &mut VirtualCuboardNodes, hue_offsets: [f32; 6], value: f32) {
     for f in 0..6 {
         for r in 0..3 {
@@ -186,7 +429,11 @@ pub fn set_colors_hue(nodes: &mut VirtualCuboardNodes, hue_offsets: [f32; 6], va
 }
 
 // set colors by global orientation
-pub fn set_colors_ori(nodes: &mut VirtualCuboardNodes, orientation: UnitQuaternion<f32>, value: f32) {
+pub fn set_colors_ori(
+    nodes: &mut VirtualCuboardNodes,
+    orientation: UnitQuaternion<f32>,
+    value: f32,
+) {
     fn half_angle(q: UnitQuaternion<f32>) -> f32 {
         (q.i.powi(2) + q.j.powi(2) + q.k.powi(2)).sqrt().atan2(q.w)
     }
@@ -1,130 +1,442 @@
-use kiss3d::nalgebra::{Quaternion, UnitQuaternion};
+use kiss3d::event::Key;
+use kiss3d::nalgebra::{Point2, Point3, UnitQuaternion, Vector2};
+use kiss3d::text::Font;
 
-use btleplug::api::{Central, Manager, ScanFilter};
-use btleplug::platform;
 use std::error::Error;
-use std::io::{stdout, Write};
+use std::f32::consts::FRAC_PI_2;
+use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use tokio::time::{sleep, Duration};
+use std::time::{Duration, Instant};
 
-use crate::bluetooth::gancubev2::{GanCubeV2Builder, ResponseMessage};
-use crate::cube::CubeMove;
-use crate::view::virtualcuboard::{set_face_visible, VirtualCuboard};
+use crate::algorithm::library::{find_insertion, next_f2l_pair, F2L_SLOTS};
+use crate::bluetooth::connect_cube;
+use crate::bluetooth::gancubev2::ResponseMessage;
+use crate::bluetooth::sequencer::MoveSequencer;
+use crate::config;
+use crate::cube::{CubeMove, CubeState, FaceTurn, CORNER_FACELETS, EDGE_FACELETS};
+use crate::cuboard::{GestureFlash, GyroGesture, GyroGestureRecognizer, BUFFER_SIZE};
+use crate::i18n::{tr, Message};
+use crate::orientation::OrientationFilter;
+use crate::shutdown::Shutdown;
+use crate::view::animation::{ease_out, pulse, Timeline};
+use crate::view::recording::{load_recording, GyroRecorder, Playback};
+use crate::view::virtualcuboard::{
+    set_face_turned, set_face_visible, set_facelets_ghost, set_facelets_visible, set_net_colors,
+    VirtualCuboard,
+};
+use crate::view::{GuideHandler, InputHandler};
 
-struct UnitQuaternionSmoother<const N: usize>([UnitQuaternion<f32>; N], usize);
+// what is shown of the next unsolved F2L pair
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum F2lHint {
+    // the stickers of the pair, raised where they are
+    Pair,
+    // and the algorithm inserting it, if found in the library
+    Insertion,
+}
+
+const SLOT_NAMES: [&str; 4] = ["FR pair", "FL pair", "BL pair", "BR pair"];
+
+// the typed text shown is cut to the end of the line
+const TYPED_WIDTH: usize = 32;
+
+// the faces of the nodes of U, R, F, D, L, B
+const CUBEMOVE_TO_FACEINDEX: [usize; 6] = [2, 4, 3, 5, 1, 0];
+
+// the raised face follows the turn, and the cube grows for finishing and shrinks for cancelling
+const TURN_DURATION: Duration = Duration::from_millis(150);
+const BOUNCE_DURATION: Duration = Duration::from_millis(250);
+const BOUNCE_SCALE: f32 = 0.12;
+
+// the piece on each facelet, named by `Corner` and `Edge` with the name rotated by the twist or
+// the flip, such as `RFU` for the URF corner twisted in place
+fn piece_labels(state: &CubeState) -> Vec<Option<String>> {
+    let mut labels = vec![None; 54];
+    for (facelets, corner) in CORNER_FACELETS.iter().zip(state.corners) {
+        for &facelet in facelets {
+            labels[facelet] = Some(corner.to_string());
+        }
+    }
+    for (facelets, edge) in EDGE_FACELETS.iter().zip(state.edges) {
+        for &facelet in facelets {
+            labels[facelet] = Some(edge.to_string());
+        }
+    }
+    labels
+}
 
-impl<const N: usize> UnitQuaternionSmoother<N> {
-    fn new() -> Self {
-        UnitQuaternionSmoother([UnitQuaternion::default(); N], 0)
+// the view of the cube updated by its messages
+struct ViewState {
+    orientation: Box<dyn OrientationFilter + Send>,
+    last_move: Option<CubeMove>,
+    recognizer: GyroGestureRecognizer<BUFFER_SIZE>,
+    flash: GestureFlash,
+    turn: Timeline<CubeMove>,
+    bounce: Timeline<GyroGesture>,
+    recorder: Option<GyroRecorder>,
+    // the face to turn next, which is shown instead of the last turned face
+    hint: Option<FaceTurn>,
+    f2l: Option<F2lHint>,
+    // the turn shown ahead by pressing the letter of its face
+    preview: Option<FaceTurn>,
+    // tracked from the reported state for the F2L hint and the preview
+    state: Option<CubeState>,
+    sequencer: MoveSequencer,
+    // the text given by the input handler
+    typed: Option<String>,
+}
+
+impl ViewState {
+    fn new(recorder: Option<GyroRecorder>, f2l: Option<F2lHint>) -> Self {
+        ViewState {
+            orientation: config::get().view.filter.filter(),
+            last_move: None,
+            recognizer: GyroGestureRecognizer::new(),
+            flash: GestureFlash::default(),
+            turn: Timeline::new(TURN_DURATION),
+            bounce: Timeline::new(BOUNCE_DURATION),
+            recorder,
+            hint: None,
+            f2l,
+            preview: None,
+            state: None,
+            sequencer: MoveSequencer::default(),
+            typed: None,
+        }
     }
 
-    fn put(&mut self, q: UnitQuaternion<f32>) {
-        self.0[self.1] = q;
-        self.1 = (self.1 + 1) % N;
+    fn track(&mut self, msg: ResponseMessage) {
+        let sequenced = self.sequencer.handle_message(msg, Instant::now());
+        if let ResponseMessage::State {
+            count: _,
+            state: Some(mut state),
+        } = msg
+        {
+            state.reset_centers();
+            self.state = Some(state);
+        }
+        if let Some(state) = &mut self.state {
+            for (mv, _) in sequenced.moves {
+                state.apply(mv);
+            }
+        }
     }
 
-    fn get(&self) -> UnitQuaternion<f32> {
-        let q = self
-            .0
+    // the facelets where the pieces of the next unsolved F2L pair are, with the faces they show
+    fn f2l_pair(&self) -> Option<(usize, Vec<(usize, usize)>)> {
+        let state = self.state?;
+        let slot = next_f2l_pair(&state)?;
+        let (corner, edge) = F2L_SLOTS[slot];
+        let faces = state.facelets();
+        let corner_pos = state.corners.iter().position(|c| c.0 == corner)?;
+        let edge_pos = state.edges.iter().position(|e| e.0 == edge)?;
+        let facelets = CORNER_FACELETS[corner_pos]
             .iter()
-            .map(|q| q.quaternion())
-            .fold(Quaternion::default(), |acc, q| acc + q);
-        UnitQuaternion::new_normalize(q)
+            .chain(&EDGE_FACELETS[edge_pos])
+            .map(|&facelet| {
+                (
+                    facelet,
+                    "URFDLB".find(&faces[facelet..facelet + 1]).unwrap(),
+                )
+            })
+            .collect();
+        Some((slot, facelets))
     }
-}
 
-pub async fn run() -> Result<(), Box<dyn Error>> {
-    let orientation = Arc::new(Mutex::new(UnitQuaternionSmoother::<5>::new()));
-    let last_move: Arc<Mutex<Option<CubeMove>>> = Arc::new(Mutex::new(None));
+    // pressing the letter of a face again turns it further, until it is back, and escape stops
+    // the preview
+    fn preview_key(&mut self, key: Key) {
+        let face = match key {
+            Key::U => CubeMove::U,
+            Key::R => CubeMove::R,
+            Key::F => CubeMove::F,
+            Key::D => CubeMove::D,
+            Key::L => CubeMove::L,
+            Key::B => CubeMove::B,
+            Key::Escape => {
+                self.preview = None;
+                return;
+            }
+            _ => return,
+        };
+        let amount = match self.preview {
+            Some(turn) if turn.face() == face => turn.amount() + 1,
+            _ => 1,
+        };
+        self.preview = FaceTurn::new(face, amount);
+    }
+
+    // the facelets changed by the previewed turn, with the faces they show after it; the state
+    // is solved until it is reported, such as in the replay
+    fn preview_facelets(&self) -> Option<Vec<(usize, usize)>> {
+        let turn = self.preview?;
+        let state = self.state.unwrap_or_default();
+        let mut next = state;
+        for mv in turn.moves() {
+            next.apply(mv);
+        }
+        let (faces, next_faces) = (state.facelets(), next.facelets());
+        let facelets = faces
+            .chars()
+            .zip(next_faces.chars())
+            .enumerate()
+            .filter(|(_, (face, next_face))| face != next_face)
+            .map(|(facelet, (_, next_face))| (facelet, "URFDLB".find(next_face).unwrap()))
+            .collect();
+        Some(facelets)
+    }
+
+    fn handle_message(&mut self, msg: ResponseMessage) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.put(msg);
+        }
+        let now = Instant::now();
+        let gesture = self.recognizer.handle_message(msg);
+        if let Some(gesture) = gesture {
+            self.bounce.start(gesture, now);
+        }
+        self.flash.put(gesture, now);
+        if let Some((frame1, frame2)) = msg.orientation_frames() {
+            // average the orientations of both gyroscopes
+            let q = frame1.orientation.into_inner() + frame2.orientation.into_inner();
+            self.orientation.put(UnitQuaternion::new_normalize(q));
+        }
+        if let ResponseMessage::Moves {
+            count: _,
+            moves,
+            times: _,
+        } = msg
+        {
+            self.last_move = moves[0];
+            if let Some(mv) = moves[0] {
+                self.turn.start(mv, Instant::now());
+            }
+        }
+        self.track(msg);
+    }
+
+    fn render(&self, cube: &mut VirtualCuboard, font: &Rc<Font>) {
+        let now = Instant::now();
+        cube.set_orientation(self.orientation.get());
+        let scale = match self.bounce.get(now) {
+            Some((GyroGesture::TurningAround, t)) => 1.0 + BOUNCE_SCALE * pulse(t),
+            Some((GyroGesture::Shaking, t)) => 1.0 - BOUNCE_SCALE * pulse(t),
+            None => 1.0,
+        };
+        cube.node.set_local_scale(scale, scale, scale);
+        if cube.flat {
+            // the net shows the tracked state, which is solved until it is reported
+            let view = &config::get().view;
+            let colors = view.palette.colors(&view.custom);
+            let facelets = self.state.unwrap_or_default().facelets();
+            set_net_colors(&mut cube.net, &facelets, colors);
+        }
+
+        let preview = self.preview_facelets();
+        set_facelets_ghost(&mut cube.components_raise, preview.is_some());
+        if let (Some(turn), Some(facelets)) = (self.preview, preview) {
+            // the stickers after the turn are outlined over the current ones
+            let view = &config::get().view;
+            let colors = view.palette.colors(&view.custom);
+            set_facelets_visible(&mut cube.components_raise, &facelets, colors);
 
-    // get the first bluetooth adapter
-    let manager = platform::Manager::new().await.unwrap();
-    let adapters = manager.adapters().await?;
-    let adapter = adapters.into_iter().next().unwrap();
+            let label = format!("{} preview (esc to stop)", turn);
+            let color = Point3::new(0.6, 0.8, 1.0);
+            cube.window
+                .draw_text(&label, &Point2::new(20.0, 320.0), 50.0, font, &color);
+        } else if let Some(f2l) = self.f2l {
+            // the pair is shown instead of the turned face
+            let pair = self.f2l_pair();
+            let facelets = pair.as_ref().map_or(&[][..], |(_, facelets)| facelets);
+            let view = &config::get().view;
+            let colors = view.palette.colors(&view.custom);
+            set_facelets_visible(&mut cube.components_raise, facelets, colors);
 
-    // start scanning for devices
-    adapter.start_scan(ScanFilter::default()).await?;
-    print!("scan devices");
+            let label = match (&pair, f2l) {
+                (Some((slot, _)), F2lHint::Insertion) => {
+                    let state = self.state.unwrap_or_default();
+                    let insertion = find_insertion(&state, *slot);
+                    let name = SLOT_NAMES[*slot];
+                    match insertion {
+                        Some(alg) => format!("{}: {}", name, alg),
+                        None => format!("{}: no insertion found", name),
+                    }
+                }
+                (Some((slot, _)), F2lHint::Pair) => SLOT_NAMES[*slot].to_string(),
+                (None, _) if self.state.is_some() => "F2L solved".to_string(),
+                (None, _) => String::new(),
+            };
+            let color = Point3::new(1.0, 1.0, 0.4);
+            cube.window
+                .draw_text(&label, &Point2::new(20.0, 320.0), 50.0, font, &color);
+        } else {
+            let mut visible = [false; 6];
+            let face = self.hint.map(|turn| turn.face()).or(self.last_move);
+            if let Some(mv) = face {
+                visible[CUBEMOVE_TO_FACEINDEX[(mv.repr() / 2) as usize]] = true;
+            }
+            set_face_visible(&mut cube.components_raise, visible);
+        }
 
-    let builder = 'a: loop {
-        print!(".");
-        let _ = stdout().flush();
+        // the raised face starts where it was before the last turn, unless another face is shown
+        let turning = self
+            .turn
+            .get(now)
+            .filter(|_| self.hint.is_none() && self.f2l.is_none() && self.preview.is_none());
+        let turned = turning.map(|(mv, t)| {
+            let angle = if mv.is_clockwise() {
+                FRAC_PI_2
+            } else {
+                -FRAC_PI_2
+            };
+            let face = CUBEMOVE_TO_FACEINDEX[(mv.repr() / 2) as usize];
+            (face, angle * (1.0 - ease_out(t)))
+        });
+        set_face_turned(&mut cube.components_raise, turned);
 
-        let found = GanCubeV2Builder::find_gancube_device(&adapter).await?;
-        if let Some(builder) = found.into_iter().next() {
-            break 'a builder;
+        if cube.labels && !cube.flat {
+            // the labels are centered on the stickers
+            const SCALE: f32 = 28.0;
+            let labels = piece_labels(&self.state.unwrap_or_default());
+            let color = Point3::new(1.0, 1.0, 1.0);
+            for (facelet, center) in cube.facing_facelets() {
+                let Some(label) = &labels[facelet] else {
+                    continue;
+                };
+                let offset = Vector2::new(label.len() as f32 * SCALE / 4.0, SCALE / 2.0);
+                cube.window
+                    .draw_text(label, &(center - offset), SCALE, font, &color);
+            }
         }
 
-        sleep(Duration::from_secs(1)).await;
-    };
-    println!();
+        if let Some(turn) = self.hint {
+            let direction = match turn.amount() {
+                1 => "clockwise",
+                2 => "half turn",
+                _ => "counterclockwise",
+            };
+            let label = format!("{} ({})", turn, direction);
+            let color = Point3::new(1.0, 1.0, 0.4);
+            cube.window
+                .draw_text(&label, &Point2::new(20.0, 220.0), 60.0, font, &color);
+        }
+
+        if let Some(typed) = &self.typed {
+            let skip = typed.chars().count().saturating_sub(TYPED_WIDTH);
+            let label = typed.chars().skip(skip).collect::<String>() + "_";
+            let color = Point3::new(1.0, 1.0, 1.0);
+            cube.window
+                .draw_text(&label, &Point2::new(20.0, 420.0), 60.0, font, &color);
+        }
 
-    adapter.stop_scan().await?;
+        // acknowledge the recognized gesture
+        if let Some(gesture) = self.flash.get(now) {
+            let (label, color) = match gesture {
+                GyroGesture::TurningAround => ("enter", Point3::new(0.2, 1.0, 0.2)),
+                GyroGesture::Shaking => ("cancel", Point3::new(1.0, 0.2, 0.2)),
+            };
+            cube.window
+                .draw_text(label, &Point2::new(20.0, 20.0), 80.0, font, &color);
+        }
+    }
+}
 
-    println!("connect to GANCube...");
-    let gancube = builder.connect().await?;
-    println!("connected! have fun~");
+// show the cube, and record its gyroscope messages to the file if given, or highlight the face
+// to turn given by the guide, or the next unsolved F2L pair, with the text typed by the input
+pub async fn run(
+    shutdown: Shutdown,
+    record: Option<String>,
+    mut guide: Option<GuideHandler>,
+    f2l: Option<F2lHint>,
+    mut input: Option<InputHandler>,
+) -> Result<(), Box<dyn Error>> {
+    let gancube = connect_cube(shutdown.clone()).await?;
+    println!("{}", tr(Message::Connected, &[]));
     println!();
 
-    let orientation_msg = Arc::clone(&orientation);
-    let last_move_msg = Arc::clone(&last_move);
-    gancube
-        .register_handler(Box::new(move |msg| match msg {
-            ResponseMessage::Gyroscope {
-                q1,
-                q1p: _,
-                q2,
-                q2p: _,
-            } => {
-                let Ok(mut ori) = orientation_msg.lock() else {
-                        return;
-                    };
-
-                let q1 = Quaternion::new(q1.0, q1.2, q1.3, q1.1);
-                let q2 = Quaternion::new(q2.0, q2.2, q2.3, q2.1);
-                ori.put(UnitQuaternion::new_normalize(q1 + q2))
-            }
-            ResponseMessage::Moves {
-                count: _,
-                moves,
-                times: _,
-            } => {
-                let Ok(mut mv) = last_move_msg.lock() else {
-                        return;
-                    };
-
-                *mv = moves[0];
+    let recorder = record.as_ref().map(|_| GyroRecorder::new());
+    let state = Arc::new(Mutex::new(ViewState::new(recorder, f2l)));
+    let state_msg = Arc::clone(&state);
+    let handle = gancube
+        .register_handler(Box::new(move |msg| {
+            if let Ok(mut state) = state_msg.lock() {
+                state.handle_message(msg);
+                if let Some(guide) = &mut guide {
+                    state.hint = guide(msg);
+                }
+                if let Some(input) = &mut input {
+                    state.typed = Some(input(msg));
+                }
             }
-            _ => {}
         }))
         .await?;
 
     gancube.subscribe_response().await?;
+    // the guide and the F2L hint count the moves from the reported state
+    gancube.request_cube_state().await?;
 
-    let orientation_cube = Arc::clone(&orientation);
-    let last_move_cube = Arc::clone(&last_move);
+    let state_cube = Arc::clone(&state);
+    let font = Font::default();
+    let shutdown_cube = shutdown.clone();
     let mut cube = VirtualCuboard::new();
     cube.render_loop(move |cube| {
-        const CUBEMOVE_TO_FACEINDEX: [usize; 6] = [
-            // U, R, F, D, L, B,
-            2, 4, 3, 5, 1, 0,
-        ];
-
-        let Ok(ori) = orientation_cube.lock() else {
+        if shutdown_cube.is_requested() {
+            cube.window.close();
             return;
-        };
-        let Ok(mv) = last_move_cube.lock() else {
-            return;
-        };
+        }
 
-        let orientation = ori.get();
-        cube.set_orientation(orientation);
+        if let Ok(mut state) = state_cube.lock() {
+            for &key in &cube.released_keys {
+                state.preview_key(key);
+            }
+            state.render(cube, &font);
+        }
+    });
+
+    shutdown.close(&gancube).await?;
+    handle.abort();
 
-        let mut visible = [false; 6];
-        if let Some(mv) = *mv {
-            visible[CUBEMOVE_TO_FACEINDEX[(mv.repr() / 2) as usize]] = true;
+    if let Some(path) = record {
+        let state = state.lock().map_err(|err| err.to_string())?;
+        if let Some(recorder) = &state.recorder {
+            recorder.save(&path)?;
+            println!("{}", tr(Message::RecordedTo, &[&path]));
         }
-        set_face_visible(&mut cube.components_raise, visible);
+    }
+
+    Ok(())
+}
+
+// replay the recorded gyroscope messages without the cube
+pub fn replay(path: &str, shutdown: Shutdown) -> Result<(), Box<dyn Error>> {
+    let mut playback = Playback::new(load_recording(path)?);
+    println!("{}", tr(Message::ReplayHint, &[]));
+
+    let mut state = ViewState::new(None, None);
+    let font = Font::default();
+    let mut last_frame = Instant::now();
+    let mut cube = VirtualCuboard::new();
+    cube.render_loop(move |cube| {
+        if shutdown.is_requested() {
+            cube.window.close();
+            return;
+        }
+
+        for &key in &cube.released_keys {
+            playback.handle_key(key);
+            state.preview_key(key);
+        }
+        let now = Instant::now();
+        for msg in playback.advance(now - last_frame) {
+            state.handle_message(msg);
+        }
+        last_frame = now;
+
+        state.render(cube, &font);
+        let color = Point3::new(0.8, 0.8, 0.8);
+        let position = Point2::new(20.0, 120.0);
+        cube.window
+            .draw_text(&playback.status(), &position, 40.0, &font, &color);
     });
 
     Ok(())
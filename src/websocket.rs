@@ -0,0 +1,252 @@
+use std::{io, pin::Pin, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{stream, Stream, StreamExt};
+use sha1::{Digest, Sha1};
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader, Lines,
+    },
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:9899";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+// the clients only send control frames, whose payload is at most 125 bytes
+const MAX_PAYLOAD: u64 = 0xFFFF;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+// the `Sec-WebSocket-Accept` answering the `Sec-WebSocket-Key` of the handshake
+fn accept_key(key: &str) -> String {
+    let digest = Sha1::digest(format!("{}{}", key.trim(), ACCEPT_GUID));
+    STANDARD.encode(digest)
+}
+
+// an unmasked frame, as sent by servers
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// the frames sent by the clients, which only listen, so that their data is dropped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientFrame {
+    Ping(Vec<u8>),
+    // the status code and the reason, if any
+    Close(Vec<u8>),
+    Data,
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<ClientFrame> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header).await?;
+    let len = match header[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        len => len as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+    }
+    let mut mask = [0; 4];
+    if header[1] & 0x80 != 0 {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    match header[0] & 0x0F {
+        OPCODE_PING => Ok(ClientFrame::Ping(payload)),
+        OPCODE_CLOSE => Ok(ClientFrame::Close(payload)),
+        _ => Ok(ClientFrame::Data),
+    }
+}
+
+// the frames of the client until it leaves, which can be awaited in a `select!` without losing
+// the frame being read
+pub fn client_frames<R: AsyncRead + Unpin>(
+    reader: R,
+) -> Pin<Box<impl Stream<Item = io::Result<ClientFrame>>>> {
+    Box::pin(stream::unfold(reader, |mut reader| async move {
+        match read_frame(&mut reader).await {
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+            frame => Some((frame, reader)),
+        }
+    }))
+}
+
+// answer a ping by a pong and a close by a close, and tell whether the connection is still open
+pub async fn answer<W: AsyncWrite + Unpin>(writer: &mut W, frame: ClientFrame) -> io::Result<bool> {
+    match frame {
+        ClientFrame::Ping(payload) => {
+            writer
+                .write_all(&self::frame(OPCODE_PONG, &payload))
+                .await?;
+            Ok(true)
+        }
+        ClientFrame::Close(payload) => {
+            // only the status code is echoed
+            let code = &payload[..payload.len().min(2)];
+            writer.write_all(&self::frame(OPCODE_CLOSE, code)).await?;
+            writer.shutdown().await?;
+            Ok(false)
+        }
+        ClientFrame::Data => Ok(true),
+    }
+}
+
+// the path and the `Sec-WebSocket-Key` of a request, whose headers end with an empty line
+pub async fn read_request<R: AsyncBufRead + Unpin>(
+    lines: &mut Lines<R>,
+) -> io::Result<(String, Option<String>)> {
+    let request = async {
+        let line = lines.next_line().await?.unwrap_or_default();
+        let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+        let mut key = None;
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("sec-websocket-key") {
+                    key = Some(value.trim().to_string());
+                }
+            }
+        }
+        io::Result::Ok((path, key))
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, request).await?
+}
+
+// answer the handshake, then text frames can be sent
+pub async fn accept<W: AsyncWrite + Unpin>(writer: &mut W, key: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key),
+    );
+    writer.write_all(response.as_bytes()).await
+}
+
+pub async fn send_text<W: AsyncWrite + Unpin>(writer: &mut W, text: &str) -> io::Result<()> {
+    writer.write_all(&frame(OPCODE_TEXT, text.as_bytes())).await
+}
+
+// upgrade the connection, then send the texts until the client leaves or closes it
+async fn serve_client(stream: TcpStream, mut texts: broadcast::Receiver<String>) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let Some(key) = read_request(&mut lines).await?.1 else {
+        writer
+            .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n")
+            .await?;
+        return writer.shutdown().await;
+    };
+    accept(&mut writer, &key).await?;
+
+    let mut frames = client_frames(lines.into_inner());
+    loop {
+        tokio::select! {
+            text = texts.recv() => match text {
+                Ok(text) => send_text(&mut writer, &text).await?,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return writer.shutdown().await,
+            },
+            frame = frames.next() => {
+                let Some(frame) = frame else {
+                    return Ok(());
+                };
+                if !answer(&mut writer, frame?).await? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// send the accepted text to all clients of websocket, which only listen
+pub async fn serve(listener: TcpListener, texts: broadcast::Sender<String>) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let client = serve_client(stream, texts.subscribe());
+        tokio::spawn(async move {
+            let _ = client.await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn texts_are_sent_to_websocket_clients() {
+        // the example of RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (texts, _) = broadcast::channel(16);
+        tokio::spawn(serve(listener, texts.clone()));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                       Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                       Sec-WebSocket-Version: 13\r\n\r\n";
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = vec![0; 129];
+        stream.read_exact(&mut response).await.unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.ends_with("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"));
+
+        // wait for the client to subscribe
+        while texts.receiver_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+        texts.send("hi".to_string()).unwrap();
+        let mut frame = [0; 4];
+        stream.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame, [0x81, 2, b'h', b'i']);
+
+        // the frames of clients are masked
+        let ping = [0x89, 0x82, 1, 2, 3, 4, b'h' ^ 1, b'o' ^ 2];
+        stream.write_all(&ping).await.unwrap();
+        stream.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame, [0x8A, 2, b'h', b'o']);
+
+        // closing with the status code 1000
+        let close = [0x88, 0x82, 1, 2, 3, 4, 0x03 ^ 1, 0xE8 ^ 2];
+        stream.write_all(&close).await.unwrap();
+        stream.read_exact(&mut frame).await.unwrap();
+        assert_eq!(frame, [0x88, 2, 0x03, 0xE8]);
+        assert_eq!(stream.read(&mut frame).await.unwrap(), 0);
+    }
+}
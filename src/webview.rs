@@ -0,0 +1,222 @@
+use std::io;
+
+use tokio::sync::broadcast;
+
+use crate::{
+    cube::{CubeState, FaceTurn},
+    daemon::DaemonEvent,
+};
+
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:9900";
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebViewError {
+    #[cfg_attr(feature = "web", allow(dead_code))]
+    #[error("built without the web viewer, enable the feature `web`")]
+    Unsupported,
+    #[cfg_attr(not(feature = "web"), allow(dead_code))]
+    #[error("web viewer error: {0}")]
+    Io(#[from] io::Error),
+}
+
+// the cube shown by the page, tracked by the events of the daemon
+#[cfg_attr(not(feature = "web"), allow(dead_code))]
+#[derive(Clone, Debug, Default)]
+pub struct CubeView {
+    state: Option<CubeState>,
+    orientation: Option<DaemonEvent>,
+}
+
+#[cfg_attr(not(feature = "web"), allow(dead_code))]
+impl CubeView {
+    // the events sent to the page, where moves are turned into the states after them
+    pub fn handle(&mut self, event: DaemonEvent) -> Option<DaemonEvent> {
+        match event {
+            DaemonEvent::State { ref facelets, .. } => {
+                self.state = CubeState::from_facelets(facelets).ok();
+                Some(event)
+            }
+            DaemonEvent::Moves { moves } => {
+                // moves before the state is known are dropped
+                let state = self.state.as_mut()?;
+                for mv in moves {
+                    for mv in mv.parse::<FaceTurn>().ok()?.moves() {
+                        state.apply(mv);
+                    }
+                }
+                Some(DaemonEvent::state(state))
+            }
+            DaemonEvent::Orientation { .. } => {
+                self.orientation = Some(event.clone());
+                Some(event)
+            }
+            DaemonEvent::Battery { .. } | DaemonEvent::Disconnect => Some(event),
+            _ => None,
+        }
+    }
+
+    // the events bringing a new page up to date
+    pub fn snapshot(&self) -> Vec<DaemonEvent> {
+        let state = self.state.as_ref().map(DaemonEvent::state);
+        state.into_iter().chain(self.orientation.clone()).collect()
+    }
+}
+
+// serve the page showing the cube, and the events it shows by websocket, until the events end
+#[cfg(feature = "web")]
+pub async fn serve(
+    address: String,
+    mut events: broadcast::Receiver<DaemonEvent>,
+) -> Result<(), WebViewError> {
+    use std::sync::{Arc, Mutex};
+
+    use futures::StreamExt;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use crate::websocket;
+
+    const PAGE: &str = include_str!("webview.html");
+    const EVENT_CAPACITY: usize = 256;
+
+    async fn serve_client(
+        stream: TcpStream,
+        view: Arc<Mutex<CubeView>>,
+        shown: broadcast::Sender<DaemonEvent>,
+    ) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let key = match websocket::read_request(&mut lines).await? {
+            (path, Some(key)) if path == "/events" => key,
+            (path, _) if path == "/" => {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n\
+                     Content-Type: text/html; charset=utf-8\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    PAGE.len(),
+                    PAGE,
+                );
+                writer.write_all(response.as_bytes()).await?;
+                return writer.shutdown().await;
+            }
+            _ => {
+                writer
+                    .write_all(b"HTTP/1.1 404 Not Found\r\nConnection: close\r\n\r\n")
+                    .await?;
+                return writer.shutdown().await;
+            }
+        };
+        websocket::accept(&mut writer, &key).await?;
+
+        // subscribe with the lock held, so that no event is missed after the snapshot
+        let (snapshot, mut receiver) = {
+            let view = view
+                .lock()
+                .map_err(|err| io::Error::other(err.to_string()))?;
+            (view.snapshot(), shown.subscribe())
+        };
+        for event in snapshot {
+            websocket::send_text(&mut writer, &serde_json::to_string(&event)?).await?;
+        }
+        let mut frames = websocket::client_frames(lines.into_inner());
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => {
+                        websocket::send_text(&mut writer, &serde_json::to_string(&event)?).await?
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return writer.shutdown().await,
+                },
+                frame = frames.next() => {
+                    let Some(frame) = frame else {
+                        return Ok(());
+                    };
+                    if !websocket::answer(&mut writer, frame?).await? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    let listener = TcpListener::bind(&address).await?;
+    println!("web viewer on http://{}", address);
+
+    let view = Arc::new(Mutex::new(CubeView::default()));
+    let (shown, _) = broadcast::channel(EVENT_CAPACITY);
+    let clients = {
+        let view = Arc::clone(&view);
+        let shown = shown.clone();
+        async move {
+            loop {
+                let (stream, _) = listener.accept().await?;
+                let client = serve_client(stream, Arc::clone(&view), shown.clone());
+                tokio::spawn(async move {
+                    let _ = client.await;
+                });
+            }
+        }
+    };
+    tokio::spawn(async move {
+        let res: io::Result<()> = clients.await;
+        if let Err(err) = res {
+            println!("web viewer error: {}", err);
+        }
+    });
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        let Ok(mut view) = view.lock() else {
+            return Ok(());
+        };
+        if let Some(event) = view.handle(event) {
+            // no page is open
+            let _ = shown.send(event);
+        }
+    }
+}
+
+#[cfg(not(feature = "web"))]
+pub async fn serve(
+    _address: String,
+    _events: broadcast::Receiver<DaemonEvent>,
+) -> Result<(), WebViewError> {
+    Err(WebViewError::Unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_are_shown_as_states() {
+        let mut view = CubeView::default();
+        let moves = DaemonEvent::Moves {
+            moves: vec!["R".to_string()],
+        };
+        assert_eq!(view.handle(moves.clone()), None);
+        assert_eq!(view.handle(DaemonEvent::Cancel), None);
+
+        let solved = DaemonEvent::state(&CubeState::default());
+        assert_eq!(view.handle(solved.clone()), Some(solved));
+        let r = DaemonEvent::state(&CubeState::of_move(crate::cube::CubeMove::R));
+        assert_eq!(view.handle(moves), Some(r.clone()));
+
+        let orientation = DaemonEvent::Orientation {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        };
+        assert_eq!(view.handle(orientation.clone()), Some(orientation.clone()));
+        assert_eq!(view.snapshot(), vec![r, orientation]);
+    }
+}